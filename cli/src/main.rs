@@ -53,7 +53,7 @@ struct Args {
     #[clap(short = 'l', long = "qivm-lib-path")]
     pub lib_path: Option<String>,
 
-    /// Optimization level
+    /// Optimization level of the C compiler and of the quantum circuits, from 0 to 3
     #[clap(short = 'O', long = "opt-level")]
     pub optimization_level: Option<u32>,
 
@@ -64,6 +64,52 @@ struct Args {
     /// C compiler flags
     #[clap(long = "cc-flags")]
     pub c_compiler_flags: Option<String>,
+
+    /// Append the quantum circuits executed by the program to this file as OpenQASM
+    #[clap(long = "emit-qasm")]
+    pub emit_qasm: Option<String>,
+
+    /// OpenQASM version of `--emit-qasm`
+    #[clap(long = "qasm-version", default_value = "3")]
+    pub qasm_version: u32,
+
+    /// Print the statistics of the quantum circuits executed by the program to the standard error
+    #[clap(long = "stats")]
+    pub stats: bool,
+}
+
+/// Generate a C constructor setting the QIVM runtime settings of the compiled program,
+/// unless they are overridden by the environment when running it.
+fn runtime_env_source(runtime_env: &BTreeMap<&str, String>) -> String {
+    if runtime_env.is_empty() {
+        return String::new();
+    }
+    let mut source = String::from(
+        "\n#include <stdlib.h>\n\n__attribute__((constructor))\nstatic void stateq_runtime_env(void)\n{\n"
+    );
+    for (name, value) in runtime_env {
+        source += &format!(
+            "    setenv({}, {}, 0);\n", c_string_literal(name), c_string_literal(value)
+        );
+    }
+    source + "}\n"
+}
+
+/// Quote the string as a C string literal. The bytes other than the printable ASCII characters
+/// are written as octal escapes, and so is `?` to avoid the trigraphs.
+fn c_string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' ' ..= b'~' if byte != b'?' => literal.push(byte as char),
+            _ => literal += &format!("\\{:03o}", byte),
+        }
+    }
+    literal + "\""
 }
 
 fn print_error_src(src_path: &str, line: i32, column: i32) {
@@ -159,7 +205,25 @@ fn main() {
     let compiled_source = fs::read_to_string(tmp_dir.join("target.c")).unwrap_or_else(|_| {
         raise_error!("Unable to read the compiled source file");
     });
-    let full_target_source = embedded_source.replace_embedded_source(&compiled_source);
+    let mut runtime_env = BTreeMap::<&str, String>::new();
+    if let Some(qasm_path) = &args.emit_qasm {
+        if !matches!(args.qasm_version, 2 | 3) {
+            raise_error!("Unsupported OpenQASM version: {}", args.qasm_version);
+        }
+        runtime_env.insert("STATEQ_EMIT_QASM", qasm_path.clone());
+        runtime_env.insert("STATEQ_QASM_VERSION", args.qasm_version.to_string());
+    }
+    if args.stats {
+        runtime_env.insert("STATEQ_PRINT_STATS", "1".to_string());
+    }
+    if let Some(level) = args.optimization_level {
+        if level > 3 {
+            raise_error!("Unsupported optimization level: {}", level);
+        }
+        runtime_env.insert("STATEQ_OPT_LEVEL", level.to_string());
+    }
+    let full_target_source = embedded_source.replace_embedded_source(&compiled_source)
+        + &runtime_env_source(&runtime_env);
     File::create(format!("{}.target.c", file_name)).unwrap_or_else(|_| {
         raise_error!("Unable to create target source file {}.target.c", file_name);
     }).write_all(full_target_source.as_bytes()).unwrap_or_else(|_| {
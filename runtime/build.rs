@@ -22,6 +22,8 @@ fn generate_qivm_runtime_headers() {
 
 fn main() {
     println!("cargo:rustc-link-arg=-Wl,-rpath=$ORIGIN");
-    println!("cargo:rustc-cfg=static_link_backend");
+    if env::var("CARGO_FEATURE_QIL_BACKEND").is_ok() {
+        println!("cargo:rustc-cfg=static_link_backend");
+    }
     generate_qivm_runtime_headers();
 }
\ No newline at end of file
@@ -1,143 +1,79 @@
-use lapack::{c32, c64};
-use nalgebra::{DefaultAllocator, Dim, DimDiv, U2};
-use nalgebra::allocator::Allocator;
 use num::complex::Complex64;
 use num_traits::Zero;
-use crate::algebra::{DMat, Mat};
+use crate::algebra::DMat;
 
-pub struct CosSinDecomposition<D: Dim + DimDiv<U2>>
-where
-    DefaultAllocator:
-        Allocator<Complex64, <D as DimDiv<U2>>::Output> +
-        Allocator<Complex64, <D as DimDiv<U2>>::Output, <D as DimDiv<U2>>::Output>
-{
-    pub u1: Mat<<D as DimDiv<U2>>::Output>,
-    pub u2: Mat<<D as DimDiv<U2>>::Output>,
+/// Cosine-sine decomposition of a unitary matrix of even dimension:
+///  X = [U1 0; 0 U2] [C -S; S C] [V1H 0; 0 V2H]
+/// where C = diag(cos θ) and S = diag(sin θ).
+pub struct CosSinDecomposition {
+    pub u1: DMat,
+    pub u2: DMat,
     pub cs: Vec<f64>,
-    pub v1h: Mat<<D as DimDiv<U2>>::Output>,
-    pub v2h: Mat<<D as DimDiv<U2>>::Output>,
+    pub v1h: DMat,
+    pub v2h: DMat,
 }
 
-impl<D: Dim + DimDiv<U2>> CosSinDecomposition<D>
-where
-    DefaultAllocator:
-        Allocator<Complex64, D> +
-        Allocator<Complex64, D, D> +
-        Allocator<Complex64, <D as DimDiv<U2>>::Output> +
-        Allocator<Complex64, <D as DimDiv<U2>>::Output, <D as DimDiv<U2>>::Output>,
-{
-    pub fn new(mat: &Mat<D>) -> CosSinDecomposition<D> {
-        assert_eq!(mat.nrows(), mat.ncols());
+impl CosSinDecomposition {
+    pub fn new(mat: &DMat) -> CosSinDecomposition {
+        assert!(mat.is_square());
+        assert_eq!(mat.nrows() % 2, 0);
         let size = mat.nrows();
-        let mut x11: Vec<c32> = mat.slice((0, 0), (size / 2, size / 2)).iter()
-            .map(|x| c32::new(x.re as f32, x.im as f32)).collect::<Vec<c32>>();
-        let mut x12 = mat.slice((0, size / 2), (size / 2, size / 2)).iter()
-            .map(|x| c32::new(x.re as f32, x.im as f32)).collect::<Vec<c32>>();
-        let mut x21 = mat.slice((size / 2, 0), (size / 2, size / 2)).iter()
-            .map(|x| c32::new(x.re as f32, x.im as f32)).collect::<Vec<c32>>();
-        let mut x22 = mat.slice((size / 2, size / 2), (size / 2, size / 2)).iter()
-            .map(|x| c32::new(x.re as f32, x.im as f32)).collect::<Vec<c32>>();
-        let mut angles = vec![0f32; size / 2];
-        let mut u1 = vec![c32::zero(); 2usize.pow((size / 2) as u32)];
-        let mut u2 = vec![c32::zero(); 2usize.pow((size / 2) as u32)];
-        let mut v1h = vec![c32::zero(); 2usize.pow((size / 2) as u32)];
-        let mut v2h = vec![c32::zero(); 2usize.pow((size / 2) as u32)];
+        let half = size / 2;
+        // blocks are stored in column-major order, as well as the nalgebra matrices
+        let block = |row, col| -> Vec<Complex64> {
+            mat.slice((row, col), (half, half)).iter().copied().collect()
+        };
+        let (mut x11, mut x12) = (block(0, 0), block(0, half));
+        let (mut x21, mut x22) = (block(half, 0), block(half, half));
+        let mut angles = vec![0f64; half];
+        let mut u1 = vec![Complex64::zero(); half * half];
+        let mut u2 = vec![Complex64::zero(); half * half];
+        let mut v1h = vec![Complex64::zero(); half * half];
+        let mut v2h = vec![Complex64::zero(); half * half];
+        let mut iwork = vec![0i32; half];
 
-        macro_rules! csd_call {
-            { $csd:ident,
-                $size:expr, $x11:expr, $x12:expr, $x21:expr, $x22:expr,
-                $angles:expr, $u1:expr, $u2:expr, $v1h:expr, $v2h:expr
-            } => {
-                let mut work = vec![c32::new(0f32, 0f32)];
-                let mut rwork = vec![0f32];
-                let mut iwork = vec![0i32; size / 2];
-                let mut result = 0i32;
-                unsafe {
-                    csd_call!(@call $csd,
-                        $size, $x11, $x12, $x21, $x22,
-                        $angles, $u1, $u2, $v1h, $v2h,
-                        work.as_mut_slice(), -1,
-                        rwork.as_mut_slice(), -1,
-                        iwork.as_mut_slice(),
-                        &mut result
-                    );
-                    assert_eq!(result, 0);
-                    let lwork: i32 = std::mem::transmute_copy::<c32, i32>(&work[0]);
-                    let lrwork: i32 = std::mem::transmute_copy::<f32, i32>(&rwork[0]);
-                    let mut work = vec![c32::new(0f32, 0f32); lwork as usize];
-                    let mut rwork = vec![0f32; lrwork as usize];
-                    csd_call!(@call $csd,
-                        $size, $x11, $x12, $x21, $x22,
-                        $angles, $u1, $u2, $v1h, $v2h,
-                        work.as_mut_slice(), lwork,
-                        rwork.as_mut_slice(), lrwork,
-                        iwork.as_mut_slice(),
-                        &mut result
-                    );
-                    assert_eq!(result, 0);
-                }
-            };
-
-            { @call $csd:ident,
-                $size:expr, $x11:expr, $x12:expr, $x21:expr, $x22:expr,
-                $angles:expr, $u1:expr, $u2:expr, $v1h:expr, $v2h:expr,
-                $work:expr, $lwork:expr, $rwork:expr, $lrwork:expr,
-                $iwork:expr, $result:expr
-            } => {
-                lapack::$csd(
-                    'Y' as u8, // compute u1
-                    'Y' as u8, // compute u2
-                    'Y' as u8, // compute v1h
-                    'Y' as u8, // compute v2h
-                    'F' as u8, // trans
-                    'F' as u8, // swap sign
-                    $size as i32, // mat size
-                    ($size / 2) as i32, // the number of rows in X11 and X12. 0 <= P <= M.
-                    &[($size / 2)  as i32], // the number of cols in X11 and X21. 0 <= Q <= M.
-                    $x11.as_mut_slice(), // x11 workspace
-                    ($size / 2) as i32, // x11 size
-                    $x12.as_mut_slice(), // x12 workspace
-                    ($size / 2) as i32, // x12 size
-                    $x21.as_mut_slice(), // x21 workspace
-                    ($size / 2) as i32, // x21 size
-                    $x22.as_mut_slice(), // x22 workspace
-                    ($size / 2) as i32, // x22 size
-                    $angles.as_mut_slice(),
-                    $u1.as_mut_slice(),
-                    ($size / 2) as i32,
-                    $u2.as_mut_slice(),
-                    ($size / 2) as i32,
-                    $v1h.as_mut_slice(),
-                    ($size / 2) as i32,
-                    $v2h.as_mut_slice(),
-                    ($size / 2) as i32,
-                    $work, $lwork, $rwork, $lrwork, $iwork,
-                    $result
-                )
-            };
-        }
-
-        csd_call! { cuncsd,
-            size, x11, x12, x21, x22,
-            angles, u1, u2, v1h, v2h
-        }
-
-        fn c32_slice_to_mat<D: Dim>(data: &[c32], size: usize) -> Mat<D>
-        where
-            DefaultAllocator: Allocator<Complex64, D> + Allocator<Complex64, D, D>
-        {
-            Mat::from_vec_generic(
-                D::from_usize(size), D::from_usize(size),
-                data.iter().map(|z| c64::new(z.re as f64, z.im as f64)).collect()
-            )
-        }
+        let mut csd = |work: &mut [Complex64], lwork: i32, rwork: &mut [f64], lrwork: i32| {
+            let mut result = 0i32;
+            unsafe {
+                lapack::zuncsd(
+                    b'Y', // compute u1
+                    b'Y', // compute u2
+                    b'Y', // compute v1h
+                    b'Y', // compute v2h
+                    b'N', // column-major order
+                    b'D', // default signs, the upper-right block is -S
+                    size as i32, // mat size
+                    half as i32, // the number of rows in X11 and X12
+                    &[half as i32], // the number of cols in X11 and X21
+                    &mut x11, half as i32,
+                    &mut x12, half as i32,
+                    &mut x21, half as i32,
+                    &mut x22, half as i32,
+                    &mut angles,
+                    &mut u1, half as i32,
+                    &mut u2, half as i32,
+                    &mut v1h, half as i32,
+                    &mut v2h, half as i32,
+                    work, lwork, rwork, lrwork, &mut iwork,
+                    &mut result,
+                );
+            }
+            assert_eq!(result, 0, "Cosine-sine decomposition failed");
+        };
+        // query the optimal workspace sizes first
+        let mut work = vec![Complex64::zero()];
+        let mut rwork = vec![0f64];
+        csd(&mut work, -1, &mut rwork, -1);
+        let (lwork, lrwork) = (work[0].re as usize, rwork[0] as usize);
+        let (mut work, mut rwork) = (vec![Complex64::zero(); lwork], vec![0f64; lrwork]);
+        csd(&mut work, lwork as i32, &mut rwork, lrwork as i32);
 
         Self {
-            u1: c32_slice_to_mat::<<D as DimDiv<U2>>::Output>(&u1, size / 2),
-            u2: c32_slice_to_mat::<<D as DimDiv<U2>>::Output>(&u2, size / 2),
-            cs: angles.iter().map(|&x| x as f64).collect(),
-            v1h: c32_slice_to_mat::<<D as DimDiv<U2>>::Output>(&v1h, size / 2),
-            v2h: c32_slice_to_mat::<<D as DimDiv<U2>>::Output>(&v2h, size / 2),
+            u1: DMat::from_vec(half, half, u1),
+            u2: DMat::from_vec(half, half, u2),
+            cs: angles,
+            v1h: DMat::from_vec(half, half, v1h),
+            v2h: DMat::from_vec(half, half, v2h),
         }
     }
 }
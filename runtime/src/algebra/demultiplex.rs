@@ -1,67 +1,39 @@
-use nalgebra::{Const, DefaultAllocator, Dim, DimDiff, DimDiv, DimMax, DimMaximum, DimMin, DimSub, U1, U2, U3};
-use nalgebra::allocator::Allocator;
-use num::complex::{Complex64, ComplexFloat};
-use crate::algebra::{DMat, IsHermitian, Mat};
+use num::complex::Complex64;
+use crate::c64;
+use crate::algebra::{DMat, hermitian_eigen};
 
-pub struct Demultiplex<D: Dim>
-where
-    DefaultAllocator: Allocator<Complex64, D> + Allocator<Complex64, D, D>
-{
-    pub v_mat: Mat<D>,
-    pub w_mat: Mat<D>,
+/// Demultiplex a block diagonal unitary:
+///  [U1 0; 0 U2] = [V 0; 0 V] [D 0; 0 D†] [W 0; 0 W]
+/// where D = diag(exp(-iφ/2)), so that [D 0; 0 D†] is a multiplexed RZ(φ).
+///
+/// reference: https://arxiv.org/pdf/quant-ph/0406176.pdf
+pub struct Demultiplex {
+    pub v_mat: DMat,
+    pub w_mat: DMat,
     pub rz_angles: Vec<f64>,
 }
 
-impl<D: Dim + DimDiv<U2> + DimSub<U1>> Demultiplex<D>
-where
-    DefaultAllocator:
-        Allocator<Complex64, D> +
-        Allocator<Complex64, D, D> +
-        Allocator<Complex64, <D as DimSub<U1>>::Output> +
-        Allocator<Complex64, D, <D as DimSub<U1>>::Output>,
-    DefaultAllocator:
-        Allocator<f64, D> +
-        Allocator<f64, <D as DimDiv<U2>>::Output> +
-        Allocator<f64, <D as DimSub<U1>>::Output>,
-{
-    pub fn new(u1: &Mat<D>, u2: &Mat<D>) -> Self {
+impl Demultiplex {
+    pub fn new(u1: &DMat, u2: &DMat) -> Self {
         assert_eq!(u1.shape(), u2.shape());
+        // X = U1 U2† = V D² V† is unitary, so it is diagonalized by the eigenvectors of the
+        // Hermitian matrix (X e^{iα} + X† e^{-iα}) / 2, unless its eigenvalues are degenerate.
         let u1_u2h = u1 * u2.adjoint();
-        let (eigen_vals, v_mat) = if u1_u2h.is_hermitian() {
-            let eigen = u1_u2h.symmetric_eigen();
-            (eigen.eigenvalues.map(Complex64::from), eigen.eigenvectors)
-        } else {
-            let (eigen_vals, v_mat) = u1_u2h.schur().unpack();
-            (eigen_vals.diagonal(), v_mat)
-        };
-        // d_vals: complex vector
+        let (v_mat, eigen_vals) = (0 .. 16).find_map(|i| {
+            let phase = Complex64::from_polar(1.0, 0.5 + 0.37 * i as f64);
+            let hermitian = (&u1_u2h * phase + u1_u2h.adjoint() * phase.conj()) / c64!(2);
+            let (_, v_mat) = hermitian_eigen(&hermitian);
+            let diag = v_mat.adjoint() * &u1_u2h * &v_mat;
+            let is_diagonal = diag.row_iter().enumerate().all(|(r, row)| {
+                row.iter().enumerate().all(|(c, value)| r == c || value.norm() < 1e-9)
+            });
+            is_diagonal.then(|| (v_mat, diag.diagonal()))
+        }).expect("Unitary matrix is not diagonalizable");
         let d_vals = eigen_vals.map(Complex64::sqrt);
-        // d_mat: complex matrix
-        let d_mat = Mat::<D>::from_partial_diagonal_generic(
-            D::from_usize(d_vals.len()),
-            D::from_usize(d_vals.len()),
-            d_vals.as_slice()
-        );
-        // w_mat: complex matrix
-        let w_mat = d_mat * v_mat.adjoint() * u2;
-        // angles: f64 vector
-        let angles = d_vals.conjugate().scale(2f64)
-            .map(|z| f64::atan(z.im / z.re));
+        let w_mat = DMat::from_diagonal(&d_vals) * v_mat.adjoint() * u2;
         Self {
             v_mat, w_mat,
-            rz_angles: angles.as_slice().to_vec(),
+            rz_angles: d_vals.iter().map(|d| -2.0 * d.arg()).collect(),
         }
     }
 }
-
-pub struct DemultiplexRotation {
-
-}
-
-impl DemultiplexRotation {
-    pub fn new(angles: &[f64]) -> DemultiplexRotation {
-        let dim = angles.len();
-        assert!(dim.is_power_of_two());
-        todo!()
-    }
-}
@@ -100,6 +100,61 @@ where
     mat / mat.determinant().pow(1.0 / (RANK as f64))
 }
 
+/// Eigen decomposition of a Hermitian matrix with the cyclic Jacobi method.
+///
+/// Return the eigenvalues and the unitary matrix of the eigenvectors, which is real if the matrix
+/// is real. Unlike `symmetric_eigen`, the residual off-diagonal entries are close to the machine
+/// precision, which is required to diagonalize unitaries exactly.
+pub fn hermitian_eigen(mat: &DMat) -> (Vec<f64>, DMat) {
+    assert!(mat.is_square());
+    let dim = mat.nrows();
+    let tolerance = 8.0 * f64::EPSILON * mat.norm();
+    let mut diag = mat.clone();
+    let mut eigenvectors = DMat::identity(dim, dim);
+    for _ in 0 .. 64 {
+        let off_diag = (0 .. dim)
+            .flat_map(|r| (0 .. dim).filter(move |&c| c != r).map(move |c| (r, c)))
+            .map(|index| diag[index].norm_sqr())
+            .sum::<f64>();
+        if off_diag.sqrt() <= tolerance {
+            break;
+        }
+        for p in 0 .. dim {
+            for q in p + 1 .. dim {
+                let norm = diag[(p, q)].norm();
+                if norm <= tolerance * f64::EPSILON {
+                    continue;
+                }
+                // J = diag(1, exp(-iφ)) [c s; -s c], where exp(iφ) is the phase of A_pq
+                let phase = (diag[(p, q)] / norm).conj();
+                let theta = (diag[(q, q)].re - diag[(p, p)].re) / (2.0 * norm);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let cos = 1.0 / (t * t + 1.0).sqrt();
+                let sin = t * cos;
+                let rotation = [
+                    [Complex64::new(cos, 0.0), Complex64::new(sin, 0.0)],
+                    [-phase * sin, phase * cos],
+                ];
+                // A ← A J, V ← V J
+                for matrix in [&mut diag, &mut eigenvectors] {
+                    for k in 0 .. dim {
+                        let (kp, kq) = (matrix[(k, p)], matrix[(k, q)]);
+                        matrix[(k, p)] = kp * rotation[0][0] + kq * rotation[1][0];
+                        matrix[(k, q)] = kp * rotation[0][1] + kq * rotation[1][1];
+                    }
+                }
+                // A ← J† A
+                for k in 0 .. dim {
+                    let (pk, qk) = (diag[(p, k)], diag[(q, k)]);
+                    diag[(p, k)] = rotation[0][0].conj() * pk + rotation[1][0].conj() * qk;
+                    diag[(q, k)] = rotation[0][1].conj() * pk + rotation[1][1].conj() * qk;
+                }
+            }
+        }
+    }
+    ((0 .. dim).map(|i| diag[(i, i)].re).collect(), eigenvectors)
+}
+
 /// Get the phase angle α in:
 ///  U = exp(iα) V
 ///  where V is an SU(2) matrix
@@ -109,6 +164,32 @@ pub fn phase_angle(mat: &Mat2) -> f64 {
     f64::atan2(det.im, det.re) / 2.0
 }
 
+/// Matrix of the gate controlled by qubits preceding its targets, which are in the given states.
+pub fn controlled(mat: &DMat, conditions: &[bool]) -> DMat {
+    let block = mat.nrows();
+    let offset = conditions.iter().fold(0, |offset, &condition| offset << 1 | condition as usize);
+    let mut result = DMat::identity(block << conditions.len(), block << conditions.len());
+    result.slice_mut((offset * block, offset * block), (block, block)).copy_from(mat);
+    result
+}
+
+/// Matrix of the gate applied to the targets among `size` qubits, the first being the most
+/// significant.
+pub fn embed(mat: &DMat, targets: &[usize], size: usize) -> DMat {
+    let bit = |qubit: usize| size - 1 - qubit;
+    let mask = targets.iter().fold(0, |mask, &qubit| mask | 1 << bit(qubit));
+    let sub_index = |index: usize| {
+        targets.iter().fold(0, |sub, &qubit| sub << 1 | (index >> bit(qubit) & 1))
+    };
+    DMat::from_fn(1 << size, 1 << size, |row, col| {
+        if row & !mask == col & !mask {
+            mat[(sub_index(row), sub_index(col))]
+        } else {
+            Complex64::new(0.0, 0.0)
+        }
+    })
+}
+
 pub trait MatSqrt {
     fn mat_sqrt(&self) -> Self;
 }
@@ -1,67 +1,44 @@
-use nalgebra::{DefaultAllocator, Dim, DimDiv, DimSub, U1, U2};
-use nalgebra::allocator::Allocator;
-use num::complex::Complex64;
 use crate::algebra::cos_sin::CosSinDecomposition;
 use crate::algebra::demultiplex::Demultiplex;
-use crate::algebra::Mat;
+use crate::algebra::DMat;
 
 ///  Decomposes unitary matrix into one and two qubit gates using Quantum Shannon Decomposition.
 //        ┌───┐               ┌───┐     ┌───┐     ┌───┐
 //       ─┤   ├─       ───────┤ Rz├─────┤ Ry├─────┤ Rz├─────
 //        │   │    ≃     ┌───┐└─┬─┘┌───┐└─┬─┘┌───┐└─┬─┘┌───┐
-//      /─┤   ├─       /─┤   ├──□──┤   ├──□──┤   ├──□──┤   ├
+//      /─┤   ├─       /─┤ u4├──□──┤ u3├──□──┤ u2├──□──┤ u1├
 //        └───┘          └───┘     └───┘     └───┘     └───┘
-pub struct QuantumShannonDecomposition<D: Dim + DimDiv<U2>>
-where
-    DefaultAllocator:
-    Allocator<Complex64, <D as DimDiv<U2>>::Output> +
-    Allocator<Complex64, <D as DimDiv<U2>>::Output, <D as DimDiv<U2>>::Output>
-{
-    u1_mat: Mat<<D as DimDiv<U2>>::Output>,
-    ucrz1_angles: Vec<f64>,
-    u2_mat: Mat<<D as DimDiv<U2>>::Output>,
-    ucry_angles: Vec<f64>,
-    u3_mat: Mat<<D as DimDiv<U2>>::Output>,
-    ucrz2_angles: Vec<f64>,
-    u4_mat: Mat<<D as DimDiv<U2>>::Output>,
+/// The first qubit is the most significant one, the rotations on it are multiplexed by the other
+/// qubits, and the `u*_mat` act on the other qubits.
+pub struct QuantumShannonDecomposition {
+    pub u1_mat: DMat,
+    pub ucrz1_angles: Vec<f64>,
+    pub u2_mat: DMat,
+    pub ucry_angles: Vec<f64>,
+    pub u3_mat: DMat,
+    pub ucrz2_angles: Vec<f64>,
+    pub u4_mat: DMat,
 }
 
-impl<D: Dim + DimDiv<U2> + DimSub<U1>> QuantumShannonDecomposition<D>
-where
-    DefaultAllocator:
-        Allocator<Complex64, D> +
-        Allocator<Complex64, D, D> +
-        Allocator<Complex64, <D as DimDiv<U2>>::Output> +
-        Allocator<Complex64, <D as DimDiv<U2>>::Output, <D as DimDiv<U2>>::Output> +
-        Allocator<Complex64, <<D as DimDiv<U2>>::Output as DimSub<U1>>::Output> +
-        Allocator<Complex64, <D as DimDiv<U2>>::Output, <<D as DimDiv<U2>>::Output as DimSub<U1>>::Output>,
-    DefaultAllocator:
-        Allocator<f64, D> +
-        Allocator<f64, <D as DimDiv<U2>>::Output> +
-        Allocator<f64, <<D as DimDiv<U2>>::Output as DimDiv<U2>>::Output> +
-        Allocator<f64, <D as DimSub<U1>>::Output> +
-        Allocator<f64, <<D as DimDiv<U2>>::Output as DimSub<U1>>::Output>,
-    <D as DimDiv<U2>>::Output: DimDiv<U2>,
-    <D as DimDiv<U2>>::Output: DimSub<U1>,
-{
-    pub fn new(mat: &Mat<D>) -> Self {
-        assert_eq!(mat.ncols(), mat.nrows());
-        let dim = mat.ncols();
-        let size = dim.ilog2();
-        assert!(size > 1);
+impl QuantumShannonDecomposition {
+    pub fn new(mat: &DMat) -> Self {
+        assert!(mat.is_square());
+        assert!(mat.nrows().is_power_of_two());
+        assert!(mat.nrows() > 4);
 
         let CosSinDecomposition {
             u1, u2, cs, v1h, v2h
-        } = CosSinDecomposition::<D>::new(mat);
+        } = CosSinDecomposition::new(mat);
 
-        let u1_u2_demultiplexed = Demultiplex::<<D as DimDiv<U2>>::Output>::new(&u1, &u2);
-        let u3_u4_demultiplexed = Demultiplex::<<D as DimDiv<U2>>::Output>::new(&v1h, &v2h);
+        let u1_u2_demultiplexed = Demultiplex::new(&u1, &u2);
+        let u3_u4_demultiplexed = Demultiplex::new(&v1h, &v2h);
 
         Self {
             u1_mat: u1_u2_demultiplexed.v_mat,
             ucrz1_angles: u1_u2_demultiplexed.rz_angles,
             u2_mat: u1_u2_demultiplexed.w_mat,
-            ucry_angles: cs,
+            // [C -S; S C] is a multiplexed RY(2θ)
+            ucry_angles: cs.iter().map(|theta| 2.0 * theta).collect(),
             u3_mat: u3_u4_demultiplexed.v_mat,
             ucrz2_angles: u3_u4_demultiplexed.rz_angles,
             u4_mat: u3_u4_demultiplexed.w_mat,
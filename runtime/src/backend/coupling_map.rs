@@ -0,0 +1,267 @@
+//! Coupling map of a target device.
+//!
+//! The coupling map tells which pairs of physical qubits a two-qubit gate can act on, so that the
+//! routing pass moves the qubits of the other gates next to each other. The error rates of the
+//! qubits and of the couplings guide the placement of the logical qubits. It is either reported
+//! by the backend with [`QivmBackend::coupling_map`](crate::backend::QivmBackend::coupling_map),
+//! built by hand, or loaded from a text file with one coupling per line:
+//!
+//! ```text
+//! # an optional number of physical qubits, the highest coupled qubit is used otherwise
+//! qubits 4
+//! # first second [error]
+//! 0 1 0.008
+//! 1 2
+//! 2 3 0.012
+//! # qubit qubit error
+//! qubit 3 0.0002
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use crate::qubit::QubitAddr;
+
+#[derive(Clone, PartialEq)]
+pub enum CouplingMapError {
+    Io { path: String, reason: String },
+    Syntax { line: usize, reason: String },
+    InvalidQubit { qubit: QubitAddr, size: usize },
+    NotCoupled { first: QubitAddr, second: QubitAddr },
+}
+
+impl Debug for CouplingMapError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for CouplingMapError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CouplingMapError::Io { path, reason } => {
+                write!(formatter, "Unable to read coupling map `{}`: {}", path, reason)
+            }
+            CouplingMapError::Syntax { line, reason } => {
+                write!(formatter, "Invalid coupling map at line {}: {}", line, reason)
+            }
+            CouplingMapError::InvalidQubit { qubit, size } => write!(
+                formatter, "Invalid physical qubit {} of a coupling map of {} qubits", qubit, size
+            ),
+            CouplingMapError::NotCoupled { first, second } => {
+                write!(formatter, "The physical qubits {} and {} are not coupled", first, second)
+            }
+        }
+    }
+}
+
+impl Error for CouplingMapError {}
+
+/// Undirected couplings between the physical qubits of a device, with their error rates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CouplingMap {
+    neighbours: Vec<Vec<QubitAddr>>,
+    /// Error rate of the gates on each qubit.
+    qubit_errors: Vec<f64>,
+    /// Error rate of the two-qubit gates on each coupling, the qubits in ascending order.
+    edge_errors: BTreeMap<(QubitAddr, QubitAddr), f64>,
+}
+
+impl CouplingMap {
+    /// Coupling map of `size` physical qubits without any coupling.
+    pub fn new(size: usize) -> Self {
+        Self {
+            neighbours: vec![vec![]; size],
+            qubit_errors: vec![0.0; size],
+            edge_errors: BTreeMap::new(),
+        }
+    }
+
+    /// Qubits coupled in a line: 0 - 1 - ... - (size - 1).
+    pub fn linear(size: usize) -> Self {
+        let mut coupling_map = Self::new(size);
+        for qubit in 1 .. size as QubitAddr {
+            coupling_map.add_edge(qubit - 1, qubit).unwrap();
+        }
+        coupling_map
+    }
+
+    /// Qubits coupled in a grid of `rows` rows, numbered row after row.
+    pub fn grid(rows: usize, columns: usize) -> Self {
+        let mut coupling_map = Self::new(rows * columns);
+        for row in 0 .. rows {
+            for column in 0 .. columns {
+                let qubit = (row * columns + column) as QubitAddr;
+                if column + 1 < columns {
+                    coupling_map.add_edge(qubit, qubit + 1).unwrap();
+                }
+                if row + 1 < rows {
+                    coupling_map.add_edge(qubit, qubit + columns as QubitAddr).unwrap();
+                }
+            }
+        }
+        coupling_map
+    }
+
+    /// Load the coupling map from a file, see the [module documentation](self) for the format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CouplingMapError> {
+        let text = fs::read_to_string(&path).map_err(|err| CouplingMapError::Io {
+            path: path.as_ref().display().to_string(),
+            reason: err.to_string(),
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parse the textual form of the coupling map, see the [module documentation](self).
+    pub fn parse(text: &str) -> Result<Self, CouplingMapError> {
+        let mut size = None;
+        let mut edges = vec![];
+        let mut qubit_errors = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.is_empty() {
+                continue;
+            }
+            let syntax_error = |reason| CouplingMapError::Syntax { line: index + 1, reason };
+            let parse_qubit = |field: &str| field.parse::<QubitAddr>().map_err(|_| {
+                syntax_error(format!("invalid qubit `{}`", field))
+            });
+            let parse_error = |field: &str| field.parse::<f64>().map_err(|_| {
+                syntax_error(format!("invalid error rate `{}`", field))
+            });
+            match fields[..] {
+                ["qubits", qubits] => {
+                    size = Some(qubits.parse::<usize>().map_err(|_| {
+                        syntax_error(format!("invalid number of qubits `{}`", qubits))
+                    })?);
+                }
+                ["qubit", qubit, error] => {
+                    qubit_errors.push((parse_qubit(qubit)?, parse_error(error)?));
+                }
+                [first, second] => edges.push((parse_qubit(first)?, parse_qubit(second)?, None)),
+                [first, second, error] => {
+                    let error = Some(parse_error(error)?);
+                    edges.push((parse_qubit(first)?, parse_qubit(second)?, error));
+                }
+                _ => return Err(syntax_error(
+                    format!("expected 2 or 3 fields, found {}", fields.len())
+                )),
+            }
+        }
+        let size = size.unwrap_or_else(|| {
+            edges.iter()
+                .map(|&(first, second, _)| first.max(second) as usize + 1)
+                .max()
+                .unwrap_or(0)
+        });
+        let mut coupling_map = Self::new(size);
+        for (first, second, error) in edges {
+            coupling_map.add_edge(first, second)?;
+            if let Some(error) = error {
+                coupling_map.set_edge_error(first, second, error)?;
+            }
+        }
+        for (qubit, error) in qubit_errors {
+            coupling_map.set_qubit_error(qubit, error)?;
+        }
+        Ok(coupling_map)
+    }
+
+    /// Couple two physical qubits, return an error if one of them is not on the device.
+    pub fn add_edge(
+        &mut self, first: QubitAddr, second: QubitAddr
+    ) -> Result<(), CouplingMapError> {
+        let size = self.size();
+        if let Some(&qubit) = [first, second].iter().find(|&&qubit| qubit as usize >= size) {
+            return Err(CouplingMapError::InvalidQubit { qubit, size });
+        }
+        if first != second && !self.is_coupled(first, second) {
+            self.neighbours[first as usize].push(second);
+            self.neighbours[second as usize].push(first);
+        }
+        Ok(())
+    }
+
+    /// Set the error rate of the gates on a qubit.
+    pub fn set_qubit_error(
+        &mut self, qubit: QubitAddr, error: f64
+    ) -> Result<(), CouplingMapError> {
+        let size = self.size();
+        let slot = self.qubit_errors.get_mut(qubit as usize)
+            .ok_or(CouplingMapError::InvalidQubit { qubit, size })?;
+        *slot = error;
+        Ok(())
+    }
+
+    /// Set the error rate of the two-qubit gates on a coupling.
+    pub fn set_edge_error(
+        &mut self, first: QubitAddr, second: QubitAddr, error: f64
+    ) -> Result<(), CouplingMapError> {
+        if !self.is_coupled(first, second) {
+            return Err(CouplingMapError::NotCoupled { first, second });
+        }
+        self.edge_errors.insert((first.min(second), first.max(second)), error);
+        Ok(())
+    }
+
+    /// Error rate of the gates on a qubit, `0` if it is unknown.
+    pub fn qubit_error(&self, qubit: QubitAddr) -> f64 {
+        self.qubit_errors.get(qubit as usize).copied().unwrap_or_default()
+    }
+
+    /// Error rate of the two-qubit gates on a coupling, `0` if it is unknown.
+    pub fn edge_error(&self, first: QubitAddr, second: QubitAddr) -> f64 {
+        self.edge_errors.get(&(first.min(second), first.max(second))).copied().unwrap_or_default()
+    }
+
+    /// Number of physical qubits.
+    pub fn size(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    pub fn is_coupled(&self, first: QubitAddr, second: QubitAddr) -> bool {
+        self.neighbours.get(first as usize).map_or(false, |neighbours| {
+            neighbours.contains(&second)
+        })
+    }
+
+    pub fn neighbours(&self, qubit: QubitAddr) -> &[QubitAddr] {
+        self.neighbours.get(qubit as usize).map_or(&[], Vec::as_slice)
+    }
+
+    /// Couplings as pairs of qubits in ascending order.
+    pub fn edges(&self) -> BTreeSet<(QubitAddr, QubitAddr)> {
+        self.neighbours.iter().enumerate().flat_map(|(qubit, neighbours)| {
+            let qubit = qubit as QubitAddr;
+            neighbours.iter().filter(move |&&neighbour| qubit < neighbour)
+                .map(move |&neighbour| (qubit, neighbour))
+        }).collect()
+    }
+
+    /// Number of couplings on the shortest path between each pair of qubits, `usize::MAX` if
+    /// they are not connected.
+    pub fn distances(&self) -> Vec<Vec<usize>> {
+        (0 .. self.size()).map(|source| {
+            let mut distances = vec![usize::MAX; self.size()];
+            distances[source] = 0;
+            let mut queue = VecDeque::from([source]);
+            while let Some(qubit) = queue.pop_front() {
+                for &neighbour in &self.neighbours[qubit] {
+                    if distances[neighbour as usize] == usize::MAX {
+                        distances[neighbour as usize] = distances[qubit] + 1;
+                        queue.push_back(neighbour as usize);
+                    }
+                }
+            }
+            distances
+        }).collect()
+    }
+
+    /// Return true if there is a path between any two qubits.
+    pub fn is_connected(&self) -> bool {
+        self.size() == 0 || self.distances()[0].iter().all(|&distance| distance != usize::MAX)
+    }
+}
@@ -0,0 +1,70 @@
+use std::ffi::{CString, OsStr};
+use std::os::raw::c_char;
+use libloading::Library;
+use crate::backend::{BackendError, ExecuteResult, QIVM_ABI_VERSION, QivmBackend, RawExecuteResult};
+use crate::bytecode::ByteCode;
+
+type FnQivmAbiVersion = unsafe extern "C" fn() -> u32;
+type FnQivmAvailableQubits = unsafe extern "C" fn() -> u32;
+type FnQivmIsGateAvailable = unsafe extern "C" fn(*const c_char) -> bool;
+type FnQivmExecBytecode = unsafe extern "C" fn(*const u8, u32, u32) -> RawExecuteResult;
+
+/// A backend library loaded at runtime.
+pub struct DynamicLinkBackend {
+    available_qubits: FnQivmAvailableQubits,
+    is_gate_available: FnQivmIsGateAvailable,
+    exec_bytecode: FnQivmExecBytecode,
+    // The function pointers above stay valid as long as the library is loaded.
+    _library: Library,
+}
+
+impl DynamicLinkBackend {
+
+    /// Load the backend library from `path`.
+    ///
+    /// All the symbols of the backend contract are resolved up front, and the ABI version reported
+    /// by `qivm_abi_version` must match [`QIVM_ABI_VERSION`].
+    pub fn load<P: AsRef<OsStr>>(path: P) -> Result<Self, BackendError> {
+        let path = path.as_ref();
+        let library = unsafe { Library::new(path) }.map_err(|err| BackendError::LibraryLoad {
+            path: path.to_string_lossy().to_string(),
+            reason: err.to_string(),
+        })?;
+
+        let abi_version: FnQivmAbiVersion = resolve(&library, "qivm_abi_version")?;
+        let found = unsafe { abi_version() };
+        if found != QIVM_ABI_VERSION {
+            return Err(BackendError::AbiVersionMismatch { expected: QIVM_ABI_VERSION, found });
+        }
+
+        Ok(Self {
+            available_qubits: resolve(&library, "qivm_available_qubits")?,
+            is_gate_available: resolve(&library, "qivm_is_gate_available")?,
+            exec_bytecode: resolve(&library, "qivm_exec_bytecode")?,
+            _library: library,
+        })
+    }
+}
+
+fn resolve<T: Copy>(library: &Library, symbol: &'static str) -> Result<T, BackendError> {
+    unsafe { library.get::<T>(symbol.as_bytes()) }
+        .map(|symbol| *symbol)
+        .map_err(|_| BackendError::MissingSymbol(symbol))
+}
+
+impl QivmBackend for DynamicLinkBackend {
+    fn available_qubits(&self) -> usize {
+        unsafe { (self.available_qubits)() as usize }
+    }
+
+    fn is_gate_available(&self, gate_ident: &str) -> bool {
+        let c_str = CString::new(gate_ident).unwrap();
+        unsafe { (self.is_gate_available)(c_str.as_ptr()) }
+    }
+
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        unsafe {
+            (self.exec_bytecode)(bytecode.as_ptr(), bytecode.len() as u32, shots as u32).into()
+        }
+    }
+}
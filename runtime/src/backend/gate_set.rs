@@ -0,0 +1,189 @@
+//! Native gate set of a target device.
+//!
+//! The gate set tells the decomposer which gates are native on the target, and how much each of
+//! them costs, so that non-native gates are lowered onto the cheapest native ones. It is either
+//! queried from the backend with [`GateSet::from_backend`], built by hand, or loaded from a text
+//! file with one gate per line:
+//!
+//! ```text
+//! # ident arity [cost [error]]
+//! RZ 1 0
+//! RX 1 1 0.0002
+//! CZ 2 10 0.008
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use strum::VariantNames;
+use crate::backend::QivmBackend;
+use crate::gate::canonical::CANONICAL_IDENT;
+use crate::gate::standard::{StandardDoubleGate, StandardSingleGate, StandardTripleGate};
+
+/// Cost of a native gate whose cost is not specified.
+pub const DEFAULT_GATE_COST: i32 = 1;
+
+#[derive(Clone, PartialEq)]
+pub enum GateSetError {
+    Io { path: String, reason: String },
+    Syntax { line: usize, reason: String },
+    ArityMismatch { ident: String, expected: usize, found: usize },
+    InvalidGate { ident: String, reason: String },
+}
+
+impl Debug for GateSetError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for GateSetError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GateSetError::Io { path, reason } => {
+                write!(formatter, "Unable to read gate set `{}`: {}", path, reason)
+            }
+            GateSetError::Syntax { line, reason } => {
+                write!(formatter, "Invalid gate set at line {}: {}", line, reason)
+            }
+            GateSetError::ArityMismatch { ident, expected, found } => write!(
+                formatter, "Invalid arity of gate `{}`, expected: {}, found: {}",
+                ident, expected, found
+            ),
+            GateSetError::InvalidGate { ident, reason } => {
+                write!(formatter, "Invalid gate `{}`: {}", ident, reason)
+            }
+        }
+    }
+}
+
+impl Error for GateSetError {}
+
+/// A gate available on the target device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NativeGate {
+    pub ident: String,
+    pub arity: usize,
+    /// Relative cost of the gate, recipes emitting cheaper gates are preferred.
+    pub cost: i32,
+    /// Error rate of the gate.
+    pub error: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GateSet {
+    gates: BTreeMap<String, NativeGate>,
+}
+
+impl GateSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gate set of the standard gates the backend reports available, with the default cost.
+    pub fn from_backend(backend: &dyn QivmBackend) -> Self {
+        let mut gate_set = Self::new();
+        for (&ident, arity) in standard_gate_idents() {
+            if backend.is_gate_available(ident) {
+                gate_set.gates.insert(ident.to_string(), NativeGate {
+                    ident: ident.to_string(), arity, cost: DEFAULT_GATE_COST, error: 0.0,
+                });
+            }
+        }
+        gate_set
+    }
+
+    /// Load the gate set from a file, see the [module documentation](self) for the format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GateSetError> {
+        let text = fs::read_to_string(&path).map_err(|err| GateSetError::Io {
+            path: path.as_ref().display().to_string(),
+            reason: err.to_string(),
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parse the textual form of the gate set, see the [module documentation](self).
+    pub fn parse(text: &str) -> Result<Self, GateSetError> {
+        let mut gate_set = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.is_empty() {
+                continue;
+            }
+            let syntax_error = |reason: String| GateSetError::Syntax { line: index + 1, reason };
+            if fields.len() < 2 || fields.len() > 4 {
+                return Err(syntax_error(format!("expected 2 to 4 fields, found {}", fields.len())));
+            }
+            let arity = fields[1].parse().map_err(|_| {
+                syntax_error(format!("invalid arity `{}`", fields[1]))
+            })?;
+            let cost = fields.get(2).map_or(Ok(DEFAULT_GATE_COST), |cost| cost.parse().map_err(|_| {
+                syntax_error(format!("invalid cost `{}`", cost))
+            }))?;
+            let error = fields.get(3).map_or(Ok(0.0), |error| error.parse().map_err(|_| {
+                syntax_error(format!("invalid error rate `{}`", error))
+            }))?;
+            check_cost_and_error(cost, error).map_err(syntax_error)?;
+            gate_set.add_gate(fields[0], arity, cost, error)?;
+        }
+        Ok(gate_set)
+    }
+
+    /// Add a native gate, or replace the gate with the same ident.
+    /// Return an error if the gate is a standard gate of another arity, if its cost is negative,
+    /// or if its error rate is not between `0` and `1`.
+    pub fn add_gate(
+        &mut self, ident: &str, arity: usize, cost: i32, error: f64
+    ) -> Result<(), GateSetError> {
+        check_cost_and_error(cost, error).map_err(|reason| GateSetError::InvalidGate {
+            ident: ident.to_string(), reason
+        })?;
+        match standard_gate_arity(ident) {
+            Some(expected) if expected != arity => Err(GateSetError::ArityMismatch {
+                ident: ident.to_string(), expected, found: arity,
+            }),
+            _ => {
+                let gate = NativeGate { ident: ident.to_string(), arity, cost, error };
+                self.gates.insert(ident.to_string(), gate);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get(&self, ident: &str) -> Option<&NativeGate> {
+        self.gates.get(ident)
+    }
+
+    pub fn contains(&self, ident: &str) -> bool {
+        self.gates.contains_key(ident)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NativeGate> {
+        self.gates.values()
+    }
+}
+
+/// Idents and arities of the standard gates known by the decomposer.
+pub(crate) fn standard_gate_idents() -> impl Iterator<Item = (&'static &'static str, usize)> {
+    StandardSingleGate::VARIANTS.iter().map(|ident| (ident, 1))
+        .chain(StandardDoubleGate::VARIANTS.iter().map(|ident| (ident, 2)))
+        .chain(StandardTripleGate::VARIANTS.iter().map(|ident| (ident, 3)))
+        .chain([(&CANONICAL_IDENT, 2)])
+}
+
+fn standard_gate_arity(ident: &str) -> Option<usize> {
+    standard_gate_idents().find(|(&standard, _)| standard == ident).map(|(_, arity)| arity)
+}
+
+fn check_cost_and_error(cost: i32, error: f64) -> Result<(), String> {
+    if cost < 0 {
+        return Err(format!("negative cost `{}`", cost));
+    }
+    if !(0.0 ..= 1.0).contains(&error) {
+        return Err(format!("error rate `{}` is not between 0 and 1", error));
+    }
+    Ok(())
+}
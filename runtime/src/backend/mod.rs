@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests;
+#[cfg(static_link_backend)]
+pub mod static_link;
+pub mod coupling_map;
+pub mod dynamic_link;
+pub mod gate_set;
+pub mod native;
+
+use std::env;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt::{Debug, Display, Formatter};
+use crate::backend::coupling_map::CouplingMap;
+use crate::bytecode::ByteCode;
+use crate::measurement::{MeasurementResult, RawMeasurementResult};
+#[cfg(all(not(any(test, feature = "native-backend")), static_link_backend))]
+use crate::backend::static_link::StaticLinkBackend;
+use crate::backend::dynamic_link::DynamicLinkBackend;
+#[cfg(any(test, feature = "native-backend"))]
+use crate::backend::native::NativeBackend;
+
+#[derive(Clone, Debug)]
+pub struct ExecuteResult {
+    pub error_code: u8,
+    pub measurement: MeasurementResult,
+}
+
+#[repr(C)]
+pub struct RawExecuteResult {
+    pub error_code: u8,
+    pub measurement: RawMeasurementResult,
+}
+
+impl From<RawExecuteResult> for ExecuteResult {
+    fn from(raw: RawExecuteResult) -> Self {
+        Self {
+            error_code: raw.error_code,
+            measurement: raw.measurement.into(),
+        }
+    }
+}
+
+impl From<ExecuteResult> for RawExecuteResult {
+    fn from(result: ExecuteResult) -> Self {
+        Self {
+            error_code: result.error_code,
+            measurement: result.measurement.into(),
+        }
+    }
+}
+
+/// A quantum backend which is able to execute QIVM bytecode.
+///
+/// Backends are owned by a [`QuantumInterfaceVirtualMachine`](crate::QuantumInterfaceVirtualMachine),
+/// so several backends can be used side by side in the same process.
+pub trait QivmBackend: Send {
+
+    /// Get the number of available qubits for the backend.
+    fn available_qubits(&self) -> usize;
+
+    /// Check if the gate is available in the backend.
+    fn is_gate_available(&self, gate_ident: &str) -> bool;
+
+    /// Execute the compiled bytecode.
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult;
+
+    /// Get the coupling map of the device, or `None` if any two qubits are coupled.
+    fn coupling_map(&self) -> Option<CouplingMap> {
+        None
+    }
+}
+
+/// Version of the backend ABI this runtime is built against.
+///
+/// Dynamically loaded backends must export `qivm_abi_version` returning the same version.
+pub const QIVM_ABI_VERSION: u32 = 1;
+
+/// Environment variable holding the path of the backend library to load at startup.
+pub const QIVM_BACKEND_PATH_ENV: &str = "QIVM_BACKEND_PATH";
+
+/// Environment variable holding the path of the native gate set file of the target.
+///
+/// When it is not set, the native gates are the ones the backend reports available.
+pub const QIVM_GATE_SET_PATH_ENV: &str = "QIVM_GATE_SET_PATH";
+
+/// Environment variable holding the path of the coupling map file of the target.
+///
+/// When it is not set, the coupling map is the one the backend reports.
+pub const QIVM_COUPLING_MAP_PATH_ENV: &str = "QIVM_COUPLING_MAP_PATH";
+
+/// Error code reported when the backend fails to execute the bytecode.
+pub const ERROR_BACKEND: u8 = 1;
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum BackendError {
+    LibraryLoad { path: String, reason: String },
+    MissingSymbol(&'static str),
+    AbiVersionMismatch { expected: u32, found: u32 },
+}
+
+impl BackendError {
+    /// Status code reported through the C API.
+    pub fn code(&self) -> u8 {
+        match self {
+            BackendError::LibraryLoad { .. } => 1,
+            BackendError::MissingSymbol(_) => 2,
+            BackendError::AbiVersionMismatch { .. } => 3,
+        }
+    }
+}
+
+impl Debug for BackendError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for BackendError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::LibraryLoad { path, reason } => {
+                write!(formatter, "Unable to load backend library `{}`: {}", path, reason)
+            }
+            BackendError::MissingSymbol(symbol) => {
+                write!(formatter, "Missing symbol `{}` in backend library", symbol)
+            }
+            BackendError::AbiVersionMismatch { expected, found } => write!(
+                formatter, "Backend ABI version mismatch, expected: {}, found: {}", expected, found
+            ),
+        }
+    }
+}
+
+impl Error for BackendError {}
+
+/// Placeholder backend used when the backend library could not be loaded.
+///
+/// It has no qubits and no gates, and every execution fails with [`ERROR_BACKEND`], so the host
+/// program can still replace the backend with `qivm_load_backend` instead of aborting.
+pub struct UnavailableBackend(BackendError);
+
+impl UnavailableBackend {
+    pub fn new(error: BackendError) -> Self {
+        Self(error)
+    }
+
+    pub fn error(&self) -> &BackendError {
+        &self.0
+    }
+}
+
+impl QivmBackend for UnavailableBackend {
+    fn available_qubits(&self) -> usize {
+        0
+    }
+
+    fn is_gate_available(&self, _gate_ident: &str) -> bool {
+        false
+    }
+
+    fn execute(&self, _bytecode: &ByteCode, _shots: usize) -> ExecuteResult {
+        ExecuteResult {
+            error_code: ERROR_BACKEND,
+            measurement: MeasurementResult { shots: 0, measurements: vec![] },
+        }
+    }
+}
+
+/// Load the backend library from `path`, falling back to an [`UnavailableBackend`] on failure.
+pub fn load_backend<P: AsRef<OsStr>>(path: P) -> Box<dyn QivmBackend> {
+    match DynamicLinkBackend::load(path) {
+        Ok(backend) => Box::new(backend),
+        Err(err) => Box::new(UnavailableBackend::new(err)),
+    }
+}
+
+/// Create the default backend.
+///
+/// The library named by the `QIVM_BACKEND_PATH` environment variable takes precedence over the
+/// backend selected at build time, except in tests.
+pub fn default_backend() -> Box<dyn QivmBackend> {
+    match env::var_os(QIVM_BACKEND_PATH_ENV) {
+        Some(path) if !cfg!(test) => load_backend(path),
+        _ => builtin_backend(),
+    }
+}
+
+/// The in-process simulator is used for tests and when the `native-backend` feature is enabled.
+#[cfg(any(test, feature = "native-backend"))]
+fn builtin_backend() -> Box<dyn QivmBackend> {
+    Box::new(NativeBackend)
+}
+
+/// The statically linked `libqil` is used when the `qil-backend` feature is enabled.
+#[cfg(all(not(any(test, feature = "native-backend")), static_link_backend))]
+fn builtin_backend() -> Box<dyn QivmBackend> {
+    Box::new(StaticLinkBackend)
+}
+
+/// `libqil.so` is loaded dynamically when no backend is linked at build time.
+#[cfg(not(any(test, feature = "native-backend", static_link_backend)))]
+fn builtin_backend() -> Box<dyn QivmBackend> {
+    load_backend("libqil.so")
+}
@@ -0,0 +1,21 @@
+use crate::backend::{ExecuteResult, QivmBackend};
+use crate::bytecode::ByteCode;
+use crate::simulator;
+
+/// The in-process state-vector simulator.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NativeBackend;
+
+impl QivmBackend for NativeBackend {
+    fn available_qubits(&self) -> usize {
+        simulator::available_qubits() as usize
+    }
+
+    fn is_gate_available(&self, gate_ident: &str) -> bool {
+        simulator::is_gate_available(gate_ident)
+    }
+
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        simulator::exec_bytecode(bytecode.as_slice(), shots as u32)
+    }
+}
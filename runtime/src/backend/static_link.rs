@@ -0,0 +1,32 @@
+use std::ffi::CString;
+use std::os::raw::c_char;
+use crate::backend::{ExecuteResult, QivmBackend, RawExecuteResult};
+use crate::bytecode::ByteCode;
+
+#[link(name = "qil")]
+extern "C" {
+    fn qivm_available_qubits() -> u32;
+    fn qivm_is_gate_available(gate_ident: *const c_char) -> bool;
+    fn qivm_exec_bytecode(raw_bytecode: *const u8, bytecode_size: u32, shots: u32) -> RawExecuteResult;
+}
+
+/// The backend library linked at build time (`libqil`).
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StaticLinkBackend;
+
+impl QivmBackend for StaticLinkBackend {
+    fn available_qubits(&self) -> usize {
+        unsafe { qivm_available_qubits() as usize }
+    }
+
+    fn is_gate_available(&self, gate_ident: &str) -> bool {
+        let c_str = CString::new(gate_ident).unwrap();
+        unsafe { qivm_is_gate_available(c_str.as_ptr()) }
+    }
+
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        unsafe {
+            qivm_exec_bytecode(bytecode.as_ptr(), bytecode.len() as u32, shots as u32).into()
+        }
+    }
+}
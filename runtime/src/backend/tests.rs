@@ -0,0 +1,199 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::backend::coupling_map::{CouplingMap, CouplingMapError};
+use crate::backend::{
+    BackendError, ERROR_BACKEND, ExecuteResult, load_backend, QivmBackend, UnavailableBackend,
+};
+use crate::backend::dynamic_link::DynamicLinkBackend;
+use crate::backend::gate_set::{GateSet, GateSetError, NativeGate};
+use crate::backend::native::NativeBackend;
+use crate::bytecode::ByteCode;
+use crate::bytecode::instruction::Instruction;
+use crate::error::QivmError;
+use crate::gate::standard::StandardDoubleGate::CX;
+use crate::gate::standard::StandardSingleGate::H;
+use crate::program::builder::QuantumProgramContextBuilder;
+use crate::program::QuantumProgramContext;
+use crate::qubits;
+use crate::QuantumInterfaceVirtualMachine;
+
+/// A backend which forwards to the native simulator and counts the executions.
+struct CountingBackend {
+    executions: Arc<AtomicUsize>,
+}
+
+impl QivmBackend for CountingBackend {
+    fn available_qubits(&self) -> usize {
+        4
+    }
+
+    fn is_gate_available(&self, gate_ident: &str) -> bool {
+        NativeBackend.is_gate_available(gate_ident)
+    }
+
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        self.executions.fetch_add(1, Ordering::SeqCst);
+        NativeBackend.execute(bytecode, shots)
+    }
+}
+
+fn bell_state(mut ctx: QuantumProgramContext) -> Vec<u64> {
+    ctx.enter();
+    let alloc = ctx.alloc(2).unwrap();
+    let qreg = alloc.borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.measure(qreg);
+    ctx.exit().unwrap();
+    let result = ctx.execute(128).unwrap();
+    assert_eq!(result.error_code, 0);
+    result.measurement.measurements.iter().map(|entry| entry.value).collect()
+}
+
+#[test]
+fn test_several_backends() {
+    let executions = Arc::new(AtomicUsize::new(0));
+    let counting = CountingBackend { executions: executions.clone() };
+
+    let native_ctx = QuantumProgramContextBuilder::with_backend(NativeBackend).build();
+    let counting_ctx = QuantumProgramContextBuilder::with_backend(counting).build();
+    assert_eq!(native_ctx.qivm().lock().unwrap().available_qubits(), 24);
+    assert_eq!(counting_ctx.qivm().lock().unwrap().available_qubits(), 4);
+
+    assert_eq!(bell_state(native_ctx), vec![0b00, 0b11]);
+    assert_eq!(executions.load(Ordering::SeqCst), 0);
+    assert_eq!(bell_state(counting_ctx), vec![0b00, 0b11]);
+    assert_eq!(executions.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_qivm_gate_availability() {
+    let qivm = QuantumInterfaceVirtualMachine::new(Box::new(NativeBackend));
+    assert!(qivm.is_gate_available("H"));
+    assert!(qivm.is_gate_available("RZ"));
+}
+
+#[test]
+fn test_dynamic_link_missing_library() {
+    let err = DynamicLinkBackend::load("libqil-does-not-exist.so").err().unwrap();
+    assert!(matches!(err, BackendError::LibraryLoad { .. }));
+    assert_eq!(err.code(), 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_dynamic_link_missing_symbol() {
+    let err = DynamicLinkBackend::load("libc.so.6").err().unwrap();
+    assert_eq!(err, BackendError::MissingSymbol("qivm_abi_version"));
+    assert_eq!(err.code(), 2);
+}
+
+#[test]
+fn test_unavailable_backend() {
+    let backend = load_backend("libqil-does-not-exist.so");
+    assert_eq!(backend.available_qubits(), 0);
+    assert!(!backend.is_gate_available("H"));
+    let result = backend.execute(&ByteCode::from(Vec::<Instruction>::new()), 16);
+    assert_eq!(result.error_code, ERROR_BACKEND);
+
+    let err = BackendError::MissingSymbol("qivm_exec_bytecode");
+    let mut ctx = QuantumProgramContextBuilder::with_backend(UnavailableBackend::new(err)).build();
+    ctx.enter();
+    let qreg = ctx.alloc(1).unwrap().borrow().clone();
+    ctx.measure(qreg);
+    ctx.exit().unwrap();
+    assert!(matches!(ctx.execute(16), Err(QivmError::Execution(ERROR_BACKEND))));
+    assert!(ctx.get_measurement_result().is_none());
+}
+
+#[test]
+fn test_gate_set_parse() {
+    let gate_set = GateSet::parse("# native gates\nRZ 1 0\nRX 1  # default cost\n\nCZ 2 10 0.008\n")
+        .unwrap();
+    assert_eq!(gate_set.iter().count(), 3);
+    assert_eq!(gate_set.get("RX").unwrap().cost, 1);
+    assert_eq!(gate_set.get("CZ"), Some(&NativeGate {
+        ident: "CZ".to_string(), arity: 2, cost: 10, error: 0.008,
+    }));
+    assert!(!gate_set.contains("CX"));
+}
+
+#[test]
+fn test_gate_set_parse_error() {
+    let err = GateSet::parse("RZ 1\nCZ two\n").unwrap_err();
+    assert_eq!(err, GateSetError::Syntax { line: 2, reason: "invalid arity `two`".to_string() });
+    let err = GateSet::parse("CX 3").unwrap_err();
+    assert_eq!(err.to_string(), "Invalid arity of gate `CX`, expected: 2, found: 3");
+    let err = GateSet::load("gate-set-does-not-exist.txt").unwrap_err();
+    assert!(matches!(err, GateSetError::Io { .. }));
+    assert_eq!(QivmError::from(err).code(), 25);
+    let err = GateSet::parse("RZ 1\nCZ 2 -1\n").unwrap_err();
+    assert_eq!(err, GateSetError::Syntax { line: 2, reason: "negative cost `-1`".to_string() });
+    let err = GateSet::parse("CZ 2 10 1.5").unwrap_err();
+    assert_eq!(
+        err.to_string(), "Invalid gate set at line 1: error rate `1.5` is not between 0 and 1"
+    );
+    let err = GateSet::new().add_gate("CZ", 2, 10, -0.1).unwrap_err();
+    assert!(matches!(err, GateSetError::InvalidGate { ident, .. } if ident == "CZ"));
+}
+
+#[test]
+fn test_gate_set_from_backend() {
+    let gate_set = GateSet::from_backend(&NativeBackend);
+    assert!(gate_set.contains("H"));
+    assert!(gate_set.contains("CX"));
+    assert_eq!(gate_set.get("CX").unwrap().arity, 2);
+    assert!(!gate_set.contains("FOO"));
+}
+
+#[test]
+fn test_coupling_map_parse() {
+    let coupling_map = CouplingMap::parse("# a ring\nqubits 5\n0 1\n1 2\n2 3 # last\n3 0\n1 0\n")
+        .unwrap();
+    assert_eq!(coupling_map.size(), 5);
+    let edges = coupling_map.edges().into_iter().collect::<Vec<_>>();
+    assert_eq!(edges, [(0, 1), (0, 3), (1, 2), (2, 3)]);
+    assert!(coupling_map.is_coupled(3, 0));
+    assert!(!coupling_map.is_coupled(0, 2));
+    // the qubit 4 is not coupled to the ring
+    assert!(!coupling_map.is_connected());
+    assert_eq!(CouplingMap::parse("0 1\n1 2\n").unwrap(), CouplingMap::linear(3));
+}
+
+#[test]
+fn test_coupling_map_error_rates() {
+    let coupling_map = CouplingMap::parse("0 1 0.008\n1 2\nqubit 2 0.0002\n").unwrap();
+    assert_eq!(coupling_map.edge_error(1, 0), 0.008);
+    assert_eq!(coupling_map.edge_error(1, 2), 0.0);
+    assert_eq!(coupling_map.qubit_error(2), 0.0002);
+    assert_eq!(coupling_map.qubit_error(0), 0.0);
+    let err = CouplingMap::parse("0 1\n1 2\n0 2 x\n").unwrap_err();
+    assert_eq!(err.to_string(), "Invalid coupling map at line 3: invalid error rate `x`");
+    let err = CouplingMap::linear(3).set_edge_error(0, 2, 0.01).unwrap_err();
+    assert_eq!(err, CouplingMapError::NotCoupled { first: 0, second: 2 });
+}
+
+#[test]
+fn test_coupling_map_parse_error() {
+    let err = CouplingMap::parse("0 1\n1 two\n").unwrap_err();
+    assert_eq!(err, CouplingMapError::Syntax {
+        line: 2, reason: "invalid qubit `two`".to_string(),
+    });
+    let err = CouplingMap::parse("qubits 2\n0 2\n").unwrap_err();
+    assert_eq!(err.to_string(), "Invalid physical qubit 2 of a coupling map of 2 qubits");
+    let err = CouplingMap::load("coupling-map-does-not-exist.txt").unwrap_err();
+    assert!(matches!(err, CouplingMapError::Io { .. }));
+    assert_eq!(QivmError::from(err).code(), 26);
+}
+
+#[test]
+fn test_coupling_map_distances() {
+    let distances = CouplingMap::linear(4).distances();
+    assert_eq!(distances[0], [0, 1, 2, 3]);
+    assert_eq!(distances[2], [2, 1, 0, 1]);
+    let grid = CouplingMap::grid(2, 3);
+    assert!(grid.is_connected());
+    assert_eq!(grid.neighbours(1), [0, 2, 4]);
+    assert_eq!(grid.distances()[0][5], 3);
+    assert_eq!(CouplingMap::new(2).distances()[0][1], usize::MAX);
+}
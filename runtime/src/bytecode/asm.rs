@@ -0,0 +1,151 @@
+//! Textual assembly of the instruction stream.
+//!
+//! ```text
+//! // Bell state
+//! ALLOC 2
+//! H [0]
+//! CX [0, 1]
+//! RZ(0.785) [1]
+//! @FOO(7) [0, 1]
+//! MEASURE 0, 1
+//! ```
+//!
+//! Each line holds at most one instruction, and `//` or `#` start a comment. Custom gates are
+//! prefixed with `@`. Parameters of standard gates are always floats; other parameters are
+//! unsigned integers when written as `5`, signed integers when written with a sign as `+5` or
+//! `-5`, and floats otherwise. The printer is the `Display` impl of [`Instruction`], so
+//! disassembled code assembles back to the same instructions.
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use crate::bytecode::instruction::{InstrParam, Instruction, PrimitiveOpCode, StandardOpCode};
+
+#[derive(Clone)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Debug for AsmError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for AsmError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "Assembly error at line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+type ParseResult<T> = Result<T, String>;
+
+/// Assemble the source into instructions.
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, AsmError> {
+    source.lines().enumerate()
+        .filter_map(|(index, line)| {
+            let line = strip_comment(line).trim();
+            (!line.is_empty()).then(|| {
+                parse_instruction(line).map_err(|message| AsmError { line: index + 1, message })
+            })
+        })
+        .collect()
+}
+
+/// Print the instructions, one per line.
+pub fn disassemble(instructions: &[Instruction]) -> String {
+    instructions.iter().map(|instruction| format!("{}\n", instruction)).collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    let end = [line.find("//"), line.find('#')].into_iter().flatten().min();
+    &line[.. end.unwrap_or(line.len())]
+}
+
+fn parse_instruction(line: &str) -> ParseResult<Instruction> {
+    let ident_end = line.find(|c: char| c.is_whitespace() || c == '(' || c == '[')
+        .unwrap_or(line.len());
+    let (ident, operands) = (&line[.. ident_end], line[ident_end ..].trim());
+
+    if let Some(name) = ident.strip_prefix('@') {
+        let (params, targets) = parse_gate_operands(operands, false)?;
+        let name = parse_custom_name(name)?;
+        return Ok(Instruction::CustomGateOperation { name, params, targets });
+    }
+
+    let mnemonic = ident.to_uppercase();
+    if mnemonic == "NOP" {
+        return if operands.is_empty() {
+            Ok(Instruction::Nop)
+        } else {
+            Err(format!("`NOP` takes no operands, got `{}`", operands))
+        };
+    }
+    if let Ok(opcode) = PrimitiveOpCode::from_str(&mnemonic) {
+        let params = split_list(operands)
+            .map(|param| parse_param(param, false))
+            .collect::<ParseResult<Vec<_>>>()?;
+        return Ok(Instruction::Primitive { opcode, params });
+    }
+    if let Ok(opcode) = StandardOpCode::from_str(&mnemonic) {
+        let (params, targets) = parse_gate_operands(operands, true)?;
+        return Ok(Instruction::StandardGateOperation { opcode, params, targets });
+    }
+    Err(format!("Unknown instruction `{}`", ident))
+}
+
+/// Parse `(params) [targets]`, where the parameters are optional.
+fn parse_gate_operands(
+    operands: &str, float_params: bool
+) -> ParseResult<(Vec<InstrParam>, Vec<u32>)> {
+    let (params, rest) = match operands.strip_prefix('(') {
+        Some(rest) => {
+            let end = rest.find(')').ok_or("Unclosed parameter list")?;
+            let params = split_list(&rest[.. end])
+                .map(|param| parse_param(param, float_params))
+                .collect::<ParseResult<Vec<_>>>()?;
+            (params, rest[end + 1 ..].trim())
+        }
+        None => (vec![], operands),
+    };
+    let rest = rest.strip_prefix('[').ok_or_else(|| format!("Expected targets, got `{}`", rest))?;
+    let end = rest.find(']').ok_or("Unclosed target list")?;
+    if !rest[end + 1 ..].trim().is_empty() {
+        return Err(format!("Unexpected `{}` after targets", rest[end + 1 ..].trim()));
+    }
+    let targets = split_list(&rest[.. end])
+        .map(|target| target.parse::<u32>().map_err(|_| format!("Invalid target `{}`", target)))
+        .collect::<ParseResult<Vec<_>>>()?;
+    Ok((params, targets))
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    let list = list.trim();
+    list.split(',').map(str::trim).filter(move |_| !list.is_empty())
+}
+
+fn parse_param(param: &str, float: bool) -> ParseResult<InstrParam> {
+    let invalid = || format!("Invalid parameter `{}`", param);
+    let digits = param.strip_prefix(|c: char| c == '+' || c == '-').unwrap_or(param);
+    let is_integer = !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit());
+    if float || !is_integer {
+        f64::from_str(param).map(InstrParam::Float).map_err(|_| invalid())
+    } else if digits.len() == param.len() {
+        param.parse().map(InstrParam::UInt).map_err(|_| invalid())
+    } else {
+        param.parse().map(InstrParam::Int).map_err(|_| invalid())
+    }
+}
+
+fn parse_custom_name(name: &str) -> ParseResult<[u8; 16]> {
+    let is_ident = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if name.is_empty() || name.len() > 16 || !is_ident {
+        return Err(format!("Invalid custom gate name `{}`", name));
+    }
+    let mut bytes = [0u8; 16];
+    bytes[.. name.len()].copy_from_slice(name.as_bytes());
+    Ok(bytes)
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use crate::bytecode::{ByteCode, BytecodeError};
+use crate::bytecode::instruction::{custom_gate_ident, Instruction, PrimitiveOpCode};
+use crate::bytecode::reader::ByteReader;
+
+/// Magic number at the beginning of every bytecode container.
+pub const MAGIC: [u8; 4] = *b"QIVM";
+
+/// Current version of the container format.
+///
+/// Version `0` denotes the legacy format, which is a bare instruction stream without a header.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// First container version in which every instruction parameter is preceded by its type tag.
+pub const TAGGED_PARAMS_VERSION: u16 = 2;
+
+pub const LITTLE_ENDIAN: u8 = 0;
+
+const FLAG_METADATA: u8 = 0b0000_0001;
+
+/// Declaration of a custom gate used by the program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomGateDecl {
+    pub name: [u8; 16],
+    pub n_targets: u8,
+    pub n_params: u8,
+}
+
+/// Header of the bytecode container.
+///
+/// ```text
+/// magic        [u8; 4]    "QIVM"
+/// version      u16
+/// endianness   u8         0 for little-endian
+/// flags        u8         bit 0: metadata section present
+/// num_qubits   u32        number of qubits allocated by `ALLOC`
+/// num_gates    u16
+/// gates        [name: [u8; 16], n_targets: u8, n_params: u8; num_gates]
+/// ```
+///
+/// The header is followed by the length of the instruction stream (`u32`), the instructions, and
+/// the optional metadata section (`u16` number of entries, each one a `u16`-prefixed key and
+/// a `u32`-prefixed value, both UTF-8).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ByteCodeHeader {
+    pub version: u16,
+    pub num_qubits: u32,
+    pub custom_gates: Vec<CustomGateDecl>,
+}
+
+/// A decoded bytecode container.
+#[derive(Debug)]
+pub struct ByteCodeContainer {
+    pub header: ByteCodeHeader,
+    pub instructions: Vec<Instruction>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl ByteCodeContainer {
+
+    /// Create a container for the instructions, deriving the header from them.
+    ///
+    /// A custom gate is declared once for each of its distinct numbers of targets and parameters.
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        let num_qubits = alloc_size(&instructions).unwrap_or(0);
+        let mut custom_gates: Vec<CustomGateDecl> = vec![];
+        for instruction in &instructions {
+            if let Instruction::CustomGateOperation { name, params, targets } = instruction {
+                let decl = CustomGateDecl {
+                    name: *name,
+                    n_targets: targets.len() as u8,
+                    n_params: params.len() as u8,
+                };
+                if !custom_gates.contains(&decl) {
+                    custom_gates.push(decl);
+                }
+            }
+        }
+        Self {
+            header: ByteCodeHeader { version: FORMAT_VERSION, num_qubits, custom_gates },
+            instructions,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Decode and validate a container, falling back to the legacy headerless format.
+    pub fn decode(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        if !bytes.starts_with(&MAGIC) {
+            let instructions = Instruction::parse_stream(&mut ByteReader::new(bytes), 0)?;
+            return Ok(Self {
+                header: ByteCodeHeader {
+                    version: 0,
+                    num_qubits: alloc_size(&instructions).unwrap_or(0),
+                    custom_gates: vec![],
+                },
+                instructions,
+                metadata: BTreeMap::new(),
+            });
+        }
+
+        let mut reader = ByteReader::new(bytes);
+        reader.read_array::<4>()?;
+
+        let version_offset = reader.offset();
+        let version = reader.read_u16()?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(BytecodeError::UnsupportedVersion { offset: version_offset, version });
+        }
+
+        let endianness_offset = reader.offset();
+        let endianness = reader.read_u8()?;
+        if endianness != LITTLE_ENDIAN {
+            return Err(BytecodeError::UnsupportedEndianness { offset: endianness_offset, endianness });
+        }
+
+        let flags = reader.read_u8()?;
+        let num_qubits = reader.read_u32()?;
+        let num_gates = reader.read_u16()?;
+        let custom_gates = (0 .. num_gates).map(|_| Ok(CustomGateDecl {
+            name: reader.read_array()?,
+            n_targets: reader.read_u8()?,
+            n_params: reader.read_u8()?,
+        })).collect::<Result<Vec<_>, BytecodeError>>()?;
+
+        let stream_size = reader.read_u32()? as usize;
+        let stream_offset = reader.offset();
+        let stream = reader.read_bytes(stream_size)?;
+        let instructions = Instruction::parse_stream(
+            &mut ByteReader::with_base(stream, stream_offset), version
+        )?;
+
+        let mut metadata = BTreeMap::new();
+        if flags & FLAG_METADATA != 0 {
+            for _ in 0 .. reader.read_u16()? {
+                let key_size = reader.read_u16()? as usize;
+                let key = read_string(&mut reader, key_size)?;
+                let value_size = reader.read_u32()? as usize;
+                let value = read_string(&mut reader, value_size)?;
+                metadata.insert(key, value);
+            }
+        }
+        if !reader.is_empty() {
+            return Err(BytecodeError::TrailingBytes { offset: reader.offset() });
+        }
+
+        let container = Self {
+            header: ByteCodeHeader { version, num_qubits, custom_gates },
+            instructions,
+            metadata,
+        };
+        container.validate()?;
+        Ok(container)
+    }
+
+    /// Check the instructions against the declarations of the header.
+    fn validate(&self) -> Result<(), BytecodeError> {
+        let alloc = alloc_size(&self.instructions).unwrap_or(0);
+        if alloc != self.header.num_qubits {
+            return Err(BytecodeError::QubitCountMismatch {
+                declared: self.header.num_qubits, allocated: alloc
+            });
+        }
+        for instruction in &self.instructions {
+            if let Instruction::CustomGateOperation { name, params, targets } = instruction {
+                let declared = self.header.custom_gates.iter().any(|decl| {
+                    decl.name == *name
+                        && decl.n_targets as usize == targets.len()
+                        && decl.n_params as usize == params.len()
+                });
+                if !declared {
+                    return Err(BytecodeError::UndeclaredCustomGate {
+                        name: custom_gate_ident(name)
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode the container in the format version of its header.
+    ///
+    /// Version `0` produces a legacy headerless instruction stream, dropping the metadata.
+    pub fn encode(self) -> Vec<u8> {
+        let version = self.header.version;
+        if version == 0 {
+            return self.instructions.into_iter()
+                .flat_map(|instruction| instruction.encode(version))
+                .collect();
+        }
+
+        let mut bytes: Vec<u8> = vec![];
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.push(LITTLE_ENDIAN);
+        bytes.push(if self.metadata.is_empty() { 0 } else { FLAG_METADATA });
+        bytes.extend_from_slice(&self.header.num_qubits.to_le_bytes());
+        bytes.extend_from_slice(&(self.header.custom_gates.len() as u16).to_le_bytes());
+        for decl in &self.header.custom_gates {
+            bytes.extend_from_slice(&decl.name);
+            bytes.push(decl.n_targets);
+            bytes.push(decl.n_params);
+        }
+
+        let stream = self.instructions.into_iter()
+            .flat_map(|instruction| instruction.encode(version))
+            .collect::<Vec<u8>>();
+        bytes.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+        bytes.extend(stream);
+
+        if !self.metadata.is_empty() {
+            bytes.extend_from_slice(&(self.metadata.len() as u16).to_le_bytes());
+            for (key, value) in &self.metadata {
+                bytes.extend_from_slice(&(key.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(key.as_bytes());
+                bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(value.as_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+impl From<ByteCodeContainer> for ByteCode {
+    fn from(container: ByteCodeContainer) -> Self {
+        ByteCode(container.encode())
+    }
+}
+
+fn alloc_size(instructions: &[Instruction]) -> Option<u32> {
+    instructions.iter().find_map(|instruction| match instruction {
+        Instruction::Primitive { opcode: PrimitiveOpCode::Alloc, params } => {
+            params.first().map(|size| size.as_u64() as u32)
+        }
+        _ => None,
+    })
+}
+
+fn read_string(reader: &mut ByteReader, size: usize) -> Result<String, BytecodeError> {
+    let offset = reader.offset();
+    String::from_utf8(reader.read_bytes(size)?.to_vec())
+        .map_err(|_| BytecodeError::InvalidUtf8 { offset })
+}
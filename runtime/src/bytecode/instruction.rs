@@ -1,15 +1,19 @@
 use std::fmt::{Debug, Display, Formatter, Write};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use strum_macros::IntoStaticStr;
-use crate::bytecode::ByteCode;
-use crate::{dispatch, raise_error, use_enum};
+use strum_macros::{EnumString, EnumVariantNames, IntoStaticStr};
+use crate::bytecode::{ByteCode, BytecodeError};
+use crate::bytecode::container::{FORMAT_VERSION, TAGGED_PARAMS_VERSION};
+use crate::bytecode::reader::ByteReader;
+use crate::{dispatch, use_enum};
+use crate::error::{QivmError, QivmResult};
 use crate::gate::standard::{StandardDoubleGate, StandardSingleGate, StandardTripleGate};
 use crate::operation::elementary::ElementaryOperation;
 use crate::operation::elementary::standard::StandardOperation;
 use crate::operation::{DoubleTargetOperation, DynamicTargetOperation, Operation, SingleTargetOperation, TripleTargetOperation};
 
 #[repr(u8)]
+#[derive(Clone, PartialEq)]
 pub enum Instruction {
     Nop,
     Primitive {
@@ -28,10 +32,12 @@ pub enum Instruction {
     },
 }
 
-impl From<Operation> for Instruction {
-    fn from(operation: Operation) -> Self {
+impl TryFrom<Operation> for Instruction {
+    type Error = QivmError;
+
+    fn try_from(operation: Operation) -> QivmResult<Self> {
         use InstrParam::*;
-        match operation {
+        let instruction = match operation {
             Operation::Elementary(ElementaryOperation::Standard(operation)) => {
                 match operation {
                     StandardOperation::Single(operation) => {
@@ -101,12 +107,16 @@ impl From<Operation> for Instruction {
             }
             Operation::Elementary(ElementaryOperation::Custom(operation)) => {
                 let gate = operation.get_gate();
-                let ident: [u8; 16] = gate.ident()
-                    .chars().map(|c| c as u8)
-                    .collect::<Vec<u8>>()
-                    .try_into().unwrap();
+                let ident = gate.ident();
+                if ident.len() > 16 {
+                    return Err(QivmError::Compile(
+                        format!("Custom gate identifier `{}` is longer than 16 bytes", ident)
+                    ));
+                }
+                let mut name = [0u8; 16];
+                name[.. ident.len()].copy_from_slice(ident.as_bytes());
                 Instruction::CustomGateOperation {
-                    name: ident,
+                    name,
                     params: gate.get_params().iter().map(|&param| UInt(param)).collect(),
                     targets: operation.get_target().to_vec(),
                 }
@@ -121,28 +131,83 @@ impl From<Operation> for Instruction {
                     targets: vec![target0, target1],
                 }
             }
-            _ => raise_error! {
-                "Only elementary operations can be compiled to instructions"
-            }
-        }
+            _ => return Err(QivmError::Compile(
+                "Only elementary operations can be compiled to instructions".to_string()
+            )),
+        };
+        Ok(instruction)
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum InstrParam {
     Float(f64),
     Int(i64),
     UInt(u64),
 }
 
-impl Into<Vec<u8>> for InstrParam {
-    fn into(self) -> Vec<u8> {
+impl InstrParam {
+    /// Interpret the parameter as a float, reinterpreting the raw bits of integer parameters.
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            InstrParam::Float(value) => value,
+            InstrParam::Int(value) => f64::from_bits(value as u64),
+            InstrParam::UInt(value) => f64::from_bits(value),
+        }
+    }
+
+    /// Interpret the parameter as an unsigned integer, reinterpreting the raw bits of floats.
+    pub fn as_u64(&self) -> u64 {
+        match *self {
+            InstrParam::Float(value) => value.to_bits(),
+            InstrParam::Int(value) => value as u64,
+            InstrParam::UInt(value) => value,
+        }
+    }
+}
+
+impl InstrParam {
+    pub fn param_type(&self) -> ParamType {
+        match self {
+            InstrParam::Float(_) => ParamType::Float,
+            InstrParam::Int(_) => ParamType::Int,
+            InstrParam::UInt(_) => ParamType::UInt,
+        }
+    }
+
+    fn from_bits(param_type: ParamType, bits: u64) -> Self {
+        match param_type {
+            ParamType::Float => InstrParam::Float(f64::from_bits(bits)),
+            ParamType::Int => InstrParam::Int(bits as i64),
+            ParamType::UInt => InstrParam::UInt(bits),
+        }
+    }
+
+    /// Serialize the parameter, preceded by its type tag if the format version has them.
+    fn encode(self, version: u16, bytes: &mut Vec<u8>) {
+        if version >= TAGGED_PARAMS_VERSION {
+            bytes.push(self.param_type() as u8);
+        }
         use_enum!(InstrParam);
-        dispatch!(self; Float | Int | UInt => |value| value.to_le_bytes().to_vec())
+        bytes.extend(dispatch!(self; Float | Int | UInt => |value| value.to_le_bytes()));
     }
 }
 
+/// Type tag preceding each parameter in the bytecode.
+///
+/// Tags above `UInt` are reserved for future parameter types, such as references to
+/// classical registers.
 #[repr(u8)]
-#[derive(Copy, Clone, IntoStaticStr, FromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum ParamType {
+    Float = 0x00,
+    Int = 0x01,
+    UInt = 0x02,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, IntoStaticStr, EnumString, FromPrimitive)]
+#[strum(serialize_all = "UPPERCASE")]
 pub enum PrimitiveOpCode {
     Alloc = 0x00,
     Reset = 0x01,
@@ -150,7 +215,7 @@ pub enum PrimitiveOpCode {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, IntoStaticStr, FromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, IntoStaticStr, EnumString, EnumVariantNames, FromPrimitive)]
 pub enum StandardOpCode {
 /*
     # generated by the following python code
@@ -200,32 +265,70 @@ pub enum StandardOpCode {
     CSWP    = 0x22,
 }
 
-fn params_to_string(params: &[InstrParam]) -> String {
+#[cfg(test)]
+impl StandardOpCode {
+    /// Number of parameters and targets of the gate.
+    pub(crate) fn signature(self) -> (usize, usize) {
+        use StandardOpCode::*;
+        match self {
+            I | H | X | Y | Z | S | SD | T | TD | V | VD => (0, 1),
+            XPOW | YPOW | ZPOW | P | RX | RY | RZ => (1, 1),
+            RN => (4, 1),
+            U => (3, 1),
+            CX | CY | CZ | CH | SWP | SSWP | SSWPD | ISWP | ISWPD | SISWP | SISWPD => (0, 2),
+            CP => (1, 2),
+            CAN => (3, 2),
+            CCX | CSWP => (0, 3),
+        }
+    }
+}
+
+pub(crate) fn params_to_string(params: &[InstrParam]) -> String {
     let params_str = params.iter().map(|param| {
-        use_enum!(InstrParam);
-        dispatch!(param; Float | Int | UInt => |param| format!("{}", param))
+        match param {
+            // Always print the fractional part of floats, so that angles are not mistaken for
+            // integers, and the sign of signed integers, so that they are not mistaken for unsigned
+            InstrParam::Float(value) => format!("{:?}", value),
+            InstrParam::Int(value) => format!("{:+}", value),
+            InstrParam::UInt(value) => format!("{}", value),
+        }
     }).fold(String::new(), |acc, param| format!("{}, {}", acc, param));
     params_str.trim_start_matches(", ").to_string()
 }
 
+/// Name of a custom gate, without the trailing padding.
+pub(crate) fn custom_gate_ident(name: &[u8; 16]) -> String {
+    String::from_utf8_lossy(name).trim_end_matches('\0').to_string()
+}
+
+fn gate_to_string(gate_ident: &str, params: &[InstrParam], targets: &[u32]) -> String {
+    if params.is_empty() {
+        format!("{} {:?}", gate_ident, targets)
+    } else {
+        format!("{}({}) {:?}", gate_ident, params_to_string(params), targets)
+    }
+}
+
+/// Prints the instruction in the assembly syntax of [`crate::bytecode::asm`].
 impl Display for Instruction {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Instruction::Nop => formatter.write_str("NOP"),
             Instruction::Primitive { opcode: op_code, params } => {
                 let instr_ident: &'static str = op_code.into();
-                formatter.write_str(&format!("{} {}", instr_ident, params_to_string(params)))
-            }
-            Instruction::StandardGateOperation { opcode: op_code, params, targets } => {
-                let gate_ident: &'static str = op_code.into();
                 if params.is_empty() {
-                    formatter.write_str(&format!("{} {:?}", gate_ident, targets))
+                    formatter.write_str(instr_ident)
                 } else {
-                    formatter.write_str(&format!("{}({}) {:?}", gate_ident, params_to_string(params), targets))
+                    formatter.write_str(&format!("{} {}", instr_ident, params_to_string(params)))
                 }
             }
-            Instruction::CustomGateOperation { .. } => {
-                todo!()
+            Instruction::StandardGateOperation { opcode: op_code, params, targets } => {
+                let gate_ident: &'static str = op_code.into();
+                formatter.write_str(&gate_to_string(gate_ident, params, targets))
+            }
+            Instruction::CustomGateOperation { name, params, targets } => {
+                let gate_ident = format!("@{}", custom_gate_ident(name));
+                formatter.write_str(&gate_to_string(&gate_ident, params, targets))
             }
         }
     }
@@ -239,10 +342,26 @@ impl Debug for Instruction {
 
 impl From<Instruction> for Vec<u8> {
     fn from(instruction: Instruction) -> Self {
+        instruction.encode(FORMAT_VERSION)
+    }
+}
+
+impl TryFrom<ByteCode> for Vec<Instruction> {
+    type Error = BytecodeError;
+
+    fn try_from(bytecode: ByteCode) -> Result<Self, Self::Error> {
+        Ok(bytecode.decode()?.instructions)
+    }
+}
+
+impl Instruction {
+
+    /// Serialize the instruction in the instruction stream format of the given container version.
+    pub fn encode(self, version: u16) -> Vec<u8> {
         let mut bytes: Vec<u8> = vec![];
         bytes.reserve(16);
 
-        match instruction {
+        match self {
             Instruction::Nop { .. } => {
                 bytes.push(0x00);
             }
@@ -252,7 +371,7 @@ impl From<Instruction> for Vec<u8> {
                 bytes.push(op_code as u8);
                 bytes.push(params.len() as u8);
                 for param in params {
-                    bytes.append(&mut param.into());
+                    param.encode(version, &mut bytes);
                 }
             }
 
@@ -261,7 +380,7 @@ impl From<Instruction> for Vec<u8> {
                 bytes.push(op_code as u8);
                 bytes.push(params.len() as u8);
                 for param in params {
-                    bytes.append(&mut param.into());
+                    param.encode(version, &mut bytes);
                 }
                 bytes.push(targets.len() as u8);
                 for target in targets {
@@ -276,7 +395,7 @@ impl From<Instruction> for Vec<u8> {
                 }
                 bytes.push(params.len() as u8);
                 for param in params {
-                    bytes.append(&mut param.into());
+                    param.encode(version, &mut bytes);
                 }
                 bytes.push(targets.len() as u8);
                 for target in targets {
@@ -287,142 +406,77 @@ impl From<Instruction> for Vec<u8> {
 
         bytes
     }
-}
 
-impl From<ByteCode> for Vec<Instruction> {
-    fn from(bytecode: ByteCode) -> Self {
-        Instruction::parse(&bytecode.0)
+    /// Decode an instruction stream of the current format version.
+    pub fn parse(bytes: &[u8]) -> Result<Vec<Instruction>, BytecodeError> {
+        Instruction::parse_stream(&mut ByteReader::new(bytes), FORMAT_VERSION)
     }
-}
-
-impl Instruction {
-
-    pub fn parse(bytes: &[u8]) -> Vec<Instruction> {
 
+    /// Decode instructions of the given container version until the end of the reader.
+    pub(crate) fn parse_stream(
+        reader: &mut ByteReader, version: u16
+    ) -> Result<Vec<Instruction>, BytecodeError> {
         let mut instructions: Vec<Instruction> = Vec::new();
-        let mut buffer = bytes.to_vec();
-
-        while !buffer.is_empty() {
-            if buffer[0] == 0x00 {
-                buffer.remove(0);
-                instructions.push(Instruction::Nop);
-            } else if buffer[0] == 0x01 {
-                buffer.remove(0);
-                instructions.push(Instruction::primitive_parse(&mut buffer));
-            } else if buffer[0] == 0x02 {
-                buffer.remove(0);
-                instructions.push(Instruction::standard_gate_operation_parse(&mut buffer));
-            } else if buffer[0] == 0x03 {
-                buffer.remove(0);
-                instructions.push(Instruction::custom_gate_operation_parse(&mut buffer));
-            }
-        }
-
-        instructions
-    }
-
-    fn primitive_parse(bytes: &mut Vec<u8>) -> Instruction {
-
-        // read op_code
-        let op_code = PrimitiveOpCode::from_u8(bytes[0]).unwrap_or_else(|| {
-            raise_error!("Invalid opcode {}", bytes[0])
-        });
-
-        // read n_params
-        let n_params = bytes[1];
-        bytes.remove(0);
-        bytes.remove(0);
-
-        // read params
-        let params = Instruction::params_parse(bytes, n_params);
-
-        Instruction::Primitive {
-            opcode: op_code,
-            params,
-        }
-    }
-
-    fn standard_gate_operation_parse(bytes: &mut Vec<u8>) -> Instruction {
-
-        // read op_code
-        let op_code = StandardOpCode::from_u8(bytes[0]).unwrap_or_else(|| {
-            raise_error!("Invalid opcode {}", bytes[0])
-        });
-
-        // read n_params
-        let n_params = bytes[1];
-        bytes.remove(0);
-        bytes.remove(0);
-
-        // read params
-        let params = Instruction::params_parse(bytes, n_params);
-
-        let n_targets = bytes[0];
-        bytes.remove(0);
-        let targets = Instruction::targets_parse(bytes, n_targets);
-
-        Instruction::StandardGateOperation {
-            opcode: op_code,
-            params,
-            targets,
+        while !reader.is_empty() {
+            instructions.push(Instruction::parse_one(reader, version)?);
         }
+        Ok(instructions)
     }
 
-    fn custom_gate_operation_parse(bytes: &mut Vec<u8>) -> Instruction {
-
-        // read name
-        let name = Instruction::name_parse(bytes);
-
-        // read n_params
-        let n_params = bytes[0];
-        bytes.remove(0);
-
-        // read params
-        let params = Instruction::params_parse(bytes, n_params);
-
-        let n_targets = bytes[0];
-        bytes.remove(0);
-        let targets = Instruction::targets_parse(bytes, n_targets);
-
-        Instruction::CustomGateOperation {
-            name,
-            params,
-            targets,
+    fn parse_one(reader: &mut ByteReader, version: u16) -> Result<Instruction, BytecodeError> {
+        let offset = reader.offset();
+        match reader.read_u8()? {
+            0x00 => Ok(Instruction::Nop),
+            0x01 => {
+                let opcode = Instruction::opcode_parse(reader, PrimitiveOpCode::from_u8)?;
+                let params = Instruction::params_parse(reader, version, ParamType::UInt)?;
+                Ok(Instruction::Primitive { opcode, params })
+            }
+            0x02 => {
+                let opcode = Instruction::opcode_parse(reader, StandardOpCode::from_u8)?;
+                let params = Instruction::params_parse(reader, version, ParamType::Float)?;
+                let targets = Instruction::targets_parse(reader)?;
+                Ok(Instruction::StandardGateOperation { opcode, params, targets })
+            }
+            0x03 => {
+                let name = reader.read_array::<16>()?;
+                let params = Instruction::params_parse(reader, version, ParamType::UInt)?;
+                let targets = Instruction::targets_parse(reader)?;
+                Ok(Instruction::CustomGateOperation { name, params, targets })
+            }
+            instr_type => Err(BytecodeError::InvalidInstructionType { offset, instr_type }),
         }
     }
 
-
-    fn params_parse(bytes_vector: &mut Vec<u8>, n_params: u8) -> Vec<InstrParam> {
-        let mut params: Vec<InstrParam> = Vec::new();
-
-        for _ in 0 .. n_params {
-            let raw_bytes = <[u8; 8]>::try_from(bytes_vector[0..8].to_vec());
-            bytes_vector.drain(0..8);
-
-            let param = InstrParam::UInt(u64::from_be_bytes(raw_bytes.unwrap()));
-            params.push(param);
-        }
-
-        params
+    fn opcode_parse<T>(
+        reader: &mut ByteReader, from_u8: impl Fn(u8) -> Option<T>
+    ) -> Result<T, BytecodeError> {
+        let offset = reader.offset();
+        let opcode = reader.read_u8()?;
+        from_u8(opcode).ok_or(BytecodeError::InvalidOpCode { offset, opcode })
     }
 
-    fn targets_parse(bytes_vector: &mut Vec<u8>, n_targets: u8) -> Vec<u32> {
-        let mut targets: Vec<u32> = Vec::new();
-
-        for _ in 0 .. n_targets {
-            let raw_bytes = <[u8; 4]>::try_from(bytes_vector[0..4].to_vec());
-            bytes_vector.drain(0..4);
-            let target = u32::from_be_bytes(raw_bytes.unwrap());
-            targets.push(target);
-        }
-
-        targets
+    /// Parameters before [`TAGGED_PARAMS_VERSION`] are untagged, their type is inferred from the
+    /// kind of the instruction.
+    fn params_parse(
+        reader: &mut ByteReader, version: u16, inferred_type: ParamType
+    ) -> Result<Vec<InstrParam>, BytecodeError> {
+        let n_params = reader.read_u8()?;
+        (0 .. n_params).map(|_| {
+            let param_type = if version >= TAGGED_PARAMS_VERSION {
+                let offset = reader.offset();
+                let param_type = reader.read_u8()?;
+                ParamType::from_u8(param_type)
+                    .ok_or(BytecodeError::InvalidParamType { offset, param_type })?
+            } else {
+                inferred_type
+            };
+            Ok(InstrParam::from_bits(param_type, reader.read_u64()?))
+        }).collect()
     }
 
-    fn name_parse(bytes_vector: &mut Vec<u8>) -> [u8; 16] {
-        let mut name: [u8; 16] = [0; 16];
-        name[..16].copy_from_slice(&bytes_vector[..16]);
-        bytes_vector.drain(0..16);
-        name
+    fn targets_parse(reader: &mut ByteReader) -> Result<Vec<u32>, BytecodeError> {
+        let n_targets = reader.read_u8()?;
+        (0 .. n_targets).map(|_| reader.read_u32()).collect()
     }
 }
@@ -1,10 +1,17 @@
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use crate::bytecode::container::ByteCodeContainer;
 use crate::bytecode::instruction::Instruction;
 
+pub mod asm;
+pub mod container;
 pub mod instruction;
+pub mod reader;
 
 #[cfg(test)]
 mod test;
 
+/// Serialized bytecode container, as passed to the backends.
 pub struct ByteCode(Vec<u8>);
 
 impl ByteCode {
@@ -15,12 +22,85 @@ impl ByteCode {
     pub fn as_ptr(&self) -> *const u8 {
         self.0.as_ptr()
     }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Decode and validate the container.
+    pub fn decode(&self) -> Result<ByteCodeContainer, BytecodeError> {
+        ByteCodeContainer::decode(&self.0)
+    }
 }
 
 impl From<Vec<Instruction>> for ByteCode {
     fn from(instructions: Vec<Instruction>) -> Self {
-        ByteCode(
-            instructions.into_iter().flat_map(Vec::<u8>::from).collect::<Vec<u8>>()
-        )
+        ByteCodeContainer::new(instructions).into()
+    }
+}
+
+impl From<Vec<u8>> for ByteCode {
+    fn from(bytes: Vec<u8>) -> Self {
+        ByteCode(bytes)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum BytecodeError {
+    UnexpectedEnd { offset: usize },
+    InvalidInstructionType { offset: usize, instr_type: u8 },
+    InvalidOpCode { offset: usize, opcode: u8 },
+    InvalidParamType { offset: usize, param_type: u8 },
+    UnsupportedVersion { offset: usize, version: u16 },
+    UnsupportedEndianness { offset: usize, endianness: u8 },
+    TrailingBytes { offset: usize },
+    InvalidUtf8 { offset: usize },
+    QubitCountMismatch { declared: u32, allocated: u32 },
+    UndeclaredCustomGate { name: String },
+}
+
+impl Debug for BytecodeError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
     }
 }
+
+impl Display for BytecodeError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        use BytecodeError::*;
+        match self {
+            UnexpectedEnd { offset } => {
+                write!(formatter, "Unexpected end of bytecode at offset {}", offset)
+            }
+            InvalidInstructionType { offset, instr_type } => write!(
+                formatter, "Invalid instruction type {:#04x} at offset {}", instr_type, offset
+            ),
+            InvalidOpCode { offset, opcode } => {
+                write!(formatter, "Invalid opcode {:#04x} at offset {}", opcode, offset)
+            }
+            InvalidParamType { offset, param_type } => write!(
+                formatter, "Invalid parameter type {:#04x} at offset {}", param_type, offset
+            ),
+            UnsupportedVersion { offset, version } => write!(
+                formatter, "Unsupported bytecode version {} at offset {}", version, offset
+            ),
+            UnsupportedEndianness { offset, endianness } => write!(
+                formatter, "Unsupported bytecode endianness {} at offset {}", endianness, offset
+            ),
+            TrailingBytes { offset } => {
+                write!(formatter, "Unexpected trailing bytes at offset {}", offset)
+            }
+            InvalidUtf8 { offset } => {
+                write!(formatter, "Invalid UTF-8 string at offset {}", offset)
+            }
+            QubitCountMismatch { declared, allocated } => write!(
+                formatter, "Qubit count mismatch, declared: {}, allocated: {}", declared, allocated
+            ),
+            UndeclaredCustomGate { name } => {
+                write!(formatter, "Custom gate `{}` is not declared in the header", name)
+            }
+        }
+    }
+}
+
+impl Error for BytecodeError {}
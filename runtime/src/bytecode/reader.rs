@@ -0,0 +1,60 @@
+use crate::bytecode::BytecodeError;
+
+/// Little-endian cursor over a byte slice.
+///
+/// Offsets are reported relative to `base`, so a reader over a section of the bytecode reports
+/// the offsets within the whole bytecode.
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    base: usize,
+}
+
+impl<'a> ByteReader<'a> {
+
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self::with_base(bytes, 0)
+    }
+
+    pub fn with_base(bytes: &'a [u8], base: usize) -> Self {
+        Self { bytes, offset: 0, base }
+    }
+
+    /// Offset of the next byte to read.
+    pub fn offset(&self) -> usize {
+        self.base + self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    pub fn read_bytes(&mut self, size: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self.offset.checked_add(size)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(BytecodeError::UnexpectedEnd { offset: self.base + self.bytes.len() })?;
+        let bytes = &self.bytes[self.offset .. end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], BytecodeError> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, BytecodeError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+}
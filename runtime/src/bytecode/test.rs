@@ -1,3 +1,8 @@
+use crate::bytecode::{asm, ByteCode, BytecodeError};
+use crate::bytecode::container::{ByteCodeContainer, FORMAT_VERSION, MAGIC};
+use crate::bytecode::instruction::{InstrParam, Instruction, ParamType, PrimitiveOpCode, StandardOpCode};
+use num_traits::FromPrimitive;
+use rand::Rng;
 
 /// TODO: remove this function
 #[test]
@@ -6,3 +11,363 @@ fn example_test() {
 }
 
 // TODO: add more tests
+
+fn custom_gate_name(ident: &str) -> [u8; 16] {
+    let mut name = [0u8; 16];
+    name[.. ident.len()].copy_from_slice(ident.as_bytes());
+    name
+}
+
+fn sample_program() -> Vec<Instruction> {
+    vec![
+        Instruction::Primitive { opcode: PrimitiveOpCode::Alloc, params: vec![InstrParam::UInt(3)] },
+        Instruction::StandardGateOperation {
+            opcode: StandardOpCode::RZ, params: vec![InstrParam::Float(0.5)], targets: vec![2],
+        },
+        Instruction::Nop,
+        Instruction::CustomGateOperation {
+            name: custom_gate_name("FOO"), params: vec![InstrParam::UInt(7)], targets: vec![0, 1],
+        },
+        Instruction::Primitive {
+            opcode: PrimitiveOpCode::Measure, params: vec![InstrParam::UInt(0), InstrParam::UInt(1)],
+        },
+    ]
+}
+
+fn instruction_stream(instructions: Vec<Instruction>) -> Vec<u8> {
+    instructions.into_iter().flat_map(Vec::<u8>::from).collect()
+}
+
+#[test]
+fn test_container_header() {
+    let bytecode = ByteCode::from(sample_program());
+    assert!(bytecode.as_slice().starts_with(&MAGIC));
+    let container = bytecode.decode().unwrap();
+    assert_eq!(container.header.version, FORMAT_VERSION);
+    assert_eq!(container.header.num_qubits, 3);
+    assert_eq!(container.header.custom_gates.len(), 1);
+    assert_eq!(container.header.custom_gates[0].name, custom_gate_name("FOO"));
+    assert_eq!(container.header.custom_gates[0].n_targets, 2);
+    assert_eq!(container.header.custom_gates[0].n_params, 1);
+    assert_eq!(container.instructions.len(), 5);
+    assert!(container.metadata.is_empty());
+}
+
+#[test]
+fn test_container_round_trip() {
+    let container = ByteCodeContainer::new(sample_program())
+        .with_metadata("source", "bell.stq")
+        .with_metadata("compiler", "stateq");
+    let bytes = container.encode();
+    let decoded = ByteCodeContainer::decode(&bytes).unwrap();
+    assert_eq!(decoded.metadata.get("source").unwrap(), "bell.stq");
+    assert_eq!(decoded.metadata.get("compiler").unwrap(), "stateq");
+    assert_eq!(decoded.encode(), bytes);
+}
+
+#[test]
+fn test_custom_gate_overloads_round_trip() {
+    let mut program = sample_program();
+    program.insert(4, Instruction::CustomGateOperation {
+        name: custom_gate_name("FOO"), params: vec![], targets: vec![2],
+    });
+    let bytes = ByteCodeContainer::new(program.clone()).encode();
+    let decoded = ByteCodeContainer::decode(&bytes).unwrap();
+    let arities: Vec<(u8, u8)> = decoded.header.custom_gates.iter()
+        .map(|decl| (decl.n_targets, decl.n_params))
+        .collect();
+    assert_eq!(arities, vec![(2, 1), (1, 0)]);
+    assert_eq!(decoded.instructions, program);
+}
+
+#[test]
+fn test_legacy_headerless_bytecode() {
+    let mut program = sample_program();
+    program.remove(3);
+    let mut legacy = ByteCodeContainer::new(program.clone());
+    legacy.header.version = 0;
+    let bytes = legacy.encode();
+    assert!(!bytes.starts_with(&MAGIC));
+    let container = ByteCodeContainer::decode(&bytes).unwrap();
+    assert_eq!(container.header.version, 0);
+    assert_eq!(container.header.num_qubits, 3);
+    assert_eq!(container.instructions, program);
+}
+
+#[test]
+fn test_untagged_params_version() {
+    let mut container = ByteCodeContainer::new(sample_program());
+    container.header.version = 1;
+    let untagged = container.encode();
+    let tagged = ByteCodeContainer::new(sample_program()).encode();
+    // One tag for each of the five parameters of the sample program
+    assert_eq!(tagged.len(), untagged.len() + 5);
+
+    let decoded = ByteCodeContainer::decode(&untagged).unwrap();
+    assert_eq!(decoded.header.version, 1);
+    assert_eq!(decoded.instructions, sample_program());
+}
+
+#[test]
+fn test_typed_params() {
+    let instruction = Instruction::Primitive {
+        opcode: PrimitiveOpCode::Reset,
+        params: vec![InstrParam::Float(1.5), InstrParam::Int(-2), InstrParam::UInt(3)],
+    };
+    let bytes: Vec<u8> = instruction.clone().into();
+    assert_eq!(bytes[3], ParamType::Float as u8);
+    assert_eq!(bytes[12], ParamType::Int as u8);
+    assert_eq!(bytes[21], ParamType::UInt as u8);
+    assert_eq!(Instruction::parse(&bytes).unwrap(), vec![instruction]);
+
+    let mut invalid = bytes.clone();
+    invalid[12] = 0x03;
+    assert_eq!(
+        Instruction::parse(&invalid),
+        Err(BytecodeError::InvalidParamType { offset: 12, param_type: 0x03 })
+    );
+}
+
+#[test]
+fn test_display_params() {
+    let rz = Instruction::StandardGateOperation {
+        opcode: StandardOpCode::RZ, params: vec![InstrParam::Float(1.0)], targets: vec![3],
+    };
+    assert_eq!(rz.to_string(), "RZ(1.0) [3]");
+    let u = Instruction::StandardGateOperation {
+        opcode: StandardOpCode::U,
+        params: vec![InstrParam::Float(0.785), InstrParam::Float(-2.5), InstrParam::Float(0.0)],
+        targets: vec![0],
+    };
+    assert_eq!(u.to_string(), "U(0.785, -2.5, 0.0) [0]");
+    let alloc = Instruction::Primitive {
+        opcode: PrimitiveOpCode::Alloc, params: vec![InstrParam::UInt(5)],
+    };
+    assert_eq!(alloc.to_string(), "ALLOC 5");
+    let reset = Instruction::Primitive {
+        opcode: PrimitiveOpCode::Reset, params: vec![InstrParam::Int(5), InstrParam::Int(-2)],
+    };
+    assert_eq!(reset.to_string(), "RESET +5, -2");
+    assert_eq!(sample_program()[3].to_string(), "@FOO(7) [0, 1]");
+}
+
+#[test]
+fn test_assemble() {
+    let source = "
+        // Allocate the register
+        ALLOC 3
+        rz(0.5) [2]   # lowercase mnemonics are accepted
+        NOP
+        @FOO(7) [0,1]
+        MEASURE 0,1
+    ";
+    assert_eq!(asm::assemble(source).unwrap(), sample_program());
+
+    let instructions = asm::assemble("CX [0, 1]\nRESET\nU(1, -2.5e-3, +3) [4]").unwrap();
+    assert_eq!(instructions, vec![
+        Instruction::StandardGateOperation {
+            opcode: StandardOpCode::CX, params: vec![], targets: vec![0, 1],
+        },
+        Instruction::Primitive { opcode: PrimitiveOpCode::Reset, params: vec![] },
+        Instruction::StandardGateOperation {
+            opcode: StandardOpCode::U,
+            params: vec![InstrParam::Float(1.0), InstrParam::Float(-2.5e-3), InstrParam::Float(3.0)],
+            targets: vec![4],
+        },
+    ]);
+}
+
+#[test]
+fn test_disassemble_round_trip() {
+    let mut rng = rand::thread_rng();
+    for _ in 0 .. 256 {
+        let instructions = (0 .. rng.gen_range(0 .. 32)).map(|_| {
+            match random_instruction(&mut rng) {
+                // Standard gates only take float parameters, and custom gates need printable names
+                Instruction::StandardGateOperation { opcode, params, targets } => {
+                    let params = params.iter()
+                        .map(|_| InstrParam::Float(rng.gen_range(-1e3 .. 1e3)))
+                        .collect();
+                    Instruction::StandardGateOperation { opcode, params, targets }
+                }
+                Instruction::CustomGateOperation { params, targets, .. } => {
+                    let name = custom_gate_name(&format!("G_{}", rng.gen::<u16>()));
+                    Instruction::CustomGateOperation { name, params, targets }
+                }
+                instruction => instruction,
+            }
+        }).collect::<Vec<_>>();
+        let source = asm::disassemble(&instructions);
+        assert_eq!(asm::assemble(&source).unwrap(), instructions, "{}", source);
+    }
+}
+
+#[test]
+fn test_assemble_errors() {
+    let error = |source: &str| asm::assemble(source).err().unwrap();
+
+    let err = error("ALLOC 2\n\nFOO [0]");
+    assert_eq!(err.line, 3);
+    assert_eq!(err.message, "Unknown instruction `FOO`");
+    assert_eq!(error("H").message, "Expected targets, got ``");
+    assert_eq!(error("H [0").message, "Unclosed target list");
+    assert_eq!(error("RZ(0.5 [0]").message, "Unclosed parameter list");
+    assert_eq!(error("H [0] [1]").message, "Unexpected `[1]` after targets");
+    assert_eq!(error("H [-1]").message, "Invalid target `-1`");
+    assert_eq!(error("RZ(x) [0]").message, "Invalid parameter `x`");
+    assert_eq!(error("MEASURE 0, 1, ").message, "Invalid parameter ``");
+    assert_eq!(error("NOP 1").message, "`NOP` takes no operands, got `1`");
+    assert_eq!(
+        error("@ABCDEFGHIJKLMNOPQ [0]").message,
+        "Invalid custom gate name `ABCDEFGHIJKLMNOPQ`"
+    );
+}
+
+#[test]
+fn test_invalid_container() {
+    let bytes = ByteCodeContainer::new(sample_program()).encode();
+
+    let mut unsupported_version = bytes.clone();
+    unsupported_version[4] = 0xff;
+    assert!(matches!(
+        ByteCodeContainer::decode(&unsupported_version),
+        Err(BytecodeError::UnsupportedVersion { offset: 4, .. })
+    ));
+
+    let mut big_endian = bytes.clone();
+    big_endian[6] = 1;
+    assert!(matches!(
+        ByteCodeContainer::decode(&big_endian),
+        Err(BytecodeError::UnsupportedEndianness { offset: 6, endianness: 1 })
+    ));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0x00);
+    assert!(matches!(
+        ByteCodeContainer::decode(&trailing),
+        Err(BytecodeError::TrailingBytes { .. })
+    ));
+
+    assert!(matches!(
+        ByteCodeContainer::decode(&bytes[.. bytes.len() - 4]),
+        Err(BytecodeError::UnexpectedEnd { .. })
+    ));
+
+    let mut qubit_count = bytes.clone();
+    qubit_count[8] = 4;
+    assert!(matches!(
+        ByteCodeContainer::decode(&qubit_count),
+        Err(BytecodeError::QubitCountMismatch { declared: 4, allocated: 3 })
+    ));
+
+    let mut container = ByteCodeContainer::new(sample_program());
+    container.header.custom_gates.clear();
+    assert!(matches!(
+        ByteCodeContainer::decode(&container.encode()),
+        Err(BytecodeError::UndeclaredCustomGate { .. })
+    ));
+}
+
+fn random_param<R: Rng>(rng: &mut R) -> InstrParam {
+    match rng.gen_range(0 .. 3) {
+        0 => InstrParam::Float(rng.gen_range(-10.0 .. 10.0)),
+        1 => InstrParam::Int(rng.gen()),
+        _ => InstrParam::UInt(rng.gen()),
+    }
+}
+
+fn random_instruction<R: Rng>(rng: &mut R) -> Instruction {
+    let params = |rng: &mut R| {
+        (0 .. rng.gen_range(0 ..= 4)).map(|_| random_param(rng)).collect::<Vec<_>>()
+    };
+    let targets = |rng: &mut R| {
+        (0 .. rng.gen_range(0 ..= 3)).map(|_| rng.gen()).collect::<Vec<u32>>()
+    };
+    match rng.gen_range(0 .. 4) {
+        0 => Instruction::Nop,
+        1 => Instruction::Primitive {
+            opcode: PrimitiveOpCode::from_u8(rng.gen_range(0 ..= 2)).unwrap(),
+            params: params(rng),
+        },
+        2 => Instruction::StandardGateOperation {
+            opcode: StandardOpCode::from_u8(rng.gen_range(0 ..= 0x22)).unwrap(),
+            params: params(rng),
+            targets: targets(rng),
+        },
+        _ => Instruction::CustomGateOperation {
+            name: rng.gen(),
+            params: params(rng),
+            targets: targets(rng),
+        },
+    }
+}
+
+#[test]
+fn test_round_trip_every_standard_opcode() {
+    let mut rng = rand::thread_rng();
+    let opcodes = (0 ..= u8::MAX).filter_map(StandardOpCode::from_u8).collect::<Vec<_>>();
+    assert_eq!(opcodes.len(), 35);
+    for opcode in opcodes {
+        for _ in 0 .. 64 {
+            let instruction = Instruction::StandardGateOperation {
+                opcode,
+                params: (0 .. rng.gen_range(0 ..= 4))
+                    .map(|_| InstrParam::Float(rng.gen_range(-1e3 .. 1e3)))
+                    .collect(),
+                targets: (0 .. rng.gen_range(0 ..= 3)).map(|_| rng.gen()).collect(),
+            };
+            let bytes: Vec<u8> = instruction.clone().into();
+            assert_eq!(Instruction::parse(&bytes).unwrap(), vec![instruction]);
+        }
+    }
+}
+
+#[test]
+fn test_round_trip_instruction_stream() {
+    let mut rng = rand::thread_rng();
+    for _ in 0 .. 256 {
+        let instructions = (0 .. rng.gen_range(0 .. 32))
+            .map(|_| random_instruction(&mut rng))
+            .collect::<Vec<_>>();
+        let bytes = instruction_stream(instructions.clone());
+        assert_eq!(Instruction::parse(&bytes).unwrap(), instructions);
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(
+        Instruction::parse(&[0x00, 0x00, 0x07]),
+        Err(BytecodeError::InvalidInstructionType { offset: 2, instr_type: 0x07 })
+    );
+    assert_eq!(
+        Instruction::parse(&[0x00, 0x02, 0x23, 0x00, 0x00]),
+        Err(BytecodeError::InvalidOpCode { offset: 2, opcode: 0x23 })
+    );
+    assert_eq!(
+        Instruction::parse(&[0x01, 0x03, 0x00]),
+        Err(BytecodeError::InvalidOpCode { offset: 1, opcode: 0x03 })
+    );
+
+    let bytes = instruction_stream(sample_program());
+    for size in 1 .. bytes.len() {
+        let result = Instruction::parse(&bytes[.. size]);
+        if let Err(err) = result {
+            assert_eq!(err, BytecodeError::UnexpectedEnd { offset: size });
+        }
+    }
+    assert!(Instruction::parse(&bytes[.. bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_container_error_offset() {
+    let mut bytes = ByteCodeContainer::new(sample_program()).encode();
+    // The first instruction starts after the header and the size of the instruction stream.
+    let stream_offset = 4 + 2 + 1 + 1 + 4 + 2 + 18 + 4;
+    assert_eq!(bytes[stream_offset], 0x01);
+    bytes[stream_offset] = 0x09;
+    assert_eq!(
+        ByteCodeContainer::decode(&bytes).err(),
+        Some(BytecodeError::InvalidInstructionType { offset: stream_offset, instr_type: 0x09 })
+    );
+}
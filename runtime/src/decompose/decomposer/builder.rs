@@ -1,55 +1,167 @@
+use std::iter;
 use strum::VariantNames;
-use crate::backend::is_gate_available;
-use crate::decompose::decomposer::ElementaryGateDecomposer;
+use crate::backend::QivmBackend;
+use crate::backend::gate_set::{GateSet, standard_gate_idents};
+use crate::decompose::decomposer::{Decomposition, ElementaryGateDecomposer};
+use crate::decompose::double::kak_decompose;
+use crate::decompose::shannon::{shannon_cx_count, shannon_decompose};
 use crate::decompose::single::{decompose_single, zyz_decompose};
-use crate::gate::standard::StandardSingleGate;
-use crate::gate::unitary::{DOUBLE_UNITARY_IDENT, SINGLE_UNITARY_IDENT};
+use crate::decompose::standard::{
+    ccx_to_cx, cp_to_cx, cx_to_cz, cx_to_iswp, cz_to_cx, cz_to_iswp, iswp_to_cx, iswp_to_cz,
+    swp_to_cx,
+};
+use crate::gate::canonical::CANONICAL_IDENT;
+use crate::gate::standard::{StandardDoubleGate, StandardSingleGate};
+use crate::gate::unitary::{DOUBLE_UNITARY_IDENT, DYNAMIC_UNITARY_IDENT, SINGLE_UNITARY_IDENT};
+use crate::gate::{DoubleTargetGate, IntoUnitary};
 use crate::algebra::ToMat2;
 use crate::operation::elementary::{ElementaryOperation, SingleOperation};
-use crate::operation::elementary::unitary::UnitarySingleOperation;
-use crate::operation::{ElementaryGateOperation, SingleTargetOperation};
-use crate::QIVM_INSTANCE;
+use crate::operation::elementary::standard::StandardOperation;
+use crate::operation::elementary::unitary::{UnitaryOperation, UnitarySingleOperation};
+use crate::operation::{
+    DoubleTargetOperation, DynamicTargetOperation, ElementaryGateOperation, SingleTargetOperation,
+    TargetDouble, TripleTargetOperation,
+};
 
 pub struct ElementaryDecomposerBuilder {
     decomposer: ElementaryGateDecomposer,
+    gate_set: GateSet,
 }
 
 impl ElementaryDecomposerBuilder {
 
-    pub fn new() -> Self {
-        Self { decomposer: ElementaryGateDecomposer::new() }
+    /// Create a builder targeting the gates the backend reports available.
+    pub fn new(backend: &dyn QivmBackend) -> Self {
+        Self::with_gate_set(GateSet::from_backend(backend))
+    }
+
+    /// Create a builder targeting the native gate set.
+    pub fn with_gate_set(gate_set: GateSet) -> Self {
+        Self { decomposer: ElementaryGateDecomposer::new(), gate_set }
     }
 
     pub fn build(mut self) -> ElementaryGateDecomposer {
         self.add_standard_gates();
         self.add_unitary_gates();
+        self.init_standard_double_gates();
         self.init_std_single_gates_to_unitary();
         self.init_unitary_zyz_decompose();
+        self.init_kak_decompose();
+        self.init_shannon_decompose();
         self.decomposer
     }
 
+    /// Add a built-in decomposition. The gates it refers to are added first if the builder has
+    /// not added them, as non-native gates.
+    fn add_decomposition(
+        &mut self, from: &str, to: Vec<&str>, cost: i32, decomposition: Decomposition
+    ) {
+        for &gate_ident in iter::once(&from).chain(&to) {
+            if !self.decomposer.contains_gate(gate_ident) {
+                self.decomposer.add_gate(gate_ident, false);
+            }
+        }
+        // every gate of the recipe is known, so the decomposer accepts it
+        let added = self.decomposer.add_decomposition(from, to, cost, decomposition);
+        debug_assert!(added.is_ok());
+    }
+
     fn add_unitary_gates(&mut self) {
         self.decomposer.add_gate(SINGLE_UNITARY_IDENT, false);
         self.decomposer.add_gate(DOUBLE_UNITARY_IDENT, false);
+        self.decomposer.add_gate(DYNAMIC_UNITARY_IDENT, false);
     }
 
     fn add_standard_gates(&mut self) {
-        for &gate_ident in StandardSingleGate::VARIANTS {
-            if is_gate_available(gate_ident) {
-                self.decomposer.add_gate(gate_ident, true);
-            } else {
-                self.decomposer.add_gate(gate_ident, false);
+        for (&gate_ident, _) in standard_gate_idents() {
+            match self.gate_set.get(gate_ident) {
+                Some(gate) => self.decomposer.add_native_gate(gate_ident, gate.cost),
+                None => self.decomposer.add_gate(gate_ident, false),
             }
         }
     }
 
+    /// Decompose standard double and triple gates into each other:
+    /// CX, CZ and ISWP are decomposed into each other, SWP, CP and CCX into CX, and the other
+    /// double gates with KAK decomposition.
+    fn init_standard_double_gates(&mut self) {
+        let recipes: [(&str, Vec<&str>, Decomposition); 6] = [
+            (
+                "CX", materials(&[("CZ", 1), ("H", 2)]),
+                Box::new(|op| cx_to_cz(double_target(op))),
+            ),
+            (
+                "CX", materials(&[("ISWP", 2), ("RX", 2), ("RZ", 3)]),
+                Box::new(|op| cx_to_iswp(double_target(op))),
+            ),
+            (
+                "CZ", materials(&[("CX", 1), ("H", 2)]),
+                Box::new(|op| cz_to_cx(double_target(op))),
+            ),
+            (
+                "CZ", materials(&[("ISWP", 2), ("H", 2), ("RX", 2), ("RZ", 3)]),
+                Box::new(|op| cz_to_iswp(double_target(op))),
+            ),
+            (
+                "ISWP", materials(&[("CX", 2), ("S", 2), ("H", 2)]),
+                Box::new(|op| iswp_to_cx(double_target(op))),
+            ),
+            (
+                "ISWP", materials(&[("CZ", 2), ("S", 2), ("H", 6)]),
+                Box::new(|op| iswp_to_cz(double_target(op))),
+            ),
+        ];
+        for (from, materials, decomposition) in recipes {
+            self.add_decomposition(from, materials, 0, decomposition);
+        }
+        self.add_decomposition(
+            "SWP", materials(&[("CX", 3)]), 0, Box::new(|op| swp_to_cx(double_target(op))),
+        );
+        self.add_decomposition(
+            "CP", materials(&[("CX", 2), ("P", 3)]), 0, Box::new(|op| match op {
+                ElementaryOperation::Standard(StandardOperation::Double(op)) => {
+                    match op.get_gate() {
+                        StandardDoubleGate::CP { angle } => cp_to_cx(angle, op.get_target()),
+                        _ => unreachable!("`CP` is a controlled phase gate"),
+                    }
+                }
+                _ => unreachable!("`CP` is a standard double gate"),
+            })
+        );
+        self.add_decomposition(
+            "CCX", materials(&[("CX", 6), ("H", 2), ("T", 4), ("TD", 3)]), 0,
+            Box::new(|op| match op {
+                ElementaryOperation::Standard(StandardOperation::Triple(op)) => {
+                    ccx_to_cx(op.get_target())
+                }
+                _ => unreachable!("`CCX` is a standard triple gate"),
+            })
+        );
+        // the other double gates fall back to KAK decomposition
+        for &gate_ident in StandardDoubleGate::VARIANTS {
+            if gate_ident == "CX" {
+                continue;
+            }
+            self.add_decomposition(
+                gate_ident, vec![DOUBLE_UNITARY_IDENT], 0, Box::new(|op| {
+                    match op {
+                        ElementaryOperation::Standard(StandardOperation::Double(op)) => {
+                            vec![op.get_gate().into_unitary().apply_to(op.get_target()).into()]
+                        }
+                        _ => unreachable!("standard double gate expected"),
+                    }
+                })
+            );
+        }
+    }
+
     /// Transform standard single gates to unitary single gates.
     fn init_std_single_gates_to_unitary(&mut self) {
         for &gate_ident in StandardSingleGate::VARIANTS {
             if gate_ident == "I" {
-                self.decomposer.add_decomposition(gate_ident, vec![], -1, Box::new(|_| vec![]));
+                self.add_decomposition(gate_ident, vec![], 0, Box::new(|_| vec![]));
             } else {
-                self.decomposer.add_decomposition(
+                self.add_decomposition(
                     gate_ident, vec![SINGLE_UNITARY_IDENT], 0, Box::new(|op| {
                         let single_op: SingleOperation = op.clone().try_into().unwrap();
                         vec![UnitarySingleOperation::from_mat(
@@ -63,11 +175,58 @@ impl ElementaryDecomposerBuilder {
 
     /// Decompose unitary single gates with ZYZ decomposition.
     fn init_unitary_zyz_decompose(&mut self) {
-        self.decomposer.add_decomposition(
-            SINGLE_UNITARY_IDENT, vec!["RY", "RZ", "P"], 4, Box::new(|op| {
+        self.add_decomposition(
+            SINGLE_UNITARY_IDENT, materials(&[("RZ", 2), ("RY", 1)]), 0, Box::new(|op| {
                 let single_op: SingleOperation = op.clone().try_into().unwrap();
                 decompose_single(&single_op)
             })
         )
     }
+
+    /// Decompose two-qubit unitary and canonical gates with KAK decomposition.
+    fn init_kak_decompose(&mut self) {
+        let materials = materials(&[("CX", 3), ("RY", 2), ("RZ", 3), (SINGLE_UNITARY_IDENT, 4)]);
+        self.add_decomposition(
+            DOUBLE_UNITARY_IDENT, materials.clone(), 0, Box::new(|op| match op {
+                ElementaryOperation::Unitary(UnitaryOperation::Double(op)) => kak_decompose(op),
+                _ => unreachable!("`{}` is a double unitary gate", DOUBLE_UNITARY_IDENT),
+            })
+        );
+        self.add_decomposition(
+            CANONICAL_IDENT, materials, 0, Box::new(|op| match op {
+                ElementaryOperation::Canonical(op) => kak_decompose(op),
+                _ => unreachable!("`{}` is a canonical gate", CANONICAL_IDENT),
+            })
+        );
+    }
+
+    /// Decompose dynamic unitary gates with Quantum Shannon Decomposition.
+    fn init_shannon_decompose(&mut self) {
+        // dynamic unitary gates have at least 3 qubits, which take 4 KAK decompositions and 3
+        // multiplexed rotations of 4 rotations
+        let materials = materials(&[
+            ("CX", shannon_cx_count(3)), ("RY", 12), ("RZ", 20), (SINGLE_UNITARY_IDENT, 16),
+        ]);
+        self.add_decomposition(
+            DYNAMIC_UNITARY_IDENT, materials, 0, Box::new(|op| match op {
+                ElementaryOperation::Unitary(UnitaryOperation::Dynamic(op)) => {
+                    shannon_decompose(op.get_gate().mat(), &op.get_target().to_vec())
+                }
+                _ => unreachable!("`{}` is a dynamic unitary gate", DYNAMIC_UNITARY_IDENT),
+            })
+        );
+    }
+}
+
+/// Materials of a recipe, each material is repeated by its multiplicity.
+fn materials<'a>(counts: &[(&'a str, usize)]) -> Vec<&'a str> {
+    counts.iter().flat_map(|&(ident, count)| vec![ident; count]).collect()
+}
+
+/// Target of a standard double gate operation.
+fn double_target(op: &ElementaryOperation) -> TargetDouble {
+    match op {
+        ElementaryOperation::Standard(StandardOperation::Double(op)) => op.get_target(),
+        _ => unreachable!("standard double gate expected"),
+    }
 }
@@ -1,155 +1,254 @@
-use std::collections::{HashMap, HashSet};
-use std::fmt::Display;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::hash::Hash;
-use std::marker::PhantomData;
-use std::sync::{Arc, Mutex};
-use crate::raise_error;
+use std::sync::Mutex;
+use crate::error::{QivmError, QivmResult};
 
 #[cfg(test)]
 mod tests;
 
-type RecipeRef<T> = Arc<Mutex<Recipe<T>>>;
-type ItemRef<T> = Arc<Mutex<Item<T>>>;
-
 pub type Delegate<T> = dyn Fn(&T) -> Vec<T> + Send + 'static;
 
-struct FeasibleRecipe<T> {
-    pub recipe: RecipeRef<T>,
-    pub total_cost: i32,
+struct Item {
+    is_material: bool,
+    cost: i32,
+    /// Recipes having the item as a material.
+    used_by: Vec<usize>,
 }
 
-impl<T> FeasibleRecipe<T> {
-    pub fn new(recipe: RecipeRef<T>, total_cost: i32) -> Self {
-        Self { recipe, total_cost }
+impl Item {
+    fn new(is_material: bool, cost: i32) -> Self {
+        Self { is_material, cost, used_by: vec![] }
     }
 }
 
-pub struct Item<T> {
-    candidate_recipes: Vec<RecipeRef<T>>,
-    is_material: bool,
-    is_decomposable: bool,
-    is_decomposing: bool,
-    recipe: Option<FeasibleRecipe<T>>,
+struct Recipe<T> {
+    target: usize,
+    /// Materials of the recipe with their multiplicity.
+    materials: Vec<(usize, i32)>,
+    cost: i32,
+    delegate: Box<Delegate<T>>,
 }
 
-impl<T> Item<T> {
-    pub fn new(is_material: bool) -> Self {
-        Self {
-            candidate_recipes: vec![],
-            is_material,
-            is_decomposable: true,
-            is_decomposing: false,
-            recipe: None
-        }
-    }
+/// Result of the search: the lowest total cost of each item, and the recipe achieving it.
+/// Materials have their own cost and no recipe.
+struct Routes {
+    total_costs: Vec<Option<i32>>,
+    recipes: Vec<Option<usize>>,
 }
 
-pub struct Recipe<T> {
-    cost: i32,
-    materials: Vec<ItemRef<T>>,
-    delegate: Box<Delegate<T>>,
-    type_phantom: PhantomData<T>,
+/// A recipe of the route chosen to decompose an item.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecompositionStep<I> {
+    pub target: I,
+    /// Materials of the recipe with their multiplicity.
+    pub materials: Vec<(I, i32)>,
+    pub cost: i32,
+    /// Cost of the recipe and of all the materials it is made of.
+    pub total_cost: i32,
 }
 
-impl<T> Recipe<T> {
-    pub fn new(materials: Vec<ItemRef<T>>, cost: i32, delegate: Box<Delegate<T>>) -> Self {
-        Self { cost, materials, delegate, type_phantom: PhantomData }
+impl<I: Display> Display for DecompositionStep<I> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        let materials = self.materials.iter().map(|(material, count)| match count {
+            1 => material.to_string(),
+            _ => format!("{}*{}", count, material),
+        }).collect::<Vec<String>>();
+        write!(
+            formatter, "{} => [{}] : {} (total {})",
+            self.target, materials.join(", "), self.cost, self.total_cost
+        )
     }
 }
 
-pub struct DecompositionGraph<I: Eq + Hash + Ord + Display, T> {
-    items: HashMap<I, ItemRef<T>>,
+/// Graph of items and of the recipes making them from other items.
+///
+/// Materials are used as is, and the other items are made with the route of recipes of the
+/// lowest total cost: the cost of a recipe plus the total cost of each of its materials,
+/// weighted by their multiplicity. Costs must be non-negative.
+///
+/// The routes are searched lazily and cached until the graph is modified.
+pub struct DecompositionGraph<I: Eq + Hash + Ord + Display + Clone, T> {
+    ids: Vec<I>,
+    indices: HashMap<I, usize>,
+    items: Vec<Item>,
+    recipes: Vec<Recipe<T>>,
+    routes: Mutex<Option<Routes>>,
 }
 
-impl<I: Eq + Hash + Ord + Display, T> DecompositionGraph<I, T> {
+impl<I: Eq + Hash + Ord + Display + Clone, T> DecompositionGraph<I, T> {
     pub fn new() -> Self {
-        Self { items: HashMap::new() }
+        Self {
+            ids: vec![],
+            indices: HashMap::new(),
+            items: vec![],
+            recipes: vec![],
+            routes: Mutex::new(None),
+        }
     }
 
+    /// Add an item, or update the item with the same id.
     pub fn add_item(&mut self, id: I, is_material: bool) {
-        self.items.insert(id, ItemRef::new(Item::new(is_material).into()));
+        self.insert_item(id, Item::new(is_material, 0));
     }
 
+    /// Add a material whose use costs `cost` in the recipes it is part of.
+    pub fn add_material(&mut self, id: I, cost: i32) {
+        self.insert_item(id, Item::new(true, cost));
+    }
+
+    fn insert_item(&mut self, id: I, item: Item) {
+        *self.routes.get_mut().unwrap() = None;
+        match self.indices.get(&id) {
+            Some(&index) => {
+                self.items[index].is_material = item.is_material;
+                self.items[index].cost = item.cost;
+            }
+            None => {
+                self.indices.insert(id.clone(), self.items.len());
+                self.ids.push(id);
+                self.items.push(item);
+            }
+        }
+    }
+
+    /// Add a recipe making the target from the materials, a material may appear several times.
+    /// Return an error if the target or one of the materials is not an item of the graph.
     pub fn add_recipe(
-        &mut self, target_id: &I, materials_id: HashSet<I>, cost: i32, delegate: Box<Delegate<T>>
-    ) {
-        let materials: Vec<ItemRef<T>> = materials_id.iter().map(|id| {
-            self.items.get(id).cloned().unwrap_or_else(|| raise_error!("Invalid item id: {}", id))
-        }).collect();
-        let recipe = RecipeRef::new(Recipe::new(materials, cost, delegate).into());
-        self.items[target_id].lock().unwrap_or_else(|_| {
-            raise_error!("Invalid target id: {}", target_id)
-        }).candidate_recipes.push(recipe);
+        &mut self, target_id: &I, materials_id: Vec<I>, cost: i32, delegate: Box<Delegate<T>>
+    ) -> QivmResult<()> {
+        let mut materials = Vec::<(usize, i32)>::new();
+        for id in &materials_id {
+            let index = *self.indices.get(id).ok_or_else(|| {
+                QivmError::InvalidDecomposition(format!("Invalid item id: {}", id))
+            })?;
+            match materials.iter_mut().find(|(material, _)| *material == index) {
+                Some((_, count)) => *count += 1,
+                None => materials.push((index, 1)),
+            }
+        }
+        let target = *self.indices.get(target_id).ok_or_else(|| {
+            QivmError::InvalidDecomposition(format!("Invalid target id: {}", target_id))
+        })?;
+        *self.routes.get_mut().unwrap() = None;
+        let recipe_index = self.recipes.len();
+        for &(material, _) in &materials {
+            self.items[material].used_by.push(recipe_index);
+        }
+        self.recipes.push(Recipe { target, materials, cost, delegate });
+        Ok(())
+    }
+
+    pub fn contains(&self, id: &I) -> bool {
+        self.indices.contains_key(id)
     }
 
+    /// Return true if the item is a material, which is used as is.
     pub fn is_available(&self, id: &I) -> bool {
-        self.items.get(id).and_then(|item| {
-            if item.lock().unwrap().is_material { None } else { Some(()) }
-        }).is_some()
+        self.indices.get(id).map_or(false, |&index| self.items[index].is_material)
     }
 
     pub fn is_decomposable(&self, id: &I) -> bool {
-        let mut item = self.items[id].lock().unwrap();
-        item.search_recipe();
-        item.recipe.is_some()
+        self.recipe_of(id).is_some()
     }
 
-    pub fn execute_decomposition(&mut self, id: &I, value: &T) -> Option<Vec<T>> {
-        let mut item = self.items[id].lock().unwrap();
-        item.search_recipe();
-        item.recipe.as_ref().map(|recipe| {
-            let delegate = &*recipe.recipe.lock().unwrap().delegate;
-            delegate(value)
-        })
+    /// Total cost of the item: its own cost for a material, the cost of its route otherwise.
+    pub fn total_cost(&self, id: &I) -> Option<i32> {
+        let index = *self.indices.get(id)?;
+        self.with_routes(|routes| routes.total_costs[index])
     }
-}
 
-impl<T> Item<T> {
-    pub fn search_recipe(&mut self) -> Option<i32> {
-        // base cases
-        if self.is_material {
-            self.is_decomposable = false;
-            return Some(0);
-        } else if !self.is_decomposable {
-            return None;
-        } else if self.is_decomposing {
-            // if the search path forms a circle,
-            // then we consider the item not decomposable
-            self.is_decomposable = false;
-            return None;
-        } else if let Some(recipe) = &self.recipe {
-            return Some(recipe.total_cost);
-        }
+    pub fn execute_decomposition(&self, id: &I, value: &T) -> Option<Vec<T>> {
+        self.recipe_of(id).map(|recipe| (self.recipes[recipe].delegate)(value))
+    }
 
-        // set decomposing flag
-        self.is_decomposing = true;
+    /// Explain the route chosen to decompose the item: its recipe, followed by the recipes of
+    /// the materials which are decomposed in turn, in depth-first order.
+    /// Return `None` if the item is not decomposable, and no step for a material.
+    pub fn explain_decomposition(&self, id: &I) -> Option<Vec<DecompositionStep<I>>> {
+        let index = *self.indices.get(id)?;
+        self.with_routes(|routes| {
+            routes.total_costs[index]?;
+            let mut steps = vec![];
+            let mut visited = HashSet::new();
+            let mut stack = vec![index];
+            while let Some(index) = stack.pop() {
+                if !visited.insert(index) {
+                    continue;
+                }
+                if let Some(recipe_index) = routes.recipes[index] {
+                    let recipe = &self.recipes[recipe_index];
+                    steps.push(DecompositionStep {
+                        target: self.ids[index].clone(),
+                        materials: recipe.materials.iter().map(|&(material, count)| {
+                            (self.ids[material].clone(), count)
+                        }).collect(),
+                        cost: recipe.cost,
+                        total_cost: routes.total_costs[index].unwrap(),
+                    });
+                    stack.extend(recipe.materials.iter().rev().map(|&(material, _)| material));
+                }
+            }
+            Some(steps)
+        })
+    }
+
+    fn recipe_of(&self, id: &I) -> Option<usize> {
+        let index = *self.indices.get(id)?;
+        self.with_routes(|routes| routes.recipes[index])
+    }
 
-        // sort candidate recipes
-        self.candidate_recipes.sort_by_key(|recipe| recipe.lock().unwrap().cost);
+    fn with_routes<R>(&self, f: impl FnOnce(&Routes) -> R) -> R {
+        let mut routes = self.routes.lock().unwrap();
+        f(routes.get_or_insert_with(|| self.search_routes()))
+    }
 
-        // search recipe
-        for recipe in &mut self.candidate_recipes {
-            let mut recipe_mut = recipe.lock().unwrap();
-            let cost = recipe_mut.materials.iter_mut().map(|material| {
-                match material.try_lock() {
-                    Ok(mut item) => item.search_recipe(),
-                    Err(_) => None,
+    /// Search the routes of the lowest total cost with the generalization of Dijkstra's
+    /// algorithm to grammar problems: an item is settled when it is the cheapest one in the
+    /// queue, and a recipe is queued when all of its materials are settled.
+    ///
+    /// reference: D. E. Knuth, A generalization of Dijkstra's algorithm, 1977
+    fn search_routes(&self) -> Routes {
+        let mut routes = Routes {
+            total_costs: vec![None; self.items.len()],
+            recipes: vec![None; self.items.len()],
+        };
+        let mut pending_materials = self.recipes.iter()
+            .map(|recipe| recipe.materials.len())
+            .collect::<Vec<usize>>();
+        let mut queue = BinaryHeap::<Reverse<(i32, usize, Option<usize>)>>::new();
+        for (index, item) in self.items.iter().enumerate() {
+            if item.is_material {
+                queue.push(Reverse((item.cost, index, None)));
+            }
+        }
+        for (index, recipe) in self.recipes.iter().enumerate() {
+            if recipe.materials.is_empty() && !self.items[recipe.target].is_material {
+                queue.push(Reverse((recipe.cost, recipe.target, Some(index))));
+            }
+        }
+        while let Some(Reverse((total_cost, index, recipe))) = queue.pop() {
+            if routes.total_costs[index].is_some() {
+                continue;
+            }
+            routes.total_costs[index] = Some(total_cost);
+            routes.recipes[index] = recipe;
+            for &recipe_index in &self.items[index].used_by {
+                pending_materials[recipe_index] -= 1;
+                let recipe = &self.recipes[recipe_index];
+                let target = recipe.target;
+                if pending_materials[recipe_index] > 0
+                    || self.items[target].is_material
+                    || routes.total_costs[target].is_some() {
+                    continue;
                 }
-            }).fold(Some(0), |acc, decompose| {
-                acc.and_then(|acc| decompose.map(|val| val + acc))
-            });
-            if let Some(cost) = cost {
-                self.recipe = Some(FeasibleRecipe::new(RecipeRef::clone(recipe), cost));
-                self.is_decomposing = false;
-                return Some(cost);
+                let total_cost = recipe.materials.iter().fold(recipe.cost, |acc, &(id, count)| {
+                    acc.saturating_add(routes.total_costs[id].unwrap().saturating_mul(count))
+                });
+                queue.push(Reverse((total_cost, target, Some(recipe_index))));
             }
         }
-
-        // remove decomposing flag
-        self.is_decomposing = false;
-
-        // the item is not decomposable
-        self.is_decomposable = false;
-        None /* No valid recipe found */
+        routes
     }
 }
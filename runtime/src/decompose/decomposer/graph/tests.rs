@@ -1,5 +1,4 @@
 use super::*;
-use std::collections::HashSet;
 
 type TestGraph<'a> = DecompositionGraph<&'a str, &'a str>;
 
@@ -12,9 +11,9 @@ macro_rules! graph {
     };
     (@recipes $graph:ident $($name:ident = $($materials:ident),* => $target:ident : $cost:literal);*) => {
         $($graph.add_recipe(
-            &stringify!($target), HashSet::from([$(stringify!($materials)),*]),
+            &stringify!($target), vec![$(stringify!($materials)),*],
             $cost, Box::new(|_| vec![stringify!($name)]),
-        );)*
+        ).unwrap();)*
     };
     {
         materials = $($mat_id:ident),*;
@@ -77,6 +76,18 @@ fn test_decomposition_simple_case() {
     assert_decompositions!(graph => C: a);
 }
 
+#[test]
+fn test_availability() {
+    let graph = graph! {
+        materials = A;
+        items = B;
+        a = [A] => B : 1;
+    };
+    assert!(graph.is_available(&"A"));
+    assert!(!graph.is_available(&"B"));
+    assert!(!graph.is_available(&"C"));
+}
+
 #[test]
 fn test_decomposition_lowest_cost() {
     let mut graph = graph! {
@@ -91,6 +102,19 @@ fn test_decomposition_lowest_cost() {
     assert_decompositions!(graph => D: c);
 }
 
+#[test]
+fn test_add_recipe_with_unknown_item() {
+    let mut graph: TestGraph = DecompositionGraph::new();
+    graph.add_item("A", true);
+    graph.add_item("B", false);
+    let recipe = |_: &&str| vec!["a"];
+    let err = graph.add_recipe(&"B", vec!["C"], 1, Box::new(recipe)).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid decomposition: Invalid item id: C");
+    let err = graph.add_recipe(&"C", vec!["A"], 1, Box::new(recipe)).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid decomposition: Invalid target id: C");
+    assert_not_decomposable!(graph => B);
+}
+
 #[test]
 fn test_decomposition_simple_one_to_one_case() {
     let mut graph = graph! {
@@ -328,3 +352,74 @@ fn test_decomposition_contain_item_to_item_strange_case() {
     assert_decompositions!(graph => C: a);
     assert_decompositions!(graph => E: d);
 }
+
+#[test]
+fn test_decomposition_total_cost() {
+    // the recipe `b` is cheaper, but its material `D` is expensive
+    let mut graph = graph! {
+        materials = A, B;
+        items = C, D;
+        a = [A, B] => C : 5;
+        b = [D] => C : 1;
+        c = [A, A, B] => D : 10;
+    };
+    assert_decomposable!(graph => C, D);
+    assert_decompositions!(graph => C: a);
+    assert_eq!(graph.total_cost(&"C"), Some(5));
+    assert_eq!(graph.total_cost(&"D"), Some(10));
+}
+
+#[test]
+fn test_decomposition_material_multiplicity() {
+    let mut graph: TestGraph = DecompositionGraph::new();
+    graph.add_material("A", 3);
+    graph.add_material("B", 5);
+    graph.add_item("C", false);
+    graph.add_recipe(&"C", vec!["A", "A", "A"], 1, Box::new(|_| vec!["a"])).unwrap();
+    graph.add_recipe(&"C", vec!["B"], 1, Box::new(|_| vec!["b"])).unwrap();
+    assert_decompositions!(graph => C: b);
+    assert_eq!(graph.total_cost(&"C"), Some(6));
+    assert_eq!(graph.total_cost(&"A"), Some(3));
+    // the routes are searched again when the graph is modified
+    graph.add_material("B", 20);
+    assert_decompositions!(graph => C: a);
+    assert_eq!(graph.total_cost(&"C"), Some(10));
+}
+
+#[test]
+fn test_decomposition_through_loop() {
+    // `D` and `E` are made from each other, but only `E` is made from materials
+    let mut graph = graph! {
+        materials = A, B;
+        items = C, D, E;
+        a = [D] => C : 1;
+        b = [E] => D : 2;
+        c = [D] => E : 2;
+        d = [A, B] => E : 7;
+    };
+    assert_decomposable!(graph => C, D, E);
+    assert_decompositions!(graph => C: a);
+    assert_decompositions!(graph => D: b);
+    assert_decompositions!(graph => E: d);
+    assert_eq!(graph.total_cost(&"C"), Some(10));
+}
+
+#[test]
+fn test_explain_decomposition() {
+    let graph = graph! {
+        materials = A, B;
+        items = C, D, E;
+        a = [A, B] => C : 5;
+        b = [B, C, C] => D : 3;
+        c = [C, D] => E : 10;
+    };
+    let steps = graph.explain_decomposition(&"E").unwrap();
+    let steps = steps.iter().map(|step| step.to_string()).collect::<Vec<String>>();
+    assert_eq!(steps, [
+        "E => [C, D] : 10 (total 28)",
+        "C => [A, B] : 5 (total 5)",
+        "D => [B, 2*C] : 3 (total 13)",
+    ]);
+    assert_eq!(graph.explain_decomposition(&"A"), Some(vec![]));
+    assert_eq!(graph.explain_decomposition(&"F"), None);
+}
@@ -1,19 +1,26 @@
 mod graph;
-mod builder;
+pub mod builder;
+
+pub use graph::DecompositionStep;
 
 use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
+use crate::backend::QivmBackend;
 use crate::decompose::decomposer::builder::ElementaryDecomposerBuilder;
 use crate::decompose::decomposer::graph::DecompositionGraph;
+use crate::error::QivmResult;
 use crate::gate::elementary::ElementaryGate;
 use crate::operation::elementary::ElementaryOperation;
+use crate::gate::{DoubleTargetGate, DynamicTargetGate, IntoUnitary, SingleTargetGate};
+use crate::operation::elementary::custom::CustomOperation;
 use crate::operation::elementary::standard::StandardOperation;
-use crate::operation::SingleTargetOperation;
+use crate::operation::{DoubleTargetOperation, DynamicTargetOperation, SingleTargetOperation};
 
 type Decomposition = Box<graph::Delegate<ElementaryOperation>>;
 type DecomposeResult = Result<Vec<ElementaryOperation>, DecomposeError>;
 
+#[derive(Clone)]
 pub struct DecomposeError(String);
 
 impl Debug for DecomposeError {
@@ -40,6 +47,7 @@ impl Error for DecomposeError {}
 ///
 /// The graph is populated with the following methods:
 /// - `add_gate`: adds a gate to the graph.
+/// - `add_native_gate`: adds a gate available in the target device to the graph.
 /// - `add_decomposition`: adds a decomposition recipe for a gate.
 ///
 /// Once the graph is populated, you can use the `decompose` method to decompose a gate into other
@@ -55,8 +63,8 @@ impl ElementaryGateDecomposer {
         Self { graph: DecompositionGraph::new() }
     }
 
-    pub fn builder() -> ElementaryDecomposerBuilder {
-        ElementaryDecomposerBuilder::new()
+    pub fn builder(backend: &dyn QivmBackend) -> ElementaryDecomposerBuilder {
+        ElementaryDecomposerBuilder::new(backend)
     }
 
     /// Adds a gate to the decomposer.
@@ -64,13 +72,25 @@ impl ElementaryGateDecomposer {
         self.graph.add_item(gate.to_string(), is_valid);
     }
 
-    /// Adds a decomposition recipe for a gate.
+    /// Adds a gate available in the target device, emitting it costs `cost`.
+    pub fn add_native_gate(&mut self, gate: &str, cost: i32) {
+        self.graph.add_material(gate.to_string(), cost);
+    }
+
+    /// Adds a decomposition recipe for a gate, emitting the gates of `to` as many times as they
+    /// appear in it.
+    /// Returns an error if one of the gates has not been added to the decomposer.
     pub fn add_decomposition(
         &mut self, from: &str, to: Vec<&str>, cost: i32,
         decomposition: Decomposition,
-    ) {
+    ) -> QivmResult<()> {
         let to_idents = to.iter().map(|s| s.to_string()).collect();
-        self.graph.add_recipe(&from.to_string(), to_idents, cost, decomposition);
+        self.graph.add_recipe(&from.to_string(), to_idents, cost, decomposition)
+    }
+
+    /// Return true if the gate has been added to the decomposer.
+    pub fn contains_gate(&self, gate_ident: &str) -> bool {
+        self.graph.contains(&gate_ident.to_string())
     }
 
     /// Return true if the gate is decomposable.
@@ -78,6 +98,14 @@ impl ElementaryGateDecomposer {
         self.graph.is_decomposable(&gate.ident())
     }
 
+    /// Return the recipes chosen to decompose the gate, see
+    /// [`DecompositionGraph::explain_decomposition`].
+    pub fn explain_decomposition(
+        &self, gate_ident: &str
+    ) -> Option<Vec<DecompositionStep<String>>> {
+        self.graph.explain_decomposition(&gate_ident.to_string())
+    }
+
     /// Return true if the gate is available in the target device backend.
     pub fn is_gate_available(&self, gate_ident: &str) -> bool {
         self.graph.is_available(&gate_ident.to_string())
@@ -85,9 +113,16 @@ impl ElementaryGateDecomposer {
 
     /// Decompose a gate into a list of elementary gates.
     /// If the gate is not decomposable, return an `DecomposeError`.
+    ///
+    /// Custom gates with a known matrix are decomposed as unitary gates.
     pub fn decompose(&mut self, gate_op: &ElementaryOperation) -> DecomposeResult {
         let gate_ident = gate_op.get_ident();
-        let decompose_result = self.graph.execute_decomposition(&gate_ident, gate_op);
+        let decompose_result = if self.graph.contains(&gate_ident) {
+            self.graph.execute_decomposition(&gate_ident, gate_op)
+        } else {
+            // custom gates unknown to the decomposer are decomposed as unitary gates
+            custom_to_unitary(gate_op).map(|unitary_op| vec![unitary_op])
+        };
         match decompose_result {
             None => Err(DecomposeError(gate_ident)),
             Some(decomposed) => {
@@ -102,3 +137,19 @@ impl ElementaryGateDecomposer {
         }
     }
 }
+
+/// Unitary gate operation of a custom gate operation whose matrix is known.
+fn custom_to_unitary(gate_op: &ElementaryOperation) -> Option<ElementaryOperation> {
+    match gate_op {
+        ElementaryOperation::Custom(CustomOperation::Single(op)) => {
+            Some(op.get_gate().into_unitary().apply_to(op.get_target()).into())
+        }
+        ElementaryOperation::Custom(CustomOperation::Double(op)) => {
+            Some(op.get_gate().into_unitary().apply_to(op.get_target()).into())
+        }
+        ElementaryOperation::Custom(CustomOperation::Dynamic(op)) => {
+            Some(op.get_gate().into_unitary().apply_to(op.get_target()).into())
+        }
+        _ => None,
+    }
+}
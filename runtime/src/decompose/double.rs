@@ -1,8 +1,13 @@
+use std::f64::consts::FRAC_PI_2;
 use nalgebra::{Const, Matrix4, SVD};
 use num::complex::Complex64;
 use crate::{c64, const_mat4, mat4};
 use crate::gate::custom::CustomSingleGate;
-use crate::algebra::{Mat4, su};
+use crate::gate::SingleTargetGate;
+use crate::gate::DoubleTargetGate;
+use crate::gate::standard::StandardDoubleGate::CX;
+use crate::gate::standard::StandardSingleGate::{RY, RZ};
+use crate::algebra::{close_to_zero, DMat, hermitian_eigen, Mat2, Mat4, su};
 use crate::operation::{DoubleTargetOperation, Operation, SingleTargetOperation};
 use crate::operation::elementary::custom::{CustomOperation, CustomSingleOperation};
 use crate::operation::elementary::ElementaryOperation;
@@ -16,25 +21,124 @@ const B_NONNORM_MAT: Mat4 = const_mat4! {
 };
 
 const B_NONNORM_MAT_DAGGER: Mat4 = const_mat4! {
-    c64!(1), c64!(-1 i), c64!(0), c64!(0);
-    c64!(0), c64!(0), c64!(1 i), c64!(1);
-    c64!(0), c64!(0), c64!(1 i), c64!(-1);
-    c64!(1), c64!(1 i), c64!(0), c64!(0);
+    c64!(1), c64!(0), c64!(0), c64!(1);
+    c64!(-1 i), c64!(0), c64!(0), c64!(1 i);
+    c64!(0), c64!(-1 i), c64!(-1 i), c64!(0);
+    c64!(0), c64!(1), c64!(-1), c64!(0);
 };
 
+/// Transform the matrix into the magic basis, or back from it if `reverse` is true.
+///
+/// The magic basis matrix is `B_NONNORM_MAT / √2`, so both products are scaled by 1/2.
 fn transform_magic_basis(mat: Mat4, reverse: bool) -> Mat4 {
-    if reverse {
+    let mat = if reverse {
         B_NONNORM_MAT_DAGGER * mat * B_NONNORM_MAT
     } else {
         B_NONNORM_MAT * mat * B_NONNORM_MAT_DAGGER
+    };
+    mat * c64!(0.5)
+}
+
+/// KAK decomposition of a two-qubit unitary, up to a global phase:
+///  U ~ (A0 ⊗ A1) exp(i(a XX + b YY + c ZZ)) (B0 ⊗ B1)
+/// where the first factor of each tensor product acts on the first target.
+pub struct KakDecomposition {
+    /// Local gates (B0, B1) applied before the interaction.
+    pub before: (Mat2, Mat2),
+    /// Interaction coefficients (a, b, c).
+    pub interaction: (f64, f64, f64),
+    /// Local gates (A0, A1) applied after the interaction.
+    pub after: (Mat2, Mat2),
+}
+
+/// Compute the KAK decomposition of a two-qubit unitary.
+///
+/// reference: https://arxiv.org/pdf/quant-ph/0308006.pdf
+pub fn kak(mat: &Mat4) -> KakDecomposition {
+    let phase = mat.determinant().arg() / 4.0;
+    let u = transform_magic_basis(mat * Complex64::from_polar(1.0, -phase), true);
+    // M2 = Uᵀ U is symmetric and unitary, so its real and imaginary parts are commuting real
+    // symmetric matrices, which are diagonalized by a real orthogonal matrix P.
+    let m2 = u.transpose() * u;
+    let (p, d) = (0 .. 16).find_map(|i| {
+        let (sin, cos) = (0.5 + 0.37 * i as f64).sin_cos();
+        let combination = m2.map(|value| c64!(value.re * cos + value.im * sin));
+        let combination = DMat::from_column_slice(4, 4, combination.as_slice());
+        let (_, eigenvectors) = hermitian_eigen(&combination);
+        let mut p = Matrix4::from_fn(|row, col| eigenvectors[(row, col)].re);
+        if p.determinant() < 0.0 {
+            p.column_mut(3).neg_mut();
+        }
+        let p = p.map(|value| c64!(value));
+        let d = p.transpose() * m2 * p;
+        let is_diagonal = (0 .. 16).all(|i| i % 5 == 0 || d[i].norm() < 1e-9);
+        is_diagonal.then(|| (p, d))
+    }).expect("Unitary matrix in the magic basis is not diagonalizable");
+    // D = exp(2iθ), with the θ summing to 0 so that K1 is special orthogonal
+    let mut theta = [0.0; 4];
+    for i in 0 .. 3 {
+        theta[i] = -d[(i, i)].arg() / 2.0;
+    }
+    theta[3] = -theta[0] - theta[1] - theta[2];
+    let exp_theta = Mat4::from_diagonal(&theta.map(|t| Complex64::from_polar(1.0, t)).into());
+    let k1 = transform_magic_basis(u * p * exp_theta, false);
+    let k2 = transform_magic_basis(p.transpose(), false);
+    KakDecomposition {
+        before: local_decompose(&k2),
+        interaction: (
+            (theta[1] + theta[3]) / 2.0,
+            (theta[0] + theta[3]) / 2.0,
+            (theta[2] + theta[3]) / 2.0,
+        ),
+        after: local_decompose(&k1),
     }
 }
 
-pub fn kak_decompose(operation: impl DoubleTargetOperation) -> Vec<ElementaryOperation> {
-    // TODO: consider global phase
-    // reference: https://github.com/qclib/qclib/blob/7ddfb77c67c7b8f4d1f3d612b4e93a9206754cd3/qclib/decompose2q.py#L146
-    let u = su::<4>(&operation.to_mat4());
-    todo!()
+/// Decompose a special unitary product gate K = K0 ⊗ K1 into its factors (K0, K1).
+fn local_decompose(mat: &Mat4) -> (Mat2, Mat2) {
+    // Each block of K is a multiple of K1, take a block which is not too close to zero.
+    let (row, col) = if mat.fixed_slice::<2, 2>(0, 0).determinant().norm() < 0.1 {
+        (2, 0)
+    } else {
+        (0, 0)
+    };
+    let k1: Mat2 = mat.fixed_slice::<2, 2>(row, col).into();
+    let k1 = k1 / k1.determinant().sqrt();
+    let k0 = Mat2::from_fn(|r, c| {
+        let block: Mat2 = mat.fixed_slice::<2, 2>(2 * r, 2 * c).into();
+        (block * k1.adjoint()).trace() / c64!(2)
+    });
+    let k0 = k0 / k0.determinant().sqrt();
+    (k0, k1)
+}
+
+/// Decompose a two-qubit gate into at most three CX and single-qubit gates, up to a global
+/// phase.
+pub fn kak_decompose(operation: &impl DoubleTargetOperation) -> Vec<ElementaryOperation> {
+    let (target0, target1) = operation.get_target();
+    let KakDecomposition { before, interaction: (a, b, c), after } = kak(&operation.to_mat4());
+    let local = |(mat0, mat1): (Mat2, Mat2)| -> Vec<ElementaryOperation> {
+        vec![
+            UnitarySingleOperation::from_mat(mat0, target0).into(),
+            UnitarySingleOperation::from_mat(mat1, target1).into(),
+        ]
+    };
+    let interaction: Vec<ElementaryOperation> = if [a, b, c].iter().all(|&k| close_to_zero(k)) {
+        vec![/* product gate */]
+    } else {
+        // exp(i(a XX + b YY + c ZZ)), see https://arxiv.org/pdf/quant-ph/0308006.pdf
+        vec![
+            RZ { angle: FRAC_PI_2 }.apply_to(target1).into(),
+            CX.apply_to((target1, target0)).into(),
+            RZ { angle: FRAC_PI_2 - 2.0 * c }.apply_to(target0).into(),
+            RY { angle: FRAC_PI_2 - 2.0 * a }.apply_to(target1).into(),
+            CX.apply_to((target0, target1)).into(),
+            RY { angle: 2.0 * b - FRAC_PI_2 }.apply_to(target1).into(),
+            CX.apply_to((target1, target0)).into(),
+            RZ { angle: -FRAC_PI_2 }.apply_to(target0).into(),
+        ]
+    };
+    local(before).into_iter().chain(interaction).chain(local(after)).collect()
 }
 
 /// Decompose a 2-qubit unitary composed of two 1-qubit gates.
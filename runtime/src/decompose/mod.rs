@@ -1,9 +1,8 @@
 pub mod decomposer;
 pub mod single;
 pub mod double;
-pub mod multi;
+pub mod standard;
 pub mod shannon;
-pub mod cos_sin;
 pub mod multiplex;
 
 #[cfg(test)]
@@ -0,0 +1,38 @@
+use crate::gate::{DoubleTargetGate, SingleTargetGate};
+use crate::gate::rotation::Rotation;
+use crate::gate::standard::StandardDoubleGate::CX;
+use crate::gate::standard::StandardSingleGate;
+use crate::operation::elementary::ElementaryOperation;
+use crate::qubit::QubitAddr;
+
+/// Decompose a multiplexed rotation into 2^k rotations and 2^k CX, where k is the number of
+/// select qubits.
+///
+/// The rotation of `angles[j]` is applied to the target if the select qubits are in the state
+/// |j⟩, where the first select qubit is the most significant one.
+/// reference: https://arxiv.org/pdf/quant-ph/0407010.pdf
+pub fn mux_rotation_decompose(
+    rotation: fn(f64) -> Rotation, angles: &[f64], select: &[QubitAddr], target: QubitAddr,
+) -> Vec<ElementaryOperation> {
+    assert_eq!(angles.len(), 1 << select.len());
+    let rotate = |angle| -> ElementaryOperation {
+        let gate: StandardSingleGate = rotation(angle).into();
+        gate.apply_to(target).into()
+    };
+    if select.is_empty() {
+        return vec![rotate(angles[0])];
+    }
+    // Each CX flips the sign of the next rotations. Before the i-th rotation, the flipped select
+    // qubits are the bits of the Gray code g(i), so that
+    //  θ_i = 1/2^k Σ_j (-1)^(j·g(i)) α_j
+    let gray_code = |i: usize| i ^ (i >> 1);
+    let dim = angles.len();
+    (0 .. dim).flat_map(|i| {
+        let angle = angles.iter().enumerate().map(|(j, &angle)| {
+            if (j & gray_code(i)).count_ones() % 2 == 0 { angle } else { -angle }
+        }).sum::<f64>() / dim as f64;
+        let flipped_bit = (gray_code(i) ^ gray_code((i + 1) % dim)).trailing_zeros() as usize;
+        let ctrl = select[select.len() - 1 - flipped_bit];
+        [rotate(angle), CX.apply_to((ctrl, target)).into()]
+    }).collect()
+}
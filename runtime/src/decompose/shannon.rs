@@ -0,0 +1,56 @@
+use crate::algebra::quantum_shannon::QuantumShannonDecomposition;
+use crate::algebra::{DMat, Mat2, Mat4};
+use crate::decompose::double::kak_decompose;
+use crate::decompose::multiplex::mux_rotation_decompose;
+use crate::gate::DoubleTargetGate;
+use crate::gate::rotation::Rotation;
+use crate::gate::unitary::UnitaryDoubleGate;
+use crate::operation::elementary::ElementaryOperation;
+use crate::operation::elementary::unitary::UnitarySingleOperation;
+use crate::qubit::QubitAddr;
+
+/// Decompose a unitary into CX and single-qubit gates with Quantum Shannon Decomposition, up to
+/// a global phase.
+///
+/// The first target is the most significant qubit of the matrix. Two-qubit unitaries are
+/// decomposed with KAK decomposition, so that the result has at most [`shannon_cx_count`] CX.
+/// reference: https://arxiv.org/pdf/quant-ph/0406176.pdf
+pub fn shannon_decompose(mat: &DMat, target: &[QubitAddr]) -> Vec<ElementaryOperation> {
+    assert!(mat.is_square());
+    assert_eq!(mat.nrows(), 1 << target.len());
+    match target {
+        [] => vec![],
+        &[target] => {
+            let mat = Mat2::from_column_slice(mat.as_slice());
+            vec![UnitarySingleOperation::from_mat(mat, target).into()]
+        }
+        &[target0, target1] => {
+            let mat = Mat4::from_column_slice(mat.as_slice());
+            kak_decompose(&UnitaryDoubleGate(Box::new(mat)).apply_to((target0, target1)))
+        }
+        [first, others @ ..] => {
+            let QuantumShannonDecomposition {
+                u1_mat, ucrz1_angles, u2_mat, ucry_angles, u3_mat, ucrz2_angles, u4_mat
+            } = QuantumShannonDecomposition::new(mat);
+            [
+                shannon_decompose(&u4_mat, others),
+                mux_rotation_decompose(Rotation::Rz, &ucrz2_angles, others, *first),
+                shannon_decompose(&u3_mat, others),
+                mux_rotation_decompose(Rotation::Ry, &ucry_angles, others, *first),
+                shannon_decompose(&u2_mat, others),
+                mux_rotation_decompose(Rotation::Rz, &ucrz1_angles, others, *first),
+                shannon_decompose(&u1_mat, others),
+            ].concat()
+        }
+    }
+}
+
+/// The maximal number of CX of the Quantum Shannon Decomposition of an n-qubit unitary:
+///  c(n) = 4 c(n - 1) + 3 · 2^(n - 1), c(2) = 3
+pub fn shannon_cx_count(size: usize) -> usize {
+    match size {
+        0 | 1 => 0,
+        2 => 3,
+        _ => 4 * shannon_cx_count(size - 1) + 3 * (1 << (size - 1)),
+    }
+}
@@ -0,0 +1,111 @@
+use std::f64::consts::PI;
+use crate::gate::{DoubleTargetGate, SingleTargetGate};
+use crate::gate::standard::StandardDoubleGate::{CX, CZ, ISWP};
+use crate::gate::standard::StandardSingleGate::{self, H, P, RX, RZ, S, T, TD};
+use crate::operation::elementary::ElementaryOperation;
+use crate::operation::{ElementaryGateOperation, TargetDouble, TargetTriple};
+use crate::qubit::QubitAddr;
+
+fn single(gate: StandardSingleGate, target: QubitAddr) -> ElementaryOperation {
+    gate.apply_to(target).into()
+}
+
+/// Replace every CX of the decomposition with H CZ H on its target.
+fn cx_through_cz(decomposition: Vec<ElementaryOperation>) -> Vec<ElementaryOperation> {
+    decomposition.into_iter().flat_map(|op| if op.get_ident() == "CX" {
+        let (ctrl, target) = (op.get_target()[0], op.get_target()[1]);
+        vec![single(H, target), CZ.apply_to((ctrl, target)).into(), single(H, target)]
+    } else {
+        vec![op]
+    }).collect()
+}
+
+/// CX = H(1) CZ H(1)
+pub fn cx_to_cz(target: TargetDouble) -> Vec<ElementaryOperation> {
+    cx_through_cz(vec![CX.apply_to(target).into()])
+}
+
+/// CZ = H(1) CX H(1)
+pub fn cz_to_cx(target: TargetDouble) -> Vec<ElementaryOperation> {
+    vec![single(H, target.1), CX.apply_to(target).into(), single(H, target.1)]
+}
+
+/// CX with two ISWP, up to a global phase.
+pub fn cx_to_iswp((ctrl, target): TargetDouble) -> Vec<ElementaryOperation> {
+    vec![
+        single(RZ { angle: -PI / 2.0 }, ctrl),
+        single(RX { angle: PI / 2.0 }, target),
+        single(RZ { angle: PI / 2.0 }, target),
+        ISWP.apply_to((ctrl, target)).into(),
+        single(RX { angle: PI / 2.0 }, ctrl),
+        ISWP.apply_to((ctrl, target)).into(),
+        single(RZ { angle: PI / 2.0 }, target),
+    ]
+}
+
+/// CZ with two ISWP, up to a global phase.
+pub fn cz_to_iswp(target: TargetDouble) -> Vec<ElementaryOperation> {
+    let mut decomposition = vec![single(H, target.1)];
+    decomposition.extend(cx_to_iswp(target));
+    decomposition.push(single(H, target.1));
+    decomposition
+}
+
+/// ISWP = S(0) S(1) H(0) CX(0, 1) CX(1, 0) H(1)
+pub fn iswp_to_cx((first, second): TargetDouble) -> Vec<ElementaryOperation> {
+    vec![
+        single(S, first),
+        single(S, second),
+        single(H, first),
+        CX.apply_to((first, second)).into(),
+        CX.apply_to((second, first)).into(),
+        single(H, second),
+    ]
+}
+
+pub fn iswp_to_cz(target: TargetDouble) -> Vec<ElementaryOperation> {
+    cx_through_cz(iswp_to_cx(target))
+}
+
+/// SWP = CX(0, 1) CX(1, 0) CX(0, 1)
+pub fn swp_to_cx((first, second): TargetDouble) -> Vec<ElementaryOperation> {
+    vec![
+        CX.apply_to((first, second)).into(),
+        CX.apply_to((second, first)).into(),
+        CX.apply_to((first, second)).into(),
+    ]
+}
+
+/// CP(λ) = P(λ/2)(0) CX P(-λ/2)(1) CX P(λ/2)(1)
+pub fn cp_to_cx(angle: f64, (ctrl, target): TargetDouble) -> Vec<ElementaryOperation> {
+    vec![
+        single(P { angle: angle / 2.0 }, ctrl),
+        CX.apply_to((ctrl, target)).into(),
+        single(P { angle: -angle / 2.0 }, target),
+        CX.apply_to((ctrl, target)).into(),
+        single(P { angle: angle / 2.0 }, target),
+    ]
+}
+
+/// Toffoli gate with 6 CX and 7 T gates.
+///
+/// reference: https://arxiv.org/pdf/0803.2316.pdf
+pub fn ccx_to_cx((first, second, target): TargetTriple) -> Vec<ElementaryOperation> {
+    vec![
+        single(H, target),
+        CX.apply_to((second, target)).into(),
+        single(TD, target),
+        CX.apply_to((first, target)).into(),
+        single(T, target),
+        CX.apply_to((second, target)).into(),
+        single(TD, target),
+        CX.apply_to((first, target)).into(),
+        single(T, second),
+        single(T, target),
+        single(H, target),
+        CX.apply_to((first, second)).into(),
+        single(T, first),
+        single(TD, second),
+        CX.apply_to((first, second)).into(),
+    ]
+}
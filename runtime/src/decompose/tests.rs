@@ -1,7 +1,28 @@
+use std::f64::consts::PI;
 use num::complex::Complex64;
 use num_traits::abs;
-use crate::algebra::Mat2;
+use rand::Rng;
+use crate::{c64, mat4};
+use crate::algebra::{DMat, Mat2, Mat4, ToMat2, ToMat4, ToMat8};
+use crate::decompose::double::{kak, kak_decompose, KakDecomposition};
+use crate::decompose::multiplex::mux_rotation_decompose;
+use crate::decompose::shannon::{shannon_cx_count, shannon_decompose};
 use crate::decompose::single::{EulerDecomposition, zyz_decompose};
+use crate::decompose::standard::{
+    ccx_to_cx, cp_to_cx, cx_to_cz, cx_to_iswp, cz_to_cx, cz_to_iswp, iswp_to_cx, iswp_to_cz,
+    swp_to_cx,
+};
+use crate::backend::gate_set::GateSet;
+use crate::decompose::decomposer::builder::ElementaryDecomposerBuilder;
+use crate::gate::{DoubleTargetGate, TripleTargetGate};
+use crate::gate::canonical::CanonicalGate;
+use crate::gate::rotation::Rotation;
+use crate::gate::standard::{StandardDoubleGate, StandardSingleGate, StandardTripleGate};
+use crate::gate::unitary::UnitaryDoubleGate;
+use crate::operation::ElementaryGateOperation;
+use crate::operation::elementary::{ElementaryOperation, SingleOperation};
+use crate::operation::elementary::standard::StandardOperation;
+use crate::qubit::QubitAddr;
 
 macro_rules! zyz_test {
     {
@@ -57,3 +78,209 @@ fn test_zyz_2() {
     }
 }
 
+/// Sample a Haar-random unitary from the QR decomposition of a complex Ginibre matrix.
+fn random_unitary(rng: &mut impl Rng, dim: usize) -> DMat {
+    let mut gaussian = || {
+        // Box-Muller transform
+        let (u1, u2): (f64, f64) = (1.0 - rng.gen::<f64>(), rng.gen());
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    };
+    let qr = DMat::from_fn(dim, dim, |_, _| Complex64::new(gaussian(), gaussian())).qr();
+    let (q, r) = (qr.q(), qr.r());
+    DMat::from_fn(dim, dim, |row, col| q[(row, col)] * r[(col, col)] / r[(col, col)].norm())
+}
+
+/// Whether `actual` is equal to `expected` up to a global phase.
+fn equal_up_to_phase(actual: &DMat, expected: &DMat) -> bool {
+    let index = expected.icamax_full();
+    let phase = actual[index] / expected[index];
+    abs(phase.norm() - 1.0) < 1e-8 && (actual - expected * phase).iter().all(|x| x.norm() < 1e-8)
+}
+
+fn swap_mat() -> DMat {
+    let swap: Mat4 = mat4! {
+        1f64, 0f64, 0f64, 0f64;
+        0f64, 0f64, 1f64, 0f64;
+        0f64, 1f64, 0f64, 0f64;
+        0f64, 0f64, 0f64, 1f64;
+    };
+    DMat::from_column_slice(4, 4, swap.as_slice())
+}
+
+/// Matrix of CX and single-qubit operations on the qubits `0 .. size`, where the qubit 0 is the
+/// most significant one.
+fn operations_mat(operations: &[ElementaryOperation], size: usize) -> DMat {
+    let dim = 1 << size;
+    operations.iter().fold(DMat::identity(dim, dim), |product, op| {
+        let gate_mat = match op {
+            ElementaryOperation::Standard(StandardOperation::Double(op)) => {
+                DMat::from_column_slice(4, 4, op.to_mat4().as_slice())
+            }
+            ElementaryOperation::Standard(StandardOperation::Triple(op)) => {
+                DMat::from_column_slice(8, 8, op.to_mat8().as_slice())
+            }
+            _ => {
+                let single = SingleOperation::try_from(op.clone()).unwrap().to_mat2();
+                DMat::from_column_slice(2, 2, single.as_slice())
+            }
+        };
+        expand_mat(&gate_mat, &op.get_target().to_vec(), size) * product
+    })
+}
+
+/// Matrix of a gate acting on `target` among `size` qubits.
+fn expand_mat(mat: &DMat, target: &[QubitAddr], size: usize) -> DMat {
+    let dim = 1 << size;
+    let bit = |qubit: QubitAddr| 1 << (size - 1 - qubit as usize);
+    let mask = target.iter().fold(0, |mask, &qubit| mask | bit(qubit));
+    // index of the basis state in the space of the targets, the first target is the most
+    // significant one
+    let target_index = |index: usize| target.iter().fold(0, |acc, &qubit| {
+        (acc << 1) | usize::from(index & bit(qubit) != 0)
+    });
+    DMat::from_fn(dim, dim, |row, col| if row & !mask == col & !mask {
+        mat[(target_index(row), target_index(col))]
+    } else {
+        c64!(0)
+    })
+}
+
+fn count_cx(operations: &[ElementaryOperation]) -> usize {
+    operations.iter().filter(|op| op.get_ident() == "CX").count()
+}
+
+#[test]
+fn test_kak() {
+    let mut rng = rand::thread_rng();
+    for _ in 0 .. 100 {
+        let mat = random_unitary(&mut rng, 4);
+        let KakDecomposition { before, interaction: (a, b, c), after } = kak(
+            &Mat4::from_column_slice(mat.as_slice())
+        );
+        // exp(i(a XX + b YY + c ZZ)) = CAN(-2a/π, -2b/π, -2c/π)
+        let interaction = CanonicalGate::new(-2.0 * a / PI, -2.0 * b / PI, -2.0 * c / PI).to_mat4();
+        let product = after.0.kronecker(&after.1) * interaction * before.0.kronecker(&before.1);
+        assert!(equal_up_to_phase(&DMat::from_column_slice(4, 4, product.as_slice()), &mat));
+    }
+}
+
+#[test]
+fn test_kak_decompose() {
+    let mut rng = rand::thread_rng();
+    let mats = (0 .. 100).map(|_| random_unitary(&mut rng, 4))
+        .chain([DMat::identity(4, 4), swap_mat()]);
+    for mat in mats {
+        let gate = UnitaryDoubleGate(Box::new(Mat4::from_column_slice(mat.as_slice())));
+        let operations = kak_decompose(&gate.apply_to((0, 1)));
+        assert!(count_cx(&operations) <= 3);
+        assert!(equal_up_to_phase(&operations_mat(&operations, 2), &mat));
+    }
+}
+
+#[test]
+fn test_mux_rotation_decompose() {
+    let mut rng = rand::thread_rng();
+    for select_size in 0 .. 4 {
+        let half = 1 << select_size;
+        let angles: Vec<f64> = (0 .. half).map(|_| rng.gen_range(-PI .. PI)).collect();
+        let select: Vec<QubitAddr> = (1 ..= select_size).collect();
+        let operations = mux_rotation_decompose(Rotation::Ry, &angles, &select, 0);
+        assert_eq!(count_cx(&operations), if select_size == 0 { 0 } else { half });
+        // the rotation on the qubit 0 is selected by the other qubits
+        let mut expected = DMat::zeros(2 * half, 2 * half);
+        for (j, &angle) in angles.iter().enumerate() {
+            let ry = StandardSingleGate::RY { angle }.to_mat2();
+            for (row, col) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                expected[(row * half + j, col * half + j)] = ry[(row, col)];
+            }
+        }
+        let actual = operations_mat(&operations, select_size as usize + 1);
+        assert!((actual - expected).iter().all(|x| x.norm() < 1e-8));
+    }
+}
+
+#[test]
+fn test_shannon_decompose() {
+    let mut rng = rand::thread_rng();
+    for size in [3, 4] {
+        let target: Vec<QubitAddr> = (0 .. size as QubitAddr).collect();
+        for _ in 0 .. 10 {
+            let mat = random_unitary(&mut rng, 1 << size);
+            let operations = shannon_decompose(&mat, &target);
+            assert!(count_cx(&operations) <= shannon_cx_count(size));
+            assert!(equal_up_to_phase(&operations_mat(&operations, size), &mat));
+        }
+        let ident = DMat::identity(1 << size, 1 << size);
+        let operations = shannon_decompose(&ident, &target);
+        assert!(equal_up_to_phase(&operations_mat(&operations, size), &ident));
+    }
+}
+
+fn standard_double_mat(gate: StandardDoubleGate) -> DMat {
+    DMat::from_column_slice(4, 4, gate.to_mat4().as_slice())
+}
+
+#[test]
+fn test_standard_decompose() {
+    use StandardDoubleGate::*;
+    let cases: [(DMat, Vec<ElementaryOperation>); 8] = [
+        (standard_double_mat(CX), cx_to_cz((0, 1))),
+        (standard_double_mat(CX), cx_to_iswp((0, 1))),
+        (standard_double_mat(CZ), cz_to_cx((0, 1))),
+        (standard_double_mat(CZ), cz_to_iswp((0, 1))),
+        (standard_double_mat(ISWP), iswp_to_cx((0, 1))),
+        (standard_double_mat(ISWP), iswp_to_cz((0, 1))),
+        (standard_double_mat(SWP), swp_to_cx((0, 1))),
+        (standard_double_mat(CP { angle: 0.73 }), cp_to_cx(0.73, (0, 1))),
+    ];
+    for (mat, operations) in cases {
+        assert!(equal_up_to_phase(&operations_mat(&operations, 2), &mat));
+    }
+    let ccx = StandardTripleGate::CCX.to_mat8();
+    let operations = ccx_to_cx((0, 1, 2));
+    assert_eq!(count_cx(&operations), 6);
+    assert!(equal_up_to_phase(
+        &operations_mat(&operations, 3), &DMat::from_column_slice(8, 8, ccx.as_slice())
+    ));
+}
+
+#[test]
+fn test_gate_set_decomposer() {
+    let gate_set = GateSet::parse("H 1\nRX 1\nRY 1\nRZ 1\nP 1\nISWP 2 5 0.01\n").unwrap();
+    let mut decomposer = ElementaryDecomposerBuilder::with_gate_set(gate_set.clone()).build();
+    let operations: [ElementaryOperation; 3] = [
+        StandardDoubleGate::CX.apply_to((0, 1)).into(),
+        StandardDoubleGate::CP { angle: 1.2 }.apply_to((1, 2)).into(),
+        StandardTripleGate::CCX.apply_to((0, 1, 2)).into(),
+    ];
+    for op in operations {
+        let decomposed = decomposer.decompose(&op).unwrap();
+        assert!(decomposed.iter().all(|op| gate_set.contains(&op.get_ident())));
+        assert!(equal_up_to_phase(&operations_mat(&decomposed, 3), &operations_mat(&[op], 3)));
+    }
+}
+
+#[test]
+fn test_cheapest_native_route() {
+    let explain = |gate_set: &str, gate_ident: &str| -> Vec<String> {
+        let gate_set = GateSet::parse(gate_set).unwrap();
+        let decomposer = ElementaryDecomposerBuilder::with_gate_set(gate_set).build();
+        decomposer.explain_decomposition(gate_ident).unwrap().iter()
+            .map(|step| step.to_string())
+            .collect()
+    };
+    let singles = "H 1\nRX 1\nRY 1\nRZ 1\n";
+    assert_eq!(
+        explain(&format!("{}CZ 2 10\nISWP 2 2\n", singles), "CX"),
+        ["CX => [ISWP, 2*RX, 3*RZ] : 0 (total 9)"]
+    );
+    assert_eq!(
+        explain(&format!("{}CZ 2 1\nISWP 2 2\n", singles), "CX"),
+        ["CX => [CZ, 2*H] : 0 (total 3)"]
+    );
+    assert_eq!(
+        explain(&format!("{}CZ 2 1\n", singles), "SWP"),
+        ["SWP => [3*CX] : 0 (total 9)", "CX => [CZ, 2*H] : 0 (total 3)"]
+    );
+    assert_eq!(explain(&format!("{}CZ 2 1\n", singles), "CZ"), Vec::<String>::new());
+}
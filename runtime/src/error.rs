@@ -0,0 +1,177 @@
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use crate::backend::BackendError;
+use crate::backend::coupling_map::CouplingMapError;
+use crate::backend::gate_set::GateSetError;
+use crate::bytecode::asm::AsmError;
+use crate::decompose::decomposer::DecomposeError;
+use crate::qasm::QasmError;
+
+/// Recoverable error of the runtime.
+///
+/// Every error has a status code reported through the C API by [`code`](Self::code), the
+/// message of the last error of the thread is available from `qivm_last_error`.
+#[derive(Clone)]
+pub enum QivmError {
+    Backend(BackendError),
+    Execution(u8),
+    NullPointer(&'static str),
+    InvalidArgument(String),
+    UnsupportedGate(String),
+    ControlledTarget,
+    StackUnderflow(&'static str),
+    EmptyQuantumStack,
+    ActiveControl,
+    TargetSizeMismatch { expected: usize, actual: usize },
+    UnavailableGate(String),
+    Decompose(DecomposeError),
+    InvalidDecomposition(String),
+    Compile(String),
+    Qasm(QasmError),
+    Asm(AsmError),
+    MeasurementUnavailable,
+    BufferTooSmall { required: usize, size: usize },
+    NonUnitaryGate(String),
+    UncontrollableGate(String),
+    UnloweredGates(Vec<String>),
+    InvariantViolation { pass: String, reason: String },
+    GateSet(GateSetError),
+    CouplingMap(CouplingMapError),
+}
+
+impl QivmError {
+    /// Status code reported through the C API.
+    ///
+    /// Codes `1` to `3` are the ones of [`BackendError::code`].
+    pub fn code(&self) -> u8 {
+        use QivmError::*;
+        match self {
+            Backend(err) => err.code(),
+            Execution(_) => 4,
+            NullPointer(_) => 5,
+            InvalidArgument(_) => 6,
+            UnsupportedGate(_) => 7,
+            ControlledTarget => 8,
+            StackUnderflow(_) => 9,
+            EmptyQuantumStack => 10,
+            ActiveControl => 11,
+            TargetSizeMismatch { .. } => 12,
+            UnavailableGate(_) => 13,
+            Decompose(_) => 14,
+            InvalidDecomposition(_) => 15,
+            Compile(_) => 16,
+            Qasm(_) => 17,
+            Asm(_) => 18,
+            MeasurementUnavailable => 19,
+            BufferTooSmall { .. } => 20,
+            NonUnitaryGate(_) => 21,
+            UncontrollableGate(_) => 22,
+            UnloweredGates(_) => 23,
+            InvariantViolation { .. } => 24,
+            GateSet(_) => 25,
+            CouplingMap(_) => 26,
+        }
+    }
+}
+
+impl Debug for QivmError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for QivmError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        use QivmError::*;
+        match self {
+            Backend(err) => Display::fmt(err, formatter),
+            Execution(code) => {
+                write!(formatter, "Backend failed to execute the program with error code {}", code)
+            }
+            NullPointer(name) => write!(formatter, "Invalid {}: null pointer", name),
+            InvalidArgument(message) => write!(formatter, "Invalid argument: {}", message),
+            UnsupportedGate(ident) => write!(formatter, "Unsupported standard gate: {}", ident),
+            ControlledTarget => write!(formatter, "Invalid operation: target qubit is controlled"),
+            StackUnderflow(stack) => {
+                write!(formatter, "Invalid operation: {} stack underflow", stack)
+            }
+            EmptyQuantumStack => write!(formatter, "Invalid allocation: quantum stack is empty"),
+            ActiveControl => write!(
+                formatter, "Invalid restore_ctrl operation: current control qubits are not empty"
+            ),
+            TargetSizeMismatch { expected, actual } => write!(
+                formatter, "Invalid target size, expected: {}, actual: {}", expected, actual
+            ),
+            UnavailableGate(ident) => {
+                write!(formatter, "Invalid primitive gate `{}` on target platform", ident)
+            }
+            Decompose(err) => Display::fmt(err, formatter),
+            InvalidDecomposition(message) => {
+                write!(formatter, "Invalid decomposition: {}", message)
+            }
+            Compile(message) => write!(formatter, "Compile error: {}", message),
+            Qasm(err) => Display::fmt(err, formatter),
+            Asm(err) => Display::fmt(err, formatter),
+            MeasurementUnavailable => write!(formatter, "Measurement result is not available"),
+            BufferTooSmall { required, size } => write!(
+                formatter, "Measurement result buffer is too small, required: {}, size: {}",
+                required, size
+            ),
+            NonUnitaryGate(ident) => {
+                write!(formatter, "Invalid custom gate `{}`: matrix is not unitary", ident)
+            }
+            UncontrollableGate(ident) => write!(
+                formatter, "Invalid operation: built-in gate `{}` cannot be controlled", ident
+            ),
+            UnloweredGates(idents) => write!(
+                formatter, "Unable to lower gates onto the target platform: `{}`",
+                idents.join("`, `")
+            ),
+            InvariantViolation { pass, reason } => {
+                write!(formatter, "Invariant violated after pass `{}`: {}", pass, reason)
+            }
+            GateSet(err) => Display::fmt(err, formatter),
+            CouplingMap(err) => Display::fmt(err, formatter),
+        }
+    }
+}
+
+impl Error for QivmError {}
+
+impl From<BackendError> for QivmError {
+    fn from(err: BackendError) -> Self {
+        QivmError::Backend(err)
+    }
+}
+
+impl From<DecomposeError> for QivmError {
+    fn from(err: DecomposeError) -> Self {
+        QivmError::Decompose(err)
+    }
+}
+
+impl From<QasmError> for QivmError {
+    fn from(err: QasmError) -> Self {
+        QivmError::Qasm(err)
+    }
+}
+
+impl From<AsmError> for QivmError {
+    fn from(err: AsmError) -> Self {
+        QivmError::Asm(err)
+    }
+}
+
+impl From<GateSetError> for QivmError {
+    fn from(err: GateSetError) -> Self {
+        QivmError::GateSet(err)
+    }
+}
+
+impl From<CouplingMapError> for QivmError {
+    fn from(err: CouplingMapError) -> Self {
+        QivmError::CouplingMap(err)
+    }
+}
+
+pub type QivmResult<T> = Result<T, QivmError>;
@@ -7,6 +7,8 @@ use crate::gate::unitary::UnitaryDoubleGate;
 use crate::operation::TargetDouble;
 use crate::operation::elementary::canonical::CanonicalOperation;
 
+pub const CANONICAL_IDENT: &str = "CAN";
+
 #[derive(Clone, Debug)]
 pub struct CanonicalGate {
     pub tx: f64,
@@ -59,7 +61,7 @@ impl DoubleTargetGate for CanonicalGate {
     type Operation = CanonicalOperation;
 
     fn ident(&self) -> String {
-        String::from("CAN")
+        String::from(CANONICAL_IDENT)
     }
 
     fn apply_to(self, target: TargetDouble) -> Self::Operation {
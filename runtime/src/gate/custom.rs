@@ -1,8 +1,8 @@
 use crate::gate::{Dagger, DoubleTargetGate, DynamicTargetGate, IntoUnitary, SingleTargetGate};
-use crate::algebra::{GateMat, Mat2, Mat4, ToMat, ToMat2, ToMat4};
-use crate::operation::elementary::custom::{CustomDoubleOperation, CustomDynamicOperation, CustomOperation, CustomSingleOperation};
+use crate::algebra::{DMat, GateMat, Mat2, Mat4, ToMat, ToMat2, ToMat4};
+use crate::operation::elementary::custom::{CustomBuiltinOperation, CustomDoubleOperation, CustomDynamicOperation, CustomOperation, CustomSingleOperation};
 use crate::operation::{TargetDouble, TargetMultiple, TargetSingle};
-use crate::{dispatch, into_variant, raise_error};
+use crate::{dispatch, into_variant};
 use crate::gate::elementary::ElementaryGate;
 use crate::gate::unitary::{UnitaryDoubleGate, UnitaryDynamicGate, UnitarySingleGate};
 
@@ -11,54 +11,56 @@ pub enum CustomGate {
     Single(CustomSingleGate),
     Double(CustomDoubleGate),
     Dynamic(CustomDynamicGate),
+    Builtin(CustomBuiltinGate),
 }
 
 impl CustomGate {
+    /// Create the custom gate of the matrix, whose dimension is a power of two.
+    pub fn new(ident: String, mat: DMat, params: Vec<u64>) -> Self {
+        match mat.nrows() {
+            2 => {
+                CustomSingleGate::new(ident, Mat2::from_column_slice(mat.as_slice()), params).into()
+            }
+            4 => {
+                CustomDoubleGate::new(ident, Mat4::from_column_slice(mat.as_slice()), params).into()
+            }
+            dim => {
+                let size = dim.trailing_zeros() as usize;
+                CustomDynamicGate::new(ident, GateMat::dynamic(mat), size, params).into()
+            }
+        }
+    }
+
     pub fn size(&self) -> usize {
         match self {
             CustomGate::Single(_) => 1usize,
             CustomGate::Double(_) => 2usize,
             CustomGate::Dynamic(gate) => gate.size,
+            CustomGate::Builtin(gate) => gate.size,
         }
     }
 
     pub fn ident(&self) -> String {
         use CustomGate::*;
-        dispatch!(self; Single | Dynamic => |gate| gate.ident())
+        dispatch!(self; Single | Double | Dynamic | Builtin => |gate| gate.ident())
     }
 
     pub fn get_params(&self) -> &Vec<u64> {
         use CustomGate::*;
-        dispatch!(self; Single | Dynamic => |gate| gate.get_params())
+        dispatch!(self; Single | Double | Dynamic | Builtin => |gate| gate.get_params())
     }
-}
 
-impl ToMat for CustomGate {
-    fn to_mat(&self) -> GateMat {
+    /// Matrix of the gate, `None` for a built-in gate whose matrix is unknown to the runtime.
+    pub fn matrix(&self) -> Option<GateMat> {
         match self {
-            CustomGate::Single(gate) => gate.to_mat2().into(),
-            CustomGate::Double(gate) => gate.to_mat4().into(),
-            CustomGate::Dynamic(gate) => gate.to_mat(),
+            CustomGate::Single(gate) => Some(gate.to_mat2().into()),
+            CustomGate::Double(gate) => Some(gate.to_mat4().into()),
+            CustomGate::Dynamic(gate) => Some(gate.to_mat()),
+            CustomGate::Builtin(_) => None,
         }
     }
-}
-
-impl Dagger for CustomGate {
-    fn dagger(self) -> Self {
-        use CustomGate::*;
-        dispatch!(self; Single | Dynamic => |gate| gate.dagger().into())
-    }
-}
 
-impl IntoUnitary<UnitaryDynamicGate> for CustomGate {
-    fn into_unitary(self) -> UnitaryDynamicGate {
-        UnitaryDynamicGate::new(Box::new(self.to_mat().into()))
-    }
-}
-
-impl DynamicTargetGate for CustomGate {
-    type Operation = CustomOperation;
-    fn apply_to(self, target: TargetMultiple) -> Self::Operation {
+    pub fn apply_to(self, target: TargetMultiple) -> CustomOperation {
         match self {
             CustomGate::Single(gate) => {
                 CustomSingleOperation::new(gate, target[0]).into()
@@ -69,10 +71,20 @@ impl DynamicTargetGate for CustomGate {
             CustomGate::Dynamic(gate) => {
                 CustomDynamicOperation::new(gate, target).into()
             }
+            CustomGate::Builtin(gate) => {
+                CustomBuiltinOperation::new(gate, target).into()
+            }
         }
     }
 }
 
+impl Dagger for CustomGate {
+    fn dagger(self) -> Self {
+        use CustomGate::*;
+        dispatch!(self; Single | Double | Dynamic | Builtin => |gate| gate.dagger().into())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CustomSingleGate {
     ident: String,
@@ -231,8 +243,46 @@ impl Dagger for CustomDynamicGate {
     }
 }
 
+/// Gate implemented by the backend, whose matrix is unknown to the runtime.
+///
+/// The inverse of a built-in gate is the built-in gate whose identifier has the `D` suffix.
+#[derive(Clone, Debug)]
+pub struct CustomBuiltinGate {
+    ident: String,
+    size: usize,
+    params: Vec<u64>,
+}
+
+impl CustomBuiltinGate {
+    pub fn new(ident: String, size: usize, params: Vec<u64>) -> Self {
+        Self { ident, size, params }
+    }
+
+    pub fn ident(&self) -> String {
+        self.ident.clone()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get_params(&self) -> &Vec<u64> {
+        &self.params
+    }
+}
+
+impl Dagger for CustomBuiltinGate {
+    fn dagger(self) -> Self {
+        Self {
+            ident: format!("{}D", self.ident),
+            ..self
+        }
+    }
+}
+
 into_variant! {
     CustomSingleGate => CustomGate::Single => ElementaryGate::Custom;
     CustomDoubleGate => CustomGate::Double => ElementaryGate::Custom;
     CustomDynamicGate => CustomGate::Dynamic => ElementaryGate::Custom;
+    CustomBuiltinGate => CustomGate::Builtin => ElementaryGate::Custom;
 }
@@ -33,13 +33,27 @@ impl ElementaryGate {
             Standard(gate) => gate.size(),
             Canonical(_) => 2usize,
             Custom(gate) => gate.size(),
-            Unitary(gate) => todo!(),
+            Unitary(gate) => gate.size(),
         }
     }
 
     pub fn ident(&self) -> String {
         use ElementaryGate::*;
-        dispatch!(self; Standard | Canonical | Custom => |gate| gate.ident())
+        dispatch!(self; Standard | Unitary | Canonical | Custom => |gate| gate.ident())
+    }
+
+    /// Matrix of the gate, `None` for a built-in gate whose matrix is unknown to the runtime.
+    pub fn matrix(&self) -> Option<GateMat> {
+        match self {
+            ElementaryGate::Standard(gate) => Some(gate.to_mat()),
+            ElementaryGate::Canonical(gate) => Some(gate.to_mat()),
+            ElementaryGate::Custom(gate) => gate.matrix(),
+            ElementaryGate::Unitary(UnitaryGate::Single(gate)) => Some(gate.to_mat2().into()),
+            ElementaryGate::Unitary(UnitaryGate::Double(gate)) => Some((*gate.0).into()),
+            ElementaryGate::Unitary(UnitaryGate::Dynamic(gate)) => {
+                Some(GateMat::dynamic(gate.mat().clone()))
+            }
+        }
     }
 
     pub fn apply_to(self, target: QubitAccessor) -> ElementaryOperation {
@@ -47,22 +61,16 @@ impl ElementaryGate {
             ElementaryGate::Standard(gate) => gate.apply_to(target).into(),
             ElementaryGate::Canonical(gate) => gate.apply_to((target[0], target[1])).into(),
             ElementaryGate::Custom(gate) => gate.apply_to(target).into(),
-            ElementaryGate::Unitary(gate) => todo!(),
+            ElementaryGate::Unitary(gate) => gate.apply_to(target).into(),
         }
     }
 }
 
-impl ToMat for ElementaryGate {
-    fn to_mat(&self) -> GateMat {
-        use ElementaryGate::*;
-        dispatch!(self; Standard | Canonical | Custom => |gate| gate.to_mat())
-    }
-}
 
 impl Dagger for ElementaryGate {
     fn dagger(self) -> Self {
         use ElementaryGate::*;
-        dispatch!(self; Standard | Canonical | Custom => |gate| gate.dagger().into())
+        dispatch!(self; Standard | Unitary | Canonical | Custom => |gate| gate.dagger().into())
     }
 }
 
@@ -136,7 +144,7 @@ impl SingleTargetGate for SingleGate {
 impl Dagger for SingleGate {
     fn dagger(self) -> Self {
         use SingleGate::*;
-        dispatch!(self; Standard | Custom => |gate| gate.dagger().into())
+        dispatch!(self; Standard | Unitary | Custom => |gate| gate.dagger().into())
     }
 }
 
@@ -1,8 +1,8 @@
-use crate::gate::{DoubleTargetGate, DynamicTargetGate, IntoUnitary, SingleTargetGate};
+use crate::gate::{Dagger, DoubleTargetGate, DynamicTargetGate, IntoUnitary, SingleTargetGate};
 use crate::gate::elementary::{ElementaryGate, SingleGate};
-use crate::into_variant;
+use crate::{dispatch, into_variant};
 use crate::algebra::{DMat, Mat2, Mat4, ToMat2};
-use crate::operation::elementary::unitary::{UnitaryDoubleOperation, UnitaryDynamicOperation, UnitarySingleOperation};
+use crate::operation::elementary::unitary::{UnitaryDoubleOperation, UnitaryDynamicOperation, UnitaryOperation, UnitarySingleOperation};
 use crate::operation::{TargetDouble, TargetMultiple, TargetSingle};
 
 #[derive(Clone, Debug)]
@@ -12,6 +12,48 @@ pub enum UnitaryGate {
     Dynamic(UnitaryDynamicGate),
 }
 
+impl UnitaryGate {
+    /// Create the unitary gate of the matrix, whose dimension is a power of two.
+    pub fn from_mat(mat: DMat) -> Self {
+        match mat.nrows() {
+            2 => UnitarySingleGate(Box::new(Mat2::from_column_slice(mat.as_slice()))).into(),
+            4 => UnitaryDoubleGate(Box::new(Mat4::from_column_slice(mat.as_slice()))).into(),
+            _ => UnitaryDynamicGate::new(Box::new(mat)).into(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            UnitaryGate::Single(_) => 1usize,
+            UnitaryGate::Double(_) => 2usize,
+            UnitaryGate::Dynamic(gate) => gate.size(),
+        }
+    }
+
+    pub fn ident(&self) -> String {
+        match self {
+            UnitaryGate::Single(gate) => gate.ident(),
+            UnitaryGate::Double(gate) => gate.ident(),
+            UnitaryGate::Dynamic(_) => DYNAMIC_UNITARY_IDENT.to_string(),
+        }
+    }
+
+    pub fn apply_to(self, target: TargetMultiple) -> UnitaryOperation {
+        match self {
+            UnitaryGate::Single(gate) => gate.apply_to(target[0]).into(),
+            UnitaryGate::Double(gate) => gate.apply_to((target[0], target[1])).into(),
+            UnitaryGate::Dynamic(gate) => gate.apply_to(target).into(),
+        }
+    }
+}
+
+impl Dagger for UnitaryGate {
+    fn dagger(self) -> Self {
+        use UnitaryGate::*;
+        dispatch!(self; Single | Double | Dynamic => |gate| gate.dagger().into())
+    }
+}
+
 /// Single-target unitary gate.
 #[derive(Clone, Debug)]
 pub struct UnitarySingleGate(pub Box<Mat2>);
@@ -40,6 +82,12 @@ impl ToMat2 for UnitarySingleGate {
     }
 }
 
+impl Dagger for UnitarySingleGate {
+    fn dagger(self) -> Self {
+        Self(Box::new(self.0.adjoint()))
+    }
+}
+
 /// Double-target unitary gate.
 #[derive(Clone, Debug)]
 pub struct UnitaryDoubleGate(pub Box<Mat4>);
@@ -62,10 +110,18 @@ impl DoubleTargetGate for UnitaryDoubleGate {
     }
 }
 
+impl Dagger for UnitaryDoubleGate {
+    fn dagger(self) -> Self {
+        Self(Box::new(self.0.adjoint()))
+    }
+}
+
 /// Dynamic-target unitary gate.
 #[derive(Clone, Debug)]
 pub struct UnitaryDynamicGate(Box<DMat>);
 
+pub const DYNAMIC_UNITARY_IDENT: &str = "_n";
+
 impl UnitaryDynamicGate {
     pub fn new(mat: Box<DMat>) -> Self {
         assert_eq!(mat.nrows(), mat.ncols());
@@ -77,6 +133,16 @@ impl UnitaryDynamicGate {
     pub fn mat(&self) -> &DMat {
         &self.0
     }
+
+    pub fn size(&self) -> usize {
+        self.0.nrows().trailing_zeros() as usize
+    }
+}
+
+impl Dagger for UnitaryDynamicGate {
+    fn dagger(self) -> Self {
+        Self(Box::new(self.0.adjoint()))
+    }
 }
 
 impl IntoUnitary<UnitaryDynamicGate> for UnitaryDynamicGate {
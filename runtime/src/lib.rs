@@ -6,14 +6,24 @@
 extern crate core;
 extern crate gates_def;
 
-use std::sync::Mutex;
+use std::env;
+use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
-use crate::backend::get_available_qubits;
-use crate::decompose::decomposer::ElementaryGateDecomposer;
+use crate::backend::{
+    default_backend, ExecuteResult, QivmBackend, QIVM_COUPLING_MAP_PATH_ENV, QIVM_GATE_SET_PATH_ENV,
+};
+use crate::backend::coupling_map::CouplingMap;
+use crate::backend::gate_set::GateSet;
+use crate::bytecode::ByteCode;
+use crate::decompose::decomposer::{DecompositionStep, ElementaryGateDecomposer};
+use crate::decompose::decomposer::builder::ElementaryDecomposerBuilder;
+use crate::error::{QivmError, QivmResult};
 use crate::gate::elementary::ElementaryGate;
 use crate::operation::elementary::ElementaryOperation;
 
 pub mod backend;
+pub mod error;
+pub mod prelude;
 pub mod runtime_api;
 
 mod qubit;
@@ -25,38 +35,118 @@ mod bytecode;
 mod measurement;
 mod operation;
 mod program;
+mod qasm;
+mod simulator;
 // mod experimental;
 
-struct QuantumInterfaceVirtualMachine {
+pub struct QuantumInterfaceVirtualMachine {
     decomposer: ElementaryGateDecomposer,
     available_qubits: usize,
+    coupling_map: Option<CouplingMap>,
+    backend: Box<dyn QivmBackend>,
 }
 
+/// Shared handle of a QIVM instance, held by every program context running on it.
+pub type QivmRef = Arc<Mutex<QuantumInterfaceVirtualMachine>>;
+
 lazy_static! {
-    static ref QIVM_INSTANCE: Mutex<QuantumInterfaceVirtualMachine> = Mutex::new(
+    static ref QIVM_INSTANCE: QivmRef = Arc::new(Mutex::new(
         QuantumInterfaceVirtualMachine::init()
-    );
+    ));
 }
 
 impl QuantumInterfaceVirtualMachine {
+    /// Create the QIVM instance with the default backend, configured from the environment, see
+    /// [`QuantumInterfaceVirtualMachine::with_env_config`].
+    ///
+    /// The errors of the configuration are printed, and recorded as the last error of the C API.
     pub fn init() -> Self {
-        let available_qubits = get_available_qubits();
-        let mut decomposer = ElementaryGateDecomposer::builder().build();
-        Self { available_qubits, decomposer }
+        let (qivm, errors) = Self::with_env_config(default_backend());
+        for err in errors {
+            eprintln!("[QIVM Error] {}", err);
+            runtime_api::set_last_error(err);
+        }
+        qivm
     }
 
-    pub fn is_gate_available(&self, ident: &str) -> bool {
-        self.decomposer.is_gate_available(ident)
+    /// Create a QIVM instance with the backend, configured from the environment.
+    ///
+    /// The native gate set is loaded from the file named by the `QIVM_GATE_SET_PATH` environment
+    /// variable if it is set, and queried from the backend otherwise. So is the coupling map,
+    /// with the `QIVM_COUPLING_MAP_PATH` environment variable. A file which cannot be loaded is
+    /// ignored, and its error is returned with the instance.
+    pub fn with_env_config(backend: Box<dyn QivmBackend>) -> (Self, Vec<QivmError>) {
+        let mut errors = Vec::<QivmError>::new();
+        let gate_set = match env::var_os(QIVM_GATE_SET_PATH_ENV).map(GateSet::load) {
+            Some(Ok(gate_set)) => gate_set,
+            Some(Err(err)) => {
+                errors.push(err.into());
+                GateSet::from_backend(backend.as_ref())
+            }
+            None => GateSet::from_backend(backend.as_ref()),
+        };
+        let mut qivm = Self::with_gate_set(backend, gate_set);
+        match env::var_os(QIVM_COUPLING_MAP_PATH_ENV).map(CouplingMap::load) {
+            Some(Ok(coupling_map)) => qivm.set_coupling_map(Some(coupling_map)),
+            Some(Err(err)) => errors.push(err.into()),
+            None => {}
+        }
+        (qivm, errors)
+    }
+
+    pub fn new(backend: Box<dyn QivmBackend>) -> Self {
+        let gate_set = GateSet::from_backend(backend.as_ref());
+        Self::with_gate_set(backend, gate_set)
     }
 
-    pub fn decompose_elementary(&mut self, gate_op: &ElementaryOperation) -> Vec<ElementaryOperation> {
-        match self.decomposer.decompose(gate_op) {
-            Ok(gate_ops) => gate_ops,
-            Err(err) => raise_error!("{}", err),
+    /// Create a QIVM instance lowering the programs onto the native gate set of the backend.
+    pub fn with_gate_set(backend: Box<dyn QivmBackend>, gate_set: GateSet) -> Self {
+        let available_qubits = backend.available_qubits();
+        let coupling_map = backend.coupling_map();
+        let decomposer = ElementaryDecomposerBuilder::with_gate_set(gate_set).build();
+        Self { available_qubits, coupling_map, decomposer, backend }
+    }
+
+    pub fn available_qubits(&self) -> usize {
+        self.available_qubits
+    }
+
+    /// Coupling map the programs are routed onto, `None` if any two qubits are coupled.
+    pub fn coupling_map(&self) -> Option<&CouplingMap> {
+        self.coupling_map.as_ref()
+    }
+
+    pub fn set_coupling_map(&mut self, coupling_map: Option<CouplingMap>) {
+        self.coupling_map = coupling_map;
+    }
+
+    pub fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        self.backend.execute(bytecode, shots)
+    }
+
+    /// Return true if the gate is available in the backend.
+    ///
+    /// Custom gates unknown to the decomposer are looked up in the backend directly.
+    pub fn is_gate_available(&self, ident: &str) -> bool {
+        if self.decomposer.contains_gate(ident) {
+            self.decomposer.is_gate_available(ident)
+        } else {
+            self.backend.is_gate_available(ident)
         }
     }
 
+    pub fn decompose_elementary(
+        &mut self, gate_op: &ElementaryOperation
+    ) -> QivmResult<Vec<ElementaryOperation>> {
+        Ok(self.decomposer.decompose(gate_op)?)
+    }
+
     pub fn is_gate_decomposable(&self, gate: &ElementaryGate) -> bool {
         self.decomposer.is_gate_decomposable(gate)
     }
+
+    /// Return the recipes chosen to decompose the gate onto the native gates, for debugging.
+    pub fn explain_decomposition(&self, ident: &str) -> Option<Vec<DecompositionStep<String>>> {
+        self.decomposer.explain_decomposition(ident)
+    }
 }
@@ -31,10 +31,12 @@ impl From<RawMeasurementResult> for MeasurementResult {
     fn from(raw: RawMeasurementResult) -> Self {
         Self {
             shots: raw.shots,
-            measurements: unsafe {
-                let mut measurements = slice::from_raw_parts(
-                    raw.measurements, raw.result_size as usize
-                ).to_vec();
+            measurements: if raw.measurements.is_null() {
+                vec![]
+            } else {
+                let mut measurements = unsafe {
+                    slice::from_raw_parts(raw.measurements, raw.result_size as usize)
+                }.to_vec();
                 let mut measurements = measurements.into_iter()
                     .filter(|entry| entry.count > 0)
                     .collect::<Vec<_>>();
@@ -47,11 +49,7 @@ impl From<RawMeasurementResult> for MeasurementResult {
 
 impl From<MeasurementResult> for RawMeasurementResult {
     fn from(measurement: MeasurementResult) -> Self {
-        Self {
-            shots: measurement.shots,
-            result_size: measurement.measurements.len() as u64,
-            measurements: measurement.measurements.as_ptr() as *mut MeasurementResultEntry,
-        }
+        unsafe { measurement.into_raw() }
     }
 }
 
@@ -5,6 +5,7 @@ use crate::gate::standard::StandardDoubleGate::{CP, CX, CZ};
 use crate::gate::standard::StandardSingleGate::{P, X, Z};
 use crate::{into_variant, qubits};
 use crate::algebra::{is_identity, ToMat2};
+use crate::error::{QivmError, QivmResult};
 use crate::operation::controlled::ControlledOperation;
 use crate::operation::controlled::mux::multi_target::MultiTargetMuxOperation;
 use crate::operation::elementary::ElementaryOperation;
@@ -25,63 +26,73 @@ impl ConditionalCtrlOperation {
         Self { gate, ctrl, target }
     }
 
-    pub fn dispatch(&self) -> Vec<ConditionalCtrlSingleTargetOperation> {
+    /// Return true if a controlled operation of the gate can be dispatched into controlled
+    /// single-target operations.
+    pub fn is_supported(gate: &ElementaryGate) -> bool {
+        use StandardDoubleGate::*;
+        match gate {
+            ElementaryGate::Standard(StandardGate::Double(gate)) => {
+                matches!(gate, CX | CZ | CP { .. } | SWP)
+            }
+            _ => TryInto::<SingleGate>::try_into(gate.clone()).is_ok(),
+        }
+    }
+
+    /// Dispatch the operation into controlled single-target operations, see
+    /// [`ConditionalCtrlOperation::is_supported`] for the supported gates.
+    pub fn dispatch(&self) -> QivmResult<Vec<ConditionalCtrlSingleTargetOperation>> {
+        let unsupported = || QivmError::UnsupportedGate(self.gate.ident());
         if self.gate.size() == 1 {
-            let single_gate: SingleGate = self.gate.clone().try_into().unwrap();
-            vec![ConditionalCtrlSingleTargetOperation::new(
+            let single_gate: SingleGate = self.gate.clone().try_into()
+                .map_err(|_| unsupported())?;
+            return Ok(vec![ConditionalCtrlSingleTargetOperation::new(
                 single_gate, self.ctrl.clone(), self.target[0]
-            )]
-        } else {
-            match self.gate {
-                ElementaryGate::Standard(StandardGate::Double(gate)) => {
-                    match gate {
-                        StandardDoubleGate::CX => {
-                            let mut ctrl = self.ctrl.clone();
-                            ctrl.control_one(&qubits![self.target[0]]);
-                            vec![ConditionalCtrlSingleTargetOperation::new(
-                                X.into(), ctrl, self.target[1]
-                            )]
-                        }
-                        StandardDoubleGate::CZ => {
-                            let mut ctrl = self.ctrl.clone();
-                            ctrl.control_one(&qubits![self.target[0]]);
-                            vec![ConditionalCtrlSingleTargetOperation::new(
-                                Z.into(), ctrl, self.target[1]
-                            )]
-                        }
-                        StandardDoubleGate::CP { angle } => {
-                            let mut ctrl = self.ctrl.clone();
-                            ctrl.control_one(&qubits![self.target[0]]);
-                            vec![ConditionalCtrlSingleTargetOperation::new(
-                                P { angle }.into(), ctrl, self.target[1]
-                            )]
-                        }
-                        StandardDoubleGate::SWP => {
-                            let mut ctrl0 = self.ctrl.clone();
-                            ctrl0.control_one(&qubits![self.target[0]]);
-                            let mut ctrl1 = self.ctrl.clone();
-                            ctrl1.control_one(&qubits![self.target[1]]);
-                            vec![
-                                ConditionalCtrlSingleTargetOperation::new(
-                                    X.into(), ctrl1.clone(), self.target[0]
-                                ),
-                                ConditionalCtrlSingleTargetOperation::new(
-                                    X.into(), ctrl0, self.target[1]
-                                ),
-                                ConditionalCtrlSingleTargetOperation::new(
-                                    X.into(), ctrl1, self.target[0]
-                                )
-                            ]
-                        }
-                        _ => {
-                            todo!()
-                        }
-                    }
-                }
-                _ => {
-                    todo!()
-                }
+            )]);
+        }
+        let gate = match self.gate {
+            ElementaryGate::Standard(StandardGate::Double(gate)) => gate,
+            _ => return Err(unsupported()),
+        };
+        match gate {
+            StandardDoubleGate::CX => {
+                let mut ctrl = self.ctrl.clone();
+                ctrl.control_one(&qubits![self.target[0]]);
+                Ok(vec![ConditionalCtrlSingleTargetOperation::new(
+                    X.into(), ctrl, self.target[1]
+                )])
+            }
+            StandardDoubleGate::CZ => {
+                let mut ctrl = self.ctrl.clone();
+                ctrl.control_one(&qubits![self.target[0]]);
+                Ok(vec![ConditionalCtrlSingleTargetOperation::new(
+                    Z.into(), ctrl, self.target[1]
+                )])
+            }
+            StandardDoubleGate::CP { angle } => {
+                let mut ctrl = self.ctrl.clone();
+                ctrl.control_one(&qubits![self.target[0]]);
+                Ok(vec![ConditionalCtrlSingleTargetOperation::new(
+                    P { angle }.into(), ctrl, self.target[1]
+                )])
+            }
+            StandardDoubleGate::SWP => {
+                let mut ctrl0 = self.ctrl.clone();
+                ctrl0.control_one(&qubits![self.target[0]]);
+                let mut ctrl1 = self.ctrl.clone();
+                ctrl1.control_one(&qubits![self.target[1]]);
+                Ok(vec![
+                    ConditionalCtrlSingleTargetOperation::new(
+                        X.into(), ctrl1.clone(), self.target[0]
+                    ),
+                    ConditionalCtrlSingleTargetOperation::new(
+                        X.into(), ctrl0, self.target[1]
+                    ),
+                    ConditionalCtrlSingleTargetOperation::new(
+                        X.into(), ctrl1, self.target[0]
+                    )
+                ])
             }
+            _ => Err(unsupported()),
         }
     }
 }
@@ -3,7 +3,8 @@ use crate::gate::elementary::ElementaryGate;
 use crate::operation::elementary::ElementaryOperation;
 use crate::operation::Operation;
 use crate::qubit::qubit_accessor::QubitAccessor;
-use crate::{into_variant, raise_error};
+use crate::error::{QivmError, QivmResult};
+use crate::into_variant;
 use crate::operation::controlled::ControlledOperation;
 use crate::operation::controlled::mux::MuxOperation;
 
@@ -15,19 +16,17 @@ pub struct MultiTargetMuxOperation {
 }
 
 impl MultiTargetMuxOperation {
-    pub fn new(gates: Vec<ElementaryGate>, ctrl: QubitAccessor, target: QubitAccessor) -> Self {
-        if gates.len() != 2usize.pow(ctrl.size() as u32) {
-            raise_error!(
-                "Invalid control size, expected: {}, actual: {}",
-                2usize.pow(ctrl.size() as u32), gates.len()
-            );
+    pub fn new(
+        gates: Vec<ElementaryGate>, ctrl: QubitAccessor, target: QubitAccessor
+    ) -> QivmResult<Self> {
+        let expected = 2usize.pow(ctrl.size() as u32);
+        if gates.len() != expected {
+            return Err(QivmError::TargetSizeMismatch { expected, actual: gates.len() });
         }
-        for gate in gates.iter() {
-            if gate.size() != 1 {
-                raise_error!("Invalid gate size, expected: 1, actual: {}", gate.size());
-            }
+        if let Some(gate) = gates.iter().find(|gate| gate.size() != 1) {
+            return Err(QivmError::TargetSizeMismatch { expected: 1, actual: gate.size() });
         }
-        Self { gates, ctrl, target }
+        Ok(Self { gates, ctrl, target })
     }
 
     pub fn gates_vec(&self) -> Vec<ElementaryGate> {
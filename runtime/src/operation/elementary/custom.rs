@@ -1,7 +1,7 @@
-use crate::gate::custom::{CustomDoubleGate, CustomDynamicGate, CustomGate, CustomSingleGate};
+use crate::gate::custom::{CustomBuiltinGate, CustomDoubleGate, CustomDynamicGate, CustomGate, CustomSingleGate};
 use crate::gate::Dagger;
 use crate::gate::elementary::ElementaryGate;
-use crate::{dispatch, into_variant};
+use crate::{dispatch, into_variant, qubits};
 use crate::algebra::{GateMat, Mat2, Mat4, ToMat, ToMat2, ToMat4};
 use crate::operation::{DoubleTargetOperation, DynamicTargetOperation, ElementaryGateOperation, MultiTargetOperation, SingleTargetOperation, TargetDouble, TargetMultiple, TargetSingle};
 use crate::operation::elementary::ElementaryOperation;
@@ -13,31 +13,29 @@ pub enum CustomOperation {
     Single(CustomSingleOperation),
     Double(CustomDoubleOperation),
     Dynamic(CustomDynamicOperation),
+    Builtin(CustomBuiltinOperation),
 }
 
-impl ToMat for CustomOperation {
-    fn to_mat(&self) -> GateMat {
-        self.get_gate().to_mat()
-    }
-}
-
-impl DynamicTargetOperation for CustomOperation {
-    type Gate = CustomGate;
-
-    fn get_gate(&self) -> Self::Gate {
+impl CustomOperation {
+    pub fn get_gate(&self) -> CustomGate {
         use CustomOperation::*;
-        dispatch!(self; Single | Dynamic => |op| op.get_gate().clone().into())
+        dispatch!(self; Single | Double | Dynamic | Builtin => |op| op.get_gate().clone().into())
     }
 
-    fn get_target(&self) -> TargetMultiple {
-        todo!()
+    pub fn get_target(&self) -> TargetMultiple {
+        match self {
+            CustomOperation::Single(op) => qubits![op.target],
+            CustomOperation::Double(op) => qubits![op.target.0, op.target.1],
+            CustomOperation::Dynamic(op) => op.target.clone(),
+            CustomOperation::Builtin(op) => op.target.clone(),
+        }
     }
 }
 
 impl Dagger for CustomOperation {
     fn dagger(self) -> Self {
         use CustomOperation::*;
-        dispatch!(self; Single | Dynamic => |op| op.dagger().into())
+        dispatch!(self; Single | Double | Dynamic | Builtin => |op| op.dagger().into())
     }
 }
 
@@ -159,6 +157,34 @@ impl Dagger for CustomDynamicOperation {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct CustomBuiltinOperation {
+    gate: CustomBuiltinGate,
+    target: TargetMultiple,
+}
+
+impl CustomBuiltinOperation {
+    pub fn new(gate: CustomBuiltinGate, target: TargetMultiple) -> Self {
+        Self { gate, target }
+    }
+
+    pub fn get_gate(&self) -> CustomBuiltinGate {
+        self.gate.clone()
+    }
+}
+
+impl MultiTargetOperation for CustomBuiltinOperation {
+    fn get_target_accessor(&self) -> QubitAccessor {
+        self.target.clone()
+    }
+}
+
+impl Dagger for CustomBuiltinOperation {
+    fn dagger(self) -> Self {
+        Self { gate: self.gate.dagger(), ..self }
+    }
+}
+
 into_variant! {
     CustomSingleOperation
         => CustomOperation::Single
@@ -172,4 +198,8 @@ into_variant! {
         => CustomOperation::Dynamic
         => ElementaryOperation::Custom
         => Operation::Elementary;
+    CustomBuiltinOperation
+        => CustomOperation::Builtin
+        => ElementaryOperation::Custom
+        => Operation::Elementary;
 }
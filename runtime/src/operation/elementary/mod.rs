@@ -27,26 +27,30 @@ impl ElementaryOperation {
     pub fn get_ident(&self) -> String {
         use ElementaryOperation::*;
         use crate::gate::DoubleTargetGate;
-        dispatch!(self; Standard | Canonical | Custom => |op| op.get_gate().ident())
+        dispatch!(self; Standard | Unitary | Canonical | Custom => |op| op.get_gate().ident())
     }
 
     pub fn get_gate(&self) -> ElementaryGate {
         use ElementaryOperation::*;
-        dispatch!(self; Standard | Canonical | Custom => |op| op.get_gate().clone().into())
+        dispatch!(self; Standard | Unitary | Canonical | Custom => |op| {
+            op.get_gate().clone().into()
+        })
     }
 }
 
 impl Dagger for ElementaryOperation {
     fn dagger(self) -> Self {
         use ElementaryOperation::*;
-        dispatch!(self; Standard | Canonical | Custom => |op| op.dagger().into())
+        dispatch!(self; Standard | Unitary | Canonical | Custom => |op| op.dagger().into())
     }
 }
 
 impl ElementaryGateOperation for ElementaryOperation {
     fn get_gate(&self) -> ElementaryGate {
         use ElementaryOperation::*;
-        dispatch!(self; Standard | Canonical | Custom => |op| op.get_gate().clone().into())
+        dispatch!(self; Standard | Unitary | Canonical | Custom => |op| {
+            op.get_gate().clone().into()
+        })
     }
 
     fn get_target(&self) -> QubitAccessor {
@@ -120,7 +124,7 @@ impl TryFrom<ElementaryOperation> for SingleOperation {
             ElementaryOperation::Custom(custom) => {
                 match custom {
                     CustomOperation::Single(op) => Ok(SingleOperation::Custom(op)),
-                    CustomOperation::Double(_) => Err(()),
+                    CustomOperation::Double(_) | CustomOperation::Builtin(_) => Err(()),
                     CustomOperation::Dynamic(op) => {
                         let gate = op.get_gate();
                         if gate.size() == 1 {
@@ -1,7 +1,7 @@
-use crate::gate::DynamicTargetGate;
+use crate::gate::{Dagger, DynamicTargetGate};
 use crate::gate::elementary::ElementaryGate;
 use crate::gate::unitary::{UnitaryDoubleGate, UnitaryDynamicGate, UnitarySingleGate};
-use crate::{dispatch, into_variant};
+use crate::{dispatch, into_variant, qubits};
 use crate::algebra::{GateMat, Mat2, Mat4, ToMat, ToMat2, ToMat4};
 use crate::operation::{DoubleTargetOperation, DynamicTargetOperation, ElementaryGateOperation, Operation, SingleTargetOperation, TargetDouble, TargetMultiple, TargetSingle};
 use crate::operation::elementary::ElementaryOperation;
@@ -21,7 +21,18 @@ impl ElementaryGateOperation for UnitaryOperation {
     }
 
     fn get_target(&self) -> QubitAccessor {
-        todo!()
+        match self {
+            UnitaryOperation::Single(op) => qubits![op.target],
+            UnitaryOperation::Double(op) => qubits![op.target.0, op.target.1],
+            UnitaryOperation::Dynamic(op) => op.target.clone(),
+        }
+    }
+}
+
+impl Dagger for UnitaryOperation {
+    fn dagger(self) -> Self {
+        use UnitaryOperation::*;
+        dispatch!(self; Single | Double | Dynamic => |op| op.dagger().into())
     }
 }
 
@@ -123,6 +134,24 @@ impl DynamicTargetOperation for UnitaryDynamicOperation {
     }
 }
 
+impl Dagger for UnitarySingleOperation {
+    fn dagger(self) -> Self {
+        Self { gate: self.gate.dagger(), ..self }
+    }
+}
+
+impl Dagger for UnitaryDoubleOperation {
+    fn dagger(self) -> Self {
+        Self { gate: self.gate.dagger(), ..self }
+    }
+}
+
+impl Dagger for UnitaryDynamicOperation {
+    fn dagger(self) -> Self {
+        Self { gate: self.gate.dagger(), ..self }
+    }
+}
+
 into_variant! {
     UnitarySingleOperation
         => UnitaryOperation::Single => ElementaryOperation::Unitary => Operation::Elementary;
@@ -0,0 +1,40 @@
+//! Safe Rust API for building and executing quantum programs.
+//!
+//! ```no_run
+//! use qivm::prelude::*;
+//!
+//! let mut program = QuantumProgram::with_backend(NativeBackend);
+//! let qreg = program.alloc(3)?;
+//! program.apply(H, &qreg.get(0)?)?;
+//! program.ctrl(&qreg.get(0)?, |program| {
+//!     program.apply(X, &qreg.get(1)?)?;
+//!     program.dagger(|program| program.apply(T, &qreg.get(2)?))
+//! })?;
+//! program.measure(&qreg);
+//! let result = program.execute(1024)?;
+//! # Ok::<(), QivmError>(())
+//! ```
+
+pub use crate::backend::{ExecuteResult, QivmBackend};
+pub use crate::backend::coupling_map::{CouplingMap, CouplingMapError};
+pub use crate::backend::native::NativeBackend;
+pub use crate::decompose::decomposer::DecompositionStep;
+pub use crate::error::{QivmError, QivmResult};
+pub use crate::gate::canonical::CanonicalGate;
+pub use crate::gate::elementary::ElementaryGate;
+pub use crate::gate::standard::{StandardDoubleGate, StandardGate, StandardSingleGate, StandardTripleGate};
+pub use crate::gate::standard::StandardDoubleGate::*;
+pub use crate::gate::standard::StandardSingleGate::*;
+pub use crate::gate::standard::StandardTripleGate::*;
+pub use crate::measurement::{MeasurementResult, MeasurementResultEntry};
+pub use crate::program::builder::{OptimizationLevel, QuantumProgramContextBuilder};
+pub use crate::program::layout::Layout;
+pub use crate::program::pass::manager::{InvariantCheck, PassManager};
+pub use crate::program::quantum_program::{CtrlGuard, DaggerGuard, QuantumProgram};
+pub use crate::program::stats::CircuitStats;
+pub use crate::program::QuantumProgramContext;
+pub use crate::qasm::{QasmError, QasmResult, QasmVersion};
+pub use crate::qubit::qubit_accessor::QubitAccessor;
+pub use crate::qubit::{QubitAddr, Slice};
+pub use crate::qubits;
+pub use crate::{QivmRef, QuantumInterfaceVirtualMachine};
@@ -1,10 +1,54 @@
+use std::sync::{Arc, Mutex};
+use crate::{QivmRef, QuantumInterfaceVirtualMachine};
+use crate::backend::QivmBackend;
 use crate::program::pass::cond_ctrl_decomposition::ConditionalCtrlDecompositionPass;
 use crate::program::pass::demutiplex::DemultiplexPass;
 use crate::program::pass::elementary_decomposition::ElementaryDecompositionPass;
+use crate::program::pass::gate_cancellation::GateCancellationPass;
+use crate::program::pass::layout_selection::LayoutSelectionPass;
 use crate::program::pass::multiplexed_optimization::MultiplexOptimizationPass;
 use crate::program::pass::remove_identity::RemoveIdentityPass;
+use crate::program::pass::routing::RoutingPass;
+use crate::program::pass::single_gate_fusion::SingleGateFusionPass;
+use crate::program::pass::Pass;
 use crate::program::QuantumProgramContext;
 
+/// Optimization level of the transpiler passes, each level includes the passes of the lower ones.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptimizationLevel {
+    /// Only decompose the circuit into the gates of the backend, and route it from the trivial
+    /// layout.
+    O0,
+    /// Remove the identities, and cancel the adjacent inverse gates before the decomposition.
+    O1,
+    /// Cancel and merge the gates through the gates they commute with, and fuse the runs of
+    /// single-qubit gates after the decomposition.
+    O2,
+    /// Select the layout from the interaction graph and the error rates before routing, and
+    /// cancel and fuse the gates of the routed circuit again.
+    O3,
+}
+
+impl OptimizationLevel {
+    /// Get the level from its number, from `0` to `3`.
+    pub fn from_level(level: u32) -> Option<Self> {
+        match level {
+            0 => Some(OptimizationLevel::O0),
+            1 => Some(OptimizationLevel::O1),
+            2 => Some(OptimizationLevel::O2),
+            3 => Some(OptimizationLevel::O3),
+            _ => None,
+        }
+    }
+}
+
+impl Default for OptimizationLevel {
+    /// The level of the default passes of the program contexts, [`OptimizationLevel::O2`].
+    fn default() -> Self {
+        OptimizationLevel::O2
+    }
+}
+
 pub struct QuantumProgramContextBuilder {
     program_ctx: QuantumProgramContext,
 }
@@ -15,13 +59,71 @@ impl QuantumProgramContextBuilder {
         Self { program_ctx: QuantumProgramContext::default() }
     }
 
+    /// Create a builder for a program context running on the given QIVM instance.
+    pub fn with_qivm(qivm: QivmRef) -> Self {
+        Self { program_ctx: QuantumProgramContext::new(qivm) }
+    }
+
+    /// Create a builder for a program context running on a new QIVM instance with the backend.
+    pub fn with_backend(backend: impl QivmBackend + 'static) -> Self {
+        let qivm = QuantumInterfaceVirtualMachine::new(Box::new(backend));
+        Self::with_qivm(Arc::new(Mutex::new(qivm)))
+    }
+
+    /// Append a transpiler pass, and return its unique name in the pass manager.
+    pub fn add_pass(&mut self, pass: impl Pass + 'static) -> String {
+        self.program_ctx.add_pass(pass)
+    }
+
+    /// Add the passes of the default optimization level, see [`OptimizationLevel::default`].
     pub fn default_passes(&mut self) {
-        self.program_ctx.add_pass(MultiplexOptimizationPass);
+        self.optimization_passes(OptimizationLevel::default());
+    }
+
+    /// Add the passes of the optimization level.
+    pub fn optimization_passes(&mut self, level: OptimizationLevel) {
+        use OptimizationLevel::*;
+        let qivm = self.program_ctx.qivm();
+        if level >= O2 {
+            self.program_ctx.add_pass(MultiplexOptimizationPass);
+        }
         self.program_ctx.add_pass(ConditionalCtrlDecompositionPass);
         self.program_ctx.add_pass(DemultiplexPass);
-        self.program_ctx.add_pass(RemoveIdentityPass);
-        self.program_ctx.add_pass(ElementaryDecompositionPass);
-        self.program_ctx.add_pass(RemoveIdentityPass);
+        if level >= O1 {
+            self.program_ctx.add_pass(RemoveIdentityPass);
+            if level >= O2 {
+                self.program_ctx.add_pass(GateCancellationPass::new());
+            } else {
+                self.program_ctx.add_pass(GateCancellationPass::adjacent());
+            }
+        }
+        self.program_ctx.add_pass(ElementaryDecompositionPass::new(qivm.clone()));
+        if level >= O2 {
+            self.program_ctx.add_pass(SingleGateFusionPass::new(qivm.clone()));
+        }
+        if level >= O1 {
+            self.program_ctx.add_pass(RemoveIdentityPass);
+        }
+        if qivm.lock().unwrap().coupling_map().is_none() {
+            return;
+        }
+        if level >= O3 {
+            self.program_ctx.add_pass(LayoutSelectionPass::new(qivm.clone()));
+        }
+        self.program_ctx.add_pass(RoutingPass::new(qivm.clone()));
+        self.program_ctx.add_pass(ElementaryDecompositionPass::new(qivm.clone()));
+        if level >= O3 {
+            // the merged gates may be unavailable, they are decomposed again before the fusion
+            self.program_ctx.add_pass(GateCancellationPass::new());
+            self.program_ctx.add_pass(ElementaryDecompositionPass::new(qivm.clone()));
+            self.program_ctx.add_pass(SingleGateFusionPass::new(qivm));
+            self.program_ctx.add_pass(RemoveIdentityPass);
+        }
+    }
+
+    /// Check that the transpiled circuit only uses gates available in the backend.
+    pub fn verify_native_gates(&mut self) {
+        self.program_ctx.set_verify_native_gates(true);
     }
 
     pub fn build(mut self) -> QuantumProgramContext {
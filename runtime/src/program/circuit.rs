@@ -1,10 +1,12 @@
-use std::mem::swap;
+use std::collections::BTreeSet;
+use std::mem::{swap, take};
 use std::ops::{Add, AddAssign};
 use crate::bytecode::instruction::{InstrParam, Instruction, PrimitiveOpCode};
-use crate::operation::Operation;
+use crate::error::{QivmError, QivmResult};
+use crate::operation::{ElementaryGateOperation, Operation};
 use crate::operation::elementary::ElementaryOperation;
+use crate::program::layout::Layout;
 use crate::qubit::QubitAddr;
-use crate::raise_error;
 
 /// A quantum circuit is a sequence of operations
 /// which can be compiled into a sequence of instructions
@@ -14,12 +16,18 @@ use crate::raise_error;
 pub struct QuantumCircuit {
     /// The sequence of operations.
     pub operations: Vec<CircuitOperation>,
+    /// Placement of the logical qubits on the physical qubits at the start of the circuit,
+    /// the targets of the operations are the physical qubits. `None` for the trivial layout.
+    pub initial_layout: Option<Layout>,
+    /// Placement of the logical qubits on the physical qubits at the end of the circuit, set once
+    /// the circuit is routed.
+    pub layout: Option<Layout>,
 }
 
 impl QuantumCircuit {
 
     pub fn from_operations(operations: Vec<CircuitOperation>) -> Self {
-        Self { operations }
+        Self { operations, initial_layout: None, layout: None }
     }
 
     /// Push an operation into the circuit.
@@ -28,30 +36,59 @@ impl QuantumCircuit {
     }
 
     /// Compile the circuit into a sequence of instructions.
-    pub fn compile(&self) -> Vec<Instruction> {
-        let mut instructions = Vec::<Instruction>::new();
+    /// The qubits below the highest stack top, and the physical qubits the operations act on, are
+    /// allocated.
+    /// Return an error if there is a non-elementary operation.
+    pub fn compile(&self) -> QivmResult<Vec<Instruction>> {
+        self.lower(|operation| operation.clone().try_into(), |instruction| instruction)
+    }
+
+    /// Disassemble the circuit into the textual assembly form, like the compiled circuit. The
+    /// operations which are not elementary yet are written as comments, so that a circuit can be
+    /// dumped between any two passes.
+    pub fn disassemble(&self) -> String {
+        let lines = self.lower(
+            |operation| Ok(match Instruction::try_from(operation.clone()) {
+                Ok(instruction) => instruction.to_string(),
+                Err(_) => format!("# {:?}", operation),
+            }),
+            |instruction| instruction.to_string(),
+        );
+        lines.unwrap_or_default().into_iter().map(|line| line + "\n").collect()
+    }
+
+    /// Lower the operations of the circuit, with the reset and alloc instructions of the stack.
+    fn lower<T>(
+        &self, mut lower_operation: impl FnMut(&Operation) -> QivmResult<T>,
+        primitive: impl Fn(Instruction) -> T,
+    ) -> QivmResult<Vec<T>> {
+        let mut lowered = Vec::<T>::new();
         let mut last_stack_top: QubitAddr = 0;
         let mut qubits_alloc: QubitAddr = 0;
         for CircuitOperation { operation, stack_top } in &self.operations {
             if *stack_top < last_stack_top {
-                instructions.push(Instruction::Primitive {
+                lowered.push(primitive(Instruction::Primitive {
                     opcode: PrimitiveOpCode::Reset,
                     params: (*stack_top .. last_stack_top).map(|qubit| {
                         InstrParam::UInt(qubit as u64)
                     }).collect(),
-                });
+                }));
             } else if *stack_top > qubits_alloc {
                 qubits_alloc = *stack_top;
             }
-            instructions.push(operation.clone().into());
+            if let Operation::Elementary(op) = operation {
+                let highest_target = op.get_target().iter().max().map_or(0, |&qubit| qubit + 1);
+                qubits_alloc = qubits_alloc.max(highest_target);
+            }
+            lowered.push(lower_operation(operation)?);
             last_stack_top = *stack_top;
         }
         // Alloc qubits
-        instructions.insert(0, Instruction::Primitive {
+        lowered.insert(0, primitive(Instruction::Primitive {
             opcode: PrimitiveOpCode::Alloc,
             params: vec![InstrParam::UInt(qubits_alloc as u64)],
-        });
-        instructions
+        }));
+        Ok(lowered)
     }
 
     pub fn reverse(&mut self) {
@@ -59,7 +96,11 @@ impl QuantumCircuit {
     }
 
     pub fn reversed(self) -> Self {
-        Self { operations: self.operations.into_iter().rev().collect() }
+        Self {
+            operations: self.operations.into_iter().rev().collect(),
+            initial_layout: self.layout,
+            layout: self.initial_layout,
+        }
     }
 
     pub fn flat_replace<F>(&mut self, mut transform: F)
@@ -90,23 +131,59 @@ impl QuantumCircuit {
         });
     }
 
+    /// Like `flat_replace`, but stop at the first error of the transform.
+    ///
+    /// Operations before the failing one are replaced, the others are kept untouched.
+    pub fn try_flat_replace<F>(&mut self, mut transform: F) -> QivmResult<()>
+    where
+        F: FnMut(&CircuitOperation) -> QivmResult<Option<Vec<CircuitOperation>>>
+    {
+        let mut operations = take(&mut self.operations).into_iter();
+        while let Some(op) = operations.next() {
+            match transform(&op) {
+                Ok(Some(replaced)) => self.operations.extend(replaced),
+                Ok(None) => self.operations.push(op),
+                Err(err) => {
+                    self.operations.push(op);
+                    self.operations.extend(operations);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn try_flat_replace_operation<F, OP>(&mut self, mut transform: F) -> QivmResult<()>
+    where
+        F: FnMut(&Operation) -> QivmResult<Option<Vec<OP>>>,
+        OP: Into<Operation>,
+    {
+        self.try_flat_replace(|operation| {
+            let CircuitOperation { operation, stack_top } = operation;
+            Ok(transform(operation)?.map(|decomposed| {
+                decomposed.into_iter().map(|op| {
+                    CircuitOperation::new(op.into(), *stack_top)
+                }).collect::<Vec<CircuitOperation>>()
+            }))
+        })
+    }
+
     /// Return true if all operations satisfy the predicate.
     pub fn all(&mut self, mut predict: impl FnMut(&Operation, QubitAddr) -> bool) -> bool {
         self.operations.iter().all(|op| predict(&op.operation, op.stack_top))
     }
 
     /// Return true if all elementary operations satisfy the predicate.
-    /// Raise an error if there is a non-elementary operation.
+    /// Return an error if there is a non-elementary operation.
     pub fn elementary_all(
         &mut self, mut predict: impl FnMut(&ElementaryOperation) -> bool
-    ) -> bool {
-        self.all(|operation, _| {
-            if let Operation::Elementary(op) = &operation {
-                predict(op)
-            } else {
-                raise_error!("Non-elementary operation");
+    ) -> QivmResult<bool> {
+        for CircuitOperation { operation, .. } in &self.operations {
+            if !predict(as_elementary(operation)?) {
+                return Ok(false);
             }
-        })
+        }
+        Ok(true)
     }
 
     /// Return true if any operation satisfies the predicate.
@@ -114,18 +191,40 @@ impl QuantumCircuit {
         self.operations.iter().any(|op| predict(&op.operation, op.stack_top))
     }
 
+    /// Return the sorted and deduplicated idents of the elementary operations which do not
+    /// satisfy the predicate.
+    /// Return an error if there is a non-elementary operation.
+    pub fn elementary_idents_except(
+        &self, mut predict: impl FnMut(&ElementaryOperation) -> bool
+    ) -> QivmResult<Vec<String>> {
+        let mut idents = BTreeSet::new();
+        for CircuitOperation { operation, .. } in &self.operations {
+            let op = as_elementary(operation)?;
+            if !predict(op) {
+                idents.insert(op.get_ident());
+            }
+        }
+        Ok(idents.into_iter().collect())
+    }
+
     /// Return true if any elementary operation satisfies the predicate.
-    /// Raise an error if there is a non-elementary operation.
+    /// Return an error if there is a non-elementary operation.
     pub fn elementary_any(
         &mut self, mut predict: impl FnMut(&ElementaryOperation) -> bool
-    ) -> bool {
-        self.any(|operation, _| {
-            if let Operation::Elementary(op) = &operation {
-                predict(op)
-            } else {
-                raise_error!("Non-elementary operation");
+    ) -> QivmResult<bool> {
+        for CircuitOperation { operation, .. } in &self.operations {
+            if predict(as_elementary(operation)?) {
+                return Ok(true);
             }
-        })
+        }
+        Ok(false)
+    }
+}
+
+fn as_elementary(operation: &Operation) -> QivmResult<&ElementaryOperation> {
+    match operation {
+        Operation::Elementary(op) => Ok(op),
+        _ => Err(QivmError::Compile("Non-elementary operation".to_string())),
     }
 }
 
@@ -159,8 +258,10 @@ impl CircuitOperation {
 }
 
 /// Convert a circuit operation into an instruction.
-impl Into<Instruction> for CircuitOperation {
-    fn into(self) -> Instruction {
-        self.operation.into()
+impl TryFrom<CircuitOperation> for Instruction {
+    type Error = QivmError;
+
+    fn try_from(operation: CircuitOperation) -> QivmResult<Self> {
+        operation.operation.try_into()
     }
 }
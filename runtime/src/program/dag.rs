@@ -0,0 +1,351 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use crate::error::{QivmError, QivmResult};
+use crate::operation::{ElementaryGateOperation, Operation};
+use crate::program::circuit::{CircuitOperation, QuantumCircuit};
+use crate::qubit::QubitAddr;
+
+pub type NodeId = usize;
+
+/// An operation of the DAG, linked to the previous and the next operations on each of its qubits.
+#[derive(Clone, Debug)]
+pub struct DagNode {
+    pub operation: Operation,
+    pub stack_top: QubitAddr,
+    /// Index of the run of operations with the same stack top the node belongs to.
+    scope: usize,
+    qubits: Vec<QubitAddr>,
+    /// Previous node on the wire of each qubit.
+    prev: Vec<Option<NodeId>>,
+    /// Next node on the wire of each qubit.
+    next: Vec<Option<NodeId>>,
+}
+
+impl DagNode {
+    fn new(operation: Operation, stack_top: QubitAddr, scope: usize) -> Self {
+        let qubits = operation_qubits(&operation, stack_top);
+        let (prev, next) = (vec![None; qubits.len()], vec![None; qubits.len()]);
+        Self { operation, stack_top, scope, qubits, prev, next }
+    }
+
+    /// Qubits the operation acts on.
+    pub fn qubits(&self) -> &[QubitAddr] {
+        &self.qubits
+    }
+
+    fn position(&self, qubit: QubitAddr) -> usize {
+        self.qubits.iter().position(|&q| q == qubit).unwrap()
+    }
+}
+
+/// First and last nodes on the wire of a qubit.
+#[derive(Clone, Debug, Default)]
+struct Wire {
+    first: Option<NodeId>,
+    last: Option<NodeId>,
+}
+
+/// Dependency DAG of a quantum circuit: a node per operation, and an edge between consecutive
+/// operations on the wire of each qubit.
+///
+/// Elementary operations act on their targets, the other operations act on all the qubits
+/// below their stack top. The stack top of the operations is preserved: the runs of operations
+/// with the same stack top, called scopes, are kept in order when converted back to the linear
+/// form, so that the qubits are reset at the same points.
+///
+/// Node ids are stable, ids of removed nodes are not reused.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitDag {
+    nodes: Vec<Option<DagNode>>,
+    wires: BTreeMap<QubitAddr, Wire>,
+    /// Stack top and scope of the last pushed node.
+    last_scope: Option<(QubitAddr, usize)>,
+}
+
+impl CircuitDag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an operation at the end of the circuit.
+    pub fn push(&mut self, operation: Operation, stack_top: QubitAddr) -> NodeId {
+        let scope = match self.last_scope {
+            Some((last_stack_top, scope)) if last_stack_top == stack_top => scope,
+            Some((_, scope)) => scope + 1,
+            None => 0,
+        };
+        self.last_scope = Some((stack_top, scope));
+        let id = self.insert_node(DagNode::new(operation, stack_top, scope));
+        for qubit in self.nodes[id].as_ref().unwrap().qubits.clone() {
+            let last = self.wires.get(&qubit).and_then(|wire| wire.last);
+            self.link(qubit, last, Some(id));
+        }
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&DagNode> {
+        self.nodes.get(id).and_then(Option::as_ref)
+    }
+
+    /// Qubits having at least one operation.
+    pub fn wires(&self) -> impl Iterator<Item = QubitAddr> + '_ {
+        self.wires.keys().copied()
+    }
+
+    /// Nodes on the wire of the qubit, in order.
+    pub fn wire(&self, qubit: QubitAddr) -> impl Iterator<Item = NodeId> + '_ {
+        let first = self.wires.get(&qubit).and_then(|wire| wire.first);
+        std::iter::successors(first, move |&id| {
+            let node = self.nodes[id].as_ref().unwrap();
+            node.next[node.position(qubit)]
+        })
+    }
+
+    /// Distinct nodes directly preceding the node on its wires.
+    pub fn predecessors(&self, id: NodeId) -> Vec<NodeId> {
+        self.node(id).map_or(vec![], |node| distinct(&node.prev))
+    }
+
+    /// Distinct nodes directly following the node on its wires.
+    pub fn successors(&self, id: NodeId) -> Vec<NodeId> {
+        self.node(id).map_or(vec![], |node| distinct(&node.next))
+    }
+
+    /// Nodes in a topological order, scope after scope, and in the order of their ids within a
+    /// scope when they are independent.
+    pub fn topological_order(&self) -> Vec<NodeId> {
+        let mut pending = self.nodes.iter().map(|node| node.as_ref().map_or(0, |node| {
+            distinct(&node.prev).len()
+        })).collect::<Vec<usize>>();
+        let mut queue = self.nodes.iter().enumerate()
+            .filter_map(|(id, node)| node.as_ref().map(|node| (id, node)))
+            .filter(|&(id, _)| pending[id] == 0)
+            .map(|(id, node)| Reverse((node.scope, id)))
+            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::with_capacity(pending.len());
+        while let Some(Reverse((_, id))) = queue.pop() {
+            order.push(id);
+            for successor in self.successors(id) {
+                pending[successor] -= 1;
+                if pending[successor] == 0 {
+                    let scope = self.nodes[successor].as_ref().unwrap().scope;
+                    queue.push(Reverse((scope, successor)));
+                }
+            }
+        }
+        order
+    }
+
+    /// Layers of nodes which can be executed in parallel, as soon as possible. A scope starts on a
+    /// new layer, after all the layers of the previous scopes.
+    pub fn layers(&self) -> Vec<Vec<NodeId>> {
+        let mut layers = Vec::<Vec<NodeId>>::new();
+        let mut layer_of = vec![0; self.nodes.len()];
+        let (mut scope, mut scope_start) = (None, 0);
+        for id in self.topological_order() {
+            let node = self.nodes[id].as_ref().unwrap();
+            if scope != Some(node.scope) {
+                scope = Some(node.scope);
+                scope_start = layers.len();
+            }
+            let layer = distinct(&node.prev).into_iter()
+                .map(|prev| layer_of[prev] + 1)
+                .fold(scope_start, usize::max);
+            layer_of[id] = layer;
+            if layer == layers.len() {
+                layers.push(vec![]);
+            }
+            layers[layer].push(id);
+        }
+        layers
+    }
+
+    /// Remove a node and return its operation, its neighbours on each wire are linked.
+    pub fn remove(&mut self, id: NodeId) -> QivmResult<Operation> {
+        let operation = self.node(id).ok_or_else(|| invalid_node(id))?.operation.clone();
+        self.replace_subcircuit(&[id], vec![])?;
+        Ok(operation)
+    }
+
+    /// Replace the subcircuit of the nodes with the operations, in order, and return the ids of
+    /// their nodes.
+    ///
+    /// The nodes must belong to the same scope, and form a convex subcircuit: no path between
+    /// two of them goes through another node. The operations take the stack top of the nodes and
+    /// must act on their qubits only.
+    pub fn replace_subcircuit(
+        &mut self, ids: &[NodeId], operations: Vec<Operation>
+    ) -> QivmResult<Vec<NodeId>> {
+        let subcircuit = ids.iter().copied().collect::<HashSet<NodeId>>();
+        let (stack_top, scope) = self.subcircuit_scope(ids)?;
+        if !self.is_convex(&subcircuit) {
+            return Err(QivmError::InvalidArgument(
+                "The nodes do not form a convex subcircuit".to_string()
+            ));
+        }
+        // the nodes of a convex subcircuit are consecutive on each wire
+        let mut boundaries = BTreeMap::<QubitAddr, (Option<NodeId>, Option<NodeId>)>::new();
+        for &id in &subcircuit {
+            let node = self.nodes[id].as_ref().unwrap();
+            for (index, &qubit) in node.qubits.iter().enumerate() {
+                let boundary = boundaries.entry(qubit).or_default();
+                if !node.prev[index].map_or(false, |prev| subcircuit.contains(&prev)) {
+                    boundary.0 = node.prev[index];
+                }
+                if !node.next[index].map_or(false, |next| subcircuit.contains(&next)) {
+                    boundary.1 = node.next[index];
+                }
+            }
+        }
+        let nodes = operations.into_iter()
+            .map(|operation| DagNode::new(operation, stack_top, scope))
+            .collect::<Vec<DagNode>>();
+        if let Some(qubit) = nodes.iter().flat_map(|node| node.qubits.iter())
+            .find(|qubit| !boundaries.contains_key(qubit)) {
+            return Err(QivmError::InvalidArgument(format!(
+                "The operations act on qubit {} outside of the subcircuit", qubit
+            )));
+        }
+
+        for &id in &subcircuit {
+            self.nodes[id] = None;
+        }
+        let mut tails = boundaries.iter()
+            .map(|(&qubit, &(prev, _))| (qubit, prev))
+            .collect::<BTreeMap<QubitAddr, Option<NodeId>>>();
+        let mut inserted = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let qubits = node.qubits.clone();
+            let id = self.insert_node(node);
+            for qubit in qubits {
+                let tail = tails.insert(qubit, Some(id)).unwrap();
+                self.link(qubit, tail, Some(id));
+            }
+            inserted.push(id);
+        }
+        for (qubit, (_, next)) in boundaries {
+            self.link(qubit, tails[&qubit], next);
+        }
+        Ok(inserted)
+    }
+
+    /// Convert the DAG into a circuit, in topological order.
+    pub fn into_circuit(mut self) -> QuantumCircuit {
+        let operations = self.topological_order().into_iter().map(|id| {
+            let node = self.nodes[id].take().unwrap();
+            CircuitOperation::new(node.operation, node.stack_top)
+        }).collect();
+        QuantumCircuit::from_operations(operations)
+    }
+
+    fn insert_node(&mut self, node: DagNode) -> NodeId {
+        self.nodes.push(Some(node));
+        self.nodes.len() - 1
+    }
+
+    /// Link two consecutive nodes on the wire of the qubit, `None` standing for the ends of the
+    /// wire.
+    fn link(&mut self, qubit: QubitAddr, from: Option<NodeId>, to: Option<NodeId>) {
+        let wire = self.wires.entry(qubit).or_default();
+        match from {
+            Some(from) => {
+                let node = self.nodes[from].as_mut().unwrap();
+                let position = node.position(qubit);
+                node.next[position] = to;
+            }
+            None => wire.first = to,
+        }
+        match to {
+            Some(to) => {
+                let node = self.nodes[to].as_mut().unwrap();
+                let position = node.position(qubit);
+                node.prev[position] = from;
+            }
+            None => wire.last = from,
+        }
+        if wire.first.is_none() {
+            self.wires.remove(&qubit);
+        }
+    }
+
+    /// Stack top and scope shared by the nodes.
+    fn subcircuit_scope(&self, ids: &[NodeId]) -> QivmResult<(QubitAddr, usize)> {
+        let mut scopes = ids.iter().map(|&id| {
+            self.node(id).map(|node| (node.stack_top, node.scope)).ok_or_else(|| invalid_node(id))
+        }).collect::<QivmResult<Vec<(QubitAddr, usize)>>>()?;
+        scopes.sort();
+        scopes.dedup();
+        match scopes.as_slice() {
+            [scope] => Ok(*scope),
+            [] => Err(QivmError::InvalidArgument("Empty subcircuit".to_string())),
+            _ => Err(QivmError::InvalidArgument(
+                "The nodes of the subcircuit belong to different scopes".to_string()
+            )),
+        }
+    }
+
+    /// Return true if no path leaving the subcircuit comes back into it.
+    fn is_convex(&self, subcircuit: &HashSet<NodeId>) -> bool {
+        let mut stack = subcircuit.iter()
+            .flat_map(|&id| self.successors(id))
+            .filter(|id| !subcircuit.contains(id))
+            .collect::<Vec<NodeId>>();
+        let mut visited = stack.iter().copied().collect::<HashSet<NodeId>>();
+        while let Some(id) = stack.pop() {
+            for successor in self.successors(id) {
+                if subcircuit.contains(&successor) {
+                    return false;
+                }
+                if visited.insert(successor) {
+                    stack.push(successor);
+                }
+            }
+        }
+        true
+    }
+}
+
+impl From<QuantumCircuit> for CircuitDag {
+    fn from(circuit: QuantumCircuit) -> Self {
+        let mut dag = Self::new();
+        for CircuitOperation { operation, stack_top } in circuit.operations {
+            dag.push(operation, stack_top);
+        }
+        dag
+    }
+}
+
+impl From<CircuitDag> for QuantumCircuit {
+    fn from(dag: CircuitDag) -> Self {
+        dag.into_circuit()
+    }
+}
+
+fn operation_qubits(operation: &Operation, stack_top: QubitAddr) -> Vec<QubitAddr> {
+    match operation {
+        Operation::Elementary(op) => op.get_target().iter().copied().collect(),
+        _ => (0 .. stack_top).collect(),
+    }
+}
+
+fn distinct(ids: &[Option<NodeId>]) -> Vec<NodeId> {
+    let mut distinct = Vec::with_capacity(ids.len());
+    for &id in ids.iter().flatten() {
+        if !distinct.contains(&id) {
+            distinct.push(id);
+        }
+    }
+    distinct
+}
+
+fn invalid_node(id: NodeId) -> QivmError {
+    QivmError::InvalidArgument(format!("Invalid node id: {}", id))
+}
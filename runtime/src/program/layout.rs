@@ -0,0 +1,63 @@
+use crate::qubit::QubitAddr;
+
+/// Placement of the logical qubits of a program on the physical qubits of a device.
+///
+/// The layout is a permutation of the physical qubits: the logical qubits beyond the ones used by
+/// the program stand for the free physical qubits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Physical qubit of each logical qubit.
+    physical: Vec<QubitAddr>,
+    /// Logical qubit of each physical qubit.
+    logical: Vec<QubitAddr>,
+}
+
+impl Layout {
+    /// Layout placing each logical qubit on the physical qubit of the same address.
+    pub fn trivial(size: usize) -> Self {
+        let identity = (0 .. size as QubitAddr).collect::<Vec<QubitAddr>>();
+        Self { physical: identity.clone(), logical: identity }
+    }
+
+    /// Layout placing the logical qubit `i` on the physical qubit `physical[i]`.
+    /// Return `None` if `physical` is not a permutation.
+    pub fn from_physical(physical: Vec<QubitAddr>) -> Option<Self> {
+        let mut logical = vec![None; physical.len()];
+        for (qubit, &physical_qubit) in physical.iter().enumerate() {
+            let slot = logical.get_mut(physical_qubit as usize)?;
+            if slot.replace(qubit as QubitAddr).is_some() {
+                return None;
+            }
+        }
+        let logical = logical.into_iter().collect::<Option<Vec<QubitAddr>>>()?;
+        Some(Self { physical, logical })
+    }
+
+    /// Number of physical qubits.
+    pub fn size(&self) -> usize {
+        self.physical.len()
+    }
+
+    /// Physical qubit holding the logical qubit.
+    pub fn physical(&self, logical: QubitAddr) -> QubitAddr {
+        self.physical[logical as usize]
+    }
+
+    /// Logical qubit held by the physical qubit.
+    pub fn logical(&self, physical: QubitAddr) -> QubitAddr {
+        self.logical[physical as usize]
+    }
+
+    /// Swap the logical qubits held by two physical qubits.
+    pub fn swap_physical(&mut self, first: QubitAddr, second: QubitAddr) {
+        let (first_logical, second_logical) = (self.logical(first), self.logical(second));
+        self.logical.swap(first as usize, second as usize);
+        self.physical[first_logical as usize] = second;
+        self.physical[second_logical as usize] = first;
+    }
+
+    /// Physical qubit of each logical qubit.
+    pub fn to_physical_vec(&self) -> Vec<QubitAddr> {
+        self.physical.clone()
+    }
+}
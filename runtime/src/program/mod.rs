@@ -1,6 +1,10 @@
 pub mod builder;
+pub mod layout;
+pub mod quantum_program;
+pub mod stats;
 mod circuit;
-mod pass;
+mod dag;
+pub(crate) mod pass;
 
 #[cfg(test)]
 mod tests;
@@ -10,20 +14,27 @@ use std::collections::{VecDeque};
 use std::rc::Rc;
 use crate::gate::elementary::{ElementaryGate, SingleGate};
 use crate::gate::{Dagger, SingleTargetGate};
-use crate::algebra::GateMat;
+use crate::algebra::{controlled, DMat, GateMat, IsUnitary};
 use crate::qubit::QubitAddr;
 use crate::qubit::qubit_accessor::QubitAccessor;
-use crate::{QIVM_INSTANCE, qubits, raise_error};
-use crate::backend::RawExecuteResult;
+use crate::{QIVM_INSTANCE, QivmRef, qubits};
+use crate::backend::ExecuteResult;
 use crate::bytecode::ByteCode;
 use crate::bytecode::instruction::{InstrParam, Instruction, PrimitiveOpCode};
-use crate::gate::custom::CustomGate;
+use crate::error::{QivmError, QivmResult};
+use crate::gate::custom::{CustomBuiltinGate, CustomGate};
+use crate::gate::unitary::UnitaryGate;
 use crate::gate::standard::StandardSingleGate::X;
 use crate::measurement::{MeasurementResult, MeasurementResultEntry};
 use crate::operation::controlled::cond_ctrl::ConditionalCtrlOperation;
 use crate::operation::Operation;
 use crate::program::circuit::QuantumCircuit;
+use crate::program::layout::Layout;
 use crate::program::pass::Pass;
+use crate::program::pass::manager::PassManager;
+use crate::program::stats::CircuitStats;
+use crate::qasm;
+use crate::qasm::{QasmResult, QasmVersion};
 use crate::qubit::ctrl_qubit_set::ControlQubitSet;
 use crate::qubit::qubit_set::QubitSet;
 
@@ -38,12 +49,22 @@ pub struct QuantumProgramContext {
     is_dagger: bool,
     circuit: QuantumCircuit,
     measurement: QubitAccessor,
-    transpile_passes: Vec<Box<dyn Pass>>,
+    pass_manager: PassManager,
+    verify_native_gates: bool,
     result: Option<MeasurementResult>,
+    qivm: QivmRef,
 }
 
 impl Default for QuantumProgramContext {
     fn default() -> Self {
+        Self::new(QIVM_INSTANCE.clone())
+    }
+}
+
+impl QuantumProgramContext {
+
+    /// Create a program context running on the given QIVM instance.
+    pub fn new(qivm: QivmRef) -> Self {
         Self {
             ctrl_qubits: ControlQubitSet::new(),
             ctrl_qubits_stack: VecDeque::new(),
@@ -53,49 +74,75 @@ impl Default for QuantumProgramContext {
             is_dagger: false,
             circuit: QuantumCircuit::default(),
             measurement: QubitAccessor::new(),
-            transpile_passes: vec![],
+            pass_manager: PassManager::new(qivm.clone()),
+            verify_native_gates: false,
             result: None,
+            qivm,
         }
     }
-}
 
-impl QuantumProgramContext {
+    pub fn qivm(&self) -> QivmRef {
+        self.qivm.clone()
+    }
 
     pub fn enter(&mut self) {
         self.qubits_stack.push_back(QuantumStackFrame::new(self.stack_top));
     }
 
-    pub fn exit(&mut self) {
-        self.stack_top = self.qubits_stack.pop_back().unwrap_or_else(|| {
-            raise_error!("Invalid exit operation: quantum stack underflow");
-        }).stack_base;
+    pub fn exit(&mut self) -> QivmResult<()> {
+        self.stack_top = self.qubits_stack.pop_back()
+            .ok_or(QivmError::StackUnderflow("quantum"))?
+            .stack_base;
+        Ok(())
     }
 
-    pub fn add_pass(&mut self, pass: impl Pass + 'static) {
-        self.transpile_passes.push(Box::new(pass));
+    /// Append a transpiler pass, and return its unique name in the pass manager.
+    pub fn add_pass(&mut self, pass: impl Pass + 'static) -> String {
+        self.pass_manager.add(pass)
     }
 
-    pub fn alloc(&mut self, size: usize) -> QubitAccessorRef {
-        let new_stack_top = self.stack_top + size as QubitAddr;
+    pub fn pass_manager(&self) -> &PassManager {
+        &self.pass_manager
+    }
+
+    /// Get the pass manager, to check invariants or dump the circuit after the passes.
+    pub fn pass_manager_mut(&mut self) -> &mut PassManager {
+        &mut self.pass_manager
+    }
+
+    /// Check that the transpiled circuit only uses gates available in the backend before it is
+    /// compiled, the compilation fails with the list of the other gates otherwise.
+    pub fn set_verify_native_gates(&mut self, verify: bool) {
+        self.verify_native_gates = verify;
+    }
+
+    pub fn alloc(&mut self, size: usize) -> QivmResult<QubitAccessorRef> {
+        let frame = self.qubits_stack.back_mut().ok_or(QivmError::EmptyQuantumStack)?;
+        let new_stack_top = QubitAddr::try_from(size).ok()
+            .filter(|&size| size > 0)
+            .and_then(|size| self.stack_top.checked_add(size))
+            .ok_or_else(|| QivmError::InvalidArgument(format!(
+                "Unable to allocate {} qubits above {} qubits", size, self.stack_top
+            )))?;
         let accessor = QubitAccessor::range(self.stack_top, new_stack_top - 1);
         self.stack_top = new_stack_top;
-        self.qubits_stack.back_mut().unwrap_or_else(|| {
-            raise_error!("Invalid allocation: quantum stack is empty")
-        }).alloc(accessor)
+        Ok(frame.alloc(accessor))
     }
 
-    pub fn add_qubit_accessor(&mut self, accessor: QubitAccessorRef) {
-        self.qubits_stack.back_mut().unwrap_or_else(|| {
-            raise_error!("Invalid allocation: quantum stack is empty")
-        }).add_qubit_accessor(accessor);
+    pub fn add_qubit_accessor(&mut self, accessor: QubitAccessorRef) -> QivmResult<()> {
+        self.qubits_stack.back_mut()
+            .ok_or(QivmError::EmptyQuantumStack)?
+            .add_qubit_accessor(accessor);
+        Ok(())
     }
 
-    pub fn encode(&mut self, accessor: &QubitAccessor, value: u32) {
-        accessor.to_vec().iter().enumerate().for_each(|(i, qubit)| {
+    pub fn encode(&mut self, accessor: &QubitAccessor, value: u32) -> QivmResult<()> {
+        for (i, qubit) in accessor.to_vec().iter().enumerate() {
             if value >> i == 1 {
-                self.push(X, qubits![*qubit]);
+                self.push(X, qubits![*qubit])?;
             }
-        });
+        }
+        Ok(())
     }
 
     fn push_op(&mut self, op: impl Into<Operation>) {
@@ -106,20 +153,25 @@ impl QuantumProgramContext {
         }
     }
 
-    pub fn push(&mut self, gate: impl Into<ElementaryGate>, target: QubitAccessor) {
+    pub fn push(
+        &mut self, gate: impl Into<ElementaryGate>, target: QubitAccessor
+    ) -> QivmResult<()> {
         if target.iter().any(|&qubit| self.ctrl_qubits.contains(qubit)) {
-            raise_error!("Invalid operation: target qubit is controlled");
+            return Err(QivmError::ControlledTarget);
         }
-        let gate: ElementaryGate = if self.is_dagger {
-            gate.into().dagger()
-        } else {
-            gate.into()
-        };
+        let gate: ElementaryGate = gate.into();
+        if !self.ctrl_qubits.is_empty() && !ConditionalCtrlOperation::is_supported(&gate) {
+            // the controlled matrix is pushed, which is daggered by the nested push
+            let mat = gate.matrix().ok_or_else(|| QivmError::UncontrollableGate(gate.ident()))?;
+            return self.push_controlled_unitary(mat.into(), target);
+        }
+        let gate = if self.is_dagger { gate.dagger() } else { gate };
         if self.ctrl_qubits.is_empty() {
             self.push_op(gate.apply_to(target));
         } else {
             self.push_op(ConditionalCtrlOperation::new(gate, self.ctrl_qubits.clone(), target));
         };
+        Ok(())
     }
 
     pub fn control(&mut self, ctrl: QubitAccessor, condition: bool) {
@@ -136,13 +188,13 @@ impl QuantumProgramContext {
         self.ctrl_qubits_stack.push_back(ctrl);
     }
 
-    pub fn restore_ctrl(&mut self) {
+    pub fn restore_ctrl(&mut self) -> QivmResult<()> {
         if self.ctrl_qubits.size() != 0 {
-            raise_error!("Invalid restore_ctrl operation: current control qubits are not empty");
-        } else if self.ctrl_qubits_stack.is_empty() {
-            raise_error!("Invalid restore_ctrl operation: control qubits stack underflow");
+            return Err(QivmError::ActiveControl);
         }
-        self.ctrl_qubits = self.ctrl_qubits_stack.pop_back().unwrap();
+        self.ctrl_qubits = self.ctrl_qubits_stack.pop_back()
+            .ok_or(QivmError::StackUnderflow("control qubits"))?;
+        Ok(())
     }
 
     pub fn is_dagger(&self) -> bool {
@@ -154,10 +206,9 @@ impl QuantumProgramContext {
         self.is_dagger = !self.is_dagger;
     }
 
-    pub fn end_dagger(&mut self) {
-        let dagger_section = self.dagger_stack.pop_back().unwrap_or_else(|| {
-            raise_error!("Invalid end_dagger operation: dagger stack underflow");
-        });
+    pub fn end_dagger(&mut self) -> QivmResult<()> {
+        let dagger_section = self.dagger_stack.pop_back()
+            .ok_or(QivmError::StackUnderflow("dagger"))?;
         let base_circuit: &mut QuantumCircuit = if self.dagger_stack.is_empty() {
             &mut self.circuit
         } else {
@@ -165,57 +216,180 @@ impl QuantumProgramContext {
         };
         *base_circuit += dagger_section.reversed();
         self.is_dagger = !self.is_dagger;
+        Ok(())
     }
 
+    /// Push a custom gate given by its unitary matrix.
+    ///
+    /// The gate is emitted as is when the backend provides it, otherwise it is pushed as a
+    /// unitary gate which is decomposed by the transpile passes. Multi-target gates under
+    /// control are pushed as the unitary of the controlled gate.
     pub fn push_custom(
         &mut self, ident: String, mat: GateMat,
         params: Vec<u64>, target: QubitAccessor
-    ) {
+    ) -> QivmResult<()> {
         let target_size = mat.target_size();
         if target.size() != target_size {
-            raise_error!("Invalid target size, expected: {}, actual: {}", target_size, target.size());
+            return Err(QivmError::TargetSizeMismatch {
+                expected: target_size, actual: target.size()
+            });
+        }
+        let mat: DMat = mat.into();
+        if !mat.is_unitary() {
+            return Err(QivmError::NonUnitaryGate(ident));
+        }
+        if !self.ctrl_qubits.is_empty() {
+            return if target_size == 1 {
+                self.push(UnitaryGate::from_mat(mat), target)
+            } else {
+                self.push_controlled_unitary(mat, target)
+            };
+        }
+        let gate = CustomGate::new(ident, mat.clone(), params);
+        let ident = if self.is_dagger { gate.clone().dagger().ident() } else { gate.ident() };
+        if self.qivm.lock().unwrap().is_gate_available(&ident) {
+            self.push(gate, target)
         } else {
-            todo!()
+            self.push(UnitaryGate::from_mat(mat), target)
         }
     }
 
+    /// Push the unitary of the gate controlled by the current control qubits, which precede
+    /// the targets.
+    fn push_controlled_unitary(&mut self, mat: DMat, target: QubitAccessor) -> QivmResult<()> {
+        if target.iter().any(|&qubit| self.ctrl_qubits.contains(qubit)) {
+            return Err(QivmError::ControlledTarget);
+        }
+        let (ctrls, conditions): (Vec<QubitAddr>, Vec<bool>) = self.ctrl_qubits.to_vec()
+            .into_iter()
+            .unzip();
+        let qubits = ctrls.into_iter().chain(target.into_iter()).collect::<Vec<QubitAddr>>();
+        let gate = UnitaryGate::from_mat(controlled(&mat, &conditions));
+        self.pause_ctrl();
+        let result = self.push(gate, qubits.into());
+        self.restore_ctrl()?;
+        result
+    }
+
+    /// Push a gate implemented by the backend, whose matrix is unknown to the runtime.
+    ///
+    /// Built-in gates cannot be controlled, and their inverse must be provided by the backend
+    /// as well.
     pub fn push_custom_builtin(
         &mut self, ident: String, params: Vec<u64>,
         size: usize, target: QubitAccessor
-    ) {
+    ) -> QivmResult<()> {
         if target.size() != size {
-            raise_error!("Invalid target size, expected: {}, actual: {}", size, target.size());
-        } else if !QIVM_INSTANCE.lock().unwrap().is_gate_available(&ident) {
-            raise_error!("Invalid primitive gate `{}` on target platform", ident);
-        } else {
-            todo!()
+            return Err(QivmError::TargetSizeMismatch { expected: size, actual: target.size() });
         }
+        if !self.ctrl_qubits.is_empty() {
+            return Err(QivmError::UncontrollableGate(ident));
+        }
+        let gate = CustomBuiltinGate::new(ident, size, params);
+        let ident = if self.is_dagger { gate.clone().dagger().ident() } else { gate.ident() };
+        if !self.qivm.lock().unwrap().is_gate_available(&ident) {
+            return Err(QivmError::UnavailableGate(ident));
+        }
+        self.push(gate, target)
     }
 
     pub fn measure(&mut self, targets: QubitAccessor) {
         self.measurement = targets;
     }
 
-    pub fn transpile(&mut self) {
-        self.transpile_passes.iter_mut().for_each(|pass| {
-            pass.apply(&mut self.circuit)
-        });
+    pub fn transpile(&mut self) -> QivmResult<()> {
+        self.pass_manager.run(&mut self.circuit)
+    }
+
+    /// Statistics of the circuit before the passes, and after each of them, of the last
+    /// transpilation. Empty if the program was not transpiled.
+    pub fn transpile_stats(&self) -> &[CircuitStats] {
+        self.pass_manager.stats()
+    }
+
+    /// Statistics of the circuit in its current state.
+    pub fn circuit_stats(&self) -> CircuitStats {
+        CircuitStats::of(&self.circuit)
     }
 
-    pub fn compile_circuit(&mut self) -> Vec<Instruction> {
-        self.transpile();
-        let mut instructions = self.circuit.compile();
+    pub fn compile_circuit(&mut self) -> QivmResult<Vec<Instruction>> {
+        self.transpile()?;
+        if self.verify_native_gates {
+            self.verify_circuit_gates()?;
+        }
+        let mut instructions = self.circuit.compile()?;
+        // the logical qubits are measured on their physical qubits at the end of the circuit
+        let layout = self.final_layout();
         instructions.push(Instruction::Primitive {
             opcode: PrimitiveOpCode::Measure,
-            params: self.measurement.iter().map(|qubit| {
-                InstrParam::UInt(*qubit as u64)
+            params: self.measurement.iter().map(|&qubit| match layout {
+                Some(layout) if (qubit as usize) < layout.size() => {
+                    InstrParam::UInt(layout.physical(qubit) as u64)
+                }
+                _ => InstrParam::UInt(qubit as u64),
             }).collect(),
         });
-        instructions
+        Ok(instructions)
     }
 
-    pub fn compile_bytecode(&mut self) -> ByteCode {
-        self.compile_circuit().into()
+    /// Placement of the logical qubits on the physical qubits at the end of the transpiled
+    /// circuit, `None` if the qubits were not placed on the device.
+    pub fn final_layout(&self) -> Option<&Layout> {
+        self.circuit.layout.as_ref().or(self.circuit.initial_layout.as_ref())
+    }
+
+    /// Return an error listing the gates of the circuit which are not available in the backend.
+    fn verify_circuit_gates(&self) -> QivmResult<()> {
+        let qivm = self.qivm.lock().unwrap();
+        let unavailable = self.circuit.elementary_idents_except(|op| {
+            qivm.is_gate_available(&op.get_ident())
+        })?;
+        if unavailable.is_empty() {
+            Ok(())
+        } else {
+            Err(QivmError::UnloweredGates(unavailable))
+        }
+    }
+
+    pub fn compile_bytecode(&mut self) -> QivmResult<ByteCode> {
+        Ok(self.compile_circuit()?.into())
+    }
+
+    /// Compile the program to OpenQASM.
+    pub fn export_qasm(&mut self, version: QasmVersion) -> QivmResult<String> {
+        Ok(qasm::export::export(&self.compile_circuit()?, version)?)
+    }
+
+    /// Push the gates and measurements of an OpenQASM program.
+    pub fn import_qasm(&mut self, source: &str) -> QasmResult<()> {
+        qasm::import::import(source, self)
+    }
+
+    /// Compile the program, execute it on the backend and record the measurement result.
+    pub fn execute(&mut self, shots: usize) -> QivmResult<ExecuteResult> {
+        let instructions = self.compile_circuit()?;
+        self.execute_compiled(instructions, shots)
+    }
+
+    /// Execute the instructions compiled from the program and record the measurement result.
+    /// Return an error with the error code of the backend if it fails to execute them.
+    pub fn execute_compiled(
+        &mut self, instructions: Vec<Instruction>, shots: usize
+    ) -> QivmResult<ExecuteResult> {
+        let bytecode: ByteCode = instructions.into();
+        let mut result = self.qivm.lock().unwrap().execute(&bytecode, shots);
+        if result.error_code != 0 {
+            return Err(QivmError::Execution(result.error_code));
+        }
+        if let Some(layout) = self.final_layout() {
+            // the backends put the result bit of a qubit at the position of its physical qubit
+            for entry in &mut result.measurement.measurements {
+                entry.value = logical_value(entry.value, layout);
+            }
+            result.measurement.measurements.sort_by_key(|entry| entry.value);
+        }
+        self.set_measurement_result(result.measurement.clone());
+        Ok(result)
     }
 
     pub fn set_measurement_result(&mut self, result: MeasurementResult) {
@@ -227,6 +401,18 @@ impl QuantumProgramContext {
     }
 }
 
+/// Move the bit of the physical qubit of each logical qubit of the layout to the position of the
+/// logical qubit.
+fn logical_value(value: u64, layout: &Layout) -> u64 {
+    (0 .. u64::BITS).filter(|&bit| value >> bit & 1 == 1).fold(0, |logical, bit| {
+        if (bit as usize) < layout.size() {
+            logical | 1 << layout.logical(bit as QubitAddr)
+        } else {
+            logical | 1 << bit
+        }
+    })
+}
+
 struct QuantumStackFrame {
     stack_base: QubitAddr,
     qubit_accessors: Vec<QubitAccessorRef>,
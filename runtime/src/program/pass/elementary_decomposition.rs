@@ -1,25 +1,58 @@
+use crate::error::{QivmError, QivmResult};
 use crate::operation::Operation;
 use crate::program::circuit::QuantumCircuit;
 use crate::program::pass::Pass;
-use crate::{QIVM_INSTANCE, raise_error};
+use crate::QivmRef;
 
-/// Decompose elementary gates with the decomposer.
-pub struct ElementaryDecompositionPass;
+/// Bound of the decomposition rounds, in case a decomposition keeps producing unavailable gates.
+const MAX_ROUNDS: usize = 16;
+
+/// Decompose elementary gates with the decomposer of the QIVM instance.
+///
+/// The unavailable gates are decomposed until the circuit reaches a fixpoint, or after
+/// `MAX_ROUNDS` rounds. If some gates are still unavailable, the pass fails with the list of
+/// their idents.
+pub struct ElementaryDecompositionPass {
+    qivm: QivmRef,
+}
+
+impl ElementaryDecompositionPass {
+    pub fn new(qivm: QivmRef) -> Self {
+        Self { qivm }
+    }
+}
 
 impl Pass for ElementaryDecompositionPass {
-    fn apply(&mut self, circuit: &mut QuantumCircuit) {
-        let mut qivm = QIVM_INSTANCE.lock().unwrap();
-        while circuit.elementary_all(|op| qivm.is_gate_available(&op.get_ident())) {
-            circuit.flat_replace_operation(|op| {
-                match op {
-                    Operation::Elementary(op)
-                    if !qivm.is_gate_available(&op.get_ident()) => {
-                        Some(qivm.decompose_elementary(&op.clone()))
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
+        let mut qivm = self.qivm.lock().unwrap();
+        for _ in 0 .. MAX_ROUNDS {
+            let mut is_changed = false;
+            circuit.try_flat_replace_operation(|op| match op {
+                Operation::Elementary(op) if qivm.is_gate_available(&op.get_ident()) => Ok(None),
+                Operation::Elementary(op) => match qivm.decompose_elementary(op) {
+                    Ok(decomposed) => {
+                        is_changed = true;
+                        Ok(Some(decomposed))
                     }
-                    _ => raise_error!("`ElementaryDecompositionPass` accepts only elementary gates")
-                }
-            })
+                    // the gate is reported at the fixpoint
+                    Err(QivmError::Decompose(_)) => Ok(None),
+                    Err(err) => Err(err),
+                },
+                _ => Err(QivmError::Compile(
+                    "`ElementaryDecompositionPass` accepts only elementary gates".to_string()
+                )),
+            })?;
+            if !is_changed {
+                break;
+            }
+        }
+        let unavailable = circuit.elementary_idents_except(|op| {
+            qivm.is_gate_available(&op.get_ident())
+        })?;
+        if unavailable.is_empty() {
+            Ok(())
+        } else {
+            Err(QivmError::UnloweredGates(unavailable))
         }
     }
 }
-
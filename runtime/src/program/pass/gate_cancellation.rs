@@ -0,0 +1,247 @@
+use std::mem::take;
+use crate::algebra::close_to_zero;
+use crate::error::QivmResult;
+use crate::gate::{Dagger, DoubleTargetGate, SingleTargetGate};
+use crate::gate::elementary::is_identity;
+use crate::gate::standard::{StandardDoubleGate, StandardSingleGate, StandardTripleGate};
+use crate::operation::elementary::ElementaryOperation;
+use crate::operation::elementary::standard::StandardOperation;
+use crate::operation::{
+    DoubleTargetOperation, ElementaryGateOperation, Operation, SingleTargetOperation,
+    TripleTargetOperation,
+};
+use crate::program::circuit::{CircuitOperation, QuantumCircuit};
+use crate::program::pass::Pass;
+use crate::qubit::QubitAddr;
+
+/// Cancel pairs of inverse gates and merge consecutive rotations.
+///
+/// Each gate is moved backward through the gates it commutes with, until it meets a gate it
+/// merges with:
+/// - a pair of inverse gates (H·H, CX·CX, T·TD, ...) cancels out,
+/// - rotations around the same axis, and controlled phases, are merged into one,
+/// - S·S is folded into Z and T·T into S.
+///
+/// Gates are not moved across a change of the stack top, where qubits may be reset.
+pub struct GateCancellationPass {
+    commutation: bool,
+}
+
+impl GateCancellationPass {
+    pub fn new() -> Self {
+        Self { commutation: true }
+    }
+
+    /// Only merge gates which are adjacent on their qubits, without moving them through the
+    /// gates they commute with.
+    pub fn adjacent() -> Self {
+        Self { commutation: false }
+    }
+}
+
+impl Default for GateCancellationPass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pass for GateCancellationPass {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
+        let mut operations = Vec::<CircuitOperation>::with_capacity(circuit.operations.len());
+        for operation in take(&mut circuit.operations) {
+            push_merged(&mut operations, operation, self.commutation);
+        }
+        circuit.operations = operations;
+        Ok(())
+    }
+}
+
+fn push_merged(
+    operations: &mut Vec<CircuitOperation>, operation: CircuitOperation, commutation: bool
+) {
+    for index in (0 .. operations.len()).rev() {
+        let previous = &operations[index];
+        if previous.stack_top != operation.stack_top {
+            break;
+        }
+        if let Some(merged) = merge(&previous.operation, &operation.operation) {
+            match merged {
+                Some(merged) => operations[index].operation = merged,
+                None => {
+                    operations.remove(index);
+                }
+            }
+            return;
+        }
+        let moved = if commutation {
+            commute(&previous.operation, &operation.operation)
+        } else {
+            disjoint(&previous.operation, &operation.operation)
+        };
+        if !moved {
+            break;
+        }
+    }
+    operations.push(operation);
+}
+
+/// Merge two operations applied one after the other.
+///
+/// Return `None` if they cannot be merged, `Some(None)` if they cancel out, and the merged
+/// operation otherwise.
+fn merge(first: &Operation, second: &Operation) -> Option<Option<Operation>> {
+    use StandardOperation::*;
+    let (first, second) = match (first, second) {
+        (
+            Operation::Elementary(ElementaryOperation::Standard(first)),
+            Operation::Elementary(ElementaryOperation::Standard(second)),
+        ) => (first, second),
+        _ => return None,
+    };
+    match (first, second) {
+        (Single(first), Single(second)) if first.get_target() == second.get_target() => {
+            let merged = merge_single(first.get_gate(), second.get_gate())?;
+            Some(merged.map(|gate| gate.apply_to(first.get_target()).into()))
+        }
+        (Double(first), Double(second)) => {
+            let (target, other_target) = (first.get_target(), second.get_target());
+            // all the standard double gates but CX are symmetric
+            let is_same_target = target == other_target || (
+                first.get_gate() != StandardDoubleGate::CX
+                    && target == (other_target.1, other_target.0)
+            );
+            if !is_same_target {
+                return None;
+            }
+            let merged = merge_double(first.get_gate(), second.get_gate())?;
+            Some(merged.map(|gate| gate.apply_to(target).into()))
+        }
+        (Triple(first), Triple(second)) => {
+            let (target, other_target) = (first.get_target(), second.get_target());
+            // the controls of CCX are symmetric
+            let is_same_target = target == other_target
+                || target == (other_target.1, other_target.0, other_target.2);
+            let is_inverse = first.get_gate().dagger() == second.get_gate();
+            (is_same_target && is_inverse).then(|| None)
+        }
+        _ => None,
+    }
+}
+
+fn merge_single(
+    first: StandardSingleGate, second: StandardSingleGate
+) -> Option<Option<StandardSingleGate>> {
+    use StandardSingleGate::*;
+    if first.dagger() == second {
+        return Some(None);
+    }
+    let merged = match (first, second) {
+        (RX { angle: first }, RX { angle: second }) => RX { angle: first + second },
+        (RY { angle: first }, RY { angle: second }) => RY { angle: first + second },
+        (RZ { angle: first }, RZ { angle: second }) => RZ { angle: first + second },
+        (P { angle: first }, P { angle: second }) => P { angle: first + second },
+        (XPOW { t: first }, XPOW { t: second }) => XPOW { t: first + second },
+        (YPOW { t: first }, YPOW { t: second }) => YPOW { t: first + second },
+        (ZPOW { t: first }, ZPOW { t: second }) => ZPOW { t: first + second },
+        (S, S) | (SD, SD) => Z,
+        (T, T) => S,
+        (TD, TD) => SD,
+        _ => return None,
+    };
+    let is_identity = match merged {
+        XPOW { t } | YPOW { t } | ZPOW { t } => close_to_zero(t),
+        gate => is_identity(&gate),
+    };
+    Some((!is_identity).then(|| merged))
+}
+
+fn merge_double(
+    first: StandardDoubleGate, second: StandardDoubleGate
+) -> Option<Option<StandardDoubleGate>> {
+    use StandardDoubleGate::*;
+    if first.dagger() == second {
+        return Some(None);
+    }
+    match (first, second) {
+        (CP { angle: first }, CP { angle: second }) => {
+            let angle = first + second;
+            Some((!close_to_zero(angle)).then(|| CP { angle }))
+        }
+        _ => None,
+    }
+}
+
+/// Basis in which an operation acts on one of its qubits.
+#[derive(Copy, Clone, PartialEq)]
+enum Basis {
+    /// The operation is diagonal on the qubit, or controlled by it.
+    Z,
+    /// The operation is diagonal in the X basis on the qubit.
+    X,
+    General,
+}
+
+/// Basis of each qubit the operation acts on, or `None` for non-elementary operations.
+fn qubit_bases(operation: &Operation) -> Option<Vec<(QubitAddr, Basis)>> {
+    use StandardOperation::*;
+    let operation = match operation {
+        Operation::Elementary(operation) => operation,
+        _ => return None,
+    };
+    let bases = match operation {
+        ElementaryOperation::Standard(Single(op)) => {
+            vec![(op.get_target(), single_basis(op.get_gate()))]
+        }
+        ElementaryOperation::Standard(Double(op)) => {
+            let (first, second) = op.get_target();
+            match op.get_gate() {
+                StandardDoubleGate::CX => vec![(first, Basis::Z), (second, Basis::X)],
+                StandardDoubleGate::CZ | StandardDoubleGate::CP { .. } => {
+                    vec![(first, Basis::Z), (second, Basis::Z)]
+                }
+                _ => vec![(first, Basis::General), (second, Basis::General)],
+            }
+        }
+        ElementaryOperation::Standard(Triple(op)) => {
+            let (first, second, target) = op.get_target();
+            match op.get_gate() {
+                StandardTripleGate::CCX => {
+                    vec![(first, Basis::Z), (second, Basis::Z), (target, Basis::X)]
+                }
+            }
+        }
+        _ => operation.get_target().iter().map(|&qubit| (qubit, Basis::General)).collect(),
+    };
+    Some(bases)
+}
+
+fn single_basis(gate: StandardSingleGate) -> Basis {
+    use StandardSingleGate::*;
+    match gate {
+        I | Z | ZPOW { .. } | S | SD | T | TD | RZ { .. } | P { .. } => Basis::Z,
+        X | XPOW { .. } | RX { .. } | V | VD => Basis::X,
+        _ => Basis::General,
+    }
+}
+
+/// Return true if the operations commute: they act in the same basis on each shared qubit.
+fn commute(first: &Operation, second: &Operation) -> bool {
+    match (qubit_bases(first), qubit_bases(second)) {
+        (Some(first), Some(second)) => first.iter().all(|&(qubit, basis)| {
+            second.iter().all(|&(other_qubit, other_basis)| {
+                qubit != other_qubit || (basis == other_basis && basis != Basis::General)
+            })
+        }),
+        _ => false,
+    }
+}
+
+/// Return true if the operations are elementary and act on distinct qubits.
+fn disjoint(first: &Operation, second: &Operation) -> bool {
+    match (qubit_bases(first), qubit_bases(second)) {
+        (Some(first), Some(second)) => first.iter().all(|&(qubit, _)| {
+            second.iter().all(|&(other_qubit, _)| qubit != other_qubit)
+        }),
+        _ => false,
+    }
+}
@@ -0,0 +1,277 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use crate::backend::coupling_map::CouplingMap;
+use crate::error::{QivmError, QivmResult};
+use crate::operation::{ElementaryGateOperation, Operation};
+use crate::program::circuit::QuantumCircuit;
+use crate::program::layout::Layout;
+use crate::program::pass::Pass;
+use crate::qubit::QubitAddr;
+use crate::QivmRef;
+
+/// Maximum number of rounds of the local search over the pairs of physical qubits.
+const MAX_ROUNDS: usize = 16;
+
+/// Choose the physical qubits the logical qubits are placed on before routing.
+///
+/// The cost of a layout is the number of swaps the two-qubit gates need, the distance between
+/// their qubits minus one, and then the expected error of the gates: the error of the couplings
+/// on the shortest path between the qubits of the two-qubit gates, and the error of the qubits
+/// of the single-qubit gates. Layouts needing fewer swaps are preferred, the error breaks ties.
+///
+/// The logical qubits interacting the most are placed first, each on the free physical qubit of
+/// the lowest cost. The layout is then improved by swapping pairs of physical qubits, as long as
+/// the cost decreases.
+///
+/// The targets are rewritten to the physical qubits, and the initial layout of the circuit is
+/// set. The pass does nothing without coupling map, if the circuit is already placed, or if it
+/// uses more qubits than the coupling map has, which is reported by the routing.
+pub struct LayoutSelectionPass {
+    qivm: QivmRef,
+}
+
+impl LayoutSelectionPass {
+    pub fn new(qivm: QivmRef) -> Self {
+        Self { qivm }
+    }
+}
+
+impl Pass for LayoutSelectionPass {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
+        let coupling_map = match self.qivm.lock().unwrap().coupling_map() {
+            Some(coupling_map) if circuit.initial_layout.is_none() && circuit.layout.is_none() => {
+                coupling_map.clone()
+            }
+            _ => return Ok(()),
+        };
+        let interactions = Interactions::of_circuit(circuit)?;
+        if interactions.size > coupling_map.size() || !coupling_map.is_connected() {
+            return Ok(());
+        }
+        let layout = Placement::new(&coupling_map, &interactions).select();
+        for op in &mut circuit.operations {
+            if let Operation::Elementary(elementary) = &op.operation {
+                let target = elementary.get_target().iter()
+                    .map(|&qubit| layout.physical(qubit))
+                    .collect::<Vec<QubitAddr>>();
+                op.operation = elementary.get_gate().apply_to(target.into()).into();
+            }
+        }
+        circuit.initial_layout = Some(layout);
+        Ok(())
+    }
+}
+
+/// Interaction graph of the logical qubits of a circuit.
+struct Interactions {
+    /// Number of logical qubits used by the circuit.
+    size: usize,
+    /// Number of two-qubit gates on each pair of logical qubits, in ascending order.
+    pairs: BTreeMap<(QubitAddr, QubitAddr), usize>,
+    /// Number of single-qubit gates on each logical qubit.
+    singles: Vec<usize>,
+}
+
+impl Interactions {
+    fn of_circuit(circuit: &QuantumCircuit) -> QivmResult<Self> {
+        let mut size = 0;
+        let mut pairs = BTreeMap::new();
+        let mut singles = vec![];
+        for op in &circuit.operations {
+            let elementary = match &op.operation {
+                Operation::Elementary(elementary) => elementary,
+                _ => return Err(QivmError::Compile(
+                    "`LayoutSelectionPass` accepts only elementary gates".to_string()
+                )),
+            };
+            let target = elementary.get_target().to_vec();
+            size = target.iter().fold(size.max(op.stack_top as usize), |size, &qubit| {
+                size.max(qubit as usize + 1)
+            });
+            singles.resize(size, 0);
+            match target[..] {
+                [qubit] => singles[qubit as usize] += 1,
+                [first, second] => {
+                    *pairs.entry((first.min(second), first.max(second))).or_insert(0) += 1;
+                }
+                _ => {}
+            }
+        }
+        Ok(Self { size, pairs, singles })
+    }
+
+    /// Number of two-qubit gates between the logical qubit and the ones accepted by `filter`.
+    fn weight_to(&self, qubit: QubitAddr, filter: impl Fn(QubitAddr) -> bool) -> usize {
+        self.pairs.iter().filter_map(|(&(first, second), &count)| {
+            if qubit == first && filter(second) || qubit == second && filter(first) {
+                Some(count)
+            } else {
+                None
+            }
+        }).sum()
+    }
+}
+
+/// Expected number of swaps and error of a layout.
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Cost {
+    swaps: usize,
+    error: f64,
+}
+
+impl Cost {
+    fn add(self, other: Cost) -> Cost {
+        Cost { swaps: self.swaps + other.swaps, error: self.error + other.error }
+    }
+}
+
+impl PartialOrd for Cost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.swaps.cmp(&other.swaps).then(self.error.total_cmp(&other.error)))
+    }
+}
+
+struct Placement<'a> {
+    coupling_map: &'a CouplingMap,
+    interactions: &'a Interactions,
+    distances: Vec<Vec<usize>>,
+    /// Sum of the log-infidelities of the couplings on the most reliable path between qubits.
+    path_errors: Vec<Vec<f64>>,
+}
+
+impl<'a> Placement<'a> {
+    fn new(coupling_map: &'a CouplingMap, interactions: &'a Interactions) -> Self {
+        let size = coupling_map.size();
+        let mut path_errors = vec![vec![f64::INFINITY; size]; size];
+        for (qubit, errors) in path_errors.iter_mut().enumerate() {
+            errors[qubit] = 0.0;
+        }
+        for (first, second) in coupling_map.edges() {
+            let error = log_infidelity(coupling_map.edge_error(first, second));
+            path_errors[first as usize][second as usize] = error;
+            path_errors[second as usize][first as usize] = error;
+        }
+        // Floyd-Warshall
+        for middle in 0 .. size {
+            for from in 0 .. size {
+                for to in 0 .. size {
+                    let through = path_errors[from][middle] + path_errors[middle][to];
+                    if through < path_errors[from][to] {
+                        path_errors[from][to] = through;
+                    }
+                }
+            }
+        }
+        Self { coupling_map, interactions, distances: coupling_map.distances(), path_errors }
+    }
+
+    /// Cost of `count` two-qubit gates on logical qubits placed on two physical qubits.
+    fn pair_cost(&self, count: usize, first: QubitAddr, second: QubitAddr) -> Cost {
+        let (first, second) = (first as usize, second as usize);
+        Cost {
+            swaps: count * (self.distances[first][second] - 1),
+            error: count as f64 * self.path_errors[first][second],
+        }
+    }
+
+    /// Cost of `count` single-qubit gates on a logical qubit placed on a physical qubit.
+    fn single_cost(&self, count: usize, physical: QubitAddr) -> Cost {
+        Cost {
+            swaps: 0,
+            error: count as f64 * log_infidelity(self.coupling_map.qubit_error(physical)),
+        }
+    }
+
+    fn cost(&self, physical: &[QubitAddr]) -> Cost {
+        let pairs = self.interactions.pairs.iter().map(|(&(first, second), &count)| {
+            self.pair_cost(count, physical[first as usize], physical[second as usize])
+        });
+        let singles = self.interactions.singles.iter().enumerate().map(|(qubit, &count)| {
+            self.single_cost(count, physical[qubit])
+        });
+        pairs.chain(singles).fold(Cost::default(), Cost::add)
+    }
+
+    fn select(&self) -> Layout {
+        let mut physical = self.place_greedily();
+        let mut cost = self.cost(&physical);
+        for _ in 0 .. MAX_ROUNDS {
+            let mut improved = false;
+            // swap a logical qubit with another one, or move it onto a free physical qubit
+            for first in 0 .. self.interactions.size {
+                for second in first + 1 .. physical.len() {
+                    physical.swap(first, second);
+                    let swapped = self.cost(&physical);
+                    if swapped < cost {
+                        cost = swapped;
+                        improved = true;
+                    } else {
+                        physical.swap(first, second);
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        Layout::from_physical(physical).unwrap()
+    }
+
+    /// Place the logical qubits one after the other, the one interacting the most with the placed
+    /// ones first, each on the free physical qubit of the lowest cost. Return the physical qubit
+    /// of each logical qubit, the qubits not used by the circuit are placed on the free ones.
+    fn place_greedily(&self) -> Vec<QubitAddr> {
+        let size = self.coupling_map.size();
+        let mut physical = vec![None; size];
+        let mut placed = vec![false; size];
+        let mut occupied = vec![false; size];
+        for _ in 0 .. self.interactions.size {
+            // ties are broken by the number of gates with all the qubits, then by the address
+            let logical = (0 .. self.interactions.size as QubitAddr)
+                .filter(|&qubit| !placed[qubit as usize])
+                .max_by_key(|&qubit| (
+                    self.interactions.weight_to(qubit, |other| placed[other as usize]),
+                    self.interactions.weight_to(qubit, |_| true),
+                    Reverse(qubit),
+                ))
+                .unwrap();
+            // ties are broken by the number of neighbours
+            let target = (0 .. size as QubitAddr)
+                .filter(|&qubit| !occupied[qubit as usize])
+                .map(|qubit| (self.placement_cost(logical, qubit, &physical), qubit))
+                .min_by(|(cost, qubit), (other_cost, other)| {
+                    cost.partial_cmp(other_cost).unwrap().then_with(|| {
+                        let degree = |qubit: &QubitAddr| self.coupling_map.neighbours(*qubit).len();
+                        degree(other).cmp(&degree(qubit))
+                    })
+                })
+                .unwrap().1;
+            physical[logical as usize] = Some(target);
+            placed[logical as usize] = true;
+            occupied[target as usize] = true;
+        }
+        let mut free = (0 .. size as QubitAddr).filter(|&qubit| !occupied[qubit as usize]);
+        physical.into_iter().map(|qubit| qubit.or_else(|| free.next()).unwrap()).collect()
+    }
+
+    /// Cost of the gates between a logical qubit placed on a physical one and the placed qubits.
+    fn placement_cost(
+        &self, logical: QubitAddr, candidate: QubitAddr, physical: &[Option<QubitAddr>]
+    ) -> Cost {
+        let pairs = self.interactions.pairs.iter().filter_map(|(&(first, second), &count)| {
+            let other = match logical {
+                _ if logical == first => second,
+                _ if logical == second => first,
+                _ => return None,
+            };
+            physical[other as usize].map(|other| self.pair_cost(count, candidate, other))
+        });
+        let single = self.single_cost(self.interactions.singles[logical as usize], candidate);
+        pairs.fold(single, Cost::add)
+    }
+}
+
+/// Negative logarithm of the success probability of a gate, which adds up along a sequence.
+fn log_infidelity(error: f64) -> f64 {
+    -(1.0 - error).max(f64::MIN_POSITIVE).ln()
+}
@@ -0,0 +1,318 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use num::complex::Complex64;
+use crate::algebra::DMat;
+use crate::error::{QivmError, QivmResult};
+use crate::operation::{ElementaryGateOperation, Operation};
+use crate::program::circuit::QuantumCircuit;
+use crate::program::layout::Layout;
+use crate::program::pass::Pass;
+use crate::program::stats::CircuitStats;
+use crate::qubit::QubitAddr;
+use crate::QivmRef;
+
+/// Tolerance of the comparison of the unitaries of the circuits.
+const UNITARY_TOLERANCE: f64 = 1e-6;
+
+/// Invariant checked on the circuit after a pass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvariantCheck {
+    /// The unitary of the circuit is unchanged by the pass, up to a global phase and to the
+    /// placement of the qubits. Only checked when the circuits before and after the pass act on
+    /// at most `max_qubits` qubits, without reset nor non-elementary operation.
+    UnitaryEquivalence { max_qubits: usize },
+    /// The circuit only uses gates available in the backend.
+    NativeGates,
+}
+
+struct ManagedPass {
+    name: String,
+    pass: Box<dyn Pass>,
+    checks: Vec<InvariantCheck>,
+    dump_path: Option<PathBuf>,
+}
+
+/// Run the transpiler passes of a program in order.
+///
+/// Each pass has a unique name, the name of its type followed by `#2`, `#3`, ... when the pass
+/// is added several times. The manager checks invariants on the circuit after the passes, dumps
+/// the circuit after a pass in the textual assembly form, and records the time spent in each pass
+/// with the statistics of the circuit before the passes and after each of them.
+pub struct PassManager {
+    qivm: QivmRef,
+    passes: Vec<ManagedPass>,
+    /// Invariants checked after every pass.
+    global_checks: Vec<InvariantCheck>,
+    logging: bool,
+    stats: Vec<CircuitStats>,
+    timings: Vec<(String, Duration)>,
+}
+
+impl PassManager {
+    pub fn new(qivm: QivmRef) -> Self {
+        Self {
+            qivm,
+            passes: vec![],
+            global_checks: vec![],
+            logging: false,
+            stats: vec![],
+            timings: vec![],
+        }
+    }
+
+    /// Append a pass named after its type, and return the unique name of the pass.
+    pub fn add(&mut self, pass: impl Pass + 'static) -> String {
+        let name = pass.name();
+        self.add_named(&name, pass)
+    }
+
+    /// Append a pass with the given name, and return the unique name of the pass.
+    pub fn add_named(&mut self, name: &str, pass: impl Pass + 'static) -> String {
+        let mut unique_name = name.to_string();
+        let mut index = 1;
+        while self.position(&unique_name).is_some() {
+            index += 1;
+            unique_name = format!("{}#{}", name, index);
+        }
+        self.passes.push(ManagedPass {
+            name: unique_name.clone(),
+            pass: Box::new(pass),
+            checks: vec![],
+            dump_path: None,
+        });
+        unique_name
+    }
+
+    /// Names of the passes in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name.as_str()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Check the invariant on the circuit after the named pass.
+    pub fn check_after(&mut self, pass: &str, check: InvariantCheck) -> QivmResult<()> {
+        self.pass_mut(pass)?.checks.push(check);
+        Ok(())
+    }
+
+    /// Check the invariant on the circuit after every pass.
+    pub fn check_after_each(&mut self, check: InvariantCheck) {
+        self.global_checks.push(check);
+    }
+
+    /// Write the circuit after the named pass to the file in the textual assembly form, the file
+    /// is overwritten by each transpilation.
+    pub fn dump_after(&mut self, pass: &str, path: impl Into<PathBuf>) -> QivmResult<()> {
+        self.pass_mut(pass)?.dump_path = Some(path.into());
+        Ok(())
+    }
+
+    /// Print the time spent in each pass and the size of the circuit after it to the standard
+    /// error.
+    pub fn set_logging(&mut self, enabled: bool) {
+        self.logging = enabled;
+    }
+
+    /// Statistics of the circuit before the passes, and after each of them, of the last run.
+    pub fn stats(&self) -> &[CircuitStats] {
+        &self.stats
+    }
+
+    /// Wall-clock time spent in each pass of the last run, the checks and dumps excluded.
+    pub fn timings(&self) -> &[(String, Duration)] {
+        &self.timings
+    }
+
+    /// Apply the passes to the circuit in order.
+    pub fn run(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
+        self.stats = vec![CircuitStats::of(circuit)];
+        self.timings = vec![];
+        for index in 0 .. self.passes.len() {
+            let checks = self.global_checks.iter().chain(&self.passes[index].checks)
+                .copied()
+                .collect::<Vec<_>>();
+            let max_qubits = checks.iter().filter_map(|check| match check {
+                InvariantCheck::UnitaryEquivalence { max_qubits } => Some(*max_qubits),
+                _ => None,
+            }).max();
+            let unitary_before = max_qubits.and_then(|max_qubits| {
+                logical_unitary(circuit, max_qubits)
+            });
+
+            let managed = &mut self.passes[index];
+            let start = Instant::now();
+            managed.pass.apply(circuit)?;
+            let duration = start.elapsed();
+            let stats = CircuitStats::of(circuit);
+            if self.logging {
+                eprintln!(
+                    "[QIVM Pass] {}: {:.3} ms, {} gates, depth {}",
+                    managed.name, duration.as_secs_f64() * 1e3, stats.gate_count(), stats.depth
+                );
+            }
+            self.timings.push((managed.name.clone(), duration));
+            self.stats.push(stats);
+
+            for check in checks {
+                self.check(index, check, circuit, unitary_before.as_ref())?;
+            }
+            if let Some(path) = &self.passes[index].dump_path {
+                // a dump is a debugging aid, failing to write it does not fail the transpilation
+                if let Err(err) = fs::write(path, circuit.disassemble()) {
+                    eprintln!(
+                        "[QIVM Error] Unable to dump the circuit to `{}`: {}", path.display(), err
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check(
+        &self, index: usize, check: InvariantCheck, circuit: &QuantumCircuit,
+        unitary_before: Option<&DMat>,
+    ) -> QivmResult<()> {
+        let violation = |reason: String| QivmError::InvariantViolation {
+            pass: self.passes[index].name.clone(), reason,
+        };
+        match check {
+            InvariantCheck::UnitaryEquivalence { max_qubits } => {
+                let unitary_after = logical_unitary(circuit, max_qubits);
+                match (unitary_before, unitary_after) {
+                    (Some(before), Some(after)) if !is_equal_up_to_phase(before, &after) => {
+                        Err(violation("the unitary of the circuit is changed".to_string()))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            InvariantCheck::NativeGates => {
+                let qivm = self.qivm.lock().unwrap();
+                let unavailable = circuit.elementary_idents_except(|op| {
+                    qivm.is_gate_available(&op.get_ident())
+                }).map_err(|_| violation("the circuit has non-elementary operations".to_string()))?;
+                if unavailable.is_empty() {
+                    Ok(())
+                } else {
+                    Err(violation(format!("non-native gates `{}`", unavailable.join("`, `"))))
+                }
+            }
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|pass| pass.name == name)
+    }
+
+    fn pass_mut(&mut self, name: &str) -> QivmResult<&mut ManagedPass> {
+        match self.position(name) {
+            Some(index) => Ok(&mut self.passes[index]),
+            None => Err(QivmError::InvalidArgument(format!("No pass named `{}`", name))),
+        }
+    }
+}
+
+/// Unitary of the circuit on the logical qubits, `None` if the circuit acts on more than
+/// `max_qubits` qubits, resets qubits, has non-elementary operations or built-in gates.
+///
+/// The qubit `i` is the bit `i` of the basis states, the first target of a gate is the most
+/// significant qubit of its matrix.
+fn logical_unitary(circuit: &QuantumCircuit, max_qubits: usize) -> Option<DMat> {
+    let layouts = [&circuit.initial_layout, &circuit.layout];
+    let mut size = layouts.iter().filter_map(|layout| layout.as_ref().map(Layout::size))
+        .max()
+        .unwrap_or(0);
+    let mut last_stack_top = 0;
+    for op in &circuit.operations {
+        let elementary = match &op.operation {
+            Operation::Elementary(elementary) => elementary,
+            _ => return None,
+        };
+        if op.stack_top < last_stack_top {
+            return None;
+        }
+        last_stack_top = op.stack_top;
+        let highest_target = elementary.get_target().iter().max().map_or(0, |&qubit| qubit + 1);
+        size = size.max(highest_target.max(op.stack_top) as usize);
+    }
+    if size > max_qubits {
+        return None;
+    }
+
+    let dim = 1 << size;
+    let mut unitary = DMat::identity(dim, dim);
+    for op in &circuit.operations {
+        if let Operation::Elementary(elementary) = &op.operation {
+            // the matrix of a built-in gate is unknown, the check is skipped
+            let gate: DMat = elementary.get_gate().matrix()?.into();
+            apply_gate(&mut unitary, &gate, &elementary.get_target().to_vec());
+        }
+    }
+    // the operations act on the physical qubits of the layouts
+    let initial_layout = circuit.initial_layout.as_ref();
+    let final_layout = circuit.layout.as_ref().or(initial_layout);
+    Some(DMat::from_fn(dim, dim, |row, column| {
+        unitary[(physical_state(row, final_layout), physical_state(column, initial_layout))]
+    }))
+}
+
+/// Multiply the unitary by the gate acting on the targets, on the left.
+fn apply_gate(unitary: &mut DMat, gate: &DMat, targets: &[QubitAddr]) {
+    let local_states = 1 << targets.len();
+    let local_state = |state: usize, local: usize| {
+        targets.iter().enumerate().fold(state, |state, (index, &qubit)| {
+            let bit = (local >> (targets.len() - 1 - index)) & 1;
+            state & !(1 << qubit) | bit << qubit
+        })
+    };
+    let mask = targets.iter().fold(0, |mask, &qubit| mask | 1 << qubit);
+    let mut amplitudes = vec![Complex64::default(); local_states];
+    for column in 0 .. unitary.ncols() {
+        for base in (0 .. unitary.nrows()).filter(|state| state & mask == 0) {
+            for (local, amplitude) in amplitudes.iter_mut().enumerate() {
+                *amplitude = unitary[(local_state(base, local), column)];
+            }
+            for row in 0 .. local_states {
+                unitary[(local_state(base, row), column)] = (0 .. local_states)
+                    .map(|local| gate[(row, local)] * amplitudes[local])
+                    .sum();
+            }
+        }
+    }
+}
+
+/// Basis state of the physical qubits holding the basis state of the logical qubits.
+fn physical_state(state: usize, layout: Option<&Layout>) -> usize {
+    match layout {
+        Some(layout) => (0 .. layout.size()).filter(|&qubit| state >> qubit & 1 == 1)
+            .fold(state >> layout.size() << layout.size(), |physical, qubit| {
+                physical | 1 << layout.physical(qubit as QubitAddr)
+            }),
+        None => state,
+    }
+}
+
+/// Compare the unitaries up to a global phase, the unitary on fewer qubits is extended with the
+/// identity on the missing qubits.
+fn is_equal_up_to_phase(expected: &DMat, actual: &DMat) -> bool {
+    let extend = |unitary: &DMat, dim: usize| {
+        DMat::identity(dim / unitary.nrows(), dim / unitary.nrows()).kronecker(unitary)
+    };
+    let dim = expected.nrows().max(actual.nrows());
+    let (expected, actual) = (extend(expected, dim), extend(actual, dim));
+    let (index, _) = expected.iter().enumerate()
+        .max_by(|(_, a), (_, b)| a.norm().total_cmp(&b.norm()))
+        .unwrap();
+    let phase = actual.iter().nth(index).unwrap() / expected.iter().nth(index).unwrap();
+    (phase.norm() - 1.0).abs() < UNITARY_TOLERANCE
+        && expected.iter().zip(actual.iter()).all(|(expected, actual)| {
+            (expected * phase - actual).norm() < UNITARY_TOLERANCE
+        })
+}
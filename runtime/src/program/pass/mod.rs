@@ -2,15 +2,27 @@ pub mod demutiplex;
 pub mod elementary_decomposition;
 pub mod swap_decomposition;
 pub mod multiplexed_optimization;
-pub mod pauli_x_cancellation;
+pub mod gate_cancellation;
 pub mod cond_ctrl_decomposition;
 pub mod remove_identity;
+pub mod single_gate_fusion;
+pub mod layout_selection;
+pub mod routing;
+pub mod manager;
 
+use crate::error::QivmResult;
 use crate::program::circuit::QuantumCircuit;
 
 /// A pass is a transformation that can be applied to a quantum circuit.
 /// It is usually used to optimize the circuit or to decompose gates.
-/// A pass can be applied to a circuit by calling the `apply` method.
+/// A pass can be applied to a circuit by calling the `apply` method,
+/// which returns an error if the circuit cannot be transformed.
 pub trait Pass {
-    fn apply(&mut self, circuit: &mut QuantumCircuit);
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()>;
+
+    /// Name of the pass in logs and reports, the name of its type by default.
+    fn name(&self) -> String {
+        let type_name = std::any::type_name::<Self>();
+        type_name.rsplit("::").next().unwrap_or(type_name).to_string()
+    }
 }
@@ -1,10 +1,12 @@
+use crate::error::QivmResult;
 use crate::program::circuit::QuantumCircuit;
 use crate::program::pass::Pass;
 
 pub struct MultiplexOptimizationPass;
 
 impl Pass for MultiplexOptimizationPass {
-    fn apply(&mut self, circuit: &mut QuantumCircuit) {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
         // TODO
+        Ok(())
     }
 }
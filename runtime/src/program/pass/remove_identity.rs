@@ -1,6 +1,7 @@
 use crate::operation::elementary::ElementaryOperation;
 use crate::operation::elementary::standard::StandardOperation;
 use crate::operation::{Operation, SingleTargetOperation};
+use crate::error::QivmResult;
 use crate::program::circuit::QuantumCircuit;
 use crate::program::pass::Pass;
 use crate::gate::elementary::is_identity;
@@ -9,7 +10,7 @@ use crate::use_enum;
 pub struct RemoveIdentityPass;
 
 impl Pass for RemoveIdentityPass {
-    fn apply(&mut self, circuit: &mut QuantumCircuit) {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
         circuit.flat_replace_operation(|operation| {
             use_enum!(Operation, ElementaryOperation, StandardOperation);
             if let Elementary(operation) = operation {
@@ -18,6 +19,7 @@ impl Pass for RemoveIdentityPass {
                 }
             }
             None
-        })
+        });
+        Ok(())
     }
 }
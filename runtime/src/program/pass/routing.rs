@@ -0,0 +1,344 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::mem::take;
+use crate::backend::coupling_map::CouplingMap;
+use crate::error::{QivmError, QivmResult};
+use crate::gate::DoubleTargetGate;
+use crate::gate::standard::StandardDoubleGate::SWP;
+use crate::operation::{ElementaryGateOperation, Operation};
+use crate::program::circuit::{CircuitOperation, QuantumCircuit};
+use crate::program::dag::{CircuitDag, NodeId};
+use crate::program::layout::Layout;
+use crate::program::pass::Pass;
+use crate::qubit::QubitAddr;
+use crate::QivmRef;
+
+/// Number of two-qubit gates following the front layer which are looked ahead.
+const EXTENDED_SET_SIZE: usize = 20;
+/// Weight of the distances of the extended set in the cost of a swap.
+const EXTENDED_SET_WEIGHT: f64 = 0.5;
+/// Increase of the decay of the qubits of a swap, which spreads the swaps over the qubits.
+const DECAY_INCREMENT: f64 = 0.001;
+
+/// Route the gates onto the coupling map of the QIVM instance, by inserting SWP gates.
+///
+/// The gates are routed with the SABRE heuristic: the gates of the front layer whose qubits are
+/// coupled are emitted, otherwise the swap minimizing the distances between the qubits of the
+/// front layer, and of the gates following it, is inserted. When the heuristic is stuck, the
+/// qubits of the first gate of the front layer are moved next to each other.
+///
+/// The targets are placed with the initial layout of the circuit, or the trivial layout, and the
+/// layout of the circuit is set to the placement of the logical qubits at the end of the circuit.
+/// Before the qubits of a scope are reset, they are moved back onto the physical qubits of their
+/// addresses.
+///
+/// The pass does nothing without coupling map or if the circuit is already routed, and fails on
+/// gates acting on more than two qubits.
+///
+/// reference: G. Li, Y. Ding, Y. Xie, Tackling the Qubit Mapping Problem for NISQ-Era Quantum
+/// Devices, 2019
+pub struct RoutingPass {
+    qivm: QivmRef,
+}
+
+impl RoutingPass {
+    pub fn new(qivm: QivmRef) -> Self {
+        Self { qivm }
+    }
+}
+
+impl Pass for RoutingPass {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
+        let coupling_map = match self.qivm.lock().unwrap().coupling_map() {
+            Some(coupling_map) if circuit.layout.is_none() => coupling_map.clone(),
+            _ => return Ok(()),
+        };
+        let mut router = Router::new(&coupling_map, circuit)?;
+        let mut scopes = Vec::<Vec<CircuitOperation>>::new();
+        for operation in take(&mut circuit.operations) {
+            match scopes.last_mut() {
+                Some(scope) if scope[0].stack_top == operation.stack_top => scope.push(operation),
+                _ => scopes.push(vec![operation]),
+            }
+        }
+        let stack_tops = scopes.iter().map(|scope| scope[0].stack_top).collect::<Vec<_>>();
+        for (index, scope) in scopes.into_iter().enumerate() {
+            let stack_top = stack_tops[index];
+            router.route_scope(scope, stack_top)?;
+            match stack_tops.get(index + 1) {
+                Some(&next_stack_top) if next_stack_top < stack_top => {
+                    router.restore_qubits(next_stack_top, stack_top);
+                }
+                _ => {}
+            }
+        }
+        circuit.operations = router.operations;
+        circuit.layout = Some(router.layout);
+        Ok(())
+    }
+}
+
+struct Router<'a> {
+    coupling_map: &'a CouplingMap,
+    distances: Vec<Vec<usize>>,
+    /// Layout the targets of the circuit are placed with.
+    initial_layout: Layout,
+    layout: Layout,
+    operations: Vec<CircuitOperation>,
+}
+
+impl<'a> Router<'a> {
+    fn new(coupling_map: &'a CouplingMap, circuit: &mut QuantumCircuit) -> QivmResult<Self> {
+        let size = coupling_map.size();
+        if !coupling_map.is_connected() {
+            return Err(QivmError::Compile("The coupling map is not connected".to_string()));
+        }
+        let required = circuit.operations.iter().map(|op| {
+            let highest_target = match &op.operation {
+                Operation::Elementary(op) => op.get_target().iter().max().map_or(0, |&q| q + 1),
+                _ => 0,
+            };
+            highest_target.max(op.stack_top) as usize
+        }).max().unwrap_or(0);
+        if required > size {
+            return Err(QivmError::Compile(format!(
+                "The circuit uses {} qubits, the coupling map has only {}", required, size
+            )));
+        }
+        let initial_layout = circuit.initial_layout.clone()
+            .unwrap_or_else(|| Layout::trivial(size));
+        if initial_layout.size() != size {
+            return Err(QivmError::InvalidArgument(
+                "The layout of the circuit does not match the coupling map".to_string()
+            ));
+        }
+        Ok(Self {
+            coupling_map,
+            distances: coupling_map.distances(),
+            layout: initial_layout.clone(),
+            initial_layout,
+            operations: vec![],
+        })
+    }
+
+    /// Route the operations of a scope, which share the same stack top.
+    fn route_scope(
+        &mut self, operations: Vec<CircuitOperation>, stack_top: QubitAddr
+    ) -> QivmResult<()> {
+        let dag = CircuitDag::from(QuantumCircuit::from_operations(operations));
+        let mut qubits = Vec::with_capacity(dag.len());
+        for id in 0 .. dag.len() {
+            let node = dag.node(id).unwrap();
+            match &node.operation {
+                Operation::Elementary(op) if node.qubits().len() > 2 => {
+                    return Err(QivmError::Compile(format!(
+                        "Unable to route the gate `{}` acting on {} qubits, decompose it first",
+                        op.get_ident(), node.qubits().len()
+                    )));
+                }
+                Operation::Elementary(_) => {}
+                _ => return Err(QivmError::Compile(
+                    "`RoutingPass` accepts only elementary gates".to_string()
+                )),
+            }
+            qubits.push(node.qubits().iter().map(|&qubit| {
+                self.initial_layout.logical(qubit)
+            }).collect::<Vec<QubitAddr>>());
+        }
+
+        let mut pending = (0 .. dag.len())
+            .map(|id| dag.predecessors(id).len())
+            .collect::<Vec<usize>>();
+        let mut front = (0 .. dag.len()).filter(|&id| pending[id] == 0).collect::<Vec<NodeId>>();
+        let mut decay = vec![1.0; self.coupling_map.size()];
+        let mut stuck_swaps = 0;
+        while !front.is_empty() {
+            front.sort_unstable();
+            let (executable, blocked): (Vec<NodeId>, Vec<NodeId>) = take(&mut front).into_iter()
+                .partition(|&id| self.is_executable(&qubits[id]));
+            front = blocked;
+            if !executable.is_empty() {
+                for id in executable {
+                    self.emit(&dag.node(id).unwrap().operation, &qubits[id], stack_top);
+                    for successor in dag.successors(id) {
+                        pending[successor] -= 1;
+                        if pending[successor] == 0 {
+                            front.push(successor);
+                        }
+                    }
+                }
+                decay.fill(1.0);
+                stuck_swaps = 0;
+                continue;
+            }
+            if stuck_swaps > 2 * self.coupling_map.size() {
+                let (first, second) = (qubits[front[0]][0], qubits[front[0]][1]);
+                self.move_next_to(first, second, stack_top);
+                continue;
+            }
+            let front_pairs = front.iter()
+                .map(|&id| (qubits[id][0], qubits[id][1]))
+                .collect::<Vec<_>>();
+            let extended_pairs = extended_set(&dag, &front, &qubits);
+            let (first, second) = self.best_swap(&front_pairs, &extended_pairs, &decay);
+            self.swap(first, second, stack_top);
+            decay[first as usize] += DECAY_INCREMENT;
+            decay[second as usize] += DECAY_INCREMENT;
+            stuck_swaps += 1;
+        }
+        Ok(())
+    }
+
+    fn is_executable(&self, qubits: &[QubitAddr]) -> bool {
+        match qubits {
+            &[first, second] => self.coupling_map.is_coupled(
+                self.layout.physical(first), self.layout.physical(second)
+            ),
+            _ => true,
+        }
+    }
+
+    fn emit(&mut self, operation: &Operation, qubits: &[QubitAddr], stack_top: QubitAddr) {
+        if let Operation::Elementary(op) = operation {
+            let target = qubits.iter()
+                .map(|&qubit| self.layout.physical(qubit))
+                .collect::<Vec<QubitAddr>>();
+            let operation = op.get_gate().apply_to(target.into()).into();
+            self.operations.push(CircuitOperation::new(operation, stack_top));
+        }
+    }
+
+    /// Swap the logical qubits held by two coupled physical qubits.
+    fn swap(&mut self, first: QubitAddr, second: QubitAddr, stack_top: QubitAddr) {
+        let operation = SWP.apply_to((first, second)).into();
+        self.operations.push(CircuitOperation::new(operation, stack_top));
+        self.layout.swap_physical(first, second);
+    }
+
+    /// Swap candidate of the lowest cost: the mean distance between the qubits of the front
+    /// layer, plus the weighted mean distance of the extended set, after the swap.
+    fn best_swap(
+        &self, front: &Vec<(QubitAddr, QubitAddr)>, extended: &Vec<(QubitAddr, QubitAddr)>,
+        decay: &[f64]
+    ) -> (QubitAddr, QubitAddr) {
+        let candidates = front.iter()
+            .flat_map(|&(first, second)| [first, second])
+            .map(|logical| self.layout.physical(logical))
+            .flat_map(|physical| {
+                self.coupling_map.neighbours(physical).iter()
+                    .map(move |&neighbour| (physical.min(neighbour), physical.max(neighbour)))
+            })
+            .collect::<BTreeSet<(QubitAddr, QubitAddr)>>();
+        let mut best = None;
+        for (first, second) in candidates {
+            let physical = |logical: QubitAddr| match self.layout.physical(logical) {
+                qubit if qubit == first => second,
+                qubit if qubit == second => first,
+                qubit => qubit,
+            };
+            let mean_distance = |pairs: &Vec<(QubitAddr, QubitAddr)>| if pairs.is_empty() {
+                0.0
+            } else {
+                pairs.iter().map(|&(a, b)| {
+                    self.distances[physical(a) as usize][physical(b) as usize] as f64
+                }).sum::<f64>() / pairs.len() as f64
+            };
+            let cost = decay[first as usize].max(decay[second as usize])
+                * (mean_distance(front) + EXTENDED_SET_WEIGHT * mean_distance(extended));
+            if best.map_or(true, |(best_cost, _)| cost < best_cost) {
+                best = Some((cost, (first, second)));
+            }
+        }
+        best.unwrap().1
+    }
+
+    /// Move the first logical qubit along a shortest path until it is next to the second one.
+    fn move_next_to(&mut self, first: QubitAddr, second: QubitAddr, stack_top: QubitAddr) {
+        let target = self.layout.physical(second);
+        let mut current = self.layout.physical(first);
+        while !self.coupling_map.is_coupled(current, target) {
+            let distance = self.distances[current as usize][target as usize];
+            let next = *self.coupling_map.neighbours(current).iter()
+                .find(|&&neighbour| self.distances[neighbour as usize][target as usize] < distance)
+                .unwrap();
+            self.swap(current, next, stack_top);
+            current = next;
+        }
+    }
+
+    /// Move the logical qubits `base .. top`, which are about to be reset, onto the physical
+    /// qubits of the same addresses.
+    fn restore_qubits(&mut self, base: QubitAddr, top: QubitAddr) {
+        let mut target = self.layout.clone();
+        for logical in base .. top {
+            let physical = target.physical(logical);
+            if physical != logical {
+                target.swap_physical(physical, logical);
+            }
+        }
+        self.permute(&target, top);
+    }
+
+    /// Move every logical qubit onto its physical qubit in the target layout, by eliminating the
+    /// leaves of a spanning tree of the coupling map one after the other.
+    fn permute(&mut self, target: &Layout, stack_top: QubitAddr) {
+        let size = self.coupling_map.size();
+        let mut parent = vec![0 as QubitAddr; size];
+        let mut depth = vec![usize::MAX; size];
+        let mut order = vec![];
+        if size > 0 {
+            depth[0] = 0;
+            let mut queue = VecDeque::from([0 as QubitAddr]);
+            while let Some(qubit) = queue.pop_front() {
+                order.push(qubit);
+                for &neighbour in self.coupling_map.neighbours(qubit) {
+                    if depth[neighbour as usize] == usize::MAX {
+                        depth[neighbour as usize] = depth[qubit as usize] + 1;
+                        parent[neighbour as usize] = qubit;
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+        }
+        // each qubit is a leaf of the subtree of the qubits before it in the BFS order
+        for &leaf in order.iter().rev() {
+            let (mut from, mut to) = (self.layout.physical(target.logical(leaf)), leaf);
+            let (mut up, mut down) = (vec![from], vec![to]);
+            while from != to {
+                if depth[from as usize] >= depth[to as usize] {
+                    from = parent[from as usize];
+                    up.push(from);
+                } else {
+                    to = parent[to as usize];
+                    down.push(to);
+                }
+            }
+            down.pop();
+            up.extend(down.into_iter().rev());
+            for pair in up.windows(2) {
+                self.swap(pair[0], pair[1], stack_top);
+            }
+        }
+    }
+}
+
+/// Logical qubits of the first two-qubit gates following the front layer.
+fn extended_set(
+    dag: &CircuitDag, front: &[NodeId], qubits: &[Vec<QubitAddr>]
+) -> Vec<(QubitAddr, QubitAddr)> {
+    let mut pairs = vec![];
+    let mut visited = front.iter().copied().collect::<HashSet<NodeId>>();
+    let mut queue = front.iter().flat_map(|&id| dag.successors(id)).collect::<VecDeque<_>>();
+    while let Some(id) = queue.pop_front() {
+        if pairs.len() >= EXTENDED_SET_SIZE {
+            break;
+        }
+        if !visited.insert(id) {
+            continue;
+        }
+        if let [first, second] = qubits[id][..] {
+            pairs.push((first, second));
+        }
+        queue.extend(dag.successors(id));
+    }
+    pairs
+}
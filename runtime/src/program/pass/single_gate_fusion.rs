@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::mem::take;
+use crate::algebra::{close_to_zero, Mat2, ToMat2};
+use crate::decompose::single::decompose_single;
+use crate::error::QivmResult;
+use crate::gate::SingleTargetGate;
+use crate::gate::elementary::is_identity;
+use crate::gate::unitary::UnitarySingleGate;
+use crate::operation::elementary::{ElementaryOperation, SingleOperation};
+use crate::operation::elementary::standard::StandardOperation;
+use crate::operation::elementary::unitary::UnitaryOperation;
+use crate::operation::{ElementaryGateOperation, Operation, SingleTargetOperation};
+use crate::program::circuit::{CircuitOperation, QuantumCircuit};
+use crate::program::pass::Pass;
+use crate::qubit::QubitAddr;
+use crate::QivmRef;
+
+/// Fuse the runs of single-qubit gates on the same qubit.
+///
+/// The matrices of a run are multiplied, and the product is emitted in the ZYZ form, without
+/// the rotations of a null angle. A run whose product is the identity, up to a global phase, is
+/// dropped. Otherwise the run is only replaced if the ZYZ form is shorter, and if RY and RZ are
+/// available on the target.
+///
+/// Standard and unitary gates are fused, custom gates are kept as is.
+pub struct SingleGateFusionPass {
+    qivm: QivmRef,
+}
+
+impl SingleGateFusionPass {
+    pub fn new(qivm: QivmRef) -> Self {
+        Self { qivm }
+    }
+}
+
+impl Pass for SingleGateFusionPass {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
+        let qivm = self.qivm.lock().unwrap();
+        let is_zyz_available = ["RY", "RZ"].iter().all(|ident| qivm.is_gate_available(ident));
+        let mut fusion = Fusion {
+            operations: Vec::with_capacity(circuit.operations.len()),
+            runs: BTreeMap::new(),
+            is_zyz_available,
+        };
+        let mut stack_top = None;
+        for operation in take(&mut circuit.operations) {
+            // qubits may be reset when the stack top changes
+            if stack_top != Some(operation.stack_top) {
+                fusion.flush_all();
+                stack_top = Some(operation.stack_top);
+            }
+            if let Some(single) = single_operation(&operation.operation) {
+                fusion.runs.entry(single.get_target()).or_default().push(operation);
+                continue;
+            }
+            match &operation.operation {
+                Operation::Elementary(elementary) => {
+                    for &qubit in elementary.get_target().iter() {
+                        fusion.flush(qubit);
+                    }
+                }
+                _ => fusion.flush_all(),
+            }
+            fusion.operations.push(operation);
+        }
+        fusion.flush_all();
+        circuit.operations = fusion.operations;
+        Ok(())
+    }
+}
+
+struct Fusion {
+    operations: Vec<CircuitOperation>,
+    /// Pending run of single-qubit operations of each qubit.
+    runs: BTreeMap<QubitAddr, Vec<CircuitOperation>>,
+    is_zyz_available: bool,
+}
+
+impl Fusion {
+    fn flush(&mut self, qubit: QubitAddr) {
+        if let Some(run) = self.runs.remove(&qubit) {
+            let fused = self.fuse(qubit, run);
+            self.operations.extend(fused);
+        }
+    }
+
+    fn flush_all(&mut self) {
+        for (qubit, run) in take(&mut self.runs) {
+            let fused = self.fuse(qubit, run);
+            self.operations.extend(fused);
+        }
+    }
+
+    fn fuse(&self, qubit: QubitAddr, run: Vec<CircuitOperation>) -> Vec<CircuitOperation> {
+        if run.len() < 2 {
+            return run;
+        }
+        let stack_top = run[0].stack_top;
+        let mat = run.iter().fold(Mat2::identity(), |mat, op| {
+            single_operation(&op.operation).unwrap().to_mat2() * mat
+        });
+        if is_identity_up_to_phase(&mat) {
+            return vec![];
+        }
+        let mut fused = decompose_single(&UnitarySingleGate(Box::new(mat)).apply_to(qubit));
+        fused.retain(|op| !is_identity(&op.get_gate()));
+        if self.is_zyz_available && fused.len() < run.len() {
+            fused.into_iter().map(|op| CircuitOperation::new(op.into(), stack_top)).collect()
+        } else {
+            run
+        }
+    }
+}
+
+/// The operation as a single-qubit operation, if it is a standard or a unitary one.
+fn single_operation(operation: &Operation) -> Option<SingleOperation> {
+    match operation {
+        Operation::Elementary(op @ ElementaryOperation::Standard(StandardOperation::Single(_)))
+        | Operation::Elementary(op @ ElementaryOperation::Unitary(UnitaryOperation::Single(_))) => {
+            SingleOperation::try_from(op.clone()).ok()
+        }
+        _ => None,
+    }
+}
+
+fn is_identity_up_to_phase(mat: &Mat2) -> bool {
+    let norm = mat[(0, 0)].norm();
+    if close_to_zero(norm) {
+        return false;
+    }
+    let phase = mat[(0, 0)] / norm;
+    is_identity(&UnitarySingleGate(Box::new(mat / phase)))
+}
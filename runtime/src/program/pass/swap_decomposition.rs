@@ -1,3 +1,4 @@
+use crate::error::QivmResult;
 use crate::program::circuit::QuantumCircuit;
 use crate::program::pass::Pass;
 use crate::use_enum;
@@ -11,7 +12,7 @@ use crate::operation::{DoubleTargetOperation, Operation};
 pub struct SwapDecompositionPass {}
 
 impl Pass for SwapDecompositionPass {
-    fn apply(&mut self, circuit: &mut QuantumCircuit) {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> QivmResult<()> {
         circuit.flat_replace_operation(|operation| {
             use_enum!(Operation, ElementaryOperation, StandardOperation);
             match operation {
@@ -26,6 +27,7 @@ impl Pass for SwapDecompositionPass {
                 }
                 _ => None
             }
-        })
+        });
+        Ok(())
     }
 }
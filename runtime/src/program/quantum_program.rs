@@ -0,0 +1,193 @@
+use std::ops::{Deref, DerefMut};
+use crate::backend::{ExecuteResult, QivmBackend};
+use crate::error::QivmResult;
+use crate::gate::elementary::ElementaryGate;
+use crate::measurement::MeasurementResult;
+use crate::program::builder::QuantumProgramContextBuilder;
+use crate::program::QuantumProgramContext;
+use crate::program::stats::CircuitStats;
+use crate::qubit::qubit_accessor::QubitAccessor;
+
+/// Safe builder of a quantum program, owning its program context.
+///
+/// Qubits are allocated in the quantum stack frame of the program, or in the frame of a
+/// [`scope`](Self::scope), where they are released at the end of the scope. Control and dagger
+/// sections are either closures or guards, which end the section when dropped. Invalid
+/// operations, e.g. applying a gate to one of its control qubits, return a [`QivmError`].
+///
+/// ```no_run
+/// use qivm::prelude::*;
+///
+/// let mut program = QuantumProgram::new();
+/// let qreg = program.alloc(2)?;
+/// program.apply(H, &qreg.get(0)?)?;
+/// program.ctrl(&qreg.get(0)?, |program| program.apply(X, &qreg.get(1)?))?;
+/// program.measure(&qreg);
+/// let result = program.execute(1024)?;
+/// # Ok::<(), QivmError>(())
+/// ```
+///
+/// [`QivmError`]: crate::error::QivmError
+pub struct QuantumProgram {
+    ctx: QuantumProgramContext,
+}
+
+impl QuantumProgram {
+
+    /// Create a program running on the shared QIVM instance, with the default transpile passes.
+    pub fn new() -> Self {
+        let mut builder = QuantumProgramContextBuilder::new();
+        builder.default_passes();
+        Self::from_context(builder.build())
+    }
+
+    /// Create a program running on a new QIVM instance with the backend, with the default
+    /// transpile passes.
+    pub fn with_backend(backend: impl QivmBackend + 'static) -> Self {
+        let mut builder = QuantumProgramContextBuilder::with_backend(backend);
+        builder.default_passes();
+        Self::from_context(builder.build())
+    }
+
+    /// Build the program on an empty program context.
+    pub fn from_context(mut ctx: QuantumProgramContext) -> Self {
+        ctx.enter();
+        Self { ctx }
+    }
+
+    /// Get the underlying program context, e.g. to add transpile passes.
+    pub fn context(&mut self) -> &mut QuantumProgramContext {
+        &mut self.ctx
+    }
+
+    /// Allocate a register of qubits in the current quantum stack frame.
+    pub fn alloc(&mut self, size: usize) -> QivmResult<QubitAccessor> {
+        Ok(self.ctx.alloc(size)?.borrow().clone())
+    }
+
+    /// Apply the gate to the targets, controlled by the enclosing control sections.
+    pub fn apply(
+        &mut self, gate: impl Into<ElementaryGate>, targets: &QubitAccessor
+    ) -> QivmResult<()> {
+        self.ctx.push(gate, targets.clone())
+    }
+
+    /// Run the closure in a new quantum stack frame, releasing the qubits it allocates.
+    pub fn scope<R>(
+        &mut self, body: impl FnOnce(&mut Self) -> QivmResult<R>
+    ) -> QivmResult<R> {
+        self.ctx.enter();
+        let result = body(self);
+        self.ctx.exit()?;
+        result
+    }
+
+    /// Control the operations of the closure by the qubits being `|1⟩`.
+    pub fn ctrl<R>(&mut self, ctrl: &QubitAccessor, body: impl FnOnce(&mut Self) -> R) -> R {
+        self.ctrl_if(ctrl, true, body)
+    }
+
+    /// Control the operations of the closure by the qubits being in the state of `condition`.
+    pub fn ctrl_if<R>(
+        &mut self, ctrl: &QubitAccessor, condition: bool, body: impl FnOnce(&mut Self) -> R
+    ) -> R {
+        body(&mut self.begin_ctrl(ctrl, condition))
+    }
+
+    /// Apply the inverse of the operations of the closure.
+    pub fn dagger<R>(&mut self, body: impl FnOnce(&mut Self) -> R) -> R {
+        body(&mut self.begin_dagger())
+    }
+
+    /// Begin a control section, which ends when the guard is dropped.
+    pub fn begin_ctrl(&mut self, ctrl: &QubitAccessor, condition: bool) -> CtrlGuard<'_> {
+        self.ctx.control(ctrl.clone(), condition);
+        CtrlGuard { program: self, ctrl: ctrl.clone() }
+    }
+
+    /// Begin a dagger section, which ends when the guard is dropped.
+    pub fn begin_dagger(&mut self) -> DaggerGuard<'_> {
+        self.ctx.begin_dagger();
+        DaggerGuard { program: self }
+    }
+
+    /// Set the qubits measured at the end of the program.
+    pub fn measure(&mut self, qubits: &QubitAccessor) {
+        self.ctx.measure(qubits.clone());
+    }
+
+    /// Transpile and execute the program on the backend, failing with the error code of the
+    /// backend if it is unable to execute it.
+    pub fn execute(&mut self, shots: usize) -> QivmResult<ExecuteResult> {
+        self.ctx.execute(shots)
+    }
+
+    /// Get the measurement result of the last execution.
+    pub fn measurement_result(&self) -> Option<MeasurementResult> {
+        self.ctx.get_measurement_result()
+    }
+
+    /// Get the statistics of the circuit before the passes, and after each of them, of the last
+    /// execution.
+    pub fn transpile_stats(&self) -> &[CircuitStats] {
+        self.ctx.transpile_stats()
+    }
+}
+
+impl Default for QuantumProgram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Control section of a [`QuantumProgram`], ended when dropped.
+pub struct CtrlGuard<'a> {
+    program: &'a mut QuantumProgram,
+    ctrl: QubitAccessor,
+}
+
+impl Deref for CtrlGuard<'_> {
+    type Target = QuantumProgram;
+
+    fn deref(&self) -> &Self::Target {
+        self.program
+    }
+}
+
+impl DerefMut for CtrlGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.program
+    }
+}
+
+impl Drop for CtrlGuard<'_> {
+    fn drop(&mut self) {
+        self.program.ctx.decontrol(self.ctrl.clone());
+    }
+}
+
+/// Dagger section of a [`QuantumProgram`], ended when dropped.
+pub struct DaggerGuard<'a> {
+    program: &'a mut QuantumProgram,
+}
+
+impl Deref for DaggerGuard<'_> {
+    type Target = QuantumProgram;
+
+    fn deref(&self) -> &Self::Target {
+        self.program
+    }
+}
+
+impl DerefMut for DaggerGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.program
+    }
+}
+
+impl Drop for DaggerGuard<'_> {
+    fn drop(&mut self) {
+        // The guard pushed the dagger section it ends, so the dagger stack cannot underflow.
+        let _ = self.program.ctx.end_dagger();
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use crate::operation::controlled::ControlledOperation;
+use crate::operation::{ElementaryGateOperation, Operation};
+use crate::program::circuit::QuantumCircuit;
+use crate::qubit::QubitAddr;
+
+/// Size and resource estimation of a quantum circuit.
+///
+/// Elementary operations act on their targets, the other operations act on all the qubits below
+/// their stack top and are counted as `MUX` or `CTRL`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CircuitStats {
+    /// Number of operations of each gate ident.
+    pub gate_counts: BTreeMap<String, usize>,
+    /// Number of operations acting on two qubits.
+    pub two_qubit_count: usize,
+    /// Number of layers of operations acting on disjoint qubits.
+    pub depth: usize,
+    /// Depth counting only the operations acting on two qubits.
+    pub two_qubit_depth: usize,
+    /// Number of T and TD gates.
+    pub t_count: usize,
+    /// Highest stack top of the operations, the number of qubits allocated by the program.
+    pub peak_stack_top: QubitAddr,
+    /// Number of qubits the operations act on or may act on, including the physical qubits of a
+    /// routed circuit.
+    pub qubits: usize,
+}
+
+impl CircuitStats {
+    pub fn of(circuit: &QuantumCircuit) -> Self {
+        let mut stats = Self::default();
+        // depth and two-qubit depth reached on each qubit
+        let mut depths = BTreeMap::<QubitAddr, (usize, usize)>::new();
+        for op in &circuit.operations {
+            let (ident, qubits) = match &op.operation {
+                Operation::Elementary(elementary) => {
+                    (elementary.get_ident(), elementary.get_target().to_vec())
+                }
+                Operation::Controlled(ControlledOperation::Mux(_)) => {
+                    ("MUX".to_string(), (0 .. op.stack_top).collect())
+                }
+                Operation::Controlled(ControlledOperation::ConditionalCtrl(_)) => {
+                    ("CTRL".to_string(), (0 .. op.stack_top).collect())
+                }
+            };
+            if ident == "T" || ident == "TD" {
+                stats.t_count += 1;
+            }
+            *stats.gate_counts.entry(ident).or_insert(0) += 1;
+            stats.peak_stack_top = stats.peak_stack_top.max(op.stack_top);
+            let highest_qubit = qubits.iter().max().map_or(0, |&qubit| qubit as usize + 1);
+            stats.qubits = stats.qubits.max(highest_qubit).max(op.stack_top as usize);
+
+            let is_two_qubit = qubits.len() == 2;
+            if is_two_qubit {
+                stats.two_qubit_count += 1;
+            }
+            let (depth, two_qubit_depth) = qubits.iter().fold((0, 0), |(depth, two), qubit| {
+                let (qubit_depth, qubit_two) = depths.get(qubit).copied().unwrap_or_default();
+                (depth.max(qubit_depth), two.max(qubit_two))
+            });
+            let layer = (depth + 1, two_qubit_depth + is_two_qubit as usize);
+            for qubit in qubits {
+                depths.insert(qubit, layer);
+            }
+            stats.depth = stats.depth.max(layer.0);
+            stats.two_qubit_depth = stats.two_qubit_depth.max(layer.1);
+        }
+        stats
+    }
+
+    /// Total number of operations.
+    pub fn gate_count(&self) -> usize {
+        self.gate_counts.values().sum()
+    }
+
+    /// Number of operations of the gate ident.
+    pub fn count(&self, ident: &str) -> usize {
+        self.gate_counts.get(ident).copied().unwrap_or(0)
+    }
+}
+
+impl Display for CircuitStats {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(formatter, "gates: {}", self.gate_count())?;
+        for (ident, count) in &self.gate_counts {
+            writeln!(formatter, "  {}: {}", ident, count)?;
+        }
+        writeln!(formatter, "two-qubit gates: {}", self.two_qubit_count)?;
+        writeln!(formatter, "T-count: {}", self.t_count)?;
+        writeln!(formatter, "depth: {}", self.depth)?;
+        writeln!(formatter, "two-qubit depth: {}", self.two_qubit_depth)?;
+        writeln!(formatter, "peak stack top: {}", self.peak_stack_top)?;
+        writeln!(formatter, "qubits: {}", self.qubits)
+    }
+}
+
+/// C representation of the circuit statistics, without the gate histogram.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawCircuitStats {
+    pub gate_count: u64,
+    pub two_qubit_count: u64,
+    pub depth: u64,
+    pub two_qubit_depth: u64,
+    pub t_count: u64,
+    pub peak_stack_top: u64,
+    pub qubits: u64,
+}
+
+impl From<&CircuitStats> for RawCircuitStats {
+    fn from(stats: &CircuitStats) -> Self {
+        Self {
+            gate_count: stats.gate_count() as u64,
+            two_qubit_count: stats.two_qubit_count as u64,
+            depth: stats.depth as u64,
+            two_qubit_depth: stats.two_qubit_depth as u64,
+            t_count: stats.t_count as u64,
+            peak_stack_top: stats.peak_stack_top as u64,
+            qubits: stats.qubits as u64,
+        }
+    }
+}
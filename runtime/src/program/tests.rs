@@ -3,16 +3,32 @@ use nalgebra::max;
 use num::integer::gcd;
 use num_traits::Pow;
 use rand::Rng;
-use crate::bytecode::instruction::Instruction;
-use crate::gate::standard::StandardSingleGate::{H, P, X, Z};
-use crate::program::builder::QuantumProgramContextBuilder;
+use crate::algebra::GateMat;
+use crate::bytecode::instruction::{InstrParam, Instruction};
+use crate::c64;
+use crate::gate::standard::StandardSingleGate::{H, P, RZ, S, T, TD, X, Z};
+use crate::program::builder::{OptimizationLevel, QuantumProgramContextBuilder};
+use crate::program::circuit::{CircuitOperation, QuantumCircuit};
+use crate::program::dag::CircuitDag;
+use crate::program::layout::Layout;
+use crate::program::pass::gate_cancellation::GateCancellationPass;
+use crate::program::pass::manager::InvariantCheck;
+use crate::program::pass::Pass;
+use crate::program::quantum_program::QuantumProgram;
 use crate::program::QuantumProgramContext;
 use crate::qubit::{QubitAddr, Slice};
 use crate::qubits;
-use crate::backend::{execute_bytecode, ExecuteResult};
+use crate::backend::{ExecuteResult, QivmBackend};
+use crate::backend::coupling_map::CouplingMap;
+use crate::backend::native::NativeBackend;
 use crate::bytecode::ByteCode;
-use crate::gate::standard::StandardDoubleGate::{CP, CX, SWP};
+use crate::bytecode::asm;
+use crate::error::QivmError;
+use crate::gate::standard::StandardDoubleGate::{CP, CX, CZ, ISWP, SWP};
+use crate::gate::standard::StandardTripleGate::CCX;
+use crate::gate::{DoubleTargetGate, SingleTargetGate};
 use crate::measurement::MeasurementResultEntry;
+use crate::operation::{ElementaryGateOperation, Operation};
 use crate::qubit::ctrl_qubit_set::ControlQubitSet;
 use crate::qubit::qubit_accessor::QubitAccessor;
 
@@ -22,6 +38,26 @@ fn get_ctx_with_default_passes() -> QuantumProgramContext {
     ctx_builder.build()
 }
 
+/// Build a program context running on a new QIVM instance with the backend, once the closure
+/// has configured its builder.
+fn ctx_with(
+    backend: impl QivmBackend + 'static, configure: impl FnOnce(&mut QuantumProgramContextBuilder)
+) -> QuantumProgramContext {
+    let mut builder = QuantumProgramContextBuilder::with_backend(backend);
+    configure(&mut builder);
+    builder.build()
+}
+
+/// Add the default passes, and check that the transpiled circuit only uses native gates.
+fn verified_default_passes(builder: &mut QuantumProgramContextBuilder) {
+    builder.default_passes();
+    builder.verify_native_gates();
+}
+
+fn execute_bytecode(bytecode: ByteCode, shots: usize) -> ExecuteResult {
+    NativeBackend.execute(&bytecode, shots)
+}
+
 fn print_instructions(instructions: &[Instruction]) {
     for instruction in instructions {
         println!("{:?}", instruction)
@@ -32,12 +68,12 @@ fn print_instructions(instructions: &[Instruction]) {
 fn test_simple_circuit() {
     let mut ctx = get_ctx_with_default_passes();
     ctx.enter();
-    let alloc = ctx.alloc(1);
+    let alloc = ctx.alloc(1).unwrap();
     let target = alloc.borrow()[0];
-    ctx.push(H, qubits![target]);
+    ctx.push(H, qubits![target]).unwrap();
     ctx.measure(qubits![target]);
-    ctx.exit();
-    let instructions = ctx.compile_circuit();
+    ctx.exit().unwrap();
+    let instructions = ctx.compile_circuit().unwrap();
     print_instructions(&instructions);
     let result = execute_bytecode(instructions.into(), 64);
 }
@@ -48,26 +84,26 @@ fn test_bernstein_vazirani() {
     const BIT_STR_SIZE: usize = 6;
     const BIT_STR: u64 = 0b101011;
     ctx.enter();
-    let alloc = ctx.alloc(BIT_STR_SIZE + 1);
+    let alloc = ctx.alloc(BIT_STR_SIZE + 1).unwrap();
     let qreg = alloc.borrow();
     let output = qreg[BIT_STR_SIZE];
     for i in 0 ..= BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
-    ctx.push(Z, qubits![output]);
+    ctx.push(Z, qubits![output]).unwrap();
     for i in 0 .. BIT_STR_SIZE {
         if BIT_STR & (1 << i) != 0 {
             ctx.ctrl_qubits.control(&qubits![qreg[i]], true);
-            ctx.push(X, qubits![output]);
+            ctx.push(X, qubits![output]).unwrap();
             ctx.ctrl_qubits.decontrol(&qubits![qreg[i]]);
         }
     }
     for i in 0 .. BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
     ctx.measure(qreg.slice(0, (BIT_STR_SIZE - 1) as QubitAddr, 1));
-    ctx.exit();
-    let instructions = ctx.compile_circuit();
+    ctx.exit().unwrap();
+    let instructions = ctx.compile_circuit().unwrap();
     print_instructions(&instructions);
 
     const SHOTS: usize = 64;
@@ -81,30 +117,30 @@ fn test_deutsch_jozsa_constant() {
     let mut ctx = get_ctx_with_default_passes();
     const BIT_STR_SIZE: usize = 6;
     ctx.enter();
-    let alloc = ctx.alloc(BIT_STR_SIZE + 1);
+    let alloc = ctx.alloc(BIT_STR_SIZE + 1).unwrap();
     let qreg = alloc.borrow();
     let output = qreg[BIT_STR_SIZE];
 
     for i in 0 .. BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
-    ctx.push(X, qubits![output]);
-    ctx.push(H, qubits![output]);
+    ctx.push(X, qubits![output]).unwrap();
+    ctx.push(H, qubits![output]).unwrap();
 
     // Oracle
     let rand_const = rand::thread_rng().gen_range(0u64 ..= 1u64);
     if rand_const == 1 {
-        ctx.push(X, qubits![output]);
+        ctx.push(X, qubits![output]).unwrap();
     }
 
     for i in 0 .. BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
     ctx.measure(qreg.slice(0, (BIT_STR_SIZE - 1) as QubitAddr, 1));
 
-    ctx.exit();
+    ctx.exit().unwrap();
 
-    let instructions = ctx.compile_circuit();
+    let instructions = ctx.compile_circuit().unwrap();
     print_instructions(&instructions);
 
     const SHOTS: usize = 64;
@@ -118,42 +154,42 @@ fn test_deutsch_jozsa_balance() {
     let mut ctx = get_ctx_with_default_passes();
     const BIT_STR_SIZE: usize = 6;
     ctx.enter();
-    let alloc = ctx.alloc(BIT_STR_SIZE + 1);
+    let alloc = ctx.alloc(BIT_STR_SIZE + 1).unwrap();
     let qreg = alloc.borrow();
     let output = qreg[BIT_STR_SIZE];
 
     for i in 0..BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
-    ctx.push(X, qubits![output]);
-    ctx.push(H, qubits![output]);
+    ctx.push(X, qubits![output]).unwrap();
+    ctx.push(H, qubits![output]).unwrap();
 
     // Oracle
     let rand_const = rand::thread_rng().gen_range(0u64 ..= 2u64.pow(BIT_STR_SIZE as u32));
     for i in 0 .. BIT_STR_SIZE {
         if rand_const & (1 << i) != 0 {
-            ctx.push(X, qubits![output]);
+            ctx.push(X, qubits![output]).unwrap();
         }
     }
     for i in 0 .. BIT_STR_SIZE {
         ctx.control(qubits![qreg[i]], true);
-        ctx.push(X, qubits![output]);
+        ctx.push(X, qubits![output]).unwrap();
         ctx.decontrol(qubits![qreg[i]]);
     }
     for i in 0 .. BIT_STR_SIZE {
         if rand_const & (1 << i) != 0 {
-            ctx.push(X, qubits![output]);
+            ctx.push(X, qubits![output]).unwrap();
         }
     }
 
     for i in 0 .. BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
     ctx.measure(qreg.slice(0, (BIT_STR_SIZE - 1) as QubitAddr, 1));
 
-    ctx.exit();
+    ctx.exit().unwrap();
 
-    let instructions = ctx.compile_circuit();
+    let instructions = ctx.compile_circuit().unwrap();
     print_instructions(&instructions);
 
     const SHOTS: usize = 64;
@@ -168,35 +204,35 @@ fn test_simon() {
     const BIT_STR_SIZE: usize = 6;
     const BIT_STR: u64 = 0b101100;
     ctx.enter();
-    let alloc = ctx.alloc(BIT_STR_SIZE * 2);
+    let alloc = ctx.alloc(BIT_STR_SIZE * 2).unwrap();
     let qreg = alloc.borrow();
     for i in 0 .. BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
 
     // Oracle
     for i in 0 .. BIT_STR_SIZE {
         ctx.control(qubits![qreg[i]], true);
-        ctx.push(X, qubits![qreg[i + BIT_STR_SIZE]]);
+        ctx.push(X, qubits![qreg[i + BIT_STR_SIZE]]).unwrap();
         ctx.decontrol(qubits![qreg[i]]);
     }
     let lowbit = (BIT_STR as i64) & -(BIT_STR as i64);
     for i in 0 .. BIT_STR_SIZE {
         if BIT_STR & (1 << i) != 0 {
             ctx.control(qubits![lowbit - 1], true);
-            ctx.push(X, qubits![qreg[i + BIT_STR_SIZE]]);
+            ctx.push(X, qubits![qreg[i + BIT_STR_SIZE]]).unwrap();
             ctx.decontrol(qubits![lowbit - 1]);
         }
     }
 
     for i in 0 .. BIT_STR_SIZE {
-        ctx.push(H, qubits![qreg[i]]);
+        ctx.push(H, qubits![qreg[i]]).unwrap();
     }
     ctx.measure(qreg.slice(0, (BIT_STR_SIZE - 1) as QubitAddr, 1));
 
-    ctx.exit();
+    ctx.exit().unwrap();
 
-    let instructions = ctx.compile_circuit();
+    let instructions = ctx.compile_circuit().unwrap();
     print_instructions(&instructions);
     let result: ExecuteResult = execute_bytecode(instructions.into(), 256).into();
 
@@ -209,30 +245,32 @@ fn qft(ctx: &mut QuantumProgramContext, qreg: &QubitAccessor) {
     ctx.enter();
     let n = qreg.size();
     for i in 0 .. n {
-        ctx.push(H, qubits![qreg[n - 1 - i]]);
+        ctx.push(H, qubits![qreg[n - 1 - i]]).unwrap();
         for j in i + 1 .. n {
-            ctx.push(CP { angle: PI / (1 << (j - i)) as f64 }, qubits![qreg[n-1-j], qreg[n-1-i]])
+            let angle = PI / (1 << (j - i)) as f64;
+            ctx.push(CP { angle }, qubits![qreg[n-1-j], qreg[n-1-i]]).unwrap();
         }
     }
     for i in 0 .. n / 2 {
-        ctx.push(SWP, qubits![qreg[i], qreg[n - i - 1]]);
+        ctx.push(SWP, qubits![qreg[i], qreg[n - i - 1]]).unwrap();
     }
-    ctx.exit();
+    ctx.exit().unwrap();
 }
 
 fn inv_qft(ctx: &mut QuantumProgramContext, qreg: &QubitAccessor) {
     ctx.enter();
     let n = qreg.size();
     for i in 0 .. n / 2 {
-        ctx.push(SWP, qubits![qreg[i], qreg[n - i - 1]]);
+        ctx.push(SWP, qubits![qreg[i], qreg[n - i - 1]]).unwrap();
     }
     for i in (0 .. n).rev() {
         for j in (i + 1 .. n).rev() {
-            ctx.push(CP { angle: -PI / (1 << (j - i)) as f64 }, qubits![qreg[n-1-j], qreg[n-1-i]])
+            let angle = -PI / (1 << (j - i)) as f64;
+            ctx.push(CP { angle }, qubits![qreg[n-1-j], qreg[n-1-i]]).unwrap();
         }
-        ctx.push(H, qubits![qreg[n - 1 - i]]);
+        ctx.push(H, qubits![qreg[n - 1 - i]]).unwrap();
     }
-    ctx.exit();
+    ctx.exit().unwrap();
 }
 
 /// |φ(x)> -> |φ(x+a)>
@@ -248,11 +286,11 @@ fn phase_adder(ctx: &mut QuantumProgramContext, qreg: &QubitAccessor, a: i32) {
                 ctx.push(
                     P { angle: PI * 2f64.pow(j as i32 - i as i32) },
                     qubits![qreg[j as usize]],
-                );
+                ).unwrap();
             }
         }
     }
-    ctx.exit();
+    ctx.exit().unwrap();
 }
 
 fn adder(ctx: &mut QuantumProgramContext, qreg: &QubitAccessor, value: i32) {
@@ -262,33 +300,33 @@ fn adder(ctx: &mut QuantumProgramContext, qreg: &QubitAccessor, value: i32) {
     ctx.pause_ctrl();
     qft(ctx, qreg);
     // swap(&mut empty_ctrl, &mut ctx.ctrl_qubits);
-    ctx.restore_ctrl();
+    ctx.restore_ctrl().unwrap();
     phase_adder(ctx, qreg, value);
     // swap(&mut empty_ctrl, &mut ctx.ctrl_qubits);
     ctx.pause_ctrl();
     ctx.begin_dagger();
     qft(ctx, qreg);
-    ctx.end_dagger();
+    ctx.end_dagger().unwrap();
     // swap(&mut empty_ctrl, &mut ctx.ctrl_qubits);
-    ctx.restore_ctrl();
-    ctx.exit();
+    ctx.restore_ctrl().unwrap();
+    ctx.exit().unwrap();
 }
 
 fn test_adder_helper(a: i32, b: i32, n: usize) -> i32 {
     let mut ctx = get_ctx_with_default_passes();
     ctx.enter();
-    let alloc = ctx.alloc(n);
+    let alloc = ctx.alloc(n).unwrap();
     let qreg = alloc.borrow();
     for i in 0 .. n {
         if a & (1 << i) != 0 {
-            ctx.push(X, qubits![qreg[i]]);
+            ctx.push(X, qubits![qreg[i]]).unwrap();
         }
     }
     adder(&mut ctx, &qreg, b);
     ctx.measure(qreg.slice(0, n as QubitAddr, 1));
-    ctx.exit();
+    ctx.exit().unwrap();
 
-    let instructions = ctx.compile_circuit();
+    let instructions = ctx.compile_circuit().unwrap();
     print_instructions(&instructions);
     let result: ExecuteResult = execute_bytecode(instructions.into(), 10).into();
 
@@ -307,14 +345,14 @@ fn mod_adder(ctx: &mut QuantumProgramContext, qreg: &QubitAccessor, value: i32,
 
     let a = (value % p + p) % p;
     let n = qreg.size();
-    let overflow_ancilla = ctx.alloc(1);
-    let mod_ancilla = ctx.alloc(1);
+    let overflow_ancilla = ctx.alloc(1).unwrap();
+    let mod_ancilla = ctx.alloc(1).unwrap();
     let b = qreg.clone() + overflow_ancilla.borrow().clone();
 
     adder(ctx, &b, a);
 
     adder(ctx, &b, -p);
-    ctx.push(CX, overflow_ancilla.borrow().clone() + mod_ancilla.borrow().clone());
+    ctx.push(CX, overflow_ancilla.borrow().clone() + mod_ancilla.borrow().clone()).unwrap();
 
     ctx.control(mod_ancilla.borrow().clone(), true);
     adder(ctx, &b, p);
@@ -325,36 +363,36 @@ fn mod_adder(ctx: &mut QuantumProgramContext, qreg: &QubitAccessor, value: i32,
     ctx.pause_ctrl();
     adder(ctx, &b, -a);
     // swap(&mut ctx.ctrl_qubits, &mut empty_ctrl);
-    ctx.restore_ctrl();
-    ctx.push(X, overflow_ancilla.borrow().clone());
-    ctx.push(CX, overflow_ancilla.borrow().clone() + mod_ancilla.borrow().clone());
-    ctx.push(X, overflow_ancilla.borrow().clone());
+    ctx.restore_ctrl().unwrap();
+    ctx.push(X, overflow_ancilla.borrow().clone()).unwrap();
+    ctx.push(CX, overflow_ancilla.borrow().clone() + mod_ancilla.borrow().clone()).unwrap();
+    ctx.push(X, overflow_ancilla.borrow().clone()).unwrap();
     // swap(&mut ctx.ctrl_qubits, &mut empty_ctrl);
     ctx.pause_ctrl();
     ctx.begin_dagger();
     adder(ctx, &b, -a);
-    ctx.end_dagger();
+    ctx.end_dagger().unwrap();
     // swap(&mut ctx.ctrl_qubits, &mut empty_ctrl);
-    ctx.restore_ctrl();
+    ctx.restore_ctrl().unwrap();
 
-    ctx.exit();
+    ctx.exit().unwrap();
 }
 
 fn test_mod_adder_helper(a: i32, b: i32, p: i32, n: usize) -> i32 {
     let mut ctx = get_ctx_with_default_passes();
     ctx.enter();
-    let alloc = ctx.alloc(n);
+    let alloc = ctx.alloc(n).unwrap();
     let qreg = alloc.borrow();
     for i in 0 .. n {
         if a & (1 << i) != 0 {
-            ctx.push(X, qubits![qreg[i]]);
+            ctx.push(X, qubits![qreg[i]]).unwrap();
         }
     }
     mod_adder(&mut ctx, &qreg, b, p);
     ctx.measure(qreg.slice(0, n as QubitAddr, 1));
-    ctx.exit();
+    ctx.exit().unwrap();
 
-    let instructions = ctx.compile_circuit();
+    let instructions = ctx.compile_circuit().unwrap();
 
     print_instructions(&instructions);
     let result: ExecuteResult = execute_bytecode(instructions.into(), 10).into();
@@ -406,13 +444,13 @@ fn mod_multiplier(
     let a = (value % p + p) % p;
     let b = p - mod_inv(a, p);
     let n = qreg.size();
-    let ancilla = ctx.alloc(n);
+    let ancilla = ctx.alloc(n).unwrap();
     mod_mult0(ctx, qreg, &ancilla.borrow().clone(), a, p);
     for i in 0 .. n {
-        ctx.push(SWP, qubits![qreg[i], ancilla.borrow()[i]]);
+        ctx.push(SWP, qubits![qreg[i], ancilla.borrow()[i]]).unwrap();
     }
     mod_mult0(ctx, qreg, &ancilla.borrow().clone(), b, p);
-    ctx.exit();
+    ctx.exit().unwrap();
 }
 
 fn pow_mod(mut base: i32, mut exponent: i32, modular: i32) -> i32 {
@@ -431,18 +469,18 @@ fn pow_mod(mut base: i32, mut exponent: i32, modular: i32) -> i32 {
 fn test_mod_multiplier_helper(a: i32, b: i32, p: i32, n: usize) -> i32 {
     let mut ctx = get_ctx_with_default_passes();
     ctx.enter();
-    let alloc0 = ctx.alloc(n);
+    let alloc0 = ctx.alloc(n).unwrap();
     let qreg = alloc0.borrow();
     for i in 0 .. n {
         if a & (1 << i) != 0 {
-            ctx.push(X, qubits![qreg[i]]);
+            ctx.push(X, qubits![qreg[i]]).unwrap();
         }
     }
     mod_multiplier(&mut ctx, &qreg, b, p);
     ctx.measure(qreg.slice(0, n as QubitAddr, 1));
-    ctx.exit();
+    ctx.exit().unwrap();
 
-    let instructions = ctx.compile_circuit();
+    let instructions = ctx.compile_circuit().unwrap();
 
     let shots = 5;
     let result: ExecuteResult = execute_bytecode(instructions.into(), shots).into();
@@ -468,13 +506,13 @@ fn shor_period_finder_circuit(ctx: &mut QuantumProgramContext, a: i32, p: i32) {
     let n = p.ilog2() + 1;
     println!("n = {}", n);
     ctx.enter();
-    let work = ctx.alloc(2 * n as usize);
-    let mult = ctx.alloc(n as usize);
+    let work = ctx.alloc(2 * n as usize).unwrap();
+    let mult = ctx.alloc(n as usize).unwrap();
 
-    ctx.push(X, qubits![mult.borrow()[0]]);
+    ctx.push(X, qubits![mult.borrow()[0]]).unwrap();
 
     for i in 0 .. work.borrow().size() {
-        ctx.push(H, qubits![work.borrow()[i]]);
+        ctx.push(H, qubits![work.borrow()[i]]).unwrap();
     }
 
     for i in 0 .. 2 * n {
@@ -486,9 +524,9 @@ fn shor_period_finder_circuit(ctx: &mut QuantumProgramContext, a: i32, p: i32) {
     // inv_qft(ctx, &work.borrow().clone());
     ctx.begin_dagger();
     qft(ctx, &work.borrow().clone());
-    ctx.end_dagger();
+    ctx.end_dagger().unwrap();
 
-    ctx.exit();
+    ctx.exit().unwrap();
 
     ctx.measure(work.borrow().clone());
 }
@@ -551,7 +589,7 @@ fn shor_period_finder(a: i32, p: i32) -> i32 {
     // for op in ctx.circuit.operations.iter() {
     //     println!("{:?}", op.operation);
     // }
-    let instructions = ctx.compile_circuit();
+    let instructions = ctx.compile_circuit().unwrap();
     // print_instructions(&instructions);
     println!("{} instructions", instructions.len());
     let bytecode: ByteCode = instructions.into();
@@ -581,3 +619,801 @@ fn shor_period_finder(a: i32, p: i32) -> i32 {
 fn test_shor_period_finder() {
     println!("{}", shor_period_finder(14, 15));
 }
+
+fn get_program() -> QuantumProgram {
+    QuantumProgram::from_context(QuantumProgramContextBuilder::with_backend(NativeBackend).build())
+}
+
+#[test]
+fn test_quantum_program_sections() {
+    let mut program = get_program();
+    let qreg = program.alloc(3).unwrap();
+    program.apply(H, &qreg.get(0).unwrap()).unwrap();
+    program.ctrl(&qreg.get(0).unwrap(), |program| {
+        assert!(program.context().ctrl_qubits.contains(qreg[0]));
+        program.apply(X, &qreg.get(1).unwrap())
+    }).unwrap();
+    assert!(program.context().ctrl_qubits.is_empty());
+    {
+        let mut guard = program.begin_ctrl(&qreg.get(1).unwrap(), false);
+        assert!(guard.context().ctrl_qubits.contains(qreg[1]));
+        let mut guard = guard.begin_dagger();
+        assert!(guard.context().is_dagger());
+        guard.apply(S, &qreg.get(2).unwrap()).unwrap();
+    }
+    assert!(program.context().ctrl_qubits.is_empty());
+    assert!(!program.context().is_dagger());
+}
+
+#[test]
+fn test_quantum_program_scope() {
+    let mut program = get_program();
+    assert_eq!(program.alloc(2).unwrap().to_vec(), vec![0, 1]);
+    let ancilla = program.scope(|program| program.alloc(2)).unwrap();
+    assert_eq!(ancilla.to_vec(), vec![2, 3]);
+    assert_eq!(program.alloc(1).unwrap().to_vec(), vec![2]);
+}
+
+#[test]
+fn test_quantum_program_dagger() {
+    let mut program = get_program();
+    let qreg = program.alloc(2).unwrap();
+    program.dagger(|program| {
+        program.apply(S, &qreg.get(0).unwrap())?;
+        program.apply(CX, &qreg)
+    }).unwrap();
+    program.measure(&qreg);
+    let instructions = program.context().compile_circuit().unwrap();
+    assert_eq!(asm::disassemble(&instructions), "ALLOC 2\nCX [0, 1]\nSD [0]\nMEASURE 0, 1\n");
+}
+
+#[test]
+fn test_invalid_operations() {
+    let mut ctx = get_ctx_with_default_passes();
+    assert!(matches!(ctx.exit(), Err(QivmError::StackUnderflow("quantum"))));
+    assert!(matches!(ctx.alloc(1), Err(QivmError::EmptyQuantumStack)));
+    assert!(matches!(ctx.end_dagger(), Err(QivmError::StackUnderflow("dagger"))));
+    assert!(matches!(ctx.restore_ctrl(), Err(QivmError::StackUnderflow("control qubits"))));
+
+    ctx.enter();
+    assert!(matches!(ctx.alloc(0), Err(QivmError::InvalidArgument(_))));
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    assert!(matches!(ctx.alloc(u32::MAX as usize), Err(QivmError::InvalidArgument(_))));
+    ctx.control(qubits![qreg[0]], true);
+    assert!(matches!(ctx.push(CX, qreg.clone()), Err(QivmError::ControlledTarget)));
+    ctx.pause_ctrl();
+    ctx.control(qubits![qreg[1]], true);
+    assert!(matches!(ctx.restore_ctrl(), Err(QivmError::ActiveControl)));
+    ctx.decontrol(qubits![qreg[1]]);
+    ctx.restore_ctrl().unwrap();
+    ctx.push(X, qubits![qreg[1]]).unwrap();
+    ctx.decontrol(qubits![qreg[0]]);
+    ctx.exit().unwrap();
+}
+
+#[test]
+fn test_quantum_program_errors() {
+    let mut program = get_program();
+    let qreg = program.alloc(2).unwrap();
+    let err = program.ctrl(&qreg.get(0).unwrap(), |program| program.apply(H, &qreg)).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid operation: target qubit is controlled");
+    assert!(program.context().ctrl_qubits.is_empty());
+    program.apply(H, &qreg).unwrap();
+}
+
+#[test]
+fn test_controlled_multi_target_gates() {
+    let mut program = QuantumProgram::with_backend(NativeBackend);
+    let qreg = program.alloc(5).unwrap();
+    for index in [0, 1, 4] {
+        program.apply(X, &qreg.get(index).unwrap()).unwrap();
+    }
+    program.ctrl(&qreg.get(0).unwrap(), |program| {
+        program.apply(ISWP, &qubits![qreg[1], qreg[2]])?;
+        program.dagger(|program| program.apply(CCX, &qubits![qreg[2], qreg[4], qreg[3]]))
+    }).unwrap();
+    program.measure(&qreg);
+    let result = program.execute(16).unwrap();
+    let values = result.measurement.measurements.iter()
+        .map(|entry| entry.value)
+        .collect::<Vec<u64>>();
+    assert_eq!(values, vec![0b11101]);
+}
+
+/// A backend which forwards to the native simulator and provides the custom gate `FOO`.
+struct CustomGateBackend;
+
+impl QivmBackend for CustomGateBackend {
+    fn available_qubits(&self) -> usize {
+        NativeBackend.available_qubits()
+    }
+
+    fn is_gate_available(&self, gate_ident: &str) -> bool {
+        gate_ident == "FOO" || NativeBackend.is_gate_available(gate_ident)
+    }
+
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        NativeBackend.execute(bytecode, shots)
+    }
+}
+
+fn count_ops(ctx: &mut QuantumProgramContext, ident: &str) -> usize {
+    let mut count = 0;
+    ctx.circuit.all(|op, _| {
+        if matches!(op, Operation::Elementary(op) if op.get_ident() == ident) {
+            count += 1;
+        }
+        true
+    });
+    count
+}
+
+#[test]
+fn test_push_custom() {
+    let x: GateMat = [c64!(0), c64!(1), c64!(1), c64!(0)].as_slice().into();
+    let swap: GateMat = (0 .. 16).map(|i| match i {
+        0 | 6 | 9 | 15 => c64!(1),
+        _ => c64!(0),
+    }).collect::<Vec<_>>().as_slice().into();
+    let mut ctx = QuantumProgramContextBuilder::with_backend(CustomGateBackend).build();
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+
+    ctx.push_custom("FOO".to_string(), x.clone(), vec![7], qubits![qreg[0]]).unwrap();
+    ctx.push_custom("BAR".to_string(), x.clone(), vec![], qubits![qreg[1]]).unwrap();
+    ctx.begin_dagger();
+    ctx.push_custom("FOO".to_string(), x.clone(), vec![7], qubits![qreg[0]]).unwrap();
+    ctx.end_dagger().unwrap();
+    assert_eq!(count_ops(&mut ctx, "FOO"), 1);
+    assert_eq!(count_ops(&mut ctx, "_1"), 2);
+
+    ctx.control(qubits![qreg[2]], false);
+    ctx.push_custom("SWAP".to_string(), swap.clone(), vec![], qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push_custom("FOO".to_string(), x.clone(), vec![7], qubits![qreg[0]]).unwrap();
+    assert!(ctx.ctrl_qubits.contains(qreg[2]));
+    ctx.decontrol(qubits![qreg[2]]);
+    assert_eq!(count_ops(&mut ctx, "_n"), 1);
+    assert_eq!(count_ops(&mut ctx, "FOO"), 1);
+
+    let result = ctx.push_custom("FOO".to_string(), swap, vec![], qubits![qreg[0]]);
+    assert!(matches!(result, Err(QivmError::TargetSizeMismatch { expected: 2, actual: 1 })));
+    let non_unitary: GateMat = [c64!(1), c64!(1), c64!(0), c64!(1)].as_slice().into();
+    let result = ctx.push_custom("FOO".to_string(), non_unitary, vec![], qubits![qreg[0]]);
+    assert!(matches!(result, Err(QivmError::NonUnitaryGate(_))));
+    ctx.exit().unwrap();
+}
+
+#[test]
+fn test_push_custom_builtin() {
+    let mut ctx = QuantumProgramContextBuilder::with_backend(CustomGateBackend).build();
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+
+    ctx.push_custom_builtin("FOO".to_string(), vec![7], 2, qubits![qreg[0], qreg[1]]).unwrap();
+    let result = ctx.push_custom_builtin("BAR".to_string(), vec![], 1, qubits![qreg[0]]);
+    assert!(matches!(result, Err(QivmError::UnavailableGate(ident)) if ident == "BAR"));
+    let result = ctx.push_custom_builtin("FOO".to_string(), vec![], 1, qubits![qreg[0], qreg[1]]);
+    assert!(matches!(result, Err(QivmError::TargetSizeMismatch { expected: 1, actual: 2 })));
+
+    ctx.begin_dagger();
+    let result = ctx.push_custom_builtin("FOO".to_string(), vec![7], 2, qubits![qreg[0], qreg[1]]);
+    assert!(matches!(result, Err(QivmError::UnavailableGate(ident)) if ident == "FOOD"));
+    ctx.end_dagger().unwrap();
+
+    ctx.control(qubits![qreg[2]], true);
+    let result = ctx.push_custom_builtin("FOO".to_string(), vec![7], 2, qubits![qreg[0], qreg[1]]);
+    assert!(matches!(result, Err(QivmError::UncontrollableGate(_))));
+    ctx.decontrol(qubits![qreg[2]]);
+    ctx.exit().unwrap();
+
+    let mut name = [0u8; 16];
+    name[.. 3].copy_from_slice(b"FOO");
+    let instructions = ctx.compile_circuit().unwrap();
+    assert!(instructions.contains(&Instruction::CustomGateOperation {
+        name, params: vec![InstrParam::UInt(7)], targets: vec![0, 1],
+    }));
+}
+
+/// A backend which forwards to the native simulator, with a restricted set of gates.
+struct RestrictedBackend(&'static [&'static str]);
+
+impl QivmBackend for RestrictedBackend {
+    fn available_qubits(&self) -> usize {
+        NativeBackend.available_qubits()
+    }
+
+    fn is_gate_available(&self, gate_ident: &str) -> bool {
+        self.0.contains(&gate_ident)
+    }
+
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        NativeBackend.execute(bytecode, shots)
+    }
+}
+
+#[test]
+fn test_elementary_decomposition_fixpoint() {
+    let backend = RestrictedBackend(&["H", "RY", "RZ", "P", "CZ"]);
+    let mut ctx = ctx_with(backend, verified_default_passes);
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(SWP, qubits![qreg[1], qreg[2]]).unwrap();
+    ctx.push(CP { angle: PI }, qubits![qreg[0], qreg[2]]).unwrap();
+    ctx.measure(qreg);
+    ctx.exit().unwrap();
+    ctx.compile_circuit().unwrap();
+    assert!(ctx.circuit.elementary_all(|op| {
+        ["H", "RY", "RZ", "P", "CZ"].contains(&op.get_ident().as_str())
+    }).unwrap());
+    assert_eq!(count_ops(&mut ctx, "CZ"), 6);
+}
+
+#[test]
+fn test_elementary_decomposition_unlowered_gates() {
+    let mut ctx = ctx_with(RestrictedBackend(&["H", "CX"]), verified_default_passes);
+    ctx.enter();
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(X, qubits![qreg[1]]).unwrap();
+    ctx.push(Z, qubits![qreg[0]]).unwrap();
+    ctx.exit().unwrap();
+    let err = ctx.compile_circuit().unwrap_err();
+    assert_eq!(
+        err.to_string(), "Unable to lower gates onto the target platform: `X`, `Z`"
+    );
+    assert_eq!(err.code(), 23);
+}
+
+#[test]
+fn test_verify_native_gates() {
+    let mut ctx = ctx_with(RestrictedBackend(&["H"]), |builder| builder.verify_native_gates());
+    ctx.enter();
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.exit().unwrap();
+    let result = ctx.compile_bytecode();
+    assert!(matches!(result, Err(QivmError::UnloweredGates(idents)) if idents == ["CX"]));
+}
+
+#[test]
+fn test_gate_cancellation() {
+    let mut ctx = ctx_with(NativeBackend, |builder| {
+        builder.add_pass(GateCancellationPass::new());
+    });
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    // RZ commutes with the control of CX
+    ctx.push(RZ { angle: 0.25 }, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(SWP, qubits![qreg[1], qreg[2]]).unwrap();
+    ctx.push(SWP, qubits![qreg[2], qreg[1]]).unwrap();
+    ctx.push(CX, qubits![qreg[1], qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.exit().unwrap();
+    let instructions = ctx.compile_circuit().unwrap();
+    assert_eq!(asm::disassemble(&instructions), "ALLOC 3\nRZ(0.25) [0]\nCX [1, 0]\nCX [0, 1]\n");
+}
+
+#[test]
+fn test_adjacent_gate_cancellation() {
+    let mut ctx = ctx_with(NativeBackend, |builder| {
+        builder.add_pass(GateCancellationPass::adjacent());
+    });
+    ctx.enter();
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(X, qubits![qreg[1]]).unwrap();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    // RZ commutes with the control of CX, but is not moved through it
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(RZ { angle: 0.25 }, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.exit().unwrap();
+    let instructions = ctx.compile_circuit().unwrap();
+    assert_eq!(
+        asm::disassemble(&instructions), "ALLOC 2\nX [1]\nCX [0, 1]\nRZ(0.25) [0]\nCX [0, 1]\n"
+    );
+}
+
+#[test]
+fn test_gate_merging() {
+    let mut ctx = ctx_with(NativeBackend, |builder| {
+        builder.add_pass(GateCancellationPass::new());
+    });
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+    ctx.push(RZ { angle: 0.25 }, qubits![qreg[0]]).unwrap();
+    ctx.push(T, qubits![qreg[0]]).unwrap();
+    ctx.push(S, qubits![qreg[1]]).unwrap();
+    ctx.push(RZ { angle: 0.5 }, qubits![qreg[0]]).unwrap();
+    ctx.push(S, qubits![qreg[1]]).unwrap();
+    ctx.push(TD, qubits![qreg[0]]).unwrap();
+    ctx.push(T, qubits![qreg[2]]).unwrap();
+    ctx.push(T, qubits![qreg[2]]).unwrap();
+    ctx.push(CP { angle: 0.25 }, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(CP { angle: 0.5 }, qubits![qreg[1], qreg[0]]).unwrap();
+    // H does not commute with the target of CX
+    ctx.push(H, qubits![qreg[2]]).unwrap();
+    ctx.push(CX, qubits![qreg[1], qreg[2]]).unwrap();
+    ctx.push(H, qubits![qreg[2]]).unwrap();
+    ctx.exit().unwrap();
+    let instructions = ctx.compile_circuit().unwrap();
+    assert_eq!(asm::disassemble(&instructions), "\
+ALLOC 3
+RZ(0.75) [0]
+Z [1]
+S [2]
+CP(0.75) [0, 1]
+H [2]
+CX [1, 2]
+H [2]
+");
+}
+
+#[test]
+fn test_gate_cancellation_across_scopes() {
+    let mut ctx = ctx_with(NativeBackend, |builder| {
+        builder.add_pass(GateCancellationPass::new());
+    });
+    ctx.enter();
+    let qreg = ctx.alloc(1).unwrap().borrow().clone();
+    ctx.push(X, qubits![qreg[0]]).unwrap();
+    ctx.enter();
+    let ancilla = ctx.alloc(1).unwrap().borrow().clone();
+    ctx.push(H, qubits![ancilla[0]]).unwrap();
+    ctx.exit().unwrap();
+    ctx.push(X, qubits![qreg[0]]).unwrap();
+    ctx.exit().unwrap();
+    ctx.transpile().unwrap();
+    assert_eq!(count_ops(&mut ctx, "X"), 2);
+}
+
+fn push_fusion_circuit(ctx: &mut QuantumProgramContext) {
+    ctx.enter();
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    for gate in [H, T, H, S] {
+        ctx.push(gate, qubits![qreg[0]]).unwrap();
+    }
+    // X H Z H is the identity
+    for gate in [H, Z, H, X] {
+        ctx.push(gate, qubits![qreg[1]]).unwrap();
+    }
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.exit().unwrap();
+}
+
+#[test]
+fn test_single_gate_fusion() {
+    let mut ctx = ctx_with(RestrictedBackend(&["RY", "RZ", "CX"]), verified_default_passes);
+    push_fusion_circuit(&mut ctx);
+    ctx.compile_circuit().unwrap();
+    assert_eq!(count_ops(&mut ctx, "CX"), 1);
+    assert!(count_ops(&mut ctx, "RY") + count_ops(&mut ctx, "RZ") <= 3);
+    assert!(ctx.circuit.elementary_all(|op| {
+        op.get_ident() == "CX" || op.get_target()[0] == 0
+    }).unwrap());
+}
+
+#[test]
+fn test_single_gate_fusion_without_rotations() {
+    let backend = RestrictedBackend(&["H", "S", "T", "X", "Z", "CX"]);
+    let mut ctx = ctx_with(backend, verified_default_passes);
+    push_fusion_circuit(&mut ctx);
+    ctx.compile_circuit().unwrap();
+    // the run of the first qubit is kept, the identity run of the second qubit is dropped
+    assert_eq!(count_ops(&mut ctx, "H"), 2);
+    assert_eq!(count_ops(&mut ctx, "X"), 0);
+    assert_eq!(count_ops(&mut ctx, "Z"), 0);
+    assert_eq!(ctx.circuit.operations.len(), 5);
+}
+
+fn dag_circuit(operations: Vec<(Operation, QubitAddr)>) -> CircuitDag {
+    QuantumCircuit::from_operations(operations.into_iter().map(|(operation, stack_top)| {
+        CircuitOperation::new(operation, stack_top)
+    }).collect()).into()
+}
+
+fn dag_idents(dag: CircuitDag) -> Vec<(String, QubitAddr)> {
+    QuantumCircuit::from(dag).operations.into_iter().map(|op| match op.operation {
+        Operation::Elementary(operation) => (operation.get_ident(), op.stack_top),
+        _ => unreachable!(),
+    }).collect()
+}
+
+#[test]
+fn test_circuit_dag() {
+    // the qubit 2 is reset between the scopes
+    let dag = dag_circuit(vec![
+        (H.apply_to(0).into(), 3),
+        (X.apply_to(2).into(), 3),
+        (CX.apply_to((0, 1)).into(), 2),
+        (H.apply_to(2).into(), 3),
+    ]);
+    assert_eq!(dag.len(), 4);
+    assert_eq!(dag.wires().collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert_eq!(dag.wire(0).collect::<Vec<_>>(), vec![0, 2]);
+    assert_eq!(dag.wire(2).collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(dag.predecessors(2), vec![0]);
+    assert_eq!(dag.successors(0), vec![2]);
+    assert_eq!(dag.node(2).unwrap().qubits(), &[0, 1]);
+    assert_eq!(dag.layers(), vec![vec![0, 1], vec![2], vec![3]]);
+    let expected: [(&str, QubitAddr); 4] = [("H", 3), ("X", 3), ("CX", 2), ("H", 3)];
+    assert_eq!(dag_idents(dag), expected.map(|(ident, top)| (ident.to_string(), top)));
+
+    let dag = dag_circuit(vec![
+        (H.apply_to(0).into(), 2),
+        (H.apply_to(1).into(), 2),
+        (CX.apply_to((0, 1)).into(), 2),
+        (X.apply_to(0).into(), 2),
+        (Z.apply_to(1).into(), 2),
+    ]);
+    assert_eq!(dag.layers(), vec![vec![0, 1], vec![2], vec![3, 4]]);
+}
+
+#[test]
+fn test_circuit_dag_replace_subcircuit() {
+    let operations = || vec![
+        (H.apply_to(1).into(), 2),
+        (CX.apply_to((0, 1)).into(), 2),
+        (H.apply_to(1).into(), 2),
+        (X.apply_to(0).into(), 2),
+    ];
+    let mut dag = dag_circuit(operations());
+    let inserted = dag.replace_subcircuit(&[0, 1, 2], vec![CZ.apply_to((0, 1)).into()]).unwrap();
+    assert_eq!(inserted, vec![4]);
+    assert_eq!(dag.predecessors(3), vec![4]);
+    assert_eq!(dag.wire(1).collect::<Vec<_>>(), vec![4]);
+    dag.remove(3).unwrap();
+    assert_eq!(dag_idents(dag), vec![("CZ".to_string(), 2)]);
+
+    let mut dag = dag_circuit(operations());
+    let err = dag.replace_subcircuit(&[0, 2], vec![]).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid argument: The nodes do not form a convex subcircuit");
+    let err = dag.replace_subcircuit(&[0], vec![X.apply_to(0).into()]).unwrap_err();
+    assert_eq!(err.code(), 6);
+    assert_eq!(dag.len(), 4);
+}
+
+/// A backend which forwards to the native simulator, with a coupling map.
+struct CouplingBackend(CouplingMap);
+
+impl QivmBackend for CouplingBackend {
+    fn available_qubits(&self) -> usize {
+        self.0.size()
+    }
+
+    fn is_gate_available(&self, gate_ident: &str) -> bool {
+        NativeBackend.is_gate_available(gate_ident)
+    }
+
+    fn execute(&self, bytecode: &ByteCode, shots: usize) -> ExecuteResult {
+        NativeBackend.execute(bytecode, shots)
+    }
+
+    fn coupling_map(&self) -> Option<CouplingMap> {
+        Some(self.0.clone())
+    }
+}
+
+/// Push a deterministic circuit with distant CX gates, and an ancilla which is reset before the
+/// last gate.
+fn push_routing_circuit(ctx: &mut QuantumProgramContext) {
+    ctx.enter();
+    let qreg = ctx.alloc(4).unwrap().borrow().clone();
+    ctx.push(X, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[3]]).unwrap();
+    ctx.push(CX, qubits![qreg[3], qreg[1]]).unwrap();
+    ctx.enter();
+    let ancilla = ctx.alloc(1).unwrap().borrow().clone();
+    ctx.push(CX, qubits![qreg[0], ancilla[0]]).unwrap();
+    ctx.push(CX, qubits![ancilla[0], qreg[2]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], ancilla[0]]).unwrap();
+    ctx.exit().unwrap();
+    ctx.push(CX, qubits![qreg[2], qreg[0]]).unwrap();
+    ctx.measure(qreg);
+    ctx.exit().unwrap();
+}
+
+fn routing_result(mut ctx: QuantumProgramContext) -> u64 {
+    push_routing_circuit(&mut ctx);
+    let result = ctx.execute(1).unwrap();
+    result.measurement.measurements[0].value
+}
+
+#[test]
+fn test_routing() {
+    let coupling_map = CouplingMap::linear(5);
+    let mut ctx = ctx_with(CouplingBackend(coupling_map.clone()), |builder| {
+        builder.default_passes();
+    });
+    push_routing_circuit(&mut ctx);
+    ctx.compile_circuit().unwrap();
+    let swaps = count_ops(&mut ctx, "SWP");
+    assert!(swaps > 0);
+    assert!(ctx.circuit.elementary_all(|op| {
+        let target = op.get_target();
+        target.size() != 2 || coupling_map.is_coupled(target[0], target[1])
+    }).unwrap());
+    assert_eq!(ctx.final_layout().unwrap().size(), 5);
+    // a routed circuit is not routed again
+    ctx.compile_circuit().unwrap();
+    assert_eq!(count_ops(&mut ctx, "SWP"), swaps);
+}
+
+#[test]
+fn test_routing_preserves_measurement() {
+    let routed = routing_result(ctx_with(CouplingBackend(CouplingMap::grid(2, 3)), |builder| {
+        builder.default_passes();
+    }));
+    let unrouted = routing_result(ctx_with(NativeBackend, |builder| builder.default_passes()));
+    assert_eq!(routed, unrouted);
+    assert_eq!(routed, 0b1110);
+}
+
+#[test]
+fn test_routing_errors() {
+    let mut ctx = ctx_with(CouplingBackend(CouplingMap::linear(3)), |builder| {
+        builder.default_passes();
+    });
+    push_routing_circuit(&mut ctx);
+    let err = ctx.compile_circuit().unwrap_err();
+    assert_eq!(
+        err.to_string(), "Compile error: The circuit uses 5 qubits, the coupling map has only 3"
+    );
+
+    let mut ctx = ctx_with(CouplingBackend(CouplingMap::new(5)), |builder| {
+        builder.default_passes();
+    });
+    push_routing_circuit(&mut ctx);
+    assert!(matches!(ctx.compile_circuit(), Err(QivmError::Compile(_))));
+}
+
+#[test]
+fn test_layout_selection() {
+    let mut ctx = ctx_with(CouplingBackend(CouplingMap::linear(5)), |builder| {
+        builder.optimization_passes(OptimizationLevel::O3);
+    });
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+    // the first qubit interacts with both others, it is placed between them
+    ctx.push(X, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[2]]).unwrap();
+    ctx.push(CX, qubits![qreg[1], qreg[0]]).unwrap();
+    ctx.measure(qreg);
+    ctx.exit().unwrap();
+    let result = ctx.execute(1).unwrap();
+    assert_eq!(count_ops(&mut ctx, "SWP"), 0);
+    assert_eq!(result.measurement.measurements[0].value, 0b110);
+    let layout = ctx.final_layout().unwrap();
+    assert_eq!(layout.physical(0), 1);
+    assert_eq!(layout.physical(1).abs_diff(layout.physical(2)), 2);
+}
+
+#[test]
+fn test_measurement_result_layout() {
+    let layout = Layout::from_physical(vec![1, 2, 0]).unwrap();
+    // the physical qubits 1 and 0 hold the logical qubits 0 and 2
+    assert_eq!(super::logical_value(0b011, &layout), 0b101);
+    assert_eq!(super::logical_value(0b1100, &layout), 0b1010);
+}
+
+#[test]
+fn test_layout_selection_error_rates() {
+    let mut coupling_map = CouplingMap::linear(4);
+    coupling_map.set_edge_error(0, 1, 0.2).unwrap();
+    coupling_map.set_edge_error(1, 2, 0.01).unwrap();
+    coupling_map.set_edge_error(2, 3, 0.05).unwrap();
+    coupling_map.set_qubit_error(2, 0.1).unwrap();
+    let mut ctx = ctx_with(CouplingBackend(coupling_map), |builder| {
+        builder.optimization_passes(OptimizationLevel::O3);
+    });
+    ctx.enter();
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.exit().unwrap();
+    ctx.transpile().unwrap();
+    // the most reliable coupling, the single-qubit gate avoiding the noisy qubit
+    let layout = ctx.final_layout().unwrap();
+    assert_eq!((layout.physical(0), layout.physical(1)), (1, 2));
+}
+
+#[test]
+fn test_circuit_stats() {
+    let mut ctx = QuantumProgramContextBuilder::with_backend(NativeBackend).build();
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(T, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(TD, qubits![qreg[2]]).unwrap();
+    ctx.push(CX, qubits![qreg[1], qreg[2]]).unwrap();
+    ctx.push(X, qubits![qreg[0]]).unwrap();
+    ctx.exit().unwrap();
+    let stats = ctx.circuit_stats();
+    assert_eq!(stats.gate_count(), 6);
+    assert_eq!(stats.count("CX"), 2);
+    assert_eq!(stats.two_qubit_count, 2);
+    assert_eq!(stats.t_count, 2);
+    assert_eq!(stats.depth, 4);
+    assert_eq!(stats.two_qubit_depth, 2);
+    assert_eq!(stats.peak_stack_top, 3);
+    assert_eq!(stats.qubits, 3);
+    assert!(ctx.transpile_stats().is_empty());
+    ctx.transpile().unwrap();
+    assert_eq!(ctx.transpile_stats(), [stats]);
+}
+
+#[test]
+fn test_transpile_stats() {
+    let mut ctx = ctx_with(NativeBackend, |builder| {
+        builder.add_pass(GateCancellationPass::new());
+    });
+    ctx.enter();
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.exit().unwrap();
+    ctx.transpile().unwrap();
+    let stats = ctx.transpile_stats();
+    assert_eq!(stats.len(), 2);
+    assert_eq!((stats[0].count("H"), stats[0].depth), (2, 3));
+    assert_eq!((stats[1].count("H"), stats[1].depth), (0, 1));
+    assert_eq!(stats[1].gate_counts.len(), 1);
+}
+
+fn push_pass_manager_circuit(ctx: &mut QuantumProgramContext) {
+    ctx.enter();
+    let qreg = ctx.alloc(3).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.push(SWP, qubits![qreg[1], qreg[2]]).unwrap();
+    ctx.push(T, qubits![qreg[2]]).unwrap();
+    ctx.measure(qreg);
+    ctx.exit().unwrap();
+}
+
+#[test]
+fn test_pass_manager() {
+    let mut ctx = ctx_with(NativeBackend, |builder| builder.default_passes());
+    let names = ctx.pass_manager().names().iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    assert_eq!(names[0], "MultiplexOptimizationPass");
+    assert!(names.contains(&"RemoveIdentityPass#2".to_string()));
+    let manager = ctx.pass_manager_mut();
+    manager.check_after_each(InvariantCheck::UnitaryEquivalence { max_qubits: 4 });
+    manager.check_after("ElementaryDecompositionPass", InvariantCheck::NativeGates).unwrap();
+    assert!(manager.check_after("UnknownPass", InvariantCheck::NativeGates).is_err());
+    push_pass_manager_circuit(&mut ctx);
+    ctx.compile_circuit().unwrap();
+    let timings = ctx.pass_manager().timings();
+    assert_eq!(timings.len(), names.len());
+    assert_eq!(timings[0].0, names[0]);
+    assert_eq!(ctx.transpile_stats().len(), names.len() + 1);
+}
+
+/// A faulty pass removing the last operation.
+struct DropLastPass;
+
+impl Pass for DropLastPass {
+    fn apply(&mut self, circuit: &mut QuantumCircuit) -> Result<(), QivmError> {
+        circuit.operations.pop();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_pass_manager_invariant_violation() {
+    let mut ctx = QuantumProgramContextBuilder::with_backend(NativeBackend).build();
+    let name = ctx.add_pass(DropLastPass);
+    assert_eq!(name, "DropLastPass");
+    ctx.pass_manager_mut().check_after_each(InvariantCheck::UnitaryEquivalence { max_qubits: 4 });
+    push_pass_manager_circuit(&mut ctx);
+    let err = ctx.compile_circuit().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Invariant violated after pass `DropLastPass`: the unitary of the circuit is changed"
+    );
+    assert_eq!(err.code(), 24);
+
+    let mut ctx = ctx_with(RestrictedBackend(&["H", "CX"]), verified_default_passes);
+    let name = ctx.pass_manager().names()[0].to_string();
+    ctx.pass_manager_mut().check_after(&name, InvariantCheck::NativeGates).unwrap();
+    push_pass_manager_circuit(&mut ctx);
+    let err = ctx.compile_circuit().unwrap_err();
+    assert!(matches!(err, QivmError::InvariantViolation { pass, .. } if pass == name));
+}
+
+#[test]
+fn test_pass_manager_dump() {
+    let path = std::env::temp_dir().join("qivm-test-pass-manager-dump.asm");
+    let mut ctx = ctx_with(NativeBackend, |builder| {
+        builder.add_pass(GateCancellationPass::new());
+    });
+    ctx.pass_manager_mut().dump_after("GateCancellationPass", &path).unwrap();
+    push_pass_manager_circuit(&mut ctx);
+    ctx.compile_circuit().unwrap();
+    let dump = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(dump, "ALLOC 3\nCX [0, 1]\nSWP [1, 2]\nT [2]\n");
+}
+
+#[test]
+fn test_pass_manager_dump_controlled() {
+    let path = std::env::temp_dir().join("qivm-test-pass-manager-dump-controlled.asm");
+    let mut ctx = get_ctx_with_default_passes();
+    ctx.pass_manager_mut().dump_after("MultiplexOptimizationPass", &path).unwrap();
+    ctx.enter();
+    let qreg = ctx.alloc(2).unwrap().borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.control(qubits![qreg[0]], true);
+    ctx.push(X, qubits![qreg[1]]).unwrap();
+    ctx.decontrol(qubits![qreg[0]]);
+    ctx.exit().unwrap();
+    ctx.compile_circuit().unwrap();
+    let dump = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines = dump.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[.. 2], ["ALLOC 2", "H [0]"]);
+    assert!(lines[2].starts_with("# Controlled("));
+}
+
+#[test]
+fn test_optimization_levels() {
+    let expected = [
+        "ALLOC 2\nH [0]\nH [0]\nCX [0, 1]\nRZ(0.25) [0]\nCX [0, 1]\nT [1]\nT [1]\n",
+        "ALLOC 2\nCX [0, 1]\nRZ(0.25) [0]\nCX [0, 1]\nS [1]\n",
+        "ALLOC 2\nRZ(0.25) [0]\nS [1]\n",
+        "ALLOC 2\nRZ(0.25) [0]\nS [1]\n",
+    ];
+    for (level, expected) in expected.iter().enumerate() {
+        let level = OptimizationLevel::from_level(level as u32).unwrap();
+        let backend = RestrictedBackend(&["H", "CX", "RY", "RZ", "S", "T"]);
+        let mut ctx = ctx_with(backend, |builder| builder.optimization_passes(level));
+        let check = InvariantCheck::UnitaryEquivalence { max_qubits: 4 };
+        ctx.pass_manager_mut().check_after_each(check);
+        ctx.enter();
+        let qreg = ctx.alloc(2).unwrap().borrow().clone();
+        ctx.push(H, qubits![qreg[0]]).unwrap();
+        ctx.push(H, qubits![qreg[0]]).unwrap();
+        ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+        ctx.push(RZ { angle: 0.25 }, qubits![qreg[0]]).unwrap();
+        ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+        ctx.push(T, qubits![qreg[1]]).unwrap();
+        ctx.push(T, qubits![qreg[1]]).unwrap();
+        ctx.exit().unwrap();
+        let instructions = ctx.compile_circuit().unwrap();
+        assert_eq!(asm::disassemble(&instructions), *expected, "{:?}", level);
+    }
+    assert_eq!(OptimizationLevel::from_level(4), None);
+
+    let coupling_map = CouplingMap::linear(3);
+    let ctx = ctx_with(CouplingBackend(coupling_map.clone()), |builder| {
+        builder.optimization_passes(OptimizationLevel::O0);
+    });
+    assert_eq!(ctx.pass_manager().names(), [
+        "ConditionalCtrlDecompositionPass", "DemultiplexPass", "ElementaryDecompositionPass",
+        "RoutingPass", "ElementaryDecompositionPass#2",
+    ]);
+    let ctx = ctx_with(CouplingBackend(coupling_map), |builder| {
+        builder.optimization_passes(OptimizationLevel::O3);
+    });
+    let names = ctx.pass_manager().names();
+    assert!(names.contains(&"LayoutSelectionPass"));
+    assert!(names.contains(&"GateCancellationPass#2"));
+
+    // the default passes are those of the level 2
+    let default_ctx = get_ctx_with_default_passes();
+    let mut builder = QuantumProgramContextBuilder::new();
+    builder.optimization_passes(OptimizationLevel::O2);
+    let o2_ctx = builder.build();
+    assert_eq!(default_ctx.pass_manager().names(), o2_ctx.pass_manager().names());
+}
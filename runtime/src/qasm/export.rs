@@ -0,0 +1,296 @@
+use std::f64::consts::PI;
+use std::fmt::Write;
+use num::complex::Complex64;
+use crate::algebra::{close_to_zero, Mat2, ToMat2};
+use crate::bytecode::instruction::{custom_gate_ident, InstrParam, Instruction, PrimitiveOpCode, StandardOpCode};
+use crate::gate::standard::StandardSingleGate;
+use crate::qasm::{qasm_error, QasmResult, QasmVersion};
+
+const RXX_DEF: &str = "gate rxx(theta) a, b { h a; h b; cx a, b; rz(theta) b; cx a, b; h a; h b; }";
+const RYY_DEF: &str = concat!(
+    "gate ryy(theta) a, b { sdg a; sdg b; h a; h b; cx a, b; rz(theta) b; cx a, b; ",
+    "h a; h b; s a; s b; }"
+);
+const RZZ_DEF: &str = "gate rzz(theta) a, b { cx a, b; rz(theta) b; cx a, b; }";
+const SWAP_DEF: &str = "gate swap a, b { cx a, b; cx b, a; cx a, b; }";
+const CSWAP_DEF: &str = "gate cswap a, b, c { cx c, b; ccx a, b, c; cx c, b; }";
+
+/// Export a compiled instruction stream to OpenQASM.
+///
+/// The qubits are held by the register `q`, and the measurement of `q[i]` is stored in `c[i]`,
+/// matching the bits of the QIVM measurement values. Gates without an equivalent in `qelib1.inc`
+/// or `stdgates.inc` are expressed through gate definitions, up to a global phase. Custom gates
+/// are opaque to the runtime and cannot be exported.
+pub fn export(instructions: &[Instruction], version: QasmVersion) -> QasmResult<String> {
+    let mut exporter = QasmExporter {
+        version, num_qubits: None, definitions: vec![], body: String::new()
+    };
+    for instruction in instructions {
+        exporter.export_instruction(instruction)?;
+    }
+    Ok(exporter.finish())
+}
+
+struct QasmExporter {
+    version: QasmVersion,
+    num_qubits: Option<u64>,
+    definitions: Vec<&'static str>,
+    body: String,
+}
+
+/// Call of a QASM gate on the targets of the exported instruction.
+struct GateCall {
+    name: &'static str,
+    params: Vec<f64>,
+}
+
+impl GateCall {
+    fn new(name: &'static str, params: Vec<f64>) -> Self {
+        Self { name, params }
+    }
+}
+
+impl QasmExporter {
+
+    fn export_instruction(&mut self, instruction: &Instruction) -> QasmResult<()> {
+        match instruction {
+            Instruction::Nop => {}
+            Instruction::Primitive { opcode: PrimitiveOpCode::Alloc, params } => {
+                if self.num_qubits.is_some() {
+                    return Err(qasm_error!("Qubits are allocated more than once"));
+                }
+                let size = params.first().ok_or_else(|| qasm_error!("`ALLOC` without size"))?;
+                self.num_qubits = Some(size.as_u64());
+            }
+            Instruction::Primitive { opcode: PrimitiveOpCode::Reset, params } => {
+                for qubit in self.qubits(params)? {
+                    writeln!(self.body, "reset q[{}];", qubit).unwrap();
+                }
+            }
+            Instruction::Primitive { opcode: PrimitiveOpCode::Measure, params } => {
+                for qubit in self.qubits(params)? {
+                    let measure = match self.version {
+                        QasmVersion::V2 => format!("measure q[{0}] -> c[{0}];", qubit),
+                        QasmVersion::V3 => format!("c[{0}] = measure q[{0}];", qubit),
+                    };
+                    writeln!(self.body, "{}", measure).unwrap();
+                }
+            }
+            Instruction::StandardGateOperation { opcode, params, targets } => {
+                let targets = targets.iter()
+                    .map(|&target| self.check_qubit(target as u64))
+                    .collect::<QasmResult<Vec<_>>>()?;
+                let params = params.iter().map(InstrParam::as_f64).collect::<Vec<_>>();
+                let operands = targets.iter()
+                    .map(|target| format!("q[{}]", target))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                for call in self.translate(*opcode, &params)? {
+                    if call.params.is_empty() {
+                        writeln!(self.body, "{} {};", call.name, operands).unwrap();
+                    } else {
+                        let params = call.params.iter()
+                            .map(|&param| real(param))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        writeln!(self.body, "{}({}) {};", call.name, params, operands).unwrap();
+                    }
+                }
+            }
+            Instruction::CustomGateOperation { name, .. } => {
+                return Err(qasm_error!(
+                    "Custom gate `{}` cannot be exported", custom_gate_ident(name)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_qubit(&self, qubit: u64) -> QasmResult<u64> {
+        match self.num_qubits {
+            None => Err(qasm_error!("Qubit {} is used before `ALLOC`", qubit)),
+            Some(size) if qubit >= size => {
+                Err(qasm_error!("Qubit {} is out of range ({} allocated)", qubit, size))
+            }
+            _ => Ok(qubit),
+        }
+    }
+
+    fn qubits(&self, params: &[InstrParam]) -> QasmResult<Vec<u64>> {
+        params.iter().map(|param| self.check_qubit(param.as_u64())).collect()
+    }
+
+    fn define(&mut self, definition: &'static str) {
+        if !self.definitions.contains(&definition) {
+            self.definitions.push(definition);
+        }
+    }
+
+    /// Translate a standard gate into calls of QASM gates.
+    fn translate(&mut self, opcode: StandardOpCode, params: &[f64]) -> QasmResult<Vec<GateCall>> {
+        let ident: &'static str = opcode.into();
+        let expect = |n_params: usize| if params.len() == n_params {
+            Ok(())
+        } else {
+            Err(qasm_error!("`{}` takes {} parameters, got {}", ident, n_params, params.len()))
+        };
+        let v2 = self.version == QasmVersion::V2;
+        let (u3, phase) = if v2 { ("u3", "u1") } else { ("U", "p") };
+
+        use StandardOpCode::*;
+        let fixed = match opcode {
+            I => Some("id"),
+            H => Some("h"),
+            X => Some("x"),
+            Y => Some("y"),
+            Z => Some("z"),
+            S => Some("s"),
+            SD => Some("sdg"),
+            T => Some("t"),
+            TD => Some("tdg"),
+            V if !v2 => Some("sx"),
+            CX => Some("cx"),
+            CY => Some("cy"),
+            CZ => Some("cz"),
+            CH => Some("ch"),
+            CCX => Some("ccx"),
+            SWP => {
+                if v2 { self.define(SWAP_DEF); }
+                Some("swap")
+            }
+            CSWP => {
+                if v2 { self.define(CSWAP_DEF); }
+                Some("cswap")
+            }
+            _ => None,
+        };
+        if let Some(name) = fixed {
+            expect(0)?;
+            return Ok(vec![GateCall::new(name, vec![])]);
+        }
+
+        let calls = match opcode {
+            XPOW | YPOW | ZPOW => {
+                expect(1)?;
+                let name = match opcode { XPOW => "rx", YPOW => "ry", _ => phase };
+                vec![GateCall::new(name, vec![PI * params[0]])]
+            }
+            P | RX | RY | RZ => {
+                expect(1)?;
+                let name = match opcode { P => phase, RX => "rx", RY => "ry", _ => "rz" };
+                vec![GateCall::new(name, params.to_vec())]
+            }
+            CP => {
+                expect(1)?;
+                vec![GateCall::new(if v2 { "cu1" } else { "cp" }, params.to_vec())]
+            }
+            U => {
+                expect(3)?;
+                // The parameters of `U` instructions are ordered as (θ, λ, φ)
+                vec![GateCall::new(u3, vec![params[0], params[2], params[1]])]
+            }
+            V | VD | RN => {
+                let gate = if opcode == RN {
+                    expect(4)?;
+                    StandardSingleGate::RN {
+                        nx: params[0], ny: params[1], nz: params[2], angle: params[3]
+                    }
+                } else {
+                    expect(0)?;
+                    if opcode == V { StandardSingleGate::V } else { StandardSingleGate::VD }
+                };
+                let (theta, phi, lambda) = u3_params(&gate.to_mat2());
+                vec![GateCall::new(u3, vec![theta, phi, lambda])]
+            }
+            _ => {
+                let (tx, ty, tz) = match opcode {
+                    CAN => {
+                        expect(3)?;
+                        (params[0], params[1], params[2])
+                    }
+                    _ => {
+                        expect(0)?;
+                        canonical_params(opcode).unwrap()
+                    }
+                };
+                self.canonical(tx, ty, tz)
+            }
+        };
+        Ok(calls)
+    }
+
+    /// CAN(tx, ty, tz) = exp(-iπ/2 (tx XX + ty YY + tz ZZ)) = RXX(π tx) RYY(π ty) RZZ(π tz)
+    fn canonical(&mut self, tx: f64, ty: f64, tz: f64) -> Vec<GateCall> {
+        let mut calls = vec![];
+        let rotations = [(tx, "rxx", RXX_DEF), (ty, "ryy", RYY_DEF), (tz, "rzz", RZZ_DEF)];
+        for (t, name, definition) in rotations {
+            if t != 0.0 {
+                self.define(definition);
+                calls.push(GateCall::new(name, vec![PI * t]));
+            }
+        }
+        calls
+    }
+
+    fn finish(self) -> String {
+        let mut qasm = String::new();
+        match self.version {
+            QasmVersion::V2 => qasm.push_str("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n"),
+            QasmVersion::V3 => qasm.push_str("OPENQASM 3.0;\ninclude \"stdgates.inc\";\n"),
+        }
+        for definition in &self.definitions {
+            writeln!(qasm, "{}", definition).unwrap();
+        }
+        let num_qubits = self.num_qubits.unwrap_or(0);
+        let registers = match self.version {
+            QasmVersion::V2 => format!("qreg q[{0}];\ncreg c[{0}];", num_qubits),
+            QasmVersion::V3 => format!("qubit[{0}] q;\nbit[{0}] c;", num_qubits),
+        };
+        writeln!(qasm, "{}", registers).unwrap();
+        qasm.push_str(&self.body);
+        qasm
+    }
+}
+
+/// Parameters of the canonical gate equal to a standard double gate, up to a global phase.
+pub(crate) fn canonical_params(opcode: StandardOpCode) -> Option<(f64, f64, f64)> {
+    use StandardOpCode::*;
+    Some(match opcode {
+        SWP => (-0.5, -0.5, -0.5),
+        SSWP => (-0.25, -0.25, -0.25),
+        SSWPD => (0.25, 0.25, 0.25),
+        ISWP => (-0.5, -0.5, 0.0),
+        ISWPD => (0.5, 0.5, 0.0),
+        SISWP => (-0.25, -0.25, 0.0),
+        SISWPD => (0.25, 0.25, 0.0),
+        _ => return None,
+    })
+}
+
+/// Parameters (θ, φ, λ) of the `U` gate equal to the matrix, up to a global phase.
+pub(crate) fn u3_params(mat: &Mat2) -> (f64, f64, f64) {
+    let theta = 2.0 * f64::atan2(mat[(1, 0)].norm(), mat[(0, 0)].norm());
+    // Global phase α, such that U_00 = e^(iα) cos(θ/2) and U_10 = e^(i(α + φ)) sin(θ/2)
+    let (alpha, phi) = if close_to_zero(mat[(0, 0)].norm()) {
+        (mat[(1, 0)].arg(), 0.0)
+    } else if close_to_zero(mat[(1, 0)].norm()) {
+        let alpha = mat[(0, 0)].arg();
+        return (theta, mat[(1, 1)].arg() - alpha, 0.0);
+    } else {
+        let alpha = mat[(0, 0)].arg();
+        (alpha, mat[(1, 0)].arg() - alpha)
+    };
+    // U_01 = -e^(i(α + λ)) sin(θ/2)
+    let lambda = (-mat[(0, 1)] * Complex64::from_polar(1.0, -alpha)).arg();
+    (theta, phi, lambda)
+}
+
+/// Format a float as an OpenQASM real literal, which always has a decimal point.
+fn real(value: f64) -> String {
+    let literal = format!("{:?}", value);
+    if literal.contains('.') || !literal.contains('e') {
+        literal
+    } else {
+        literal.replacen('e', ".0e", 1)
+    }
+}
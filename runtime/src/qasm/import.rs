@@ -0,0 +1,854 @@
+use std::collections::HashMap;
+use std::f64::consts::{E, PI, TAU};
+use std::fmt::{Display, Formatter};
+use num::complex::Complex64;
+use crate::algebra::{controlled, embed, DMat, ToMat2, ToMat4, ToMat8};
+use crate::gate::canonical::CanonicalGate;
+use crate::gate::custom::CustomGate;
+use crate::gate::Dagger;
+use crate::gate::elementary::ElementaryGate;
+use crate::gate::standard::{StandardDoubleGate, StandardGate, StandardSingleGate, StandardTripleGate};
+use crate::program::QuantumProgramContext;
+use crate::qasm::{qasm_error, QasmError, QasmResult, QasmVersion};
+use crate::qubit::QubitAddr;
+use crate::qubit::qubit_accessor::QubitAccessor;
+
+/// Name, number of parameters and number of qubits of the gates of `qelib1.inc` and
+/// `stdgates.inc`, which are available whether they are included or not.
+const STANDARD_LIBRARY: &[(&str, usize, usize)] = &[
+    ("U", 3, 1), ("u", 3, 1), ("u3", 3, 1), ("u2", 2, 1), ("u1", 1, 1), ("p", 1, 1),
+    ("phase", 1, 1), ("id", 0, 1), ("x", 0, 1), ("y", 0, 1), ("z", 0, 1), ("h", 0, 1),
+    ("s", 0, 1), ("sdg", 0, 1), ("t", 0, 1), ("tdg", 0, 1), ("sx", 0, 1), ("sxdg", 0, 1),
+    ("rx", 1, 1), ("ry", 1, 1), ("rz", 1, 1),
+    ("CX", 0, 2), ("cx", 0, 2), ("cy", 0, 2), ("cz", 0, 2), ("ch", 0, 2), ("csx", 0, 2),
+    ("cp", 1, 2), ("cu1", 1, 2), ("cphase", 1, 2), ("crx", 1, 2), ("cry", 1, 2), ("crz", 1, 2),
+    ("cu3", 3, 2), ("cu", 4, 2), ("swap", 0, 2), ("rxx", 1, 2), ("ryy", 1, 2), ("rzz", 1, 2),
+    ("ccx", 0, 3), ("cswap", 0, 3),
+];
+
+const STANDARD_INCLUDES: [&str; 2] = ["qelib1.inc", "stdgates.inc"];
+
+const FUNCTIONS: [&str; 6] = ["sin", "cos", "tan", "exp", "ln", "sqrt"];
+
+const SYMBOLS: [&str; 16] = ["->", "(", ")", "[", "]", "{", "}", ",", ";", "@", "+", "-", "*", "/", "^", "="];
+
+/// Import an OpenQASM 2.0 program, or a program of the gate and qubit subset of OpenQASM 3.0,
+/// into the program context.
+///
+/// The program runs in a new quantum stack frame, where every quantum register is allocated on
+/// declaration. Gates of the standard libraries map onto the standard gates, controlled by
+/// `control` when no standard controlled gate exists, and `rxx`, `ryy` and `rzz` map onto
+/// canonical gates. User gate definitions become custom gates whose matrix is computed from
+/// their body, while definitions of standard library gates are ignored. The measured qubits are
+/// measured together at the end of the program, in the order of the classical bits they are
+/// measured into, registers taken in declaration order. Qubits measured without a classical
+/// target follow, in the order of their first measurement.
+pub fn import(source: &str, ctx: &mut QuantumProgramContext) -> QasmResult<()> {
+    let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+    ctx.enter();
+    let mut importer = QasmImporter {
+        ctx,
+        qregs: HashMap::new(),
+        cregs: HashMap::new(),
+        definitions: HashMap::new(),
+        measured: vec![],
+    };
+    let result = importer.import(&mut parser);
+    importer.ctx.exit()?;
+    result
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Symbol(&'static str),
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Ident(text) | TokenKind::Number(text) => write!(formatter, "{}", text),
+            TokenKind::Str(text) => write!(formatter, "\"{}\"", text),
+            TokenKind::Symbol(symbol) => write!(formatter, "{}", symbol),
+        }
+    }
+}
+
+struct Token {
+    kind: TokenKind,
+    line: usize,
+}
+
+fn tokenize(source: &str) -> QasmResult<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            line += (c == '\n') as usize;
+            rest = &rest[c.len_utf8() ..];
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = &comment[comment.find('\n').unwrap_or(comment.len()) ..];
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("/*") {
+            let end = comment.find("*/").ok_or_else(|| qasm_error!("Unclosed comment").at_line(line))?;
+            line += comment[.. end].matches('\n').count();
+            rest = &comment[end + 2 ..];
+            continue;
+        }
+        let (kind, len) = if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+            (TokenKind::Ident(rest[.. len].to_string()), len)
+        } else if c.is_ascii_digit() || c == '.' {
+            let len = number_len(rest.as_bytes());
+            (TokenKind::Number(rest[.. len].to_string()), len)
+        } else if c == '"' {
+            let end = rest[1 ..].find(|c| c == '"' || c == '\n')
+                .filter(|&end| rest[1 + end ..].starts_with('"'))
+                .ok_or_else(|| qasm_error!("Unclosed string").at_line(line))?;
+            (TokenKind::Str(rest[1 .. 1 + end].to_string()), end + 2)
+        } else if let Some(&symbol) = SYMBOLS.iter().find(|&&symbol| rest.starts_with(symbol)) {
+            (TokenKind::Symbol(symbol), symbol.len())
+        } else {
+            return Err(qasm_error!("Unexpected character `{}`", c).at_line(line));
+        };
+        tokens.push(Token { kind, line });
+        rest = &rest[len ..];
+    }
+    Ok(tokens)
+}
+
+/// Length of the number literal at the start of the bytes, e.g. `12`, `.5` or `1.5e-3`.
+fn number_len(bytes: &[u8]) -> usize {
+    let skip_digits = |from: usize| {
+        from + bytes[from ..].iter().take_while(|byte| byte.is_ascii_digit()).count()
+    };
+    let mut len = skip_digits(0);
+    if bytes.get(len) == Some(&b'.') {
+        len = skip_digits(len + 1);
+    }
+    if matches!(bytes.get(len), Some(b'e' | b'E')) {
+        let sign = matches!(bytes.get(len + 1), Some(b'+' | b'-')) as usize;
+        if bytes.get(len + 1 + sign).map_or(false, u8::is_ascii_digit) {
+            len = skip_digits(len + 1 + sign);
+        }
+    }
+    len
+}
+
+/// Parameter expression, where parameters of gate definitions are bound at each call.
+enum Expr {
+    Number(f64),
+    Param(String),
+    Neg(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Function(String, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, env: &HashMap<&str, f64>) -> f64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Param(name) => env[name.as_str()],
+            Expr::Neg(expr) => -expr.eval(env),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(env), rhs.eval(env));
+                match *op {
+                    "+" => lhs + rhs,
+                    "-" => lhs - rhs,
+                    "*" => lhs * rhs,
+                    "/" => lhs / rhs,
+                    _ => lhs.powf(rhs),
+                }
+            }
+            Expr::Function(name, arg) => {
+                let arg = arg.eval(env);
+                match name.as_str() {
+                    "sin" => arg.sin(),
+                    "cos" => arg.cos(),
+                    "tan" => arg.tan(),
+                    "exp" => arg.exp(),
+                    "ln" => arg.ln(),
+                    _ => arg.sqrt(),
+                }
+            }
+        }
+    }
+}
+
+enum Modifier {
+    Inv,
+    Ctrl { count: usize, condition: bool },
+}
+
+struct Operand {
+    name: String,
+    index: Option<usize>,
+}
+
+struct GateCall {
+    line: usize,
+    modifiers: Vec<Modifier>,
+    name: String,
+    params: Vec<Expr>,
+    operands: Vec<Operand>,
+}
+
+impl GateCall {
+    /// Conditions of the control qubits added by the modifiers, which are the first operands.
+    fn ctrl_conditions(&self) -> Vec<bool> {
+        self.modifiers.iter()
+            .flat_map(|modifier| match modifier {
+                Modifier::Ctrl { count, condition } => vec![*condition; *count],
+                Modifier::Inv => vec![],
+            })
+            .collect()
+    }
+
+    fn is_inverse(&self) -> bool {
+        self.modifiers.iter().filter(|modifier| matches!(modifier, Modifier::Inv)).count() % 2 == 1
+    }
+}
+
+struct GateDef {
+    params: Vec<String>,
+    qubits: Vec<String>,
+    body: Vec<GateCall>,
+}
+
+/// Gate pushed for a call, applied to the last qubits of the call after its `ctrls` first
+/// qubits, with the global phase `phase` relative to the controls.
+#[derive(Clone)]
+struct MappedGate {
+    gate: ElementaryGate,
+    ctrls: usize,
+    phase: f64,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    /// Line of the next token, or of the last one at the end of input.
+    fn line(&self) -> usize {
+        self.tokens.get(self.position).or_else(|| self.tokens.last()).map_or(1, |token| token.line)
+    }
+
+    fn unexpected(&self, expected: &str) -> QasmError {
+        match self.peek() {
+            Some(kind) => qasm_error!("Expected {}, got `{}`", expected, kind),
+            None => qasm_error!("Expected {}, got end of input", expected),
+        }.at_line(self.line())
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(TokenKind::Symbol(next)) if *next == symbol)
+    }
+
+    fn is_ident(&self, ident: &str) -> bool {
+        matches!(self.peek(), Some(TokenKind::Ident(next)) if next == ident)
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let is_symbol = self.is_symbol(symbol);
+        self.position += is_symbol as usize;
+        is_symbol
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> QasmResult<()> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", symbol)))
+        }
+    }
+
+    fn expect_ident(&mut self) -> QasmResult<String> {
+        match self.peek() {
+            Some(TokenKind::Ident(ident)) => {
+                let ident = ident.clone();
+                self.position += 1;
+                Ok(ident)
+            }
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    fn expect_integer(&mut self) -> QasmResult<usize> {
+        match self.peek().and_then(|kind| match kind {
+            TokenKind::Number(number) => number.parse().ok(),
+            _ => None,
+        }) {
+            Some(integer) => {
+                self.position += 1;
+                Ok(integer)
+            }
+            None => Err(self.unexpected("integer")),
+        }
+    }
+
+    fn expect_index(&mut self) -> QasmResult<usize> {
+        self.expect_symbol("[")?;
+        let index = self.expect_integer()?;
+        self.expect_symbol("]")?;
+        Ok(index)
+    }
+
+    /// Skip the tokens up to the end of the statement.
+    fn skip_statement(&mut self) -> QasmResult<()> {
+        while !self.eat_symbol(";") {
+            if self.peek().is_none() {
+                return Err(self.unexpected("`;`"));
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    fn operand(&mut self) -> QasmResult<Operand> {
+        let name = self.expect_ident()?;
+        let index = if self.is_symbol("[") { Some(self.expect_index()?) } else { None };
+        Ok(Operand { name, index })
+    }
+
+    /// Parse a gate call with its modifiers, where the parameters may refer to `params`.
+    fn call(&mut self, params: &[String]) -> QasmResult<GateCall> {
+        let line = self.line();
+        let mut modifiers = vec![];
+        let name = loop {
+            let ident = self.expect_ident()?;
+            match ident.as_str() {
+                "inv" => modifiers.push(Modifier::Inv),
+                "ctrl" | "negctrl" => {
+                    let count = if self.eat_symbol("(") {
+                        let count = self.expect_integer()?;
+                        self.expect_symbol(")")?;
+                        count
+                    } else {
+                        1
+                    };
+                    modifiers.push(Modifier::Ctrl { count, condition: ident == "ctrl" });
+                }
+                "pow" => return Err(qasm_error!("`pow` modifier is not supported").at_line(line)),
+                _ => break ident,
+            }
+            self.expect_symbol("@")?;
+        };
+        let mut exprs = vec![];
+        if self.eat_symbol("(") && !self.eat_symbol(")") {
+            loop {
+                exprs.push(self.expr(params)?);
+                if self.eat_symbol(")") {
+                    break;
+                }
+                self.expect_symbol(",")?;
+            }
+        }
+        let mut operands = vec![self.operand()?];
+        while !self.eat_symbol(";") {
+            if !self.eat_symbol(",") {
+                return Err(self.unexpected("`,` or `;`"));
+            }
+            operands.push(self.operand()?);
+        }
+        Ok(GateCall { line, modifiers, name, params: exprs, operands })
+    }
+
+    fn expr(&mut self, params: &[String]) -> QasmResult<Expr> {
+        let mut lhs = self.term(params)?;
+        while let Some(op) = ["+", "-"].into_iter().find(|op| self.is_symbol(op)) {
+            self.position += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term(params)?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self, params: &[String]) -> QasmResult<Expr> {
+        let mut lhs = self.factor(params)?;
+        while let Some(op) = ["*", "/"].into_iter().find(|op| self.is_symbol(op)) {
+            self.position += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.factor(params)?));
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self, params: &[String]) -> QasmResult<Expr> {
+        if self.eat_symbol("-") {
+            return Ok(Expr::Neg(Box::new(self.factor(params)?)));
+        }
+        self.eat_symbol("+");
+        let base = self.primary(params)?;
+        if self.eat_symbol("^") {
+            Ok(Expr::Binary("^", Box::new(base), Box::new(self.factor(params)?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self, params: &[String]) -> QasmResult<Expr> {
+        let line = self.line();
+        let expr = match self.peek() {
+            Some(TokenKind::Number(number)) => {
+                let value = number.parse()
+                    .map_err(|_| qasm_error!("Invalid number `{}`", number).at_line(line))?;
+                Expr::Number(value)
+            }
+            Some(TokenKind::Ident(ident)) => match ident.as_str() {
+                ident if params.iter().any(|param| param == ident) => Expr::Param(ident.to_string()),
+                "pi" | "π" => Expr::Number(PI),
+                "tau" | "τ" => Expr::Number(TAU),
+                "euler" | "ℇ" => Expr::Number(E),
+                ident if FUNCTIONS.contains(&ident) => {
+                    let name = ident.to_string();
+                    self.position += 1;
+                    self.expect_symbol("(")?;
+                    let arg = self.expr(params)?;
+                    self.expect_symbol(")")?;
+                    return Ok(Expr::Function(name, Box::new(arg)));
+                }
+                ident => return Err(qasm_error!("Unknown parameter `{}`", ident).at_line(line)),
+            },
+            Some(TokenKind::Symbol("(")) => {
+                self.position += 1;
+                let expr = self.expr(params)?;
+                self.expect_symbol(")")?;
+                return Ok(expr);
+            }
+            _ => return Err(self.unexpected("expression")),
+        };
+        self.position += 1;
+        Ok(expr)
+    }
+}
+
+struct QuantumRegister {
+    qubits: Vec<QubitAddr>,
+    is_array: bool,
+}
+
+struct ClassicalRegister {
+    /// Index of the first bit of the register among the bits of every register.
+    offset: usize,
+    size: usize,
+}
+
+struct QasmImporter<'a> {
+    ctx: &'a mut QuantumProgramContext,
+    qregs: HashMap<String, QuantumRegister>,
+    cregs: HashMap<String, ClassicalRegister>,
+    definitions: HashMap<String, GateDef>,
+    /// Measured qubits, with the index of the classical bit they are measured into.
+    measured: Vec<(QubitAddr, Option<usize>)>,
+}
+
+impl<'a> QasmImporter<'a> {
+
+    fn import(&mut self, parser: &mut Parser) -> QasmResult<()> {
+        if parser.is_ident("OPENQASM") {
+            let line = parser.line();
+            parser.position += 1;
+            let version = match parser.peek() {
+                Some(TokenKind::Number(version)) => version.clone(),
+                _ => return Err(parser.unexpected("version")),
+            };
+            let major = version.split('.').next().and_then(|major| major.parse().ok());
+            if major.and_then(QasmVersion::from_major).is_none() {
+                return Err(qasm_error!("Unsupported OpenQASM version `{}`", version).at_line(line));
+            }
+            parser.position += 1;
+            parser.expect_symbol(";")?;
+        }
+        while parser.peek().is_some() {
+            let line = parser.line();
+            self.statement(parser).map_err(|error| error.at_line(line))?;
+        }
+        if !self.measured.is_empty() {
+            self.measured.sort_by_key(|&(_, bit)| bit.unwrap_or(usize::MAX));
+            let qubits: Vec<QubitAddr> = self.measured.iter().map(|&(qubit, _)| qubit).collect();
+            self.ctx.measure(QubitAccessor::from(&qubits));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, parser: &mut Parser) -> QasmResult<()> {
+        let keyword = parser.expect_ident()?;
+        match keyword.as_str() {
+            "OPENQASM" => Err(qasm_error!("`OPENQASM` must be the first statement")),
+            "include" => {
+                match parser.peek() {
+                    Some(TokenKind::Str(file)) if STANDARD_INCLUDES.contains(&file.as_str()) => {}
+                    Some(TokenKind::Str(file)) => return Err(qasm_error!("Cannot include `{}`", file)),
+                    _ => return Err(parser.unexpected("file name")),
+                }
+                parser.position += 1;
+                parser.expect_symbol(";")
+            }
+            "qreg" | "creg" => {
+                let name = parser.expect_ident()?;
+                let size = parser.expect_index()?;
+                parser.expect_symbol(";")?;
+                self.declare(name, Some(size), keyword == "qreg")
+            }
+            "qubit" | "bit" => {
+                let size = if parser.is_symbol("[") { Some(parser.expect_index()?) } else { None };
+                let name = parser.expect_ident()?;
+                parser.expect_symbol(";")?;
+                self.declare(name, size, keyword == "qubit")
+            }
+            "gate" => self.definition(parser),
+            "measure" => {
+                let qubits = parser.operand()?;
+                let bits = if parser.eat_symbol("->") { Some(parser.operand()?) } else { None };
+                parser.expect_symbol(";")?;
+                self.measure(&qubits, bits.as_ref())
+            }
+            "barrier" => parser.skip_statement(),
+            "reset" | "opaque" | "if" => Err(qasm_error!("`{}` is not supported", keyword)),
+            name if self.cregs.contains_key(name) => {
+                parser.position -= 1;
+                let bits = parser.operand()?;
+                parser.expect_symbol("=")?;
+                if !parser.is_ident("measure") {
+                    return Err(parser.unexpected("`measure`"));
+                }
+                parser.position += 1;
+                let qubits = parser.operand()?;
+                parser.expect_symbol(";")?;
+                self.measure(&qubits, Some(&bits))
+            }
+            _ => {
+                parser.position -= 1;
+                let call = parser.call(&[])?;
+                self.apply(&call)
+            }
+        }
+    }
+
+    /// Declare a register, or a single qubit or bit when the size is `None`.
+    fn declare(&mut self, name: String, size: Option<usize>, is_quantum: bool) -> QasmResult<()> {
+        if self.qregs.contains_key(&name) || self.cregs.contains_key(&name) {
+            return Err(qasm_error!("`{}` is already declared", name));
+        } else if size == Some(0) {
+            return Err(qasm_error!("Register `{}` is empty", name));
+        }
+        if is_quantum {
+            let qubits = self.ctx.alloc(size.unwrap_or(1))?.borrow().to_vec();
+            self.qregs.insert(name, QuantumRegister { qubits, is_array: size.is_some() });
+        } else {
+            let offset = self.cregs.values().map(|register| register.size).sum();
+            self.cregs.insert(name, ClassicalRegister { offset, size: size.unwrap_or(1) });
+        }
+        Ok(())
+    }
+
+    fn definition(&mut self, parser: &mut Parser) -> QasmResult<()> {
+        let name = parser.expect_ident()?;
+        let mut params = vec![];
+        if parser.eat_symbol("(") && !parser.eat_symbol(")") {
+            loop {
+                params.push(parser.expect_ident()?);
+                if parser.eat_symbol(")") {
+                    break;
+                }
+                parser.expect_symbol(",")?;
+            }
+        }
+        let mut qubits = vec![parser.expect_ident()?];
+        while !parser.eat_symbol("{") {
+            parser.expect_symbol(",")?;
+            qubits.push(parser.expect_ident()?);
+        }
+        let mut body = vec![];
+        while !parser.eat_symbol("}") {
+            if parser.is_ident("barrier") {
+                parser.skip_statement()?;
+                continue;
+            }
+            let call = parser.call(&params)?;
+            self.check_call(&call)
+                .and_then(|_| check_distinct(&call, call.operands.iter().map(|operand| {
+                    if operand.index.is_some() {
+                        Err(qasm_error!("Qubit arguments of `{}` cannot be indexed", name))
+                    } else {
+                        qubits.iter().position(|qubit| *qubit == operand.name)
+                            .ok_or_else(|| qasm_error!("Unknown qubit argument `{}`", operand.name))
+                    }
+                })))
+                .map_err(|error| error.at_line(call.line))?;
+            body.push(call);
+        }
+
+        if self.definitions.contains_key(&name) {
+            return Err(qasm_error!("Gate `{}` is already defined", name));
+        }
+        if (1 .. qubits.len()).any(|i| qubits[.. i].contains(&qubits[i])) {
+            return Err(qasm_error!("Qubit arguments of `{}` are not distinct", name));
+        }
+        if !STANDARD_LIBRARY.iter().any(|(standard, ..)| *standard == name) {
+            self.definitions.insert(name, GateDef { params, qubits, body });
+        }
+        Ok(())
+    }
+
+    fn signature(&self, name: &str) -> Option<(usize, usize)> {
+        match self.definitions.get(name) {
+            Some(definition) => Some((definition.params.len(), definition.qubits.len())),
+            None => STANDARD_LIBRARY.iter()
+                .find(|(standard, ..)| *standard == name)
+                .map(|&(_, n_params, n_qubits)| (n_params, n_qubits)),
+        }
+    }
+
+    /// Check the numbers of parameters and operands of a call.
+    fn check_call(&self, call: &GateCall) -> QasmResult<()> {
+        let (n_params, n_qubits) = self.signature(&call.name)
+            .ok_or_else(|| qasm_error!("Unknown gate `{}`", call.name))?;
+        let n_qubits = n_qubits + call.ctrl_conditions().len();
+        if call.params.len() != n_params {
+            Err(qasm_error!("`{}` takes {} parameters, got {}", call.name, n_params, call.params.len()))
+        } else if call.operands.len() != n_qubits {
+            Err(qasm_error!("`{}` takes {} qubits, got {}", call.name, n_qubits, call.operands.len()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the qubits of an operand, and whether it is a whole register to broadcast over.
+    fn qubits(&self, operand: &Operand) -> QasmResult<(Vec<QubitAddr>, bool)> {
+        let register = self.qregs.get(&operand.name)
+            .ok_or_else(|| qasm_error!("Unknown qubit `{}`", operand.name))?;
+        let qubits = &register.qubits;
+        match operand.index {
+            None => Ok((qubits.clone(), register.is_array)),
+            Some(_) if !register.is_array => Err(qasm_error!("Qubit `{}` cannot be indexed", operand.name)),
+            Some(index) => qubits.get(index).map(|&qubit| (vec![qubit], false)).ok_or_else(|| qasm_error!(
+                "Index {} is out of range of `{}` ({} qubits)", index, operand.name, qubits.len()
+            )),
+        }
+    }
+
+    fn measure(&mut self, qubits: &Operand, bits: Option<&Operand>) -> QasmResult<()> {
+        let (qubits, _) = self.qubits(qubits)?;
+        let first_bit = match bits {
+            Some(bits) => {
+                let register = self.cregs.get(&bits.name)
+                    .ok_or_else(|| qasm_error!("Unknown classical register `{}`", bits.name))?;
+                let (first_bit, n_bits) = match bits.index {
+                    None => (register.offset, register.size),
+                    Some(index) if index < register.size => (register.offset + index, 1),
+                    Some(index) => return Err(qasm_error!(
+                        "Index {} is out of range of `{}` ({} bits)",
+                        index, bits.name, register.size
+                    )),
+                };
+                if n_bits != qubits.len() {
+                    return Err(qasm_error!("Cannot measure {} qubits into {} bits", qubits.len(), n_bits));
+                }
+                Some(first_bit)
+            }
+            None => None,
+        };
+        for (i, qubit) in qubits.into_iter().enumerate() {
+            let bit = first_bit.map(|first_bit| first_bit + i);
+            if bit.is_some() && self.measured.iter().any(|&(other, other_bit)| {
+                other != qubit && other_bit == bit
+            }) {
+                return Err(qasm_error!("Several qubits are measured into the same bit"));
+            }
+            if !self.measured.iter().any(|&(other, _)| other == qubit) {
+                self.measured.push((qubit, bit));
+            }
+        }
+        Ok(())
+    }
+
+    /// Push a top-level call, broadcast over the unindexed registers.
+    fn apply(&mut self, call: &GateCall) -> QasmResult<()> {
+        self.check_call(call)?;
+        let operands = call.operands.iter()
+            .map(|operand| self.qubits(operand))
+            .collect::<QasmResult<Vec<_>>>()?;
+        let sizes = operands.iter()
+            .filter_map(|(qubits, is_register)| is_register.then(|| qubits.len()))
+            .collect::<Vec<_>>();
+        let count = sizes.first().copied().unwrap_or(1);
+        if sizes.iter().any(|&size| size != count) {
+            return Err(qasm_error!("Cannot broadcast `{}` over registers of different sizes", call.name));
+        }
+        let instances = (0 .. count)
+            .map(|i| check_distinct(call, operands.iter().map(|(qubits, is_register)| {
+                Ok(if *is_register { qubits[i] } else { qubits[0] })
+            })))
+            .collect::<QasmResult<Vec<_>>>()?;
+
+        let params = call.params.iter().map(|param| param.eval(&HashMap::new())).collect::<Vec<_>>();
+        let mut mapped = match self.definitions.get(&call.name) {
+            Some(definition) => MappedGate {
+                gate: custom_gate(&call.name, self.definition_matrix(definition, &params), &params),
+                ctrls: 0,
+                phase: 0.0,
+            },
+            None => standard_gate(&call.name, &params).unwrap(),
+        };
+        if call.is_inverse() {
+            mapped.gate = mapped.gate.dagger();
+            mapped.phase = -mapped.phase;
+        }
+        let conditions = call.ctrl_conditions();
+        for qubits in instances {
+            self.push(&conditions, mapped.clone(), &qubits)?;
+        }
+        Ok(())
+    }
+
+    /// Push a gate instance, releasing its controls even if the context rejects the gate.
+    fn push(&mut self, conditions: &[bool], mapped: MappedGate, qubits: &[QubitAddr]) -> QasmResult<()> {
+        let (ctrls, qubits) = qubits.split_at(conditions.len());
+        let (gate_ctrls, targets) = qubits.split_at(mapped.ctrls);
+        for (&qubit, &condition) in ctrls.iter().zip(conditions) {
+            self.ctx.control(QubitAccessor::single(qubit), condition);
+        }
+        if !gate_ctrls.is_empty() {
+            self.ctx.control(QubitAccessor::from(gate_ctrls), true);
+        }
+        let mut result = self.ctx.push(mapped.gate, QubitAccessor::from(targets));
+        if !gate_ctrls.is_empty() {
+            self.ctx.decontrol(QubitAccessor::from(gate_ctrls));
+        }
+        if let (Some((&last, others)), true) = (gate_ctrls.split_last(), mapped.phase != 0.0) {
+            // The phase of the controlled gate is a phase shift of its last control
+            if !others.is_empty() {
+                self.ctx.control(QubitAccessor::from(others), true);
+            }
+            result = result.and_then(|_| {
+                self.ctx.push(StandardSingleGate::P { angle: mapped.phase }, QubitAccessor::single(last))
+            });
+            if !others.is_empty() {
+                self.ctx.decontrol(QubitAccessor::from(others));
+            }
+        }
+        if !ctrls.is_empty() {
+            self.ctx.decontrol(QubitAccessor::from(ctrls));
+        }
+        Ok(result?)
+    }
+
+    /// Matrix of a checked call, where the first operand is the most significant qubit.
+    fn matrix(&self, name: &str, params: &[f64], modifiers: &[Modifier]) -> DMat {
+        let mut mat = match self.definitions.get(name) {
+            Some(definition) => self.definition_matrix(definition, params),
+            None => {
+                let mapped = standard_gate(name, params).unwrap();
+                let phase = Complex64::from_polar(1.0, mapped.phase);
+                controlled(&(standard_matrix(&mapped.gate) * phase), &vec![true; mapped.ctrls])
+            }
+        };
+        for modifier in modifiers.iter().rev() {
+            mat = match modifier {
+                Modifier::Inv => mat.adjoint(),
+                Modifier::Ctrl { count, condition } => controlled(&mat, &vec![*condition; *count]),
+            };
+        }
+        mat
+    }
+
+    fn definition_matrix(&self, definition: &GateDef, params: &[f64]) -> DMat {
+        let env = definition.params.iter()
+            .map(String::as_str)
+            .zip(params.iter().copied())
+            .collect::<HashMap<_, _>>();
+        let size = definition.qubits.len();
+        definition.body.iter().fold(DMat::identity(1 << size, 1 << size), |mat, call| {
+            let params = call.params.iter().map(|param| param.eval(&env)).collect::<Vec<_>>();
+            let targets = call.operands.iter()
+                .map(|operand| definition.qubits.iter().position(|qubit| *qubit == operand.name).unwrap())
+                .collect::<Vec<_>>();
+            embed(&self.matrix(&call.name, &params, &call.modifiers), &targets, size) * mat
+        })
+    }
+}
+
+/// Check that the resolved operands of a call are distinct.
+fn check_distinct<T: PartialEq>(
+    call: &GateCall, operands: impl Iterator<Item = QasmResult<T>>
+) -> QasmResult<Vec<T>> {
+    let operands = operands.collect::<QasmResult<Vec<_>>>()?;
+    if (1 .. operands.len()).any(|i| operands[.. i].contains(&operands[i])) {
+        return Err(qasm_error!("Qubit operands of `{}` are not distinct", call.name));
+    }
+    Ok(operands)
+}
+
+/// Map a gate of the standard libraries, with the expected number of parameters.
+fn standard_gate(name: &str, params: &[f64]) -> Option<MappedGate> {
+    use StandardSingleGate::*;
+    use StandardDoubleGate::*;
+    use StandardTripleGate::*;
+    let (ctrls, gate): (usize, ElementaryGate) = match name {
+        "U" | "u" | "u3" => (0, U { theta: params[0], phi: params[1], lambda: params[2] }.into()),
+        "u2" => (0, U { theta: PI / 2.0, phi: params[0], lambda: params[1] }.into()),
+        "u1" | "p" | "phase" => (0, P { angle: params[0] }.into()),
+        "id" => (0, I.into()),
+        "x" => (0, X.into()),
+        "y" => (0, Y.into()),
+        "z" => (0, Z.into()),
+        "h" => (0, H.into()),
+        "s" => (0, S.into()),
+        "sdg" => (0, SD.into()),
+        "t" => (0, T.into()),
+        "tdg" => (0, TD.into()),
+        "sx" => (0, V.into()),
+        "sxdg" => (0, VD.into()),
+        "rx" => (0, RX { angle: params[0] }.into()),
+        "ry" => (0, RY { angle: params[0] }.into()),
+        "rz" => (0, RZ { angle: params[0] }.into()),
+        "CX" | "cx" => (0, CX.into()),
+        "cy" => (1, Y.into()),
+        "cz" => (0, CZ.into()),
+        "ch" => (1, H.into()),
+        "csx" => (1, V.into()),
+        "cp" | "cu1" | "cphase" => (0, CP { angle: params[0] }.into()),
+        "crx" => (1, RX { angle: params[0] }.into()),
+        "cry" => (1, RY { angle: params[0] }.into()),
+        "crz" => (1, RZ { angle: params[0] }.into()),
+        "cu3" | "cu" => (1, U { theta: params[0], phi: params[1], lambda: params[2] }.into()),
+        "swap" => (0, SWP.into()),
+        // RXX(θ) = exp(-iθ/2 X⊗X) = CAN(θ/π, 0, 0)
+        "rxx" => (0, CanonicalGate::new(params[0] / PI, 0.0, 0.0).into()),
+        "ryy" => (0, CanonicalGate::new(0.0, params[0] / PI, 0.0).into()),
+        "rzz" => (0, CanonicalGate::new(0.0, 0.0, params[0] / PI).into()),
+        "ccx" => (0, CCX.into()),
+        "cswap" => (1, SWP.into()),
+        _ => return None,
+    };
+    let phase = if name == "cu" { params[3] } else { 0.0 };
+    Some(MappedGate { gate, ctrls, phase })
+}
+
+fn standard_matrix(gate: &ElementaryGate) -> DMat {
+    let (dim, mat) = match gate {
+        ElementaryGate::Standard(StandardGate::Single(gate)) => (2, gate.to_mat2().as_slice().to_vec()),
+        ElementaryGate::Standard(StandardGate::Double(gate)) => (4, gate.to_mat4().as_slice().to_vec()),
+        ElementaryGate::Standard(StandardGate::Triple(gate)) => (8, gate.to_mat8().as_slice().to_vec()),
+        ElementaryGate::Canonical(gate) => (4, gate.to_mat4().as_slice().to_vec()),
+        _ => unreachable!("gates of the standard libraries are standard or canonical gates"),
+    };
+    DMat::from_column_slice(dim, dim, &mat)
+}
+
+fn custom_gate(name: &str, mat: DMat, params: &[f64]) -> ElementaryGate {
+    let params = params.iter().map(|param| param.to_bits()).collect();
+    CustomGate::new(name.to_string(), mat, params).into()
+}
@@ -0,0 +1,80 @@
+//! OpenQASM interoperability.
+
+#[cfg(test)]
+mod tests;
+pub mod export;
+pub mod import;
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use crate::error::QivmError;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QasmVersion {
+    V2,
+    V3,
+}
+
+impl QasmVersion {
+    /// Get the version from its major number, `2` or `3`.
+    pub fn from_major(major: u32) -> Option<Self> {
+        match major {
+            2 => Some(QasmVersion::V2),
+            3 => Some(QasmVersion::V3),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct QasmError {
+    line: Option<usize>,
+    message: String,
+}
+
+impl QasmError {
+    pub(crate) fn new(message: String) -> Self {
+        Self { line: None, message }
+    }
+
+    /// Attach the source line to the error, unless it already has one.
+    pub(crate) fn at_line(self, line: usize) -> Self {
+        Self { line: self.line.or(Some(line)), ..self }
+    }
+
+    /// Line of the OpenQASM source where the error occurred, if it comes from a source.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+}
+
+impl Debug for QasmError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for QasmError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(formatter, "OpenQASM error at line {}: {}", line, self.message),
+            None => write!(formatter, "OpenQASM error: {}", self.message),
+        }
+    }
+}
+
+impl Error for QasmError {}
+
+impl From<QivmError> for QasmError {
+    fn from(err: QivmError) -> Self {
+        QasmError::new(err.to_string())
+    }
+}
+
+macro_rules! qasm_error {
+    ($($arg:tt)*) => { $crate::qasm::QasmError::new(format!($($arg)*)) };
+}
+
+pub(crate) use qasm_error;
+
+pub type QasmResult<T> = Result<T, QasmError>;
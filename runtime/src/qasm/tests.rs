@@ -0,0 +1,396 @@
+use std::str::FromStr;
+use num::complex::Complex64;
+use num_traits::FromPrimitive;
+use rand::Rng;
+use crate::algebra::{close_to_zero, controlled, embed, DMat, Mat2, Mat4, ToMat2, ToMat4};
+use crate::backend::native::NativeBackend;
+use crate::bytecode::asm;
+use crate::bytecode::instruction::StandardOpCode;
+use crate::gate::canonical::CanonicalGate;
+use crate::gate::standard::StandardDoubleGate::{CX, ISWP, ISWPD, SISWP, SISWPD, SSWP, SSWPD, SWP};
+use crate::gate::standard::StandardSingleGate;
+use crate::gate::standard::StandardSingleGate::H;
+use crate::program::builder::QuantumProgramContextBuilder;
+use crate::program::QuantumProgramContext;
+use crate::qasm::export::{canonical_params, export, u3_params};
+use crate::qasm::QasmVersion;
+use crate::qubits;
+
+/// Check that two matrices are equal up to a global phase.
+fn assert_eq_up_to_phase<const D: usize>(lhs: &[Complex64; D], rhs: &[Complex64; D]) {
+    let (index, _) = lhs.iter().enumerate()
+        .max_by(|(_, x), (_, y)| x.norm().total_cmp(&y.norm()))
+        .unwrap();
+    let phase = rhs[index] / lhs[index];
+    assert!(close_to_zero(phase.norm() - 1.0), "{:?} != {:?}", lhs, rhs);
+    for (x, y) in lhs.iter().zip(rhs) {
+        assert!(close_to_zero((x * phase - y).norm()), "{:?} != {:?}", lhs, rhs);
+    }
+}
+
+fn mat2_entries(mat: &Mat2) -> [Complex64; 4] {
+    mat.as_slice().try_into().unwrap()
+}
+
+fn mat4_entries(mat: &Mat4) -> [Complex64; 16] {
+    mat.as_slice().try_into().unwrap()
+}
+
+#[test]
+fn test_export_bell_state() {
+    let instructions = asm::assemble("ALLOC 2\nH [0]\nCX [0, 1]\nMEASURE 0, 1").unwrap();
+    assert_eq!(export(&instructions, QasmVersion::V2).unwrap(), "\
+OPENQASM 2.0;
+include \"qelib1.inc\";
+qreg q[2];
+creg c[2];
+h q[0];
+cx q[0], q[1];
+measure q[0] -> c[0];
+measure q[1] -> c[1];
+");
+    assert_eq!(export(&instructions, QasmVersion::V3).unwrap(), "\
+OPENQASM 3.0;
+include \"stdgates.inc\";
+qubit[2] q;
+bit[2] c;
+h q[0];
+cx q[0], q[1];
+c[0] = measure q[0];
+c[1] = measure q[1];
+");
+}
+
+#[test]
+fn test_export_definitions() {
+    let instructions = asm::assemble("
+        ALLOC 3
+        SWP [0, 1]
+        ISWP [1, 2]
+        CAN(0.5, 0.0, 0.25) [0, 2]
+        RESET 1
+        U(0.5, 0.25, 0.125) [2]
+        ZPOW(0.5) [1]
+    ").unwrap();
+    assert_eq!(export(&instructions, QasmVersion::V2).unwrap(), "\
+OPENQASM 2.0;
+include \"qelib1.inc\";
+gate swap a, b { cx a, b; cx b, a; cx a, b; }
+gate rxx(theta) a, b { h a; h b; cx a, b; rz(theta) b; cx a, b; h a; h b; }
+gate ryy(theta) a, b { sdg a; sdg b; h a; h b; cx a, b; rz(theta) b; cx a, b; h a; h b; s a; s b; }
+gate rzz(theta) a, b { cx a, b; rz(theta) b; cx a, b; }
+qreg q[3];
+creg c[3];
+swap q[0], q[1];
+rxx(-1.5707963267948966) q[1], q[2];
+ryy(-1.5707963267948966) q[1], q[2];
+rxx(1.5707963267948966) q[0], q[2];
+rzz(0.7853981633974483) q[0], q[2];
+reset q[1];
+u3(0.5, 0.125, 0.25) q[2];
+u1(1.5707963267948966) q[1];
+");
+    let qasm = export(&instructions, QasmVersion::V3).unwrap();
+    assert!(!qasm.contains("gate swap"));
+    assert!(qasm.contains("U(0.5, 0.125, 0.25) q[2];"));
+    assert!(qasm.contains("p(1.5707963267948966) q[1];"));
+}
+
+#[test]
+fn test_export_every_standard_opcode() {
+    let opcodes = (0 ..= u8::MAX).filter_map(StandardOpCode::from_u8).collect::<Vec<_>>();
+    for opcode in opcodes {
+        let ident: &'static str = opcode.into();
+        let (n_params, n_targets) = opcode.signature();
+        let params = vec!["0.5"; n_params].join(", ");
+        let targets = (0 .. n_targets).map(|target| target.to_string()).collect::<Vec<_>>();
+        let source = if n_params == 0 {
+            format!("ALLOC 3\n{} [{}]", ident, targets.join(", "))
+        } else {
+            format!("ALLOC 3\n{}({}) [{}]", ident, params, targets.join(", "))
+        };
+        let instructions = asm::assemble(&source).unwrap();
+        for version in [QasmVersion::V2, QasmVersion::V3] {
+            let qasm = export(&instructions, version);
+            assert!(qasm.is_ok(), "failed to export `{}`: {:?}", ident, qasm.err());
+        }
+    }
+}
+
+#[test]
+fn test_canonical_params() {
+    for gate in [SWP, SSWP, SSWPD, ISWP, ISWPD, SISWP, SISWPD] {
+        let ident: &'static str = gate.into();
+        let opcode = StandardOpCode::from_str(ident).unwrap();
+        let (tx, ty, tz) = canonical_params(opcode).unwrap();
+        assert_eq_up_to_phase(
+            &mat4_entries(&gate.to_mat4()),
+            &mat4_entries(&CanonicalGate::new(tx, ty, tz).to_mat4()),
+        );
+    }
+}
+
+#[test]
+fn test_u3_params() {
+    let mut rng = rand::thread_rng();
+    let mut gates = vec![
+        StandardSingleGate::V,
+        StandardSingleGate::VD,
+        StandardSingleGate::X,
+        StandardSingleGate::Z,
+        StandardSingleGate::RN { nx: 0.48, ny: -0.6, nz: 0.64, angle: 1.3 },
+    ];
+    for _ in 0 .. 64 {
+        gates.push(StandardSingleGate::U {
+            theta: rng.gen_range(-3.0 .. 3.0),
+            phi: rng.gen_range(-3.0 .. 3.0),
+            lambda: rng.gen_range(-3.0 .. 3.0),
+        });
+    }
+    for gate in gates {
+        let (theta, phi, lambda) = u3_params(&gate.to_mat2());
+        assert_eq_up_to_phase(
+            &mat2_entries(&gate.to_mat2()),
+            &mat2_entries(&StandardSingleGate::U { theta, phi, lambda }.to_mat2()),
+        );
+    }
+}
+
+#[test]
+fn test_export_errors() {
+    let error = |source: &str| {
+        export(&asm::assemble(source).unwrap(), QasmVersion::V3).err().unwrap()
+    };
+    assert_eq!(error("H [0]").to_string(), "OpenQASM error: Qubit 0 is used before `ALLOC`");
+    assert_eq!(
+        error("ALLOC 2\nCX [0, 2]").to_string(),
+        "OpenQASM error: Qubit 2 is out of range (2 allocated)"
+    );
+    assert_eq!(
+        error("ALLOC 2\n@FOO [0]").to_string(),
+        "OpenQASM error: Custom gate `FOO` cannot be exported"
+    );
+    assert_eq!(
+        error("ALLOC 2\nRZ [0]").to_string(),
+        "OpenQASM error: `RZ` takes 1 parameters, got 0"
+    );
+}
+
+#[test]
+fn test_export_program() {
+    let mut ctx = QuantumProgramContextBuilder::with_backend(NativeBackend).build();
+    ctx.enter();
+    let alloc = ctx.alloc(2).unwrap();
+    let qreg = alloc.borrow().clone();
+    ctx.push(H, qubits![qreg[0]]).unwrap();
+    ctx.push(CX, qubits![qreg[0], qreg[1]]).unwrap();
+    ctx.measure(qreg);
+    ctx.exit().unwrap();
+    let qasm = ctx.export_qasm(QasmVersion::V2).unwrap();
+    assert!(qasm.ends_with("h q[0];\ncx q[0], q[1];\nmeasure q[0] -> c[0];\nmeasure q[1] -> c[1];\n"));
+}
+
+fn import(source: &str) -> QuantumProgramContext {
+    let mut ctx = QuantumProgramContextBuilder::with_backend(NativeBackend).build();
+    ctx.import_qasm(source).unwrap();
+    ctx
+}
+
+fn disassemble(ctx: &mut QuantumProgramContext) -> String {
+    asm::disassemble(&ctx.compile_circuit().unwrap())
+}
+
+fn dmat(mat: &[Complex64], dim: usize) -> DMat {
+    DMat::from_column_slice(dim, dim, mat)
+}
+
+#[test]
+fn test_import_bell_state() {
+    let expected = "ALLOC 2\nH [0]\nCX [0, 1]\nMEASURE 0, 1\n";
+    let mut ctx = import("
+        OPENQASM 2.0;
+        include \"qelib1.inc\";
+        qreg q[2];
+        creg c[2];
+        h q[0];
+        cx q[0], q[1];
+        measure q -> c;
+    ");
+    assert_eq!(disassemble(&mut ctx), expected);
+    let mut ctx = import("
+        OPENQASM 3.0;
+        include \"stdgates.inc\";
+        qubit[2] q;
+        bit[2] c;
+        h q[0];
+        cx q[0], q[1];
+        c[0] = measure q[0];
+        c[1] = measure q[1];
+    ");
+    assert_eq!(disassemble(&mut ctx), expected);
+}
+
+#[test]
+fn test_import_measure_mapping() {
+    let mut ctx = import("
+        OPENQASM 2.0;
+        qreg q[2];
+        creg c[2];
+        h q[0];
+        measure q[0] -> c[1];
+        measure q[1] -> c[0];
+    ");
+    assert_eq!(disassemble(&mut ctx), "ALLOC 2\nH [0]\nMEASURE 1, 0\n");
+    let mut ctx = import("
+        OPENQASM 3.0;
+        qubit[3] q;
+        bit a;
+        bit[2] b;
+        b[1] = measure q[0];
+        b[0] = measure q[1];
+        a = measure q[2];
+    ");
+    assert_eq!(disassemble(&mut ctx), "ALLOC 3\nMEASURE 2, 1, 0\n");
+}
+
+#[test]
+fn test_import_standard_gates() {
+    let mut ctx = import("
+        OPENQASM 2.0;
+        include \"qelib1.inc\";
+        qreg q[3];
+        u3(pi/2, 0, pi) q[0]; // U instructions are ordered as (θ, λ, φ)
+        u1(-0.5) q[1];
+        cp(2 * 0.25) q[0], q[2];
+        swap q[1], q[2];
+        ccx q[0], q[1], q[2];
+        rzz(pi/2) q[0], q[1];
+        sx q[2];
+        barrier q;
+    ");
+    assert_eq!(disassemble(&mut ctx), "\
+ALLOC 3
+U(1.5707963267948966, 3.141592653589793, 0.0) [0]
+P(-0.5) [1]
+CP(0.5) [0, 2]
+SWP [1, 2]
+CCX [0, 1, 2]
+CAN(0.0, 0.0, 0.5) [0, 1]
+V [2]
+MEASURE
+");
+}
+
+#[test]
+fn test_import_modifiers_and_broadcast() {
+    let mut ctx = import("
+        OPENQASM 3;
+        qubit[2] a;
+        qubit b;
+        h a;
+        cx a, b;
+        inv @ s a[1];
+        inv @ inv @ t b;
+    ");
+    assert_eq!(disassemble(&mut ctx), "\
+ALLOC 3
+H [0]
+H [1]
+CX [0, 2]
+CX [1, 2]
+SD [1]
+T [2]
+MEASURE
+");
+}
+
+#[test]
+fn test_import_gate_definition() {
+    let mut ctx = import("
+        OPENQASM 2.0;
+        include \"qelib1.inc\";
+        gate bell a, b { h a; cx a, b; }
+        gate swap a, b { cx a, b; cx b, a; cx a, b; }
+        qreg q[3];
+        bell q[2], q[0];
+        swap q[0], q[1];
+        creg c[1];
+        measure q[2] -> c[0];
+    ");
+    // Definitions of standard gates are ignored
+    assert_eq!(disassemble(&mut ctx), "ALLOC 3\n@bell [2, 0]\nSWP [0, 1]\nMEASURE 2\n");
+}
+
+#[test]
+fn test_embed_and_controlled() {
+    let x = dmat(StandardSingleGate::X.to_mat2().as_slice(), 2);
+    let cx = dmat(CX.to_mat4().as_slice(), 4);
+    assert_eq!(controlled(&x, &[true]), cx);
+    assert_eq!(embed(&controlled(&x, &[true]), &[0, 1], 2), cx);
+
+    // CX with the control on the least significant qubit
+    let reversed = embed(&cx, &[1, 0], 2);
+    for col in 0 .. 4 {
+        let row = if col & 1 == 1 { col ^ 2 } else { col };
+        assert_eq!(reversed[(row, col)], Complex64::new(1.0, 0.0));
+    }
+
+    // X on the middle qubit of three
+    let embedded = embed(&x, &[1], 3);
+    for col in 0 .. 8 {
+        assert_eq!(embedded[(col ^ 2, col)], Complex64::new(1.0, 0.0));
+    }
+
+    // Controlled on |0⟩
+    let negctrl = controlled(&x, &[false]);
+    assert_eq!(negctrl[(1, 0)], Complex64::new(1.0, 0.0));
+    assert_eq!(negctrl[(2, 2)], Complex64::new(1.0, 0.0));
+}
+
+#[test]
+fn test_import_errors() {
+    let error = |source: &str| {
+        let mut ctx = QuantumProgramContextBuilder::with_backend(NativeBackend).build();
+        ctx.import_qasm(source).err().unwrap().to_string()
+    };
+    assert_eq!(
+        error("OPENQASM 4.0;"),
+        "OpenQASM error at line 1: Unsupported OpenQASM version `4.0`"
+    );
+    assert_eq!(
+        error("OPENQASM 2.0;\nqreg q[2];\nfoo q[0];"),
+        "OpenQASM error at line 3: Unknown gate `foo`"
+    );
+    assert_eq!(
+        error("qreg q[2];\n\nh q[2];"),
+        "OpenQASM error at line 3: Index 2 is out of range of `q` (2 qubits)"
+    );
+    assert_eq!(
+        error("qreg q[2];\ncx q[0], q[0];"),
+        "OpenQASM error at line 2: Qubit operands of `cx` are not distinct"
+    );
+    assert_eq!(
+        error("qreg q[2];\nrx q[0];"),
+        "OpenQASM error at line 2: `rx` takes 1 parameters, got 0"
+    );
+    assert_eq!(
+        error("gate g(theta) a {\n  rx(phi) a;\n}"),
+        "OpenQASM error at line 2: Unknown parameter `phi`"
+    );
+    assert_eq!(
+        error("qreg q[2];\nh q[0]\nh q[1];"),
+        "OpenQASM error at line 3: Expected `,` or `;`, got `h`"
+    );
+    assert_eq!(
+        error("qreg q[2];\ncreg c[1];\nmeasure q -> c;"),
+        "OpenQASM error at line 3: Cannot measure 2 qubits into 1 bits"
+    );
+    assert_eq!(
+        error("qreg q[2];\ncreg c[1];\nmeasure q[0] -> c[0];\nmeasure q[1] -> c[0];"),
+        "OpenQASM error at line 4: Several qubits are measured into the same bit"
+    );
+    assert_eq!(
+        error("include \"lib.inc\";"),
+        "OpenQASM error at line 1: Cannot include `lib.inc`"
+    );
+}
@@ -3,7 +3,7 @@ use std::slice::Iter;
 use std::vec::IntoIter;
 use crate::qubit::qubit_set::QubitSet;
 use crate::qubit::{QubitAddr, Slice};
-use crate::raise_error;
+use crate::error::{QivmError, QivmResult};
 
 /// An ordered set to store qubit address
 #[derive(Clone, PartialEq, Eq, Default, Debug)]
@@ -48,26 +48,28 @@ impl QubitAccessor {
         prev_size == self.qubits.len()
     }
 
-    pub fn pop(&mut self) -> QubitAddr {
-        self.qubits.pop().unwrap_or_else(|| {
-            raise_error!("Empty qubit accessor");
-        })
+    pub fn pop(&mut self) -> QivmResult<QubitAddr> {
+        self.qubits.pop().ok_or(QivmError::StackUnderflow("qubit accessor"))
     }
 
-    pub fn insert(&mut self, index: QubitAddr) {
+    pub fn insert(&mut self, index: QubitAddr) -> QivmResult<()> {
         if self.qubits.contains(&index) {
-            raise_error!("Invalid qubit index: {}", index)
+            return Err(QivmError::InvalidArgument(
+                format!("Qubit {} is already in the accessor", index)
+            ));
         }
-        self.qubits.push(index)
+        self.qubits.push(index);
+        Ok(())
     }
 
-    pub fn insert_range(&mut self, from: QubitAddr, to: QubitAddr) {
-        for qubit in &self.qubits {
-            if from <= *qubit && *qubit <= to {
-                raise_error!("Invalid qubit range: [{}, {}]", from, to);
-            }
+    pub fn insert_range(&mut self, from: QubitAddr, to: QubitAddr) -> QivmResult<()> {
+        if from > to || self.qubits.iter().any(|&qubit| from <= qubit && qubit <= to) {
+            return Err(QivmError::InvalidArgument(
+                format!("Invalid qubit range: [{}, {}]", from, to)
+            ));
         }
-        self.qubits.append(&mut Self::range(from, to).qubits)
+        self.qubits.append(&mut Self::range(from, to).qubits);
+        Ok(())
     }
 
     pub fn to_vec(&self) -> Vec<QubitAddr> {
@@ -86,10 +88,11 @@ impl QubitAccessor {
         self.qubits[0]
     }
 
-    pub fn get(&self, index: usize) -> QubitAccessor {
-        QubitAccessor::single(*self.qubits.get(index).unwrap_or_else(|| {
-            raise_error!("Invalid qubit index")
-        }))
+    pub fn get(&self, index: usize) -> QivmResult<QubitAccessor> {
+        let qubit = self.qubits.get(index).ok_or_else(|| QivmError::InvalidArgument(format!(
+            "Qubit index {} is out of range of {} qubits", index, self.qubits.len()
+        )))?;
+        Ok(QubitAccessor::single(*qubit))
     }
 }
 
@@ -97,8 +100,7 @@ impl QubitAccessor {
 macro_rules! qubits {
     ($($values:expr),* $(,)?) => {
         {
-            use $crate::qubit::qubit_accessor::QubitAccessor;
-            use $crate::qubit::QubitAddr;
+            use $crate::prelude::{QubitAccessor, QubitAddr};
             QubitAccessor::from(vec![$($values as QubitAddr),*])
         }
     };
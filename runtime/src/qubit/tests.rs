@@ -1,3 +1,4 @@
+use crate::error::QivmError;
 use crate::qubit::qubit_set::QubitSet;
 use crate::qubits;
 
@@ -17,3 +18,19 @@ fn test_qubit_set() {
     assert_eq!(qubits.pop(), Some(2));
     assert_eq!(qubits.to_vec(), vec![9, 10, 11]);
 }
+
+#[test]
+fn test_qubit_accessor_errors() {
+    let mut qubits = qubits![0, 1];
+    assert_eq!(qubits.get(1).unwrap(), qubits![1]);
+    assert!(matches!(qubits.get(2), Err(QivmError::InvalidArgument(_))));
+    assert!(matches!(qubits.insert(1), Err(QivmError::InvalidArgument(_))));
+    assert!(matches!(qubits.insert_range(1, 3), Err(QivmError::InvalidArgument(_))));
+    assert!(matches!(qubits.insert_range(4, 3), Err(QivmError::InvalidArgument(_))));
+    qubits.insert_range(2, 3).unwrap();
+    assert_eq!(qubits.to_vec(), vec![0, 1, 2, 3]);
+    for _ in 0 .. 4 {
+        qubits.pop().unwrap();
+    }
+    assert!(matches!(qubits.pop(), Err(QivmError::StackUnderflow("qubit accessor"))));
+}
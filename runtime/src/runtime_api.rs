@@ -1,19 +1,32 @@
 use std::cell::RefCell;
-use std::ffi::CStr;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::os::raw::c_char;
+use std::ptr;
 use std::ptr::slice_from_raw_parts;
 use std::rc::Rc;
 use std::slice;
 use num::complex::Complex64;
 use crate::program::QuantumProgramContext;
-use crate::{QIVM_INSTANCE, raise_error};
-use crate::backend::{execute_bytecode, ExecuteResult, RawExecuteResult};
+use crate::{QIVM_INSTANCE, QuantumInterfaceVirtualMachine};
+use crate::backend::BackendError;
+use crate::backend::dynamic_link::DynamicLinkBackend;
+use crate::bytecode::asm;
+use crate::bytecode::container::ByteCodeContainer;
+use crate::bytecode::instruction::Instruction;
+use crate::error::{QivmError, QivmResult};
 use crate::gate::standard::{StandardGate, StandardSingleGate, StandardTripleGate};
 use crate::gate::standard::StandardDoubleGate;
 use crate::measurement::{MeasurementResult, MeasurementResultEntry, RawMeasurementResult};
-use crate::program::builder::QuantumProgramContextBuilder;
+use crate::program::builder::{OptimizationLevel, QuantumProgramContextBuilder};
+use crate::program::stats::{CircuitStats, RawCircuitStats};
+use crate::qasm;
+use crate::qasm::QasmVersion;
 use crate::qubit::qubit_accessor::QubitAccessor;
 use crate::qubit::{QubitAddr, Slice};
+use crate::simulator::AVAILABLE_QUBITS;
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -34,86 +47,337 @@ impl From<RawComplex> for Complex64 {
     }
 }
 
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Get the message of the last error reported on the calling thread, or null if there is none.
+///
+/// Every entry of the C API reports its errors by its return value, a non-zero status code or
+/// null, and records the message of the error. The string is owned by the runtime and stays
+/// valid until the next error on the thread.
+#[no_mangle]
+pub extern fn qivm_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+pub(crate) fn set_last_error(err: QivmError) {
+    let message = CString::new(err.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Run the body of an entry, recording its error and returning `on_error` of it on failure.
+fn report<T>(body: impl FnOnce() -> QivmResult<T>, on_error: impl FnOnce(&QivmError) -> T) -> T {
+    body().unwrap_or_else(|err| {
+        let value = on_error(&err);
+        set_last_error(err);
+        value
+    })
+}
+
+/// Run the body of an entry, returning `0` on success and the code of the error on failure.
+fn status(body: impl FnOnce() -> QivmResult<()>) -> u8 {
+    report(|| body().map(|_| 0), QivmError::code)
+}
+
+/// Run the body of an entry, returning null on failure.
+fn pointer<T>(body: impl FnOnce() -> QivmResult<*mut T>) -> *mut T {
+    report(body, |_| ptr::null_mut())
+}
+
+unsafe fn c_str<'a>(string: *const c_char, name: &'static str) -> QivmResult<&'a str> {
+    if string.is_null() {
+        return Err(QivmError::NullPointer(name));
+    }
+    CStr::from_ptr(string).to_str().map_err(|_| {
+        QivmError::InvalidArgument(format!("{} is not valid UTF-8", name))
+    })
+}
+
+/// View a C array, which may be null when it is empty.
+unsafe fn c_slice<'a, T>(data: *const T, size: u64, name: &'static str) -> QivmResult<&'a [T]> {
+    if size == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(QivmError::NullPointer(name))
+    } else {
+        Ok(slice::from_raw_parts(data, size as usize))
+    }
+}
+
+/// Options of the program contexts created by `qivm_get_program_ctx_with_opts`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RawProgramCtxOptions {
+    /// Optimization level of the transpiler passes, from `0` to `3`.
+    pub optimization_level: u32,
+    /// Fail the compilation if the transpiled circuit uses gates unavailable in the backend.
+    pub verify_native_gates: bool,
+}
+
+/// Create a program context with the passes of the default optimization level `2`.
+///
+/// If the `STATEQ_OPT_LEVEL` environment variable is set to a level from `0` to `3`, the passes
+/// of this optimization level are used instead.
 #[no_mangle]
 pub extern fn qivm_get_program_ctx() -> *mut QuantumProgramContext {
     let mut ctx_builder = QuantumProgramContextBuilder::new();
-    ctx_builder.default_passes();
+    let level = env::var(OPT_LEVEL_ENV).ok()
+        .and_then(|level| level.trim().parse().ok())
+        .and_then(OptimizationLevel::from_level)
+        .unwrap_or_default();
+    ctx_builder.optimization_passes(level);
     Box::into_raw(Box::new(ctx_builder.build()))
 }
 
+/// Create a program context with the passes of the optimization level of the options, or as
+/// `qivm_get_program_ctx` if `options` is null.
+///
+/// Returns null if the optimization level is not supported.
+#[no_mangle]
+pub unsafe extern fn qivm_get_program_ctx_with_opts(
+    options: *const RawProgramCtxOptions
+) -> *mut QuantumProgramContext {
+    pointer(|| {
+        let options = match options.as_ref() {
+            Some(options) => options,
+            None => return Ok(qivm_get_program_ctx()),
+        };
+        let level = OptimizationLevel::from_level(options.optimization_level).ok_or_else(|| {
+            QivmError::InvalidArgument(format!(
+                "Unsupported optimization level {}", options.optimization_level
+            ))
+        })?;
+        let mut ctx_builder = QuantumProgramContextBuilder::new();
+        ctx_builder.optimization_passes(level);
+        if options.verify_native_gates {
+            ctx_builder.verify_native_gates();
+        }
+        Ok(Box::into_raw(Box::new(ctx_builder.build())))
+    })
+}
+
+const OPT_LEVEL_ENV: &str = "STATEQ_OPT_LEVEL";
+
+/// Load the backend library at `path` and use it for all the program contexts, with the gate set
+/// and the coupling map of the environment as `QuantumInterfaceVirtualMachine::init`.
+///
+/// Returns `0` on success, `1` if the library cannot be loaded, `2` if a symbol of the backend
+/// contract is missing, and `3` if the backend ABI version does not match. The current backend
+/// is kept on these failures. Returns `25` or `26` if the gate set or the coupling map file
+/// cannot be loaded, the backend is used with the ones it reports then.
+#[no_mangle]
+pub unsafe extern fn qivm_load_backend(path: *const c_char) -> u8 {
+    status(|| {
+        if path.is_null() {
+            return Err(BackendError::LibraryLoad {
+                path: String::new(), reason: "null path".to_string()
+            }.into());
+        }
+        let path = CStr::from_ptr(path).to_string_lossy().to_string();
+        let backend = DynamicLinkBackend::load(path)?;
+        let (qivm, errors) = QuantumInterfaceVirtualMachine::with_env_config(Box::new(backend));
+        *QIVM_INSTANCE.lock().unwrap() = qivm;
+        errors.into_iter().next().map_or(Ok(()), Err)
+    })
+}
+
+/// Assemble QIVM assembly text into a bytecode container, which can be passed to
+/// `qivm_exec_bytecode` of a backend.
+///
+/// The size of the bytecode is written to `size`. Returns null if the text cannot be assembled.
+/// The bytecode must be released with `qivm_free_bytecode`.
+#[no_mangle]
+pub unsafe extern fn qivm_assemble(text: *const c_char, size: *mut u64) -> *mut u8 {
+    pointer(|| {
+        let text = c_str(text, "assembly text")?;
+        let size = size.as_mut().ok_or(QivmError::NullPointer("bytecode size"))?;
+        let instructions = asm::assemble(text)?;
+        let bytecode = ByteCodeContainer::new(instructions).encode().into_boxed_slice();
+        *size = bytecode.len() as u64;
+        Ok(Box::into_raw(bytecode) as *mut u8)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern fn qivm_free_bytecode(bytecode: *mut u8, size: u64) {
+    if !bytecode.is_null() {
+        let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(bytecode, size as usize));
+    }
+}
+
 #[no_mangle]
 pub unsafe extern fn qivm_destroy_program_ctx(ctx: *mut QuantumProgramContext) {
-    // To destroy the value later, use `Box::from_raw` to create a new Box that owns it,
-    // then let that box deallocate its contained value when it goes out of scope.
-    let _ = Box::from_raw(ctx);
+    if !ctx.is_null() {
+        // To destroy the value later, use `Box::from_raw` to create a new Box that owns it,
+        // then let that box deallocate its contained value when it goes out of scope.
+        let _ = Box::from_raw(ctx);
+    }
 }
 
 #[no_mangle]
-pub unsafe extern fn qivm_measure(ctx: *mut QuantumProgramContext, accessor: *mut QubitAccessor) {
-    let ctx = ctx.unsafe_into();
-    let accessor = accessor.unsafe_into().clone();
-    ctx.measure(accessor);
+pub unsafe extern fn qivm_measure(
+    ctx: *mut QuantumProgramContext, accessor: *mut QubitAccessor
+) -> u8 {
+    status(|| {
+        let accessor = accessor.unsafe_into()?.clone();
+        ctx.unsafe_into()?.measure(accessor);
+        Ok(())
+    })
 }
 
+/// Compile and execute the program.
+///
+/// Returns `0` on success, or the code of the error, which is `4` if the backend fails to execute
+/// the program.
+///
+/// If the `STATEQ_EMIT_QASM` environment variable is set, the compiled program is also appended
+/// to the file it names as OpenQASM, of the version given by `STATEQ_QASM_VERSION` (`2` or `3`,
+/// defaults to `3`), and the program is not executed if it cannot be written. If
+/// `STATEQ_PRINT_STATS` is set, the statistics of the circuit are printed to the standard error.
 #[no_mangle]
 pub unsafe extern fn qivm_exec_program(ctx: *mut QuantumProgramContext, shots: u64) -> u8 {
-    let ctx = ctx.unsafe_into();
-    let bytecode = ctx.compile_bytecode();
-    let result: ExecuteResult = execute_bytecode(bytecode, shots as usize).into();
-    ctx.set_measurement_result(result.measurement);
-    result.error_code
+    status(|| {
+        let ctx = ctx.unsafe_into()?;
+        let instructions = ctx.compile_circuit()?;
+        if let Ok(path) = env::var(EMIT_QASM_ENV) {
+            emit_qasm(&instructions, &path)?;
+        }
+        if env::var_os(PRINT_STATS_ENV).is_some() {
+            eprint!("{}", stats_report(ctx.transpile_stats()));
+        }
+        ctx.execute_compiled(instructions, shots as usize)?;
+        Ok(())
+    })
 }
 
+const EMIT_QASM_ENV: &str = "STATEQ_EMIT_QASM";
+const QASM_VERSION_ENV: &str = "STATEQ_QASM_VERSION";
+const PRINT_STATS_ENV: &str = "STATEQ_PRINT_STATS";
+
+/// Report the statistics of the circuit before and after the passes, and a summary of the
+/// circuit after each pass.
+fn stats_report(stats: &[CircuitStats]) -> String {
+    let (first, last) = match (stats.first(), stats.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return String::new(),
+    };
+    let mut report = format!("[QIVM Stats] before the passes\n{}", first);
+    for (index, stats) in stats.iter().enumerate().skip(1) {
+        report += &format!(
+            "[QIVM Stats] after pass {}: {} gates, {} two-qubit gates, depth {}, T-count {}\n",
+            index, stats.gate_count(), stats.two_qubit_count, stats.depth, stats.t_count
+        );
+    }
+    report + &format!("[QIVM Stats] after the passes\n{}", last)
+}
+
+fn emit_qasm(instructions: &[Instruction], path: &str) -> QivmResult<()> {
+    let version = env::var(QASM_VERSION_ENV).ok()
+        .and_then(|version| version.parse().ok())
+        .and_then(QasmVersion::from_major)
+        .unwrap_or(QasmVersion::V3);
+    let qasm = qasm::export::export(instructions, version)?;
+    OpenOptions::new().create(true).append(true).open(path)
+        .and_then(|mut file| file.write_all(qasm.as_bytes()))
+        .map_err(|err| QivmError::InvalidArgument(
+            format!("Unable to write OpenQASM to `{}`: {}", path, err)
+        ))
+}
+
+/// Compile the program to OpenQASM of the given major version, `2` or `3`.
+///
+/// Returns null if the program cannot be exported. The string must be released with
+/// `qivm_free_string`.
 #[no_mangle]
-pub unsafe extern fn qivm_stack_enter(ctx: *mut QuantumProgramContext) {
-    ctx.unsafe_into().enter();
+pub unsafe extern fn qivm_export_qasm(ctx: *mut QuantumProgramContext, version: u32) -> *mut c_char {
+    pointer(|| {
+        let ctx = ctx.unsafe_into()?;
+        let version = QasmVersion::from_major(version).ok_or_else(|| {
+            QivmError::InvalidArgument(format!("Unsupported OpenQASM version {}", version))
+        })?;
+        let qasm = ctx.export_qasm(version)?;
+        CString::new(qasm).map(CString::into_raw).map_err(|_| {
+            QivmError::Compile("OpenQASM output contains a nul byte".to_string())
+        })
+    })
 }
 
 #[no_mangle]
-pub unsafe extern fn qivm_stack_exit(ctx: *mut QuantumProgramContext) {
-    ctx.unsafe_into().exit();
+pub unsafe extern fn qivm_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        let _ = CString::from_raw(string);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern fn qivm_stack_enter(ctx: *mut QuantumProgramContext) -> u8 {
+    status(|| {
+        ctx.unsafe_into()?.enter();
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern fn qivm_stack_exit(ctx: *mut QuantumProgramContext) -> u8 {
+    status(|| ctx.unsafe_into()?.exit())
 }
 
 #[no_mangle]
 pub unsafe extern fn qivm_alloc_qubits(
     ctx: *mut QuantumProgramContext, size: u64
 ) -> *mut QubitAccessor {
-    ctx.unsafe_into().alloc(size as usize).as_ptr()
+    pointer(|| Ok(ctx.unsafe_into()?.alloc(size as usize)?.as_ptr()))
 }
 
 #[no_mangle]
 pub unsafe extern fn qivm_qubit_accessor_encode(
     ctx: *mut QuantumProgramContext, accessor: *mut QubitAccessor, value: u32
-) {
-    let ctx = ctx.unsafe_into();
-    ctx.encode(accessor.unsafe_into(), value);
+) -> u8 {
+    status(|| {
+        let accessor = accessor.unsafe_into()?;
+        ctx.unsafe_into()?.encode(accessor, value)
+    })
 }
 
+/// Get the number of qubits of the accessor, or `0` if the accessor is invalid.
 #[no_mangle]
 pub unsafe extern fn qivm_qubit_accessor_size(accessor: *mut QubitAccessor) -> u64 {
-    accessor.unsafe_into().size() as u64
+    report(|| Ok(accessor.unsafe_into()?.size() as u64), |_| 0)
 }
 
 #[no_mangle]
 pub unsafe extern fn qivm_qubit_accessor_concat(
     ctx: *mut QuantumProgramContext, lhs: *mut QubitAccessor, rhs: *mut QubitAccessor,
 ) -> *mut QubitAccessor {
-    let result = Rc::new(RefCell::new(
-        lhs.unsafe_into().clone() + rhs.unsafe_into().clone()
-    ));
-    ctx.unsafe_into().add_qubit_accessor(result.clone());
-    result.as_ptr()
+    pointer(|| {
+        let result = Rc::new(RefCell::new(
+            lhs.unsafe_into()?.clone() + rhs.unsafe_into()?.clone()
+        ));
+        ctx.unsafe_into()?.add_qubit_accessor(result.clone())?;
+        Ok(result.as_ptr())
+    })
 }
 
 #[no_mangle]
 pub unsafe extern fn qivm_qubit_accessor_indexing(
     ctx: *mut QuantumProgramContext, accessor: *mut QubitAccessor, index: i64
 ) -> *mut QubitAccessor {
-    let accessor = accessor.unsafe_into();
-    let index = if index < 0 { accessor.size() as i64 + index } else { index } as usize;
-    let result = Rc::new(RefCell::new(accessor.get(index)));
-    ctx.unsafe_into().add_qubit_accessor(result.clone());
-    result.as_ptr()
+    pointer(|| {
+        let accessor = accessor.unsafe_into()?;
+        let size = accessor.size() as i64;
+        let position = if index < 0 { size + index } else { index };
+        if position < 0 || position >= size {
+            return Err(QivmError::InvalidArgument(
+                format!("Qubit index {} is out of range of {} qubits", index, size)
+            ));
+        }
+        let result = Rc::new(RefCell::new(accessor.get(position as usize)?));
+        ctx.unsafe_into()?.add_qubit_accessor(result.clone())?;
+        Ok(result.as_ptr())
+    })
 }
 
 #[no_mangle]
@@ -121,17 +385,24 @@ pub unsafe extern fn qivm_qubit_accessor_slicing(
     ctx: *mut QuantumProgramContext, accessor: *mut QubitAccessor,
     from: i32, to: i32, step: u64,
 ) -> *mut QubitAccessor {
-    let accessor = accessor.unsafe_into();
-    let size = accessor.size() as i32;
-    let result = Rc::new(RefCell::new(
-        accessor.slice(
-            if from < 0 { size + from } else { from } as QubitAddr,
-            if to < 0 { size + to } else { to } as QubitAddr,
-            step as usize
-        )
-    ));
-    ctx.unsafe_into().add_qubit_accessor(result.clone());
-    result.as_ptr()
+    pointer(|| {
+        let accessor = accessor.unsafe_into()?;
+        let size = accessor.size() as i32;
+        let (begin, end) = (
+            if from < 0 { size + from } else { from },
+            if to < 0 { size + to } else { to },
+        );
+        if begin < 0 || end < 0 || step == 0 {
+            return Err(QivmError::InvalidArgument(
+                format!("Invalid qubit slice [{}, {}] with step {}", from, to, step)
+            ));
+        }
+        let result = Rc::new(RefCell::new(
+            accessor.slice(begin as QubitAddr, end as QubitAddr, step as usize)
+        ));
+        ctx.unsafe_into()?.add_qubit_accessor(result.clone())?;
+        Ok(result.as_ptr())
+    })
 }
 
 #[no_mangle]
@@ -139,42 +410,60 @@ pub unsafe extern fn qivm_program_begin_ctrl(
     ctx: *mut QuantumProgramContext,
     ctrl_qubits: *const QubitAccessor,
     condition: bool,
-) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    ctx.control(unsafe { ctrl_qubits.as_ref().unwrap().clone() }, condition);
+) -> u8 {
+    status(|| {
+        let ctrl_qubits: &QubitAccessor = ctrl_qubits.unsafe_into()?;
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        ctx.control(ctrl_qubits.clone(), condition);
+        Ok(())
+    })
 }
 
 #[no_mangle]
 pub unsafe extern fn qivm_program_end_ctrl(
     ctx: *mut QuantumProgramContext,
     ctrl_qubits: *const QubitAccessor
-) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    ctx.decontrol(unsafe { ctrl_qubits.as_ref().unwrap().clone() });
+) -> u8 {
+    status(|| {
+        let ctrl_qubits: &QubitAccessor = ctrl_qubits.unsafe_into()?;
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        ctx.decontrol(ctrl_qubits.clone());
+        Ok(())
+    })
 }
 
 #[no_mangle]
-pub unsafe extern fn qivm_program_begin_dagger(ctx: *mut QuantumProgramContext) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    ctx.begin_dagger();
+pub unsafe extern fn qivm_program_begin_dagger(ctx: *mut QuantumProgramContext) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        ctx.begin_dagger();
+        Ok(())
+    })
 }
 
 #[no_mangle]
-pub unsafe extern fn qivm_program_end_dagger(ctx: *mut QuantumProgramContext) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    ctx.end_dagger();
+pub unsafe extern fn qivm_program_end_dagger(ctx: *mut QuantumProgramContext) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        ctx.end_dagger()
+    })
 }
 
 #[no_mangle]
-pub unsafe extern fn qivm_program_pause_ctrl(ctx: *mut QuantumProgramContext) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    ctx.pause_ctrl();
+pub unsafe extern fn qivm_program_pause_ctrl(ctx: *mut QuantumProgramContext) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        ctx.pause_ctrl();
+        Ok(())
+    })
 }
 
 #[no_mangle]
-pub unsafe extern fn qivm_program_restore_ctrl(ctx: *mut QuantumProgramContext) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    ctx.restore_ctrl();
+pub unsafe extern fn qivm_program_restore_ctrl(ctx: *mut QuantumProgramContext) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        ctx.restore_ctrl()
+    })
 }
 
 fn reinterpret_cast<T, U>(value: T) -> U {
@@ -186,29 +475,37 @@ pub unsafe extern fn qivm_program_push_op(
     ctx: *mut QuantumProgramContext, ident: *const c_char,
     target_qubits: *const QubitAccessor,
     params: *const u64, param_size: u64,
-) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    let ident = CStr::from_ptr(ident).to_str().unwrap();
-    let params = std::slice::from_raw_parts(params, param_size as usize);
-    let gate: StandardGate = match ident {
-        "H" => StandardSingleGate::H.into(),
-        "X" => StandardSingleGate::X.into(),
-        "Y" => StandardSingleGate::Y.into(),
-        "Z" => StandardSingleGate::Z.into(),
-        "S" => StandardSingleGate::S.into(),
-        "T" => StandardSingleGate::T.into(),
-        "P" => StandardSingleGate::P { angle: reinterpret_cast(params[0]) }.into(),
-        "RX" => StandardSingleGate::RX { angle: reinterpret_cast(params[0]) }.into(),
-        "RY" => StandardSingleGate::RY { angle: reinterpret_cast(params[0]) }.into(),
-        "RZ" => StandardSingleGate::RZ { angle: reinterpret_cast(params[0]) }.into(),
-        "CX" => StandardDoubleGate::CX.into(),
-        "CZ" => StandardDoubleGate::CZ.into(),
-        "SWP" => StandardDoubleGate::SWP.into(),
-        "CP" => StandardDoubleGate::CP { angle: reinterpret_cast(params[0]) }.into(),
-        "CCX" => StandardTripleGate::CCX.into(),
-        _ => raise_error!("Unsupported standard gate: {}", ident),
-    };
-    ctx.push(gate, unsafe { target_qubits.as_ref().unwrap().clone() });
+) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        let ident = c_str(ident, "gate identifier")?;
+        let params = c_slice(params, param_size, "gate parameters")?;
+        let angle = || -> QivmResult<f64> {
+            params.first().map(|&param| reinterpret_cast(param)).ok_or_else(|| {
+                QivmError::InvalidArgument(format!("Missing angle of gate `{}`", ident))
+            })
+        };
+        let gate: StandardGate = match ident {
+            "H" => StandardSingleGate::H.into(),
+            "X" => StandardSingleGate::X.into(),
+            "Y" => StandardSingleGate::Y.into(),
+            "Z" => StandardSingleGate::Z.into(),
+            "S" => StandardSingleGate::S.into(),
+            "T" => StandardSingleGate::T.into(),
+            "P" => StandardSingleGate::P { angle: angle()? }.into(),
+            "RX" => StandardSingleGate::RX { angle: angle()? }.into(),
+            "RY" => StandardSingleGate::RY { angle: angle()? }.into(),
+            "RZ" => StandardSingleGate::RZ { angle: angle()? }.into(),
+            "CX" => StandardDoubleGate::CX.into(),
+            "CZ" => StandardDoubleGate::CZ.into(),
+            "SWP" => StandardDoubleGate::SWP.into(),
+            "CP" => StandardDoubleGate::CP { angle: angle()? }.into(),
+            "CCX" => StandardTripleGate::CCX.into(),
+            _ => return Err(QivmError::UnsupportedGate(ident.to_string())),
+        };
+        let target_qubits: &QubitAccessor = target_qubits.unsafe_into()?;
+        ctx.push(gate, target_qubits.clone())
+    })
 }
 
 #[no_mangle]
@@ -217,19 +514,24 @@ pub unsafe extern fn qivm_program_push_custom_op(
     target_size: u64, mat: *const RawComplex,
     target_qubits: *const QubitAccessor,
     param_size: u64, params: *const u64,
-) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    let ident = unsafe {
-        CStr::from_ptr(ident).to_str().unwrap()
-    }.to_string();
-    let mat_slice: Vec<Complex64> = unsafe {
-        slice::from_raw_parts(mat, 2usize.pow(target_size as u32 * 2))
-    }.iter().copied().map(Into::<Complex64>::into).collect();
-    let params: Vec<u64> = unsafe {
-        slice::from_raw_parts(params, param_size as usize)
-    }.to_vec();
-    let target_qubits = unsafe { target_qubits.as_ref().unwrap().clone() };
-    ctx.push_custom(ident, mat_slice.as_slice().into(), params, target_qubits);
+) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        let ident = c_str(ident, "gate identifier")?.to_string();
+        let mat_size = u32::try_from(target_size).ok()
+            .filter(|&size| size > 0 && size <= AVAILABLE_QUBITS)
+            .and_then(|size| size.checked_mul(2))
+            .and_then(|exp| 2u64.checked_pow(exp))
+            .ok_or_else(|| QivmError::InvalidArgument(format!(
+                "Invalid target size of custom gate `{}`: {} ({} qubits available)",
+                ident, target_size, AVAILABLE_QUBITS
+            )))?;
+        let mat_slice: Vec<Complex64> = c_slice(mat, mat_size, "gate matrix")?
+            .iter().copied().map(Into::<Complex64>::into).collect();
+        let params: Vec<u64> = c_slice(params, param_size, "gate parameters")?.to_vec();
+        let target_qubits: &QubitAccessor = target_qubits.unsafe_into()?;
+        ctx.push_custom(ident, mat_slice.as_slice().into(), params, target_qubits.clone())
+    })
 }
 
 #[no_mangle]
@@ -237,72 +539,156 @@ pub unsafe extern fn qivm_program_push_custom_builtin_op(
     ctx: *mut QuantumProgramContext, ident: *const c_char,
     target_size: u64, target_qubits: *const QubitAccessor,
     param_size: u64, params: *const u64,
-) {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    let ident = unsafe {
-        CStr::from_ptr(ident).to_str().unwrap()
-    }.to_string();
-    let params: Vec<u64> = unsafe {
-        slice::from_raw_parts(params, param_size as usize)
-    }.to_vec();
-    let target_qubits = unsafe { target_qubits.as_ref().unwrap().clone() };
-    ctx.push_custom_builtin(ident, params, target_size as usize, target_qubits);
+) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        let ident = c_str(ident, "gate identifier")?.to_string();
+        let params: Vec<u64> = c_slice(params, param_size, "gate parameters")?.to_vec();
+        let target_qubits: &QubitAccessor = target_qubits.unsafe_into()?;
+        ctx.push_custom_builtin(ident, params, target_size as usize, target_qubits.clone())
+    })
 }
 
+/// Get the measurement result of the last execution.
+///
+/// Returns an empty result, with null measurements, if the result is not available.
 #[no_mangle]
 pub unsafe extern fn qivm_program_get_result(ctx: *mut QuantumProgramContext) -> RawMeasurementResult {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    let result = ctx.get_measurement_result();
-    result.unwrap_or_else(|| {
-        raise_error!("Measurement result is not available");
-    }).into_raw()
+    report(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        let result = ctx.get_measurement_result().ok_or(QivmError::MeasurementUnavailable)?;
+        Ok(result.into_raw())
+    }, |_| RawMeasurementResult { shots: 0, result_size: 0, measurements: ptr::null_mut() })
 }
 
+/// Copy the measurement result of the last execution into the buffer of `result`, whose
+/// `result_size` is the capacity of the buffer.
+///
+/// Returns the number of measurement entries, or `0` if the result is not available or does
+/// not fit in the buffer.
 #[no_mangle]
 pub unsafe extern fn qivm_program_assign_result(
     ctx: *mut QuantumProgramContext, result: *mut RawMeasurementResult,
 ) -> u64 {
-    let ctx: &mut QuantumProgramContext = ctx.unsafe_into();
-    let raw_result: &mut RawMeasurementResult = result.unsafe_into();
-    let result = ctx.get_measurement_result().unwrap_or_else(|| {
-        raise_error!("Measurement result is not available");
-    });
-    raw_result.shots = result.shots;
-    if result.measurements.len() > raw_result.result_size as usize {
-        raise_error!("Measurement result buffer is too small");
-    }
-    let raw_measurements = slice::from_raw_parts_mut(raw_result.measurements, raw_result.result_size as usize);
-    result.measurements.iter().enumerate().for_each(|(i, &v)| {
-        raw_measurements[i] = v;
-    });
-    raw_result.result_size = result.measurements.len() as u64;
-    result.measurements.len() as u64
+    report(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        let raw_result: &mut RawMeasurementResult = result.unsafe_into()?;
+        let result = ctx.get_measurement_result().ok_or(QivmError::MeasurementUnavailable)?;
+        if result.measurements.len() > raw_result.result_size as usize {
+            return Err(QivmError::BufferTooSmall {
+                required: result.measurements.len(), size: raw_result.result_size as usize
+            });
+        }
+        if !result.measurements.is_empty() {
+            if raw_result.measurements.is_null() {
+                return Err(QivmError::NullPointer("measurement result buffer"));
+            }
+            slice::from_raw_parts_mut(raw_result.measurements, result.measurements.len())
+                .copy_from_slice(&result.measurements);
+        }
+        raw_result.shots = result.shots;
+        raw_result.result_size = result.measurements.len() as u64;
+        Ok(result.measurements.len() as u64)
+    }, |_| 0)
 }
 
-unsafe impl<'a> UnsafeInto<&'a mut QuantumProgramContext> for *mut QuantumProgramContext {
-    unsafe fn unsafe_into(self) -> &'a mut QuantumProgramContext {
-        self.as_mut().unwrap_or_else(|| {
-            raise_error!("Invalid quantum program context")
+/// Copy the physical qubit of each logical qubit at the end of the routed circuit into
+/// `physical`, whose `size` is the capacity of the buffer. The bits of the measurement results
+/// of the context are already moved back to the positions of the logical qubits.
+///
+/// Returns the number of qubits of the layout, or `0` if the circuit was not routed or the
+/// layout does not fit in the buffer.
+#[no_mangle]
+pub unsafe extern fn qivm_program_get_layout(
+    ctx: *mut QuantumProgramContext, physical: *mut u32, size: u64,
+) -> u64 {
+    report(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        let layout = match ctx.final_layout() {
+            Some(layout) => layout.to_physical_vec(),
+            None => return Ok(0),
+        };
+        if layout.len() > size as usize {
+            return Err(QivmError::BufferTooSmall { required: layout.len(), size: size as usize });
+        }
+        let len = layout.len();
+        if len > 0 {
+            if physical.is_null() {
+                return Err(QivmError::NullPointer("layout buffer"));
+            }
+            let buffer = slice::from_raw_parts_mut(physical, len);
+            for (slot, qubit) in buffer.iter_mut().zip(layout) {
+                *slot = qubit as u32;
+            }
+        }
+        Ok(len as u64)
+    }, |_| 0)
+}
+
+/// Number of statistics recorded by the last transpilation of the program: one before the passes
+/// and one after each pass, `0` if the program was not transpiled.
+#[no_mangle]
+pub unsafe extern fn qivm_program_stats_count(ctx: *mut QuantumProgramContext) -> u64 {
+    report(|| Ok(ctx.unsafe_into()?.transpile_stats().len() as u64), |_| 0)
+}
+
+/// Copy the statistics of the circuit after the pass `index` of the last transpilation into
+/// `stats`, the index `0` being the circuit before the passes.
+///
+/// Returns `0` on success, or the code of the error.
+#[no_mangle]
+pub unsafe extern fn qivm_program_get_stats(
+    ctx: *mut QuantumProgramContext, index: u64, stats: *mut RawCircuitStats,
+) -> u8 {
+    status(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        let raw_stats = stats.as_mut().ok_or(QivmError::NullPointer("circuit statistics"))?;
+        let recorded = ctx.transpile_stats();
+        let stats = recorded.get(index as usize).ok_or_else(|| QivmError::InvalidArgument(
+            format!("No statistics of index {}, {} are recorded", index, recorded.len())
+        ))?;
+        *raw_stats = stats.into();
+        Ok(())
+    })
+}
+
+/// Report the statistics of the last transpilation of the program, with the gate histograms.
+///
+/// Returns null on failure. The string must be released with `qivm_free_string`.
+#[no_mangle]
+pub unsafe extern fn qivm_program_stats_report(ctx: *mut QuantumProgramContext) -> *mut c_char {
+    pointer(|| {
+        let ctx: &mut QuantumProgramContext = ctx.unsafe_into()?;
+        CString::new(stats_report(ctx.transpile_stats())).map(CString::into_raw).map_err(|_| {
+            QivmError::Compile("Statistics report contains a nul byte".to_string())
         })
+    })
+}
+
+unsafe impl<'a> UnsafeInto<&'a mut QuantumProgramContext> for *mut QuantumProgramContext {
+    unsafe fn unsafe_into(self) -> QivmResult<&'a mut QuantumProgramContext> {
+        self.as_mut().ok_or(QivmError::NullPointer("quantum program context"))
     }
 }
 
 unsafe impl<'a> UnsafeInto<&'a mut QubitAccessor> for *mut QubitAccessor {
-    unsafe fn unsafe_into(self) -> &'a mut QubitAccessor {
-        self.as_mut().unwrap_or_else(|| {
-            raise_error!("Invalid qubit accessor")
-        })
+    unsafe fn unsafe_into(self) -> QivmResult<&'a mut QubitAccessor> {
+        self.as_mut().ok_or(QivmError::NullPointer("qubit accessor"))
+    }
+}
+
+unsafe impl<'a> UnsafeInto<&'a QubitAccessor> for *const QubitAccessor {
+    unsafe fn unsafe_into(self) -> QivmResult<&'a QubitAccessor> {
+        self.as_ref().ok_or(QivmError::NullPointer("qubit accessor"))
     }
 }
 
 unsafe impl<'a> UnsafeInto<&'a mut RawMeasurementResult> for *mut RawMeasurementResult {
-    unsafe fn unsafe_into(self) -> &'a mut RawMeasurementResult {
-        self.as_mut().unwrap_or_else(|| {
-            raise_error!("Invalid measurement result")
-        })
+    unsafe fn unsafe_into(self) -> QivmResult<&'a mut RawMeasurementResult> {
+        self.as_mut().ok_or(QivmError::NullPointer("measurement result"))
     }
 }
 
 unsafe trait UnsafeInto<T>: Sized {
-    unsafe fn unsafe_into(self) -> T;
+    unsafe fn unsafe_into(self) -> QivmResult<T>;
 }
@@ -0,0 +1,358 @@
+//! Native state-vector simulator.
+//!
+//! This is a pure-Rust implementation of the backend contract (`qivm_available_qubits`,
+//! `qivm_is_gate_available` and `qivm_exec_bytecode`), which interprets the decoded instructions
+//! of the bytecode container over a dense complex state vector. It does not depend on QuEST,
+//! so the whole runtime can be built and tested with plain `cargo test`.
+
+#[cfg(test)]
+mod tests;
+pub mod state;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::panic;
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::Rng;
+use strum::VariantNames;
+use crate::algebra::{Mat4, Mat8, ToMat2, ToMat4, ToMat8};
+use crate::backend::ExecuteResult;
+use crate::bytecode::container::ByteCodeContainer;
+use crate::bytecode::instruction::{custom_gate_ident, InstrParam, Instruction, PrimitiveOpCode, StandardOpCode};
+use crate::gate::canonical::CanonicalGate;
+use crate::gate::standard::{StandardDoubleGate, StandardSingleGate, StandardTripleGate};
+use crate::measurement::{MeasurementResult, MeasurementResultEntry};
+use crate::qubit::QubitAddr;
+use crate::simulator::state::StateVector;
+
+/// Maximum number of qubits the simulator is able to allocate.
+pub const AVAILABLE_QUBITS: u32 = 24;
+
+pub const ERROR_BACKEND: u8 = 1;
+pub const ERROR_PARSE: u8 = 2;
+pub const ERROR_UNKNOWN: u8 = 255;
+
+pub struct SimulatorError(String);
+
+impl Debug for SimulatorError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for SimulatorError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "Simulator error: {}", self.0)
+    }
+}
+
+impl Error for SimulatorError {}
+
+macro_rules! simulator_error {
+    ($($arg:tt)*) => { SimulatorError(format!($($arg)*)) };
+}
+
+type SimulatorResult<T> = Result<T, SimulatorError>;
+
+pub fn available_qubits() -> u32 {
+    AVAILABLE_QUBITS
+}
+
+pub fn is_gate_available(gate_ident: &str) -> bool {
+    StandardOpCode::VARIANTS.contains(&gate_ident)
+}
+
+/// Execute the bytecode and sample the measured qubits `shots` times.
+///
+/// Error codes follow the QuEST backend: `1` for execution errors, `2` for malformed bytecode,
+/// and `255` for anything else.
+pub fn exec_bytecode(bytecode: &[u8], shots: u32) -> ExecuteResult {
+    let instructions = match ByteCodeContainer::decode(bytecode) {
+        Ok(container) => container.instructions,
+        Err(_) => return failure(ERROR_PARSE),
+    };
+    let mut rng = rand::thread_rng();
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        execute_shots(&instructions, shots as u64, &mut rng)
+    })) {
+        Ok(Ok(measurement)) => ExecuteResult { error_code: 0, measurement },
+        Ok(Err(_)) => failure(ERROR_BACKEND),
+        Err(_) => failure(ERROR_UNKNOWN),
+    }
+}
+
+/// Run the instructions and sample the measured qubits `shots` times.
+///
+/// A reset, or a measurement followed by more operations, collapses the state with a random
+/// outcome, so such a program is run again for each shot. Otherwise it is run once and the shots
+/// are sampled from its distribution.
+fn execute_shots<R: Rng>(
+    instructions: &[Instruction], shots: u64, rng: &mut R
+) -> SimulatorResult<MeasurementResult> {
+    if !collapses(instructions) || shots <= 1 {
+        let distribution = Simulator::default().execute(instructions, rng)?;
+        return Ok(sample(&distribution, shots, rng));
+    }
+    let mut counts = BTreeMap::<u64, u64>::new();
+    for _ in 0 .. shots {
+        let simulator = Simulator { collapse: true, ..Simulator::default() };
+        let distribution = simulator.execute(instructions, rng)?;
+        for entry in sample(&distribution, 1, rng).measurements {
+            *counts.entry(entry.value).or_insert(0) += entry.count;
+        }
+    }
+    Ok(MeasurementResult {
+        shots,
+        measurements: counts.into_iter()
+            .map(|(value, count)| MeasurementResultEntry { value, count })
+            .collect(),
+    })
+}
+
+/// Return true if the program resets qubits, or applies gates after a measurement.
+fn collapses(instructions: &[Instruction]) -> bool {
+    let mut measured = false;
+    for instruction in instructions {
+        match instruction {
+            Instruction::Primitive { opcode: PrimitiveOpCode::Reset, .. } => return true,
+            Instruction::Primitive { opcode: PrimitiveOpCode::Measure, .. } => measured = true,
+            Instruction::StandardGateOperation { .. }
+            | Instruction::CustomGateOperation { .. } if measured => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn failure(error_code: u8) -> ExecuteResult {
+    ExecuteResult {
+        error_code,
+        measurement: MeasurementResult { shots: 0, measurements: vec![] },
+    }
+}
+
+fn sample<R: Rng>(distribution: &[(u64, f64)], shots: u64, rng: &mut R) -> MeasurementResult {
+    let mut counts = vec![0u64; distribution.len()];
+    if let Ok(weighted) = WeightedIndex::new(distribution.iter().map(|(_, prob)| *prob)) {
+        for _ in 0 .. shots {
+            counts[weighted.sample(rng)] += 1;
+        }
+    }
+    MeasurementResult {
+        shots,
+        measurements: distribution.iter().zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(&(value, _), count)| MeasurementResultEntry { value, count })
+            .collect(),
+    }
+}
+
+#[derive(Default)]
+struct Simulator {
+    state: Option<StateVector>,
+    /// Collapse the measured qubits, the distribution is then the one of the single outcome.
+    collapse: bool,
+    measure_mask: u64,
+    /// Last outcome of each measured qubit, when the measurements collapse the state.
+    outcome: u64,
+    distribution: Vec<(u64, f64)>,
+}
+
+impl Simulator {
+
+    /// Run the instructions once and return the probability of each measurement value.
+    fn execute<R: Rng>(
+        mut self, instructions: &[Instruction], rng: &mut R
+    ) -> SimulatorResult<Vec<(u64, f64)>> {
+        for instruction in instructions {
+            match instruction {
+                Instruction::Nop => {}
+                Instruction::Primitive { opcode, params } => {
+                    self.exec_primitive(*opcode, params, rng)?
+                }
+                Instruction::StandardGateOperation { opcode, params, targets } => {
+                    self.exec_standard(*opcode, params, targets)?
+                }
+                Instruction::CustomGateOperation { name, .. } => {
+                    return Err(simulator_error!(
+                        "Custom gate `{}` is not supported", custom_gate_ident(name)
+                    ));
+                }
+            }
+        }
+        Ok(self.distribution)
+    }
+
+    fn state(&mut self) -> SimulatorResult<&mut StateVector> {
+        self.state.as_mut().ok_or_else(|| simulator_error!("Qubits are not initialized"))
+    }
+
+    fn check_qubit(&mut self, qubit: u64) -> SimulatorResult<QubitAddr> {
+        let num_qubits = self.state()?.num_qubits();
+        if qubit < num_qubits as u64 {
+            Ok(qubit as QubitAddr)
+        } else {
+            Err(simulator_error!("Qubit {} is out of range ({} allocated)", qubit, num_qubits))
+        }
+    }
+
+    fn exec_primitive<R: Rng>(
+        &mut self, opcode: PrimitiveOpCode, params: &[InstrParam], rng: &mut R
+    ) -> SimulatorResult<()> {
+        match opcode {
+            PrimitiveOpCode::Alloc => {
+                let size = match params {
+                    [size] => size.as_u64(),
+                    _ => return Err(simulator_error!("`ALLOC` takes exactly one parameter")),
+                };
+                if size > AVAILABLE_QUBITS as u64 {
+                    return Err(simulator_error!(
+                        "Unable to allocate {} qubits ({} available)", size, AVAILABLE_QUBITS
+                    ));
+                }
+                self.state = Some(StateVector::new(size as usize));
+            }
+            PrimitiveOpCode::Reset => {
+                for param in params {
+                    let qubit = self.check_qubit(param.as_u64())?;
+                    let state = self.state()?;
+                    if state.measure_qubit(qubit, rng) {
+                        state.flip(qubit);
+                    }
+                }
+            }
+            PrimitiveOpCode::Measure => {
+                for param in params {
+                    let qubit = self.check_qubit(param.as_u64())?;
+                    self.measure_mask |= 1 << qubit;
+                    if self.collapse {
+                        if self.state()?.measure_qubit(qubit, rng) {
+                            self.outcome |= 1 << qubit;
+                        } else {
+                            self.outcome &= !(1 << qubit);
+                        }
+                    }
+                }
+                self.distribution = if self.collapse {
+                    vec![(self.outcome, 1.0)]
+                } else {
+                    let mask = self.measure_mask;
+                    self.state()?.distribution(mask).into_iter().collect()
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn exec_standard(
+        &mut self, opcode: StandardOpCode, params: &[InstrParam], targets: &[u32]
+    ) -> SimulatorResult<()> {
+        let ident: &'static str = opcode.into();
+        let params = params.iter().map(InstrParam::as_f64).collect::<Vec<f64>>();
+        for (i, &target) in targets.iter().enumerate() {
+            self.check_qubit(target as u64)?;
+            if targets[.. i].contains(&target) {
+                return Err(simulator_error!("Duplicated target {} of `{}`", target, ident));
+            }
+        }
+
+        let expect = |n_params: usize, n_targets: usize| {
+            if params.len() != n_params || targets.len() != n_targets {
+                Err(simulator_error!(
+                    "`{}` takes {} parameters and {} targets, got {} and {}",
+                    ident, n_params, n_targets, params.len(), targets.len()
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        let invalid_params = || simulator_error!("Invalid parameters {:?} of `{}`", params, ident);
+
+        use StandardOpCode::*;
+        match opcode {
+            CY | CH => {
+                expect(0, 2)?;
+                let gate = match opcode {
+                    CY => StandardSingleGate::Y,
+                    _ => StandardSingleGate::H,
+                };
+                let mut mat = Mat4::identity();
+                mat.fixed_slice_mut::<2, 2>(2, 2).copy_from(&gate.to_mat2());
+                self.state()?.apply(&mat, targets);
+            }
+            CAN => {
+                expect(3, 2)?;
+                let gate = CanonicalGate::new(params[0], params[1], params[2]);
+                self.state()?.apply(&gate.to_mat4(), targets);
+            }
+            CCX => {
+                expect(0, 3)?;
+                self.state()?.apply(&StandardTripleGate::CCX.to_mat8(), targets);
+            }
+            CSWP => {
+                expect(0, 3)?;
+                let mut mat = Mat8::identity();
+                mat.fixed_slice_mut::<4, 4>(4, 4).copy_from(&StandardDoubleGate::SWP.to_mat4());
+                self.state()?.apply(&mat, targets);
+            }
+            _ if targets.len() == 1 => {
+                let gate = single_gate(opcode, &params).ok_or_else(invalid_params)?;
+                self.state()?.apply(&gate.to_mat2(), targets);
+            }
+            _ if targets.len() == 2 => {
+                let gate = double_gate(opcode, &params).ok_or_else(invalid_params)?;
+                self.state()?.apply(&gate.to_mat4(), targets);
+            }
+            _ => return Err(simulator_error!(
+                "Invalid number of targets {} of `{}`", targets.len(), ident
+            )),
+        }
+        Ok(())
+    }
+}
+
+fn single_gate(opcode: StandardOpCode, params: &[f64]) -> Option<StandardSingleGate> {
+    use StandardSingleGate::*;
+    Some(match (opcode, params) {
+        (StandardOpCode::I, []) => I,
+        (StandardOpCode::H, []) => H,
+        (StandardOpCode::X, []) => X,
+        (StandardOpCode::Y, []) => Y,
+        (StandardOpCode::Z, []) => Z,
+        (StandardOpCode::S, []) => S,
+        (StandardOpCode::SD, []) => SD,
+        (StandardOpCode::T, []) => T,
+        (StandardOpCode::TD, []) => TD,
+        (StandardOpCode::V, []) => V,
+        (StandardOpCode::VD, []) => VD,
+        (StandardOpCode::XPOW, &[t]) => XPOW { t },
+        (StandardOpCode::YPOW, &[t]) => YPOW { t },
+        (StandardOpCode::ZPOW, &[t]) => ZPOW { t },
+        (StandardOpCode::P, &[angle]) => P { angle },
+        (StandardOpCode::RX, &[angle]) => RX { angle },
+        (StandardOpCode::RY, &[angle]) => RY { angle },
+        (StandardOpCode::RZ, &[angle]) => RZ { angle },
+        (StandardOpCode::RN, &[nx, ny, nz, angle]) => RN { nx, ny, nz, angle },
+        (StandardOpCode::U, &[theta, lambda, phi]) => U { theta, phi, lambda },
+        _ => return None,
+    })
+}
+
+fn double_gate(opcode: StandardOpCode, params: &[f64]) -> Option<StandardDoubleGate> {
+    use StandardDoubleGate::*;
+    Some(match (opcode, params) {
+        (StandardOpCode::CX, []) => CX,
+        (StandardOpCode::CZ, []) => CZ,
+        (StandardOpCode::CP, &[angle]) => CP { angle },
+        (StandardOpCode::SWP, []) => SWP,
+        (StandardOpCode::SSWP, []) => SSWP,
+        (StandardOpCode::SSWPD, []) => SSWPD,
+        (StandardOpCode::ISWP, []) => ISWP,
+        (StandardOpCode::ISWPD, []) => ISWPD,
+        (StandardOpCode::SISWP, []) => SISWP,
+        (StandardOpCode::SISWPD, []) => SISWPD,
+        _ => return None,
+    })
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use nalgebra::{Dim, Matrix, Storage};
+use num::complex::Complex64;
+use num_traits::Zero;
+use rand::Rng;
+use crate::algebra::EPSILON;
+use crate::qubit::QubitAddr;
+
+/// Dense state vector of `n` qubits.
+///
+/// Qubit `q` corresponds to bit `q` of the basis state index, which matches the
+/// measurement value layout reported by the backends (`value & (1 << q)`).
+pub struct StateVector {
+    num_qubits: usize,
+    amplitudes: Vec<Complex64>,
+}
+
+impl StateVector {
+
+    /// Create a new state vector initialized to `|0...0⟩`.
+    pub fn new(num_qubits: usize) -> Self {
+        let mut amplitudes = vec![Complex64::zero(); 1 << num_qubits];
+        amplitudes[0] = Complex64::new(1.0, 0.0);
+        Self { num_qubits, amplitudes }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn amplitudes(&self) -> &[Complex64] {
+        &self.amplitudes
+    }
+
+    /// Apply a `2^k × 2^k` unitary to `k` target qubits.
+    ///
+    /// The first target is the most significant bit of the matrix index, so a `CX` applied to
+    /// `[ctrl, target]` uses `ctrl` as the control qubit.
+    pub fn apply<D, S>(&mut self, mat: &Matrix<Complex64, D, D, S>, targets: &[QubitAddr])
+    where
+        D: Dim,
+        S: Storage<Complex64, D, D>,
+    {
+        let size = 1usize << targets.len();
+        assert_eq!(mat.nrows(), size);
+
+        let offsets = (0 .. size).map(|index| {
+            targets.iter().enumerate().fold(0usize, |offset, (i, &target)| {
+                if index & (1 << (targets.len() - 1 - i)) != 0 {
+                    offset | (1 << target)
+                } else {
+                    offset
+                }
+            })
+        }).collect::<Vec<usize>>();
+        let target_mask = offsets[size - 1];
+
+        let mut buffer = vec![Complex64::zero(); size];
+        for base in 0 .. self.amplitudes.len() {
+            if base & target_mask != 0 {
+                continue;
+            }
+            for (row, amp) in buffer.iter_mut().enumerate() {
+                *amp = offsets.iter().enumerate()
+                    .map(|(col, offset)| mat[(row, col)] * self.amplitudes[base | offset])
+                    .sum();
+            }
+            for (amp, offset) in buffer.iter().zip(offsets.iter()) {
+                self.amplitudes[base | offset] = *amp;
+            }
+        }
+    }
+
+    /// Probability of each outcome, where an outcome is the basis state masked by `mask`.
+    pub fn distribution(&self, mask: u64) -> BTreeMap<u64, f64> {
+        let mut probs = BTreeMap::new();
+        for (state, amp) in self.amplitudes.iter().enumerate() {
+            let prob = amp.norm_sqr();
+            if prob > EPSILON {
+                *probs.entry(state as u64 & mask).or_insert(0.0) += prob;
+            }
+        }
+        probs
+    }
+
+    /// Measure a single qubit, collapsing the state, and return the outcome.
+    pub fn measure_qubit<R: Rng>(&mut self, qubit: QubitAddr, rng: &mut R) -> bool {
+        let bit = 1usize << qubit;
+        let prob_one = self.amplitudes.iter().enumerate()
+            .filter(|(state, _)| state & bit != 0)
+            .map(|(_, amp)| amp.norm_sqr())
+            .sum::<f64>();
+        let outcome = rng.gen::<f64>() < prob_one;
+        let norm = if outcome { prob_one } else { 1.0 - prob_one }.sqrt();
+        for (state, amp) in self.amplitudes.iter_mut().enumerate() {
+            if (state & bit != 0) == outcome {
+                *amp /= norm;
+            } else {
+                *amp = Complex64::zero();
+            }
+        }
+        outcome
+    }
+
+    /// Flip a single qubit.
+    pub fn flip(&mut self, qubit: QubitAddr) {
+        let bit = 1usize << qubit;
+        for state in 0 .. self.amplitudes.len() {
+            if state & bit == 0 {
+                self.amplitudes.swap(state, state | bit);
+            }
+        }
+    }
+}
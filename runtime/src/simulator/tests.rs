@@ -0,0 +1,229 @@
+use num_traits::FromPrimitive;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::algebra::{close_to_zero, ToMat2, ToMat4};
+use crate::bytecode::ByteCode;
+use crate::bytecode::instruction::{InstrParam, Instruction, PrimitiveOpCode, StandardOpCode};
+use crate::gate::canonical::CanonicalGate;
+use crate::gate::standard::{StandardDoubleGate, StandardSingleGate};
+use crate::simulator::state::StateVector;
+use crate::simulator::{exec_bytecode, is_gate_available, ERROR_BACKEND, ERROR_PARSE};
+use crate::backend::ExecuteResult;
+
+fn alloc(size: u64) -> Instruction {
+    Instruction::Primitive { opcode: PrimitiveOpCode::Alloc, params: vec![InstrParam::UInt(size)] }
+}
+
+fn measure(qubits: &[u64]) -> Instruction {
+    Instruction::Primitive {
+        opcode: PrimitiveOpCode::Measure,
+        params: qubits.iter().map(|&qubit| InstrParam::UInt(qubit)).collect(),
+    }
+}
+
+fn gate(opcode: StandardOpCode, params: &[f64], targets: &[u32]) -> Instruction {
+    Instruction::StandardGateOperation {
+        opcode,
+        params: params.iter().map(|&param| InstrParam::Float(param)).collect(),
+        targets: targets.to_vec(),
+    }
+}
+
+fn execute(instructions: Vec<Instruction>, shots: u32) -> ExecuteResult {
+    let bytecode: ByteCode = instructions.into();
+    exec_bytecode(bytecode.as_slice(), shots)
+}
+
+fn measured_values(result: &ExecuteResult) -> Vec<u64> {
+    result.measurement.measurements.iter().map(|entry| entry.value).collect()
+}
+
+fn random_state<R: Rng>(num_qubits: usize, rng: &mut R) -> StateVector {
+    let mut state = StateVector::new(num_qubits);
+    for qubit in 0 .. num_qubits as u32 {
+        let gate = StandardSingleGate::U {
+            theta: rng.gen_range(0.0 .. 3.0),
+            phi: rng.gen_range(0.0 .. 3.0),
+            lambda: rng.gen_range(0.0 .. 3.0),
+        };
+        state.apply(&gate.to_mat2(), &[qubit]);
+        if qubit > 0 {
+            state.apply(&StandardDoubleGate::CX.to_mat4(), &[qubit - 1, qubit]);
+        }
+    }
+    state
+}
+
+fn assert_state_eq(lhs: &StateVector, rhs: &StateVector) {
+    for (x, y) in lhs.amplitudes().iter().zip(rhs.amplitudes()) {
+        assert!(close_to_zero((x - y).norm()), "{} != {}", x, y);
+    }
+}
+
+#[test]
+fn test_gate_availability() {
+    assert!(is_gate_available("H"));
+    assert!(is_gate_available("CAN"));
+    assert!(is_gate_available("CSWP"));
+    assert!(!is_gate_available("FOO"));
+}
+
+#[test]
+fn test_bell_state() {
+    let result = execute(vec![
+        alloc(2),
+        gate(StandardOpCode::H, &[], &[0]),
+        gate(StandardOpCode::CX, &[], &[0, 1]),
+        measure(&[0, 1]),
+    ], 1000);
+    assert_eq!(result.error_code, 0);
+    assert_eq!(result.measurement.shots, 1000);
+    assert_eq!(measured_values(&result), vec![0b00, 0b11]);
+    assert_eq!(result.measurement.measurements.iter().map(|entry| entry.count).sum::<u64>(), 1000);
+}
+
+#[test]
+fn test_first_target_is_control() {
+    let result = execute(vec![
+        alloc(2),
+        gate(StandardOpCode::X, &[], &[1]),
+        gate(StandardOpCode::CX, &[], &[1, 0]),
+        measure(&[0, 1]),
+    ], 16);
+    assert_eq!(measured_values(&result), vec![0b11]);
+}
+
+#[test]
+fn test_partial_measurement() {
+    let result = execute(vec![
+        alloc(3),
+        gate(StandardOpCode::X, &[], &[2]),
+        gate(StandardOpCode::H, &[], &[0]),
+        measure(&[2]),
+    ], 16);
+    assert_eq!(measured_values(&result), vec![0b100]);
+}
+
+#[test]
+fn test_controlled_swap() {
+    let result = execute(vec![
+        alloc(3),
+        gate(StandardOpCode::X, &[], &[0]),
+        gate(StandardOpCode::X, &[], &[1]),
+        gate(StandardOpCode::CSWP, &[], &[0, 1, 2]),
+        measure(&[0, 1, 2]),
+    ], 16);
+    assert_eq!(measured_values(&result), vec![0b101]);
+}
+
+#[test]
+fn test_reset() {
+    let result = execute(vec![
+        alloc(2),
+        gate(StandardOpCode::X, &[], &[0]),
+        gate(StandardOpCode::H, &[], &[1]),
+        Instruction::Primitive {
+            opcode: PrimitiveOpCode::Reset,
+            params: vec![InstrParam::UInt(0), InstrParam::UInt(1)],
+        },
+        measure(&[0, 1]),
+    ], 16);
+    assert_eq!(measured_values(&result), vec![0]);
+}
+
+#[test]
+fn test_reset_entangled_qubit() {
+    // resetting one qubit of a Bell pair leaves the other one in a mixed state
+    let result = execute(vec![
+        alloc(2),
+        gate(StandardOpCode::H, &[], &[1]),
+        gate(StandardOpCode::CX, &[], &[1, 0]),
+        Instruction::Primitive {
+            opcode: PrimitiveOpCode::Reset,
+            params: vec![InstrParam::UInt(1)],
+        },
+        measure(&[0]),
+    ], 256);
+    assert_eq!(measured_values(&result), vec![0, 1]);
+}
+
+#[test]
+fn test_mid_circuit_measurement() {
+    // the first measurement collapses the qubit, the second H then gives either outcome
+    let result = execute(vec![
+        alloc(1),
+        gate(StandardOpCode::H, &[], &[0]),
+        measure(&[0]),
+        gate(StandardOpCode::H, &[], &[0]),
+        measure(&[0]),
+    ], 256);
+    assert_eq!(measured_values(&result), vec![0, 1]);
+    assert_eq!(result.measurement.shots, 256);
+
+    // the measured qubit keeps its outcome, which the CX copies to the other one
+    let result = execute(vec![
+        alloc(2),
+        gate(StandardOpCode::H, &[], &[0]),
+        measure(&[0]),
+        gate(StandardOpCode::CX, &[], &[0, 1]),
+        measure(&[1]),
+    ], 256);
+    assert_eq!(measured_values(&result), vec![0b00, 0b11]);
+}
+
+#[test]
+fn test_every_standard_opcode() {
+    let mut rng = rand::thread_rng();
+    let opcodes = (0 ..= u8::MAX).filter_map(StandardOpCode::from_u8).collect::<Vec<_>>();
+    assert_eq!(opcodes.len(), 35);
+    for opcode in opcodes {
+        let (n_params, n_targets) = opcode.signature();
+        let params = (0 .. n_params).map(|_| rng.gen_range(-3.0 .. 3.0)).collect::<Vec<f64>>();
+        let targets = (0 .. n_targets as u32).rev().collect::<Vec<u32>>();
+        let result = execute(vec![
+            alloc(3), gate(opcode, &params, &targets), measure(&[0, 1, 2])
+        ], 64);
+        let ident: &'static str = opcode.into();
+        assert_eq!(result.error_code, 0, "failed to execute `{}`", ident);
+        assert_eq!(result.measurement.measurements.iter().map(|entry| entry.count).sum::<u64>(), 64);
+    }
+}
+
+#[test]
+fn test_standard_gates_preserve_norm() {
+    let mut rng = rand::thread_rng();
+    let mut state = random_state(3, &mut rng);
+    let gate = StandardSingleGate::RN { nx: 0.3, ny: -0.4, nz: 0.5, angle: 1.2 };
+    state.apply(&gate.to_mat2(), &[1]);
+    state.apply(&CanonicalGate::new(0.1, 0.2, 0.3).to_mat4(), &[2, 0]);
+    let norm = state.amplitudes().iter().map(|amp| amp.norm_sqr()).sum::<f64>();
+    assert!(close_to_zero(norm - 1.0));
+}
+
+#[test]
+fn test_canonical_matches_iswap() {
+    let seed = rand::thread_rng().gen();
+    let mut lhs = random_state(3, &mut StdRng::seed_from_u64(seed));
+    let mut rhs = random_state(3, &mut StdRng::seed_from_u64(seed));
+    lhs.apply(&StandardDoubleGate::ISWP.to_mat4(), &[2, 1]);
+    rhs.apply(&CanonicalGate::new(-0.5, -0.5, 0.0).to_mat4(), &[2, 1]);
+    assert_state_eq(&lhs, &rhs);
+}
+
+#[test]
+fn test_errors() {
+    let result = execute(vec![gate(StandardOpCode::H, &[], &[0])], 16);
+    assert_eq!(result.error_code, ERROR_BACKEND);
+
+    let result = execute(vec![alloc(2), gate(StandardOpCode::H, &[], &[2])], 16);
+    assert_eq!(result.error_code, ERROR_BACKEND);
+
+    let result = execute(vec![alloc(2), gate(StandardOpCode::CX, &[], &[1, 1])], 16);
+    assert_eq!(result.error_code, ERROR_BACKEND);
+
+    let result = execute(vec![alloc(64)], 16);
+    assert_eq!(result.error_code, ERROR_BACKEND);
+
+    let result = exec_bytecode(&[0xff], 16);
+    assert_eq!(result.error_code, ERROR_PARSE);
+}
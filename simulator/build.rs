@@ -25,6 +25,7 @@ fn main() {
         .include("src")
         .include("QuEST/QuEST/include")
         .define("LOGLEVEL", if debug { "LogLevel::Info" } else { "LogLevel::Warning" })
+        .define("qivm_abi_version", "_qivm_abi_version")
         .define("qivm_available_qubits", "_qivm_available_qubits")
         .define("qivm_is_gate_available", "_qivm_is_gate_available")
         .define("qivm_exec_bytecode", "_qivm_exec_bytecode")
@@ -3,6 +3,9 @@
 #include "logger.hpp"
 #include "utils.hpp"
 
+#include <algorithm>
+#include <iterator>
+
 using std::string;
 
 enum struct InstructionType : std::uint8_t
@@ -32,28 +35,113 @@ inline T next(ByteIter& iterator, const ByteIter& end)
     }
 }
 
+const std::uint8_t MAGIC[] = { 'Q', 'I', 'V', 'M' };
+const std::uint16_t FORMAT_VERSION = 2;
+const std::uint16_t TAGGED_PARAMS_VERSION = 2;
+const std::uint8_t LITTLE_ENDIAN_TAG = 0;
+const std::uint8_t FLAG_METADATA = 0x01;
+const size_t CUSTOM_GATE_DECL_SIZE = 18;
+
+inline ByteIter skip(ByteIter iterator, const ByteIter& end, size_t size)
+{
+    if ((size_t) (end - iterator) < size) {
+        throw BytecodeParseException("Unexpected end of bytecode");
+    }
+    return iterator + (std::ptrdiff_t) size;
+}
+
 ByteCode::ByteCode(const ByteVec& bytes)
 {
-    auto iter = bytes.begin();
+    if (bytes.size() >= sizeof(MAGIC) && std::equal(std::begin(MAGIC), std::end(MAGIC), bytes.begin())) {
+        parseContainer(bytes.begin() + sizeof(MAGIC), bytes.end());
+    } else {
+        // Legacy bytecode without header
+        parseInstructions(bytes.begin(), bytes.end());
+    }
+}
+
+void ByteCode::parseContainer(ByteIter iter, const ByteIter& end)
+{
+    version = next<std::uint16_t>(iter, end);
+    if (version == 0 || version > FORMAT_VERSION) {
+        throw BytecodeParseException("Unsupported bytecode version: " + std::to_string(version));
+    }
+    if (auto endianness = next<std::uint8_t>(iter, end); endianness != LITTLE_ENDIAN_TAG) {
+        throw BytecodeParseException("Unsupported bytecode endianness: " + std::to_string(endianness));
+    }
+    auto flags = next<std::uint8_t>(iter, end);
+    numQubits = next<std::uint32_t>(iter, end);
+
+    // Custom gates are not supported in the simulator backend, skip the declarations
+    auto numCustomGates = next<std::uint16_t>(iter, end);
+    iter = skip(iter, end, numCustomGates * CUSTOM_GATE_DECL_SIZE);
 
-    while (iter != bytes.end()) {
+    auto streamSize = next<std::uint32_t>(iter, end);
+    auto streamEnd = skip(iter, end, streamSize);
+    parseInstructions(iter, streamEnd);
+    iter = streamEnd;
+
+    if (flags & FLAG_METADATA) {
+        for (auto numEntries = next<std::uint16_t>(iter, end); numEntries > 0; numEntries--) {
+            iter = skip(iter, end, next<std::uint16_t>(iter, end));
+            iter = skip(iter, end, next<std::uint32_t>(iter, end));
+        }
+    }
+    if (iter != end) {
+        throw BytecodeParseException("Unexpected trailing bytes");
+    }
+
+    std::uint64_t allocated = 0;
+    for (const auto & instr: instructions) {
+        auto primitiveInstr = std::get_if<PrimitiveInstruction>(&instr);
+        if (primitiveInstr && primitiveInstr->opcode == PrimitiveOpCode::Alloc && !primitiveInstr->params.empty()) {
+            allocated = primitiveInstr->params[0].uint64;
+            break;
+        }
+    }
+    if (allocated != numQubits) {
+        throw BytecodeParseException(
+            "Qubit count mismatch, declared: " + std::to_string(numQubits) +
+            ", allocated: " + std::to_string(allocated)
+        );
+    }
+}
+
+std::vector<InstructionParam> ByteCode::parseParams(ByteIter& iter, const ByteIter& end) const
+{
+    auto numParams = next<uint8_t>(iter, end);
+    std::vector<InstructionParam> params(numParams);
+    for (int i = 0; i < numParams; i++) {
+        // Parameters of older versions are untagged, their type is given by the instruction
+        if (version >= TAGGED_PARAMS_VERSION) {
+            if (auto paramType = next<ParamType>(iter, end); paramType > ParamType::UInt) {
+                throw BytecodeParseException(
+                    "Invalid parameter type: " + std::to_string((int) paramType)
+                );
+            }
+        }
+        params[i] = next<InstructionParam>(iter, end);
+    }
+    return params;
+}
+
+void ByteCode::parseInstructions(ByteIter iter, const ByteIter& end)
+{
+    while (iter != end) {
         auto iterBeginInstr = iter;
-        switch (auto instrType = next<InstructionType>(iter, bytes.end())) {
+        switch (auto instrType = next<InstructionType>(iter, end)) {
             case InstructionType::Nop:
                 break;
             case InstructionType::Primitive: {
                 // Only `Alloc`, `Reset` and `Measure` primitive instructions
                 //  are supported in the simulator backend
-                auto opcode = next<PrimitiveOpCode>(iter, bytes.end());
+                auto opcode = next<PrimitiveOpCode>(iter, end);
                 if ((int) opcode > 2) {
                     throw BytecodeParseException(
                         "Invalid primitive opcode: " + std::to_string((int) opcode)
                     );
                 }
-                std::vector<InstructionParam> params;
-                for (size_t size = next<byte>(iter, bytes.end()); size > 0; size--) {
-                    params.emplace_back(next<InstructionParam>(iter, bytes.end()));
-                }
+                auto params = parseParams(iter, end);
                 auto instruction = PrimitiveInstruction(opcode, params);
                 instructions.emplace_back(instruction);
                 if constexpr (LOGLEVEL >= LogLevel::Debug) {
@@ -63,16 +151,12 @@ ByteCode::ByteCode(const ByteVec& bytes)
                 break;
             }
             case InstructionType::Standard: {
-                auto gate = next<StandardGate>(iter, bytes.end());
-                auto numParams = next<uint8_t>(iter, bytes.end());
-                std::vector<InstructionParam> params(numParams);
-                for (int i = 0; i < numParams; i++) {
-                    params[i] = next<InstructionParam>(iter, bytes.end());
-                }
-                auto numTargetQubits = next<uint8_t>(iter, bytes.end());
+                auto gate = next<StandardGate>(iter, end);
+                auto params = parseParams(iter, end);
+                auto numTargetQubits = next<uint8_t>(iter, end);
                 std::vector<QubitAddr> target(numTargetQubits);
                 for (int i = 0; i < numTargetQubits; i++) {
-                    target[i] = next<QubitAddr>(iter, bytes.end());
+                    target[i] = next<QubitAddr>(iter, end);
                 }
                 auto instruction = StandardGateInstruction(gate, params, target);
                 instructions.emplace_back(instruction);
@@ -14,6 +14,14 @@ class ByteCode
   private:
 
     std::vector<Instruction> instructions;
+    std::uint16_t version = 0;
+    std::uint32_t numQubits = 0;
+
+    std::vector<InstructionParam> parseParams(ByteVec::const_iterator& iter, const ByteVec::const_iterator& end) const;
+
+    void parseInstructions(ByteVec::const_iterator iter, const ByteVec::const_iterator& end);
+
+    void parseContainer(ByteVec::const_iterator iter, const ByteVec::const_iterator& end);
 
   public:
 
@@ -24,6 +32,13 @@ class ByteCode
         return instructions.size();
     }
 
+    /// Version of the container format, `0` for the legacy headerless bytecode
+    [[nodiscard]]
+    inline std::uint16_t formatVersion() const
+    {
+        return version;
+    }
+
     inline void forEach(
         const std::function<void(const PrimitiveInstruction&)> & primitiveInstrConsumer,
         const std::function<void(const StandardGateInstruction&)> & standardGateInstrConsumer
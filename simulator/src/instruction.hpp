@@ -24,6 +24,15 @@ union InstructionParam
     std::uint64_t uint64;
 };
 
+/// Type tag preceding each parameter since version 2 of the bytecode,
+///  tags above `UInt` are reserved
+enum struct ParamType : std::uint8_t
+{
+    Float = 0x00,
+    Int = 0x01,
+    UInt = 0x02,
+};
+
 enum struct PrimitiveOpCode : std::uint8_t
 {
     Alloc = 0x00,
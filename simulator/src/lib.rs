@@ -21,6 +21,7 @@ pub struct MeasurementResultEntry {
 
 #[link(name = "qil")]
 extern "C" {
+    pub fn _qivm_abi_version() -> u32;
     pub fn _qivm_available_qubits() -> u32;
     pub fn _qivm_is_gate_available(gate_ident: *const c_char) -> bool;
     pub fn _qivm_exec_bytecode(
@@ -28,6 +29,11 @@ extern "C" {
     ) -> ExecuteResult;
 }
 
+#[no_mangle]
+pub unsafe extern fn qivm_abi_version() -> u32 {
+    unsafe { _qivm_abi_version() }
+}
+
 #[no_mangle]
 pub unsafe extern fn qivm_available_qubits() -> u32 {
     unsafe { _qivm_available_qubits() }
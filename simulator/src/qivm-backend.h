@@ -4,6 +4,9 @@
 
 #include <stdint.h>
 
+/// Version of the backend ABI, reported by `qivm_abi_version`.
+#define QIVM_ABI_VERSION 1
+
 #ifdef __cplusplus
   extern "C" {
 #endif
@@ -30,6 +33,7 @@ typedef struct ExecuteResult
 }
 ExecuteResult;
 
+uint32_t qivm_abi_version();
 uint32_t qivm_available_qubits();
 bool qivm_is_gate_available(const char*);
 struct ExecuteResult qivm_exec_bytecode(const uint8_t*, uint32_t, uint32_t);
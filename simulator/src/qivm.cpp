@@ -83,6 +83,10 @@ std::map<uint64_t, double> executeOnce(QuESTEnv & env, const ByteCode & bytecode
     return probs;
 }
 
+extern "C" uint32_t qivm_abi_version() {
+    return QIVM_ABI_VERSION;
+}
+
 extern "C" uint32_t qivm_available_qubits() {
     return 24;
 }